@@ -0,0 +1,70 @@
+//! Benchmarks of the force model, the propagation and the snapshot loading. The states are
+//! read from `benches/states`, so the results don't depend on the local snapshots directory.
+
+use std::{fs, path::PathBuf};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use eclipses::{snapshots::Snapshots, Propagator, SimState};
+
+const YEAR: f64 = 365.25 * 86400.0;
+
+fn states_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("benches")
+        .join("states")
+}
+
+/// The initial state with the Earth's oblateness and the relativistic correction.
+fn full_model() -> SimState {
+    let mut snapshots = Snapshots::open(states_dir().join("snapshots")).unwrap();
+    snapshots.get_earliest().unwrap().1.clone()
+}
+
+/// The solar system with a hundred massless bodies in the asteroid belt.
+fn asteroids() -> SimState {
+    let text = fs::read_to_string(states_dir().join("asteroids.toml")).unwrap();
+    toml::from_str(&text).unwrap()
+}
+
+fn momentum_derivative(c: &mut Criterion) {
+    let mut group = c.benchmark_group("momentum_derivative");
+    for (name, sim) in [("full_model", full_model()), ("asteroids", asteroids())] {
+        group.bench_function(name, |b| b.iter(|| black_box(&sim).momentum_derivative()));
+    }
+    group.finish();
+}
+
+fn propagation(c: &mut Criterion) {
+    let sim = full_model();
+    let mut group = c.benchmark_group("propagation");
+    group.sample_size(10);
+    group.bench_function("year", |b| {
+        b.iter_batched_ref(
+            || sim.clone(),
+            |sim| Propagator::new(300.0).advance(sim, YEAR),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("year_compensated", |b| {
+        b.iter_batched_ref(
+            || sim.clone(),
+            |sim| Propagator::new(300.0).compensated().advance(sim, YEAR),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn snapshot_loading(c: &mut Criterion) {
+    let dir = states_dir().join("snapshots");
+    c.bench_function("snapshot_loading", |b| {
+        b.iter(|| {
+            let mut snapshots = Snapshots::open(&dir).unwrap();
+            snapshots.get_closest(YEAR).map(|(time, _)| time)
+        })
+    });
+}
+
+criterion_group!(benches, momentum_derivative, propagation, snapshot_loading);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+/* C interface of the eclipses library, built with the `ffi` feature:
+ *
+ *     cargo build --release --features ffi
+ *
+ * which produces libeclipses.so (or .dylib, .dll) and libeclipses.a in target/release.
+ *
+ * Dates are UTC Unix times, in seconds. Functions returning a pointer return NULL on failure,
+ * and those returning an int a negative number; eclipses_last_error() then tells why.
+ */
+
+#ifndef ECLIPSES_H
+#define ECLIPSES_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct EclipsesSim EclipsesSim;
+
+enum {
+    ECLIPSES_MOON_OUT_OF_FRAME = 0,
+    ECLIPSES_MOON_OBSCURED = 1,
+    ECLIPSES_MOON_VISIBLE = 2,
+    ECLIPSES_MOON_TRANSITING = 3,
+};
+
+/* The message of the last error in the calling thread, or NULL. Valid until the next failing
+ * call in the same thread. */
+const char *eclipses_last_error(void);
+
+/* The built-in state of the solar system at the simulation epoch, 2000-01-01 TDB. */
+EclipsesSim *eclipses_sim_new(void);
+
+/* A state loaded from the contents of a snapshot file. */
+EclipsesSim *eclipses_sim_from_snapshot(const uint8_t *bytes, size_t len);
+
+void eclipses_sim_free(EclipsesSim *sim);
+
+/* The date of the current state. */
+double eclipses_sim_time(const EclipsesSim *sim);
+
+/* Propagates the state to the given date, forwards or backwards. Returns 0 on success. */
+int eclipses_propagate(EclipsesSim *sim, double time);
+
+/* How a built-in observer ("himawari", "goes-east", "goes-west", "meteosat" or "dscovr") sees
+ * the Moon at the given date, one of ECLIPSES_MOON_*. The state is propagated to that date,
+ * so querying dates in order is fastest. */
+int eclipses_moon_visibility(EclipsesSim *sim, const char *observer, double time);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,91 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Precision of the reported apsis times, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ApsisKind {
+    Perigee,
+    Apogee,
+}
+
+/// A passage of the Moon through the perigee or the apogee of its orbit around the Earth.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Apsis {
+    pub kind: ApsisKind,
+    pub date: DateTime<Utc>,
+    /// Distance between the centers of the Earth and the Moon, in km.
+    pub distance: f64,
+    /// Apparent diameter of the Moon from the Earth's center, in radians.
+    pub angular_diameter: f64,
+}
+
+/// Rate of change of the Earth-Moon distance, in km/s.
+fn radial_velocity(sim: &SimState) -> f64 {
+    let earth = sim.body_by_name("Earth").unwrap();
+    let moon = sim.body_by_name("Moon").unwrap();
+    let pos = moon.pos - earth.pos;
+    pos.dot(&(moon.vel - earth.vel)) / pos.norm()
+}
+
+/// Finds the lunar perigees and apogees between `start` and `end` (simulation times), `sim`
+/// being the state of the system at `start`.
+pub fn lunar_apsides<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+) -> Vec<Apsis> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut receding = radial_velocity(&sim) > 0.0;
+    let mut result = vec![];
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        let now_receding = radial_velocity(&sim) > 0.0;
+        if now_receding != receding {
+            let apsis_time = refine::bisect(
+                &propagator,
+                &sim,
+                time,
+                step,
+                REFINE_TOLERANCE,
+                |state, _| (radial_velocity(state) > 0.0) == now_receding,
+            );
+            let state = propagator.propagated(&sim, apsis_time - time);
+            let earth = state.body_by_name("Earth").unwrap();
+            let moon = state.body_by_name("Moon").unwrap();
+            let distance = moon.distance_from(earth);
+            if apsis_time <= end {
+                let kind = if now_receding {
+                    ApsisKind::Perigee
+                } else {
+                    ApsisKind::Apogee
+                };
+                let date = sim_time_to_utc(apsis_time);
+                debug!(?kind, %date, distance, "found apsis");
+                result.push(Apsis {
+                    kind,
+                    date,
+                    distance,
+                    angular_diameter: 2.0 * (moon.radius / distance).asin(),
+                });
+            }
+            receding = now_receding;
+        }
+    }
+
+    result
+}
@@ -0,0 +1,224 @@
+use std::{fs, path::Path};
+
+use crate::{
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    simulation::{Body, Oblateness, SimState},
+};
+use nalgebra::Vector3;
+
+/// Bodies the predictions can't be made without.
+pub(crate) const REQUIRED: &[&str] = &["Sun", "Earth", "Moon"];
+
+/// Loads the bodies from a TOML file, or a JSON one if the extension is `.json`. Each body is
+/// a `[[body]]` entry with `name`, `gm` (km³/s²), `radius` (km), and `pos`/`vel` (km, km/s)
+/// given at the simulation epoch in the same frame as `solar_system`. Bodies without `gm` are
+/// test particles: they move in the field of the others without perturbing them. A body can
+/// also have a J2 term, given as a `[body.oblateness]` table with `j2`, the reference `radius`
+/// and the `pole` of the rotation axis. A top-level `relativistic` field can name the body
+/// whose field includes the post-Newtonian correction.
+pub fn load(path: &Path) -> Result<SimState> {
+    let load = || -> Result<SimState> {
+        let contents = fs::read_to_string(path)?;
+        let sim = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => toml::from_str(&contents)?,
+        };
+        check_required(sim)
+    };
+    load().map_err(|err| err.in_file(path))
+}
+
+/// Parses the bodies in the TOML format of `load`.
+pub(crate) fn from_toml(contents: &str) -> Result<SimState> {
+    check_required(toml::from_str(contents)?)
+}
+
+/// Writes the bodies in the TOML format of `load`.
+pub(crate) fn to_toml(sim: &SimState) -> String {
+    toml::to_string(sim).expect("bodies are always serializable")
+}
+
+fn check_required(sim: SimState) -> Result<SimState> {
+    for name in REQUIRED {
+        if sim.body_by_name(name).is_none() {
+            return Err(Error::MissingBody(name.to_string()));
+        }
+    }
+    Ok(sim)
+}
+
+/// The Earth's J2 (EGM2008), with the rotation axis fixed at its J2000 direction.
+pub fn earth_oblateness() -> Oblateness {
+    Oblateness {
+        j2: 1.082_626_68e-3,
+        radius: 6378.1363,
+        pole: equatorial_to_ecliptic(Vector3::z()),
+    }
+}
+
+/// The Sun, the planets and the Moon at 2000-01-01T00:00:00 TDB (barycentric, ecliptic J2000,
+/// km and km/s), as given by JPL Horizons.
+pub fn solar_system() -> SimState {
+    SimState::new()
+        .with_body(Body {
+            name: "Sun".to_owned(),
+            gm: 132712440041.93938,
+            pos: Vector3::new(
+                -1.068108951496322E+06,
+                -4.177210908491462E+05,
+                3.086887010002915E+04,
+            ),
+            vel: Vector3::new(
+                9.305302656256911E-03,
+                -1.283177282717393E-02,
+                -1.631700118015769E-04,
+            ),
+            radius: 696000.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Mercury".to_owned(),
+            gm: 22031.86855,
+            pos: Vector3::new(
+                -2.212073002393702E+07,
+                -6.682435921338345E+07,
+                -3.461577076477692E+06,
+            ),
+            vel: Vector3::new(
+                3.666229234452722E+01,
+                -1.230266984222893E+01,
+                -4.368336206255391E+00,
+            ),
+            radius: 2440.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Venus".to_owned(),
+            gm: 324858.592,
+            pos: Vector3::new(
+                -1.085736592234813E+08,
+                -3.784241757371509E+06,
+                6.190088659339075E+06,
+            ),
+            vel: Vector3::new(
+                8.984650886248794E-01,
+                -3.517203951420625E+01,
+                -5.320225928762774E-01,
+            ),
+            radius: 6052.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Earth".to_owned(),
+            gm: 398600.435436,
+            pos: Vector3::new(
+                -2.627903751048988E+07,
+                1.445101984929515E+08,
+                3.025245352813601E+04,
+            ),
+            vel: Vector3::new(
+                -2.983052803412253E+01,
+                -5.220465675237847E+00,
+                -1.014855999592612E-04,
+            ),
+            radius: 6371.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Moon".to_owned(),
+            gm: 4902.800066,
+            pos: Vector3::new(
+                -2.659668775178492E+07,
+                1.442683153167126E+08,
+                6.680827660505474E+04,
+            ),
+            vel: Vector3::new(
+                -2.926974096801152E+01,
+                -6.020397935372383E+00,
+                -1.740818643718001E-03,
+            ),
+            radius: 1737.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Mars".to_owned(),
+            gm: 42828.375214,
+            pos: Vector3::new(
+                2.069270543147017E+08,
+                -3.560689745239088E+06,
+                -5.147936537447235E+06,
+            ),
+            vel: Vector3::new(
+                1.304308833322233E+00,
+                2.628158890420931E+01,
+                5.188465740839767E-01,
+            ),
+            radius: 3390.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Jupiter".to_owned(),
+            gm: 126686531.900,
+            pos: Vector3::new(
+                5.978410555886381E+08,
+                4.387048655696349E+08,
+                -1.520164176015472E+07,
+            ),
+            vel: Vector3::new(
+                -7.892632213479861E+00,
+                1.115034525890079E+01,
+                1.305100448596264E-01,
+            ),
+            radius: 69911.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Saturn".to_owned(),
+            gm: 37931206.159,
+            pos: Vector3::new(
+                9.576383364792708E+08,
+                9.821475307689621E+08,
+                -5.51898118131116E+07,
+            ),
+            vel: Vector3::new(
+                -7.419580382572883E+00,
+                6.72598247130563E+00,
+                1.775012039800541E-01,
+            ),
+            radius: 58232.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Uranus".to_owned(),
+            gm: 5793951.322,
+            pos: Vector3::new(
+                2.157706590772995E+09,
+                -2.055242872276605E+09,
+                -3.559274281048691E+07,
+            ),
+            vel: Vector3::new(
+                4.646953838324629E+00,
+                4.614361336011624E+00,
+                -4.301369677250144E-02,
+            ),
+            radius: 25362.0,
+            oblateness: None,
+        })
+        .with_body(Body {
+            name: "Neptune".to_owned(),
+            gm: 6835099.97,
+            pos: Vector3::new(
+                2.513785451779509E+09,
+                -3.739265135509532E+09,
+                1.907027540535474E+07,
+            ),
+            vel: Vector3::new(
+                4.475107938022004E+00,
+                3.06285054698897E+00,
+                -1.667293921151841E-01,
+            ),
+            radius: 24624.0,
+            oblateness: None,
+        })
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{
+    error::{Error, Result},
+    simulation::SimState,
+};
+
+/// A directory of computed results, one JSON file per query. The files are named after the
+/// CRC-32 of the query's key, which is also stored inside and compared when reading, so a
+/// collision only causes a miss.
+///
+/// The key has to describe everything the results depend on: the query, the period, the
+/// initial state (see `fingerprint`), the integrator options and where the propagation
+/// starts from. Results computed with different settings are then simply never found.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    results: T,
+}
+
+impl Cache {
+    /// Opens the cache in `dir`, creating the directory if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir).map_err(|err| Error::from(err).in_file(&dir))?;
+        Ok(Self { dir })
+    }
+
+    /// The results stored for `key`, if there are any. Unreadable entries count as missing.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let path = self.path(key);
+        let entry: Entry<T> = match fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|text| Ok(serde_json::from_str(&text)?))
+        {
+            Ok(entry) => entry,
+            Err(err) => {
+                debug!(path = %path.display(), %err, "no cached results");
+                return None;
+            }
+        };
+        if entry.key != key {
+            debug!(path = %path.display(), "cached results of another query");
+            return None;
+        }
+        info!(path = %path.display(), "using cached results");
+        Some(entry.results)
+    }
+
+    /// Stores the results for `key`, replacing any stored before.
+    pub fn put<T: Serialize>(&self, key: &str, results: &T) -> Result<()> {
+        let path = self.path(key);
+        let entry = Entry {
+            key: key.to_owned(),
+            results,
+        };
+        fs::write(&path, serde_json::to_string(&entry)?)
+            .map_err(|err| Error::from(err).in_file(&path))?;
+        debug!(path = %path.display(), "cached results");
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:08x}.json", crc32fast::hash(key.as_bytes())))
+    }
+}
+
+/// A short digest of a state, changing with any of its bodies or their parameters.
+pub fn fingerprint(sim: &SimState) -> String {
+    let state = serde_json::to_string(sim).expect("states can be serialized");
+    format!("{:08x}", crc32fast::hash(state.as_bytes()))
+}
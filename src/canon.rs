@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Datelike, NaiveDate, SubsecRound, Utc};
+
+use crate::{
+    eclipse::{Eclipse, SolarEclipse},
+    time::{self, duration, tt_minus_utc},
+};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Writes `eclipses` as a table with the columns of Espenak & Meeus' "Five Millennium Canon of
+/// Solar Eclipses": the date and time (TD) of greatest eclipse, ΔT (s), the Saros series,
+/// the type, gamma, the magnitude, the latitude and longitude of greatest eclipse and the path
+/// width (km). Like in the canon, the dates before 1582 October 15 are Julian.
+pub fn write_solar_eclipses<W: Write>(out: &mut W, eclipses: &[SolarEclipse]) -> io::Result<()> {
+    writeln!(
+        out,
+        " Calendar Date    TD of     ΔT  Saros  Ecl.   Gamma    Ecl.   Lat.    Long.   Path"
+    )?;
+    writeln!(
+        out,
+        "                 Greatest   (s)  Num   Type            Mag.                   Width"
+    )?;
+    for eclipse in eclipses {
+        let delta_t = tt_minus_utc(eclipse.greatest);
+        let td = (eclipse.greatest + duration(delta_t)).round_subsecs(0);
+        let point = eclipse.greatest_point;
+        writeln!(
+            out,
+            "{}  {}  {:>5.0}  {:>4}   {:<4} {:>8.4}  {:>6.4}  {:>5}  {:>6}  {:>5}",
+            calendar_date(td),
+            td.format("%H:%M:%S"),
+            delta_t,
+            eclipse.saros.series,
+            kind(eclipse.kind),
+            eclipse.gamma,
+            eclipse.magnitude,
+            angle(point.latitude, 'N', 'S'),
+            angle(point.longitude, 'E', 'W'),
+            eclipse
+                .path_width
+                .map_or("-".to_owned(), |width| format!("{:.0}", width)),
+        )?;
+    }
+    Ok(())
+}
+
+/// Formats the date like "-0584 May 28".
+fn calendar_date(date: DateTime<Utc>) -> String {
+    let gregorian_start = NaiveDate::from_ymd_opt(1582, 10, 15).unwrap();
+    let (year, month, day) = if date.date_naive() < gregorian_start {
+        time::julian_calendar(date)
+    } else {
+        (date.year(), date.month(), date.day())
+    };
+    let year = if year < 0 {
+        format!("-{:04}", -year)
+    } else {
+        format!("{:04}", year)
+    };
+    format!("{:>5} {} {:02}", year, MONTHS[month as usize - 1], day)
+}
+
+fn kind(kind: Eclipse) -> &'static str {
+    match kind {
+        Eclipse::TotalSolar => "T",
+        Eclipse::AnnularSolar => "A",
+        _ => "P",
+    }
+}
+
+/// Formats an angle in degrees like "16.7W".
+fn angle(value: f64, positive: char, negative: char) -> String {
+    let side = if value < 0.0 { negative } else { positive };
+    format!("{:.1}{}", value.abs(), side)
+}
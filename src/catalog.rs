@@ -0,0 +1,304 @@
+use nalgebra::Vector3;
+
+use crate::{
+    elements::OrbitalElements,
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    simulation::{Body, Oblateness, SimState},
+    time::EPOCH_JD,
+};
+
+/// Physical data of a body: GM (km³/s²), mean radius (km), and the J2 term of its gravity
+/// field with its reference radius (km), if known, and the right ascension and declination of
+/// its north pole (J2000 equator, degrees).
+pub struct CatalogBody {
+    pub name: &'static str,
+    pub gm: f64,
+    pub radius: f64,
+    pub j2: Option<(f64, f64)>,
+    pub pole: (f64, f64),
+}
+
+/// The Sun, the planets (with the GM of their systems), the Moon, Pluto and the major moons.
+/// The values are the IAU/JPL ones.
+pub const CATALOG: &[CatalogBody] = &[
+    body(
+        "Sun",
+        132712440041.93938,
+        696000.0,
+        Some((2.2e-7, 696000.0)),
+        (286.13, 63.87),
+    ),
+    body(
+        "Mercury",
+        22031.86855,
+        2439.7,
+        Some((50.3e-6, 2440.5)),
+        (281.0103, 61.4155),
+    ),
+    body(
+        "Venus",
+        324858.592,
+        6051.8,
+        Some((4.458e-6, 6051.8)),
+        (272.76, 67.16),
+    ),
+    body(
+        "Earth",
+        398600.435507,
+        6371.0,
+        Some((1.08262668e-3, 6378.1363)),
+        (0.0, 90.0),
+    ),
+    body(
+        "Moon",
+        4902.800118,
+        1737.4,
+        Some((203.3e-6, 1738.0)),
+        (266.86, 65.64),
+    ),
+    body(
+        "Mars",
+        42828.375816,
+        3389.5,
+        Some((1960.45e-6, 3396.19)),
+        (317.681, 52.887),
+    ),
+    body(
+        "Jupiter",
+        126712764.1,
+        69911.0,
+        Some((14696.5e-6, 71492.0)),
+        (268.057, 64.495),
+    ),
+    body(
+        "Saturn",
+        37940584.8418,
+        58232.0,
+        Some((16290.7e-6, 60330.0)),
+        (40.589, 83.537),
+    ),
+    body(
+        "Uranus",
+        5794556.4,
+        25362.0,
+        Some((3510.7e-6, 25559.0)),
+        (257.311, -15.175),
+    ),
+    body(
+        "Neptune",
+        6836527.10058,
+        24622.0,
+        Some((3408.4e-6, 25225.0)),
+        (299.36, 43.46),
+    ),
+    body("Pluto", 975.5, 1188.3, None, (132.993, -6.163)),
+    body("Io", 5959.916, 1821.6, None, (268.05, 64.50)),
+    body("Europa", 3202.739, 1560.8, None, (268.08, 64.51)),
+    body("Ganymede", 9887.834, 2631.2, None, (268.20, 64.57)),
+    body("Callisto", 7179.289, 2410.3, None, (268.72, 64.83)),
+    body("Rhea", 153.94, 763.8, None, (40.38, 83.55)),
+    body("Titan", 8978.14, 2574.7, None, (39.4827, 83.4279)),
+    body("Iapetus", 120.5, 734.5, None, (318.16, 75.03)),
+    body("Triton", 1427.6, 1353.4, None, (299.36, 41.17)),
+];
+
+const fn body(
+    name: &'static str,
+    gm: f64,
+    radius: f64,
+    j2: Option<(f64, f64)>,
+    pole: (f64, f64),
+) -> CatalogBody {
+    CatalogBody {
+        name,
+        gm,
+        radius,
+        j2,
+        pole,
+    }
+}
+
+/// Groups of bodies `include` can add, and `oblateness`, which adds the J2 terms of the
+/// simulated bodies but the Earth, whose is added separately.
+pub const GROUPS: &[&str] = &[
+    "pluto",
+    "jupiter-moons",
+    "saturn-moons",
+    "neptune-moons",
+    "oblateness",
+];
+
+/// A moon on a circular orbit in its primary's equatorial plane: the primary, the radius of
+/// the orbit (km), the sidereal period (days, negative for retrograde orbits) and the mean
+/// longitude from the ascending node of the equator on the ecliptic at J2000 (degrees).
+struct MoonOrbit {
+    name: &'static str,
+    primary: &'static str,
+    radius: f64,
+    period: f64,
+    longitude: f64,
+}
+
+/// The phases of the Galilean moons follow Meeus' low-accuracy theory and the others are only
+/// rough, so the moons are meant to pull their primaries around, not to be looked at.
+const MOONS: &[MoonOrbit] = &[
+    moon("Io", "Jupiter", 421800.0, 1.769137786, 163.8069),
+    moon("Europa", "Jupiter", 671100.0, 3.551181041, 358.4140),
+    moon("Ganymede", "Jupiter", 1070400.0, 7.15455296, 5.7176),
+    moon("Callisto", "Jupiter", 1882700.0, 16.6890184, 224.8092),
+    moon("Rhea", "Saturn", 527108.0, 4.518212, 0.0),
+    moon("Titan", "Saturn", 1221870.0, 15.945421, 0.0),
+    moon("Iapetus", "Saturn", 3560820.0, 79.3215, 0.0),
+    moon("Triton", "Neptune", 354759.0, -5.876854, 0.0),
+];
+
+const fn moon(
+    name: &'static str,
+    primary: &'static str,
+    radius: f64,
+    period: f64,
+    longitude: f64,
+) -> MoonOrbit {
+    MoonOrbit {
+        name,
+        primary,
+        radius,
+        period,
+        longitude,
+    }
+}
+
+pub fn by_name(name: &str) -> Option<&'static CatalogBody> {
+    CATALOG.iter().find(|body| body.name == name)
+}
+
+/// Adds the bodies of `group` (one of `GROUPS`) to `sim`, the state at the simulation time
+/// `time`, skipping the ones it already has.
+pub fn include(sim: SimState, group: &str, time: f64) -> Result<SimState> {
+    let moons = |primary: &str| -> Vec<&MoonOrbit> {
+        MOONS
+            .iter()
+            .filter(|moon| moon.primary == primary)
+            .collect()
+    };
+    match group {
+        "pluto" => add_pluto(sim, time),
+        "jupiter-moons" => add_moons(sim, &moons("Jupiter"), time),
+        "saturn-moons" => add_moons(sim, &moons("Saturn"), time),
+        "neptune-moons" => add_moons(sim, &moons("Neptune"), time),
+        "oblateness" => Ok(add_oblateness(sim)),
+        _ => Err(Error::Argument(format!("unknown body group: {}", group))),
+    }
+}
+
+/// Unit vector along the pole of a catalog body, in the simulation frame.
+fn pole(body: &CatalogBody) -> Vector3<f64> {
+    let (ra, dec) = (body.pole.0.to_radians(), body.pole.1.to_radians());
+    equatorial_to_ecliptic(Vector3::new(
+        dec.cos() * ra.cos(),
+        dec.cos() * ra.sin(),
+        dec.sin(),
+    ))
+}
+
+fn add_oblateness(mut sim: SimState) -> SimState {
+    for body in CATALOG.iter().filter(|body| body.name != "Earth") {
+        if let Some((j2, radius)) = body.j2 {
+            sim = sim.with_oblateness(
+                body.name,
+                Oblateness {
+                    j2,
+                    radius,
+                    pole: pole(body),
+                },
+            );
+        }
+    }
+    sim
+}
+
+/// Days since J2000 at the simulation time `time`.
+fn days_since_j2000(time: f64) -> f64 {
+    time / 86400.0 + EPOCH_JD - 2451545.0
+}
+
+/// Adds Pluto from the mean elements of Standish's table for 1800-2050, good to about a
+/// degree then.
+fn add_pluto(sim: SimState, time: f64) -> Result<SimState> {
+    if sim.body_by_name("Pluto").is_some() {
+        return Ok(sim);
+    }
+    let sun = sim
+        .body_by_name("Sun")
+        .ok_or_else(|| Error::MissingBody("Sun".to_owned()))?;
+    let catalog = by_name("Pluto").unwrap();
+    let days = days_since_j2000(time);
+    let centuries = days / 36525.0;
+    let node = 110.30393684 - 0.01183482 * centuries;
+    let perihelion = 224.06891629 - 0.04062942 * centuries;
+    let longitude = 238.92903833 + 145.20780515 * centuries;
+    let gm = sun.gm + catalog.gm;
+    let jd = days + 2451545.0;
+    let elements = OrbitalElements::elliptic(
+        catalog.name.to_owned(),
+        jd,
+        39.48211675,
+        0.2488273,
+        (17.14001206, node, perihelion - node),
+        longitude - perihelion,
+        gm,
+    );
+    let (pos, vel) = elements.state_at(jd, gm);
+
+    let body = Body {
+        name: catalog.name.to_owned(),
+        gm: catalog.gm,
+        pos: sun.pos + pos,
+        vel: sun.vel + vel,
+        radius: catalog.radius,
+        oblateness: None,
+    };
+    Ok(sim.with_body(body))
+}
+
+/// Adds `moons` around their primary, taking their mass out of the primary's, which is the
+/// mass of the whole system, and moving it so that the system's barycenter stays put.
+fn add_moons(mut sim: SimState, moons: &[&MoonOrbit], time: f64) -> Result<SimState> {
+    let days = days_since_j2000(time);
+    for orbit in moons {
+        if sim.body_by_name(orbit.name).is_some() {
+            continue;
+        }
+        let catalog = by_name(orbit.name).unwrap();
+        let primary_index = sim
+            .body_index_by_name(orbit.primary)
+            .ok_or_else(|| Error::MissingBody(orbit.primary.to_owned()))?;
+        let primary = sim.get_body(primary_index).clone();
+
+        // the equatorial plane, with `x` towards its ascending node on the ecliptic
+        let z = pole(by_name(orbit.primary).unwrap());
+        let x = Vector3::z().cross(&z).normalize();
+        let y = z.cross(&x);
+        let longitude = (orbit.longitude + 360.0 * days / orbit.period).to_radians();
+        let speed = (primary.gm / orbit.radius).sqrt() * orbit.period.signum();
+        let rel_pos = (x * longitude.cos() + y * longitude.sin()) * orbit.radius;
+        let rel_vel = (-x * longitude.sin() + y * longitude.cos()) * speed;
+
+        let share = catalog.gm / primary.gm;
+        let body = sim.get_body_mut(primary_index);
+        body.gm -= catalog.gm;
+        body.pos -= rel_pos * share;
+        body.vel -= rel_vel * share;
+        let body = Body {
+            name: orbit.name.to_owned(),
+            gm: catalog.gm,
+            pos: primary.pos + rel_pos * (1.0 - share),
+            vel: primary.vel + rel_vel * (1.0 - share),
+            radius: catalog.radius,
+            oblateness: None,
+        };
+        sim = sim.with_body(body);
+    }
+    Ok(sim)
+}
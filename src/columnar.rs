@@ -0,0 +1,95 @@
+//! Parquet files of the result tables, for reading with pandas, polars and the like.
+
+use std::{fs::File, path::Path, sync::Arc};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{Field, Schema};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+
+use crate::{error::Result, events::Event, trajectory::StateVector};
+
+/// Writes the states of bodies with the columns of the CSV table, the date being a UTC
+/// timestamp.
+pub fn write_states(path: &Path, states: &[StateVector]) -> Result<()> {
+    let coordinate = |get: fn(&StateVector) -> f64| -> ArrayRef {
+        Arc::new(states.iter().map(get).collect::<Float64Array>())
+    };
+    let columns = vec![
+        ("date", dates(states.iter().map(|state| state.date))),
+        (
+            "body",
+            Arc::new(StringArray::from_iter_values(
+                states.iter().map(|state| &state.body),
+            )) as ArrayRef,
+        ),
+        ("x", coordinate(|state| state.position[0])),
+        ("y", coordinate(|state| state.position[1])),
+        ("z", coordinate(|state| state.position[2])),
+        ("vx", coordinate(|state| state.velocity[0])),
+        ("vy", coordinate(|state| state.velocity[1])),
+        ("vz", coordinate(|state| state.velocity[2])),
+    ];
+    write(path, columns).map_err(|err| err.in_file(path))
+}
+
+/// Writes the visibility events with the columns of the CSV table.
+pub fn write_events(path: &Path, events: &[Event]) -> Result<()> {
+    let columns = vec![
+        (
+            "observer",
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|event| &event.observer),
+            )) as ArrayRef,
+        ),
+        (
+            "event",
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|event| format!("{:?}", event.kind)),
+            )),
+        ),
+        ("date", dates(events.iter().map(|event| event.date))),
+        (
+            "illuminated_fraction",
+            Arc::new(
+                events
+                    .iter()
+                    .map(|event| event.illuminated_fraction)
+                    .collect::<Float64Array>(),
+            ),
+        ),
+        (
+            "phase_angle",
+            Arc::new(
+                events
+                    .iter()
+                    .map(|event| event.phase_angle.to_degrees())
+                    .collect::<Float64Array>(),
+            ),
+        ),
+    ];
+    write(path, columns).map_err(|err| err.in_file(path))
+}
+
+fn dates<I: Iterator<Item = DateTime<Utc>>>(dates: I) -> ArrayRef {
+    let micros = dates.map(|date| date.timestamp_micros());
+    Arc::new(TimestampMicrosecondArray::from_iter_values(micros).with_timezone("UTC"))
+}
+
+fn write(path: &Path, columns: Vec<(&str, ArrayRef)>) -> Result<()> {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|(name, column)| Field::new(*name, column.data_type().clone(), false))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        columns.into_iter().map(|(_, column)| column).collect(),
+    )
+    .map_err(parquet::errors::ParquetError::from)?;
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
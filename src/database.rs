@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    time::sim_time_to_utc,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS searches (
+        search TEXT NOT NULL,
+        start REAL NOT NULL,
+        end REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS searches_by_search ON searches (search, start);
+    CREATE TABLE IF NOT EXISTS events (
+        search TEXT NOT NULL,
+        time REAL NOT NULL,
+        date TEXT NOT NULL,
+        event TEXT NOT NULL,
+        UNIQUE (search, time)
+    );
+    CREATE INDEX IF NOT EXISTS events_by_time ON events (search, time);
+";
+
+/// A SQLite database of the events found by searches, and of the periods they were searched
+/// in, so that searching a period again only simulates the parts that weren't searched yet.
+///
+/// Searches are told apart by a key describing what they look for, like
+/// `lunar-eclipses`. The events are stored as JSON, with their simulation times and UTC
+/// dates in separate, indexed columns. Like the snapshots, the results are only valid for the
+/// setup they were computed with, so different setups should use separate databases.
+pub struct EventDatabase {
+    connection: Connection,
+}
+
+impl EventDatabase {
+    /// Opens the database at `path`, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        let open = || -> rusqlite::Result<Connection> {
+            let connection = Connection::open(path)?;
+            connection.execute_batch(SCHEMA)?;
+            Ok(connection)
+        };
+        let connection = open().map_err(|err| Error::from(err).in_file(path))?;
+        Ok(Self { connection })
+    }
+
+    /// The parts of the period between `start` and `end` (simulation times) in which `search`
+    /// hasn't been done yet.
+    pub fn missing(&self, search: &str, start: f64, end: f64) -> Result<Vec<(f64, f64)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT start, end FROM searches WHERE search = ?1 AND end > ?2 AND start < ?3
+             ORDER BY start",
+        )?;
+        let searched = statement
+            .query_map(params![search, start, end], |row| {
+                Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut missing = vec![];
+        let mut time = start;
+        for (searched_start, searched_end) in searched {
+            if searched_start > time {
+                missing.push((time, searched_start));
+            }
+            time = time.max(searched_end);
+        }
+        if time < end {
+            missing.push((time, end));
+        }
+        Ok(missing)
+    }
+
+    /// Records that `search` was done between `start` and `end` and found `events`, with
+    /// their simulation times. Events already stored at the same times are kept.
+    pub fn insert<T: Serialize>(
+        &mut self,
+        search: &str,
+        start: f64,
+        end: f64,
+        events: &[(f64, T)],
+    ) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT OR IGNORE INTO events (search, time, date, event) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (time, event) in events {
+                statement.execute(params![
+                    search,
+                    time,
+                    sim_time_to_utc(*time).to_rfc3339(),
+                    serde_json::to_string(event)?
+                ])?;
+            }
+        }
+
+        // merge the period with the overlapping and adjacent ones
+        let overlapping: Option<(f64, f64)> = transaction
+            .query_row(
+                "SELECT min(start), max(end) FROM searches
+                 WHERE search = ?1 AND end >= ?2 AND start <= ?3 HAVING count(*) > 0",
+                params![search, start, end],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let (start, end) = match overlapping {
+            Some((other_start, other_end)) => (start.min(other_start), end.max(other_end)),
+            None => (start, end),
+        };
+        transaction.execute(
+            "DELETE FROM searches WHERE search = ?1 AND end >= ?2 AND start <= ?3",
+            params![search, start, end],
+        )?;
+        transaction.execute(
+            "INSERT INTO searches (search, start, end) VALUES (?1, ?2, ?3)",
+            params![search, start, end],
+        )?;
+        transaction.commit()?;
+        debug!(search, events = events.len(), "stored events");
+        Ok(())
+    }
+
+    /// The events found by `search` between `start` and `end` (simulation times), in order.
+    pub fn events<T: DeserializeOwned>(
+        &self,
+        search: &str,
+        start: f64,
+        end: f64,
+    ) -> Result<Vec<T>> {
+        let mut statement = self.connection.prepare(
+            "SELECT event FROM events WHERE search = ?1 AND time >= ?2 AND time <= ?3
+             ORDER BY time",
+        )?;
+        let events = statement
+            .query_map(params![search, start, end], |row| row.get::<_, String>(0))?
+            .map(|event| Ok(serde_json::from_str(&event?)?))
+            .collect::<Result<Vec<T>>>()?;
+        Ok(events)
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use nalgebra::Vector3;
+
+use crate::{
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    spk::chebyshev,
+    time::{tdb_minus_tt, EPOCH_JD},
+};
+
+const DAY: f64 = 86400.0;
+/// Offsets of the fields of the first record of a binary ephemeris, after the titles and the
+/// names of the first 400 constants.
+const SS_OFFSET: usize = 2652;
+const EMRAT_OFFSET: usize = 2688;
+const IPT_OFFSET: usize = 2696;
+const NUMDE_OFFSET: usize = 2840;
+/// The items of an ephemeris with three components, in the order of their pointers, which
+/// are the planets (the Earth-Moon barycenter for the Earth), the geocentric Moon and the Sun.
+const ITEMS: &[&str] = &[
+    "Mercury", "Venus", "EMB", "Mars", "Jupiter", "Saturn", "Uranus", "Neptune", "Pluto", "Moon",
+    "Sun",
+];
+
+/// Where an item's coefficients are in a record: the index of the first one (1-based), the
+/// number of coefficients per component and the number of subintervals.
+#[derive(Debug, Clone, Copy)]
+struct Pointer {
+    offset: usize,
+    coefficients: usize,
+    subintervals: usize,
+}
+
+enum Records {
+    /// All the records of ASCII files, read at once.
+    Memory(Vec<Vec<f64>>),
+    /// The records of a binary file, read as needed.
+    File {
+        file: Mutex<File>,
+        little_endian: bool,
+    },
+}
+
+/// A JPL DE ephemeris in the original Chebyshev format, either as the ASCII files JPL
+/// distributes (a header file and any number of data files) or in the binary form `asc2eph`
+/// converts them to.
+pub struct DeEphemeris {
+    number: u32,
+    /// Julian dates (TDB) of the start and the end of the ephemeris, and the length of a
+    /// record, in days.
+    start: f64,
+    end: f64,
+    interval: f64,
+    /// Earth/Moon mass ratio.
+    emrat: f64,
+    pointers: Vec<Pointer>,
+    record_len: usize,
+    records: Records,
+}
+
+impl DeEphemeris {
+    /// Opens a binary ephemeris, if given a single file not starting like an ASCII header,
+    /// or the ASCII header and data files otherwise.
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let paths: Vec<PathBuf> = paths.iter().map(|path| path.as_ref().to_owned()).collect();
+        match &paths[..] {
+            [] => Err(Error::De("no files given".into())),
+            [path] if !is_ascii_header(path)? => {
+                Self::open_binary(path).map_err(|err| err.in_file(path))
+            }
+            _ => Self::open_ascii(&paths),
+        }
+    }
+
+    /// The number of the ephemeris, e.g. 440.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    fn open_binary(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = vec![0u8; NUMDE_OFFSET + 4];
+        file.read_exact(&mut header)?;
+        let little_endian = {
+            let numde = &header[NUMDE_OFFSET..NUMDE_OFFSET + 4];
+            let numde = i32::from_le_bytes([numde[0], numde[1], numde[2], numde[3]]);
+            (1..10000).contains(&numde)
+        };
+        let int = |offset: usize| {
+            let bytes = [
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ];
+            if little_endian {
+                i32::from_le_bytes(bytes)
+            } else {
+                i32::from_be_bytes(bytes)
+            }
+        };
+        let double = |offset: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&header[offset..offset + 8]);
+            if little_endian {
+                f64::from_le_bytes(bytes)
+            } else {
+                f64::from_be_bytes(bytes)
+            }
+        };
+        let pointers: Vec<Pointer> = (0..ITEMS.len())
+            .map(|item| Pointer {
+                offset: int(IPT_OFFSET + item * 12) as usize,
+                coefficients: int(IPT_OFFSET + item * 12 + 4) as usize,
+                subintervals: int(IPT_OFFSET + item * 12 + 8) as usize,
+            })
+            .collect();
+        let start = double(SS_OFFSET);
+
+        let mut de = Self {
+            number: int(NUMDE_OFFSET) as u32,
+            start,
+            end: double(SS_OFFSET + 8),
+            interval: double(SS_OFFSET + 16),
+            emrat: double(EMRAT_OFFSET),
+            record_len: 0,
+            pointers,
+            records: Records::File {
+                file: Mutex::new(file),
+                little_endian,
+            },
+        };
+        // the records hold more than the items read here, e.g. the nutations and the
+        // librations, so their length is found from where the first data record starts
+        let min_len = de.pointers.iter().map(Pointer::end).max().unwrap_or(0);
+        de.record_len = (min_len..min_len + 2000)
+            .find(|&len| {
+                de.record_len = len;
+                de.read_record(0).is_ok_and(|record| record[0] == start)
+            })
+            .ok_or_else(|| Error::De("couldn't find the data records".into()))?;
+        Ok(de)
+    }
+
+    fn open_ascii(paths: &[PathBuf]) -> Result<Self> {
+        let mut header = None;
+        let mut records = vec![];
+        for path in paths {
+            let text = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+            if text.trim_start().starts_with("KSIZE") {
+                header = Some(parse_header(&text).map_err(|err| err.in_file(path))?);
+            } else {
+                records.extend(parse_records(&text).map_err(|err| err.in_file(path))?);
+            }
+        }
+        let mut de = header.ok_or_else(|| Error::De("no header file given".into()))?;
+        records.sort_by(|a: &Vec<f64>, b| a[0].total_cmp(&b[0]));
+        // consecutive data files repeat the record at their boundary
+        records.dedup_by(|a, b| a[0] == b[0]);
+        if let (Some(first), Some(last)) = (records.first(), records.last()) {
+            de.start = first[0];
+            de.end = last[1];
+        }
+        if records
+            .windows(2)
+            .any(|pair| (pair[1][0] - pair[0][1]).abs() > 1e-9)
+        {
+            return Err(Error::De("the data files leave gaps".into()));
+        }
+        de.record_len = records.first().map_or(0, Vec::len);
+        de.records = Records::Memory(records);
+        Ok(de)
+    }
+
+    fn read_record(&self, index: usize) -> Result<Vec<f64>> {
+        match &self.records {
+            Records::Memory(records) => Ok(records[index].clone()),
+            Records::File {
+                file,
+                little_endian,
+            } => {
+                let mut file = file.lock().unwrap();
+                // the first two records hold the header and the values of the constants
+                let len = self.record_len * 8;
+                file.seek(SeekFrom::Start(((index + 2) * len) as u64))?;
+                let mut bytes = vec![0u8; len];
+                file.read_exact(&mut bytes)?;
+                Ok(bytes
+                    .chunks(8)
+                    .map(|chunk| {
+                        let mut word = [0u8; 8];
+                        word.copy_from_slice(chunk);
+                        if *little_endian {
+                            f64::from_le_bytes(word)
+                        } else {
+                            f64::from_be_bytes(word)
+                        }
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Position and velocity (km, km/s, equatorial) of an item at the Julian date `jd` (TDB):
+    /// relative to the solar system barycenter, but for the Moon, which is geocentric.
+    fn item_state(&self, item: usize, jd: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        if jd < self.start || jd > self.end {
+            return Err(Error::De(format!(
+                "JD {} is outside of DE{}, which covers {} to {}",
+                jd, self.number, self.start, self.end
+            )));
+        }
+        let index = ((jd - self.start) / self.interval) as usize;
+        let count = ((self.end - self.start) / self.interval).round() as usize;
+        let record = self.read_record(index.min(count.max(1) - 1))?;
+        let pointer = self.pointers[item];
+
+        let length = self.interval / pointer.subintervals as f64;
+        let sub = (((jd - record[0]) / length) as usize).min(pointer.subintervals - 1);
+        let tau = 2.0 * (jd - record[0] - sub as f64 * length) / length - 1.0;
+        let first = pointer.offset - 1 + sub * 3 * pointer.coefficients;
+
+        let mut pos = Vector3::zeros();
+        let mut vel = Vector3::zeros();
+        for axis in 0..3 {
+            let start = first + axis * pointer.coefficients;
+            let (p, dp) = chebyshev(&record[start..start + pointer.coefficients], tau);
+            pos[axis] = p;
+            vel[axis] = dp * 2.0 / length / DAY;
+        }
+        Ok((pos, vel))
+    }
+
+    /// Position and velocity (km, km/s, ecliptic J2000) of the named body relative to the
+    /// solar system barycenter at the given simulation time.
+    pub fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        let jd = EPOCH_JD + (time + tdb_minus_tt(time)) / DAY;
+        let item = |name: &str| ITEMS.iter().position(|&item| item == name).unwrap();
+        let (pos, vel) = match name {
+            "Earth" | "Moon" => {
+                let (emb, emb_vel) = self.item_state(item("EMB"), jd)?;
+                let (moon, moon_vel) = self.item_state(item("Moon"), jd)?;
+                let earth = emb - moon / (1.0 + self.emrat);
+                let earth_vel = emb_vel - moon_vel / (1.0 + self.emrat);
+                if name == "Earth" {
+                    (earth, earth_vel)
+                } else {
+                    (earth + moon, earth_vel + moon_vel)
+                }
+            }
+            _ => match ITEMS.iter().position(|&item| item == name) {
+                Some(index) if name != "EMB" => self.item_state(index, jd)?,
+                _ => return Err(Error::De(format!("no data for {}", name))),
+            },
+        };
+        Ok((equatorial_to_ecliptic(pos), equatorial_to_ecliptic(vel)))
+    }
+}
+
+impl Pointer {
+    /// Index one past the item's last coefficient.
+    fn end(&self) -> usize {
+        self.offset + 3 * self.coefficients * self.subintervals - 1
+    }
+}
+
+fn is_ascii_header(path: &Path) -> Result<bool> {
+    let mut start = [0u8; 16];
+    let read = File::open(path)
+        .and_then(|mut file| file.read(&mut start))
+        .map_err(|err| Error::from(err).in_file(path))?;
+    Ok(String::from_utf8_lossy(&start[..read])
+        .trim_start()
+        .starts_with("KSIZE"))
+}
+
+/// Parses a number like "0.2287184500000000D+07".
+fn parse_number(value: &str) -> Result<f64> {
+    value
+        .replace(['D', 'd'], "E")
+        .parse()
+        .map_err(|_| Error::De(format!("bad number \"{}\"", value)))
+}
+
+/// The lines of the named group of an ASCII header, e.g. "GROUP   1050".
+fn group(text: &str, number: u32) -> Result<Vec<&str>> {
+    let name = format!("GROUP   {}", number);
+    let mut lines = text.lines().skip_while(|line| line.trim() != name);
+    if lines.next().is_none() {
+        return Err(Error::De(format!("no group {} in the header", number)));
+    }
+    Ok(lines
+        .take_while(|line| !line.starts_with("GROUP"))
+        .filter(|line| !line.trim().is_empty())
+        .collect())
+}
+
+fn parse_header(text: &str) -> Result<DeEphemeris> {
+    let period: Vec<f64> = group(text, 1030)?
+        .iter()
+        .flat_map(|line| line.split_whitespace())
+        .map(parse_number)
+        .collect::<Result<_>>()?;
+    let [start, end, interval] = period[..] else {
+        return Err(Error::De(
+            "group 1030 should have the start, end and interval".into(),
+        ));
+    };
+
+    let names: Vec<&str> = group(text, 1040)?
+        .iter()
+        .skip(1)
+        .flat_map(|line| line.split_whitespace())
+        .collect();
+    let values: Vec<f64> = group(text, 1041)?
+        .iter()
+        .skip(1)
+        .flat_map(|line| line.split_whitespace())
+        .map(parse_number)
+        .collect::<Result<_>>()?;
+    let constant = |name: &str| {
+        names
+            .iter()
+            .position(|&n| n == name)
+            .and_then(|index| values.get(index).copied())
+            .ok_or_else(|| Error::De(format!("no constant {} in the header", name)))
+    };
+
+    let rows: Vec<Vec<usize>> = group(text, 1050)?
+        .iter()
+        .map(|line| {
+            line.split_whitespace()
+                .map(|value| value.parse().map_err(|_| Error::De("bad pointer".into())))
+                .collect()
+        })
+        .collect::<Result<_>>()?;
+    if rows.len() < 3 || rows.iter().any(|row| row.len() < ITEMS.len()) {
+        return Err(Error::De(
+            "group 1050 should have three rows of pointers".into(),
+        ));
+    }
+    let pointers = (0..ITEMS.len())
+        .map(|item| Pointer {
+            offset: rows[0][item],
+            coefficients: rows[1][item],
+            subintervals: rows[2][item],
+        })
+        .collect();
+
+    Ok(DeEphemeris {
+        number: constant("DENUM")? as u32,
+        start,
+        end,
+        interval,
+        emrat: constant("EMRAT")?,
+        pointers,
+        record_len: 0,
+        records: Records::Memory(vec![]),
+    })
+}
+
+/// Parses the records of an ASCII data file, each a line with its number and length followed
+/// by the coefficients, three per line.
+fn parse_records(text: &str) -> Result<Vec<Vec<f64>>> {
+    let mut tokens = text.split_whitespace();
+    let mut records = vec![];
+    while let Some(_number) = tokens.next() {
+        let len: usize = tokens
+            .next()
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| Error::De("bad record header".into()))?;
+        let record = tokens
+            .by_ref()
+            .take(len)
+            .map(parse_number)
+            .collect::<Result<Vec<f64>>>()?;
+        if record.len() < len {
+            return Err(Error::De("truncated record".into()));
+        }
+        // the coefficients are padded with zeros to a multiple of three
+        let padding = (3 - len % 3) % 3;
+        tokens.by_ref().take(padding).for_each(drop);
+        records.push(record);
+    }
+    Ok(records)
+}
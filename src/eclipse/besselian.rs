@@ -0,0 +1,138 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use nalgebra::{Matrix4, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use super::{SolarEclipse, SolarShadow};
+use crate::{
+    frames::{Frame, EARTH_EQUATORIAL_RADIUS},
+    simulation::{Propagator, SimState},
+    time::{self, utc_to_sim_time},
+};
+
+/// Interval between the samples the polynomials are fitted to, in seconds.
+const SAMPLE_INTERVAL: f64 = 600.0;
+
+/// The Besselian elements of a solar eclipse, as cubic polynomials in `t`, the time in hours
+/// since `t0`; each array holds the coefficients of `t^0` to `t^3`. `x` and `y` are the
+/// coordinates of the shadow axis in the fundamental plane and `l1`, `l2` the radii of the
+/// penumbra and the umbra there (negative for the umbra, as in the published elements), all in
+/// Earth equatorial radii. `d` is the declination of the axis and `mu` its Greenwich hour
+/// angle, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BesselianElements {
+    /// The whole hour closest to the greatest eclipse, in TT.
+    pub t0: DateTime<Utc>,
+    /// TT - UT at `t0`, in seconds.
+    pub delta_t: f64,
+    pub x: [f64; 4],
+    pub y: [f64; 4],
+    pub d: [f64; 4],
+    pub mu: [f64; 4],
+    pub l1: [f64; 4],
+    pub l2: [f64; 4],
+    pub tan_f1: f64,
+    pub tan_f2: f64,
+}
+
+/// The elements at one moment: x, y, d, μ, l1 and l2.
+fn elements(shadow: &SolarShadow, time: f64) -> [f64; 6] {
+    // the z axis of the fundamental plane points from the Earth's center towards the Moon
+    let z = Frame::TrueEquatorial.from_sim(-shadow.axis, time);
+    let ra = z.y.atan2(z.x);
+    let dec = z.z.asin();
+    let x_axis = Vector3::new(-ra.sin(), ra.cos(), 0.0);
+    let y_axis = Vector3::new(-dec.sin() * ra.cos(), -dec.sin() * ra.sin(), dec.cos());
+    let moon = Frame::TrueEquatorial.from_sim(shadow.moon, time);
+
+    let earth_fixed = Frame::EarthFixed.from_sim(-shadow.axis, time);
+    let hour_angle = -earth_fixed.y.atan2(earth_fixed.x);
+    [
+        moon.dot(&x_axis) / EARTH_EQUATORIAL_RADIUS,
+        moon.dot(&y_axis) / EARTH_EQUATORIAL_RADIUS,
+        dec.to_degrees(),
+        hour_angle.rem_euclid(2.0 * PI).to_degrees(),
+        shadow.penumbra_radius(0.0) / EARTH_EQUATORIAL_RADIUS,
+        -shadow.umbra_radius(0.0) / EARTH_EQUATORIAL_RADIUS,
+    ]
+}
+
+/// Least-squares cubic through the `(t, value)` points.
+fn fit_cubic(points: &[(f64, f64)]) -> [f64; 4] {
+    let mut normal = Matrix4::zeros();
+    let mut rhs = Vector4::zeros();
+    for &(t, value) in points {
+        let powers = Vector4::new(1.0, t, t * t, t * t * t);
+        normal += powers * powers.transpose();
+        rhs += powers * value;
+    }
+    let coeffs = normal.try_inverse().unwrap_or_else(Matrix4::zeros) * rhs;
+    [coeffs[0], coeffs[1], coeffs[2], coeffs[3]]
+}
+
+/// Computes the Besselian elements of `eclipse`, fitted to the shadow between its first and
+/// last contacts with the Earth. `sim` is the state of the system at `start` (simulation
+/// time), which can't be after the first contact.
+pub fn besselian_elements<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    eclipse: &SolarEclipse,
+    propagator: P,
+) -> BesselianElements {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+
+    let t0 = (utc_to_sim_time(eclipse.greatest) / 3600.0).round() * 3600.0;
+    let first = utc_to_sim_time(eclipse.contacts.p1);
+    let last = utc_to_sim_time(eclipse.contacts.p4);
+    let mut next = t0 - ((t0 - first) / SAMPLE_INTERVAL).floor() * SAMPLE_INTERVAL;
+    let end = t0 + ((last - t0) / SAMPLE_INTERVAL).floor() * SAMPLE_INTERVAL;
+
+    let mut samples: Vec<(f64, [f64; 6])> = vec![];
+    // the cone angles change too slowly to be worth fitting
+    let mut tan_f = (0.0, 0.0);
+    while next <= end {
+        time += propagator.step(&mut sim);
+        while next <= time && next <= end {
+            let state = propagator.propagated(&sim, next - time);
+            let shadow = SolarShadow::new(&state);
+            if next == t0 {
+                tan_f = (shadow.tan_f1, shadow.tan_f2);
+            }
+            samples.push(((next - t0) / 3600.0, elements(&shadow, next)));
+            next += SAMPLE_INTERVAL;
+        }
+    }
+
+    // μ grows by about 15° an hour, so it is unwrapped before fitting
+    for i in 1..samples.len() {
+        let previous = samples[i - 1].1[3];
+        let mu = &mut samples[i].1[3];
+        *mu += ((previous - *mu) / 360.0).round() * 360.0;
+    }
+    let fit = |idx: usize| {
+        let points: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|(t, values)| (*t, values[idx]))
+            .collect();
+        fit_cubic(&points)
+    };
+    let mut mu = fit(3);
+    mu[0] = mu[0].rem_euclid(360.0);
+
+    let t0_date = time::epoch() + time::duration(t0);
+    BesselianElements {
+        t0: t0_date,
+        delta_t: time::delta_t(t0_date),
+        x: fit(0),
+        y: fit(1),
+        d: fit(2),
+        mu,
+        l1: fit(4),
+        l2: fit(5),
+        tan_f1: tan_f.0,
+        tan_f2: tan_f.1,
+    }
+}
@@ -0,0 +1,231 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, debug_span};
+
+use super::{disk_obscuration, Eclipse, SolarShadow};
+use crate::{
+    observer::{GroundStation, Observer},
+    refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// A moment of a solar eclipse seen from a ground station, with the Sun's elevation above the
+/// horizon then, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LocalContact {
+    pub date: DateTime<Utc>,
+    pub sun_altitude: f64,
+}
+
+/// Contacts of the Moon's disk with the Sun's: C1 and C4 when they touch externally, C2 and
+/// C3 at the start and end of the total or annular phase.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LocalContacts {
+    pub c1: LocalContact,
+    pub c2: Option<LocalContact>,
+    pub c3: Option<LocalContact>,
+    pub c4: LocalContact,
+}
+
+/// A solar eclipse seen from a ground station.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LocalEclipse {
+    /// `PartialSolar`, `TotalSolar` or `AnnularSolar`.
+    pub kind: Eclipse,
+    /// When the centers of the disks are the closest.
+    pub maximum: LocalContact,
+    /// Fraction of the Sun's diameter covered by the Moon at the maximum.
+    pub magnitude: f64,
+    /// Fraction of the Sun's disk area covered by the Moon at the maximum.
+    pub obscuration: f64,
+    pub contacts: LocalContacts,
+}
+
+/// Where a station is in the Moon's shadow, in km.
+struct ShadowPlace {
+    /// Distance from the shadow axis.
+    distance: f64,
+    /// Radius of the penumbra at the station's height above the fundamental plane.
+    penumbra: f64,
+    /// Radius of the umbra there, negative for the antumbra.
+    umbra: f64,
+    /// Whether the station is moving away from the axis.
+    receding: bool,
+}
+
+impl ShadowPlace {
+    /// The station's place in the shadow cast in the state `sim` at the simulation time
+    /// `time`, or `None` if the Moon isn't between the station and the Sun.
+    fn new(sim: &SimState, time: f64, station: &GroundStation) -> Option<Self> {
+        let shadow = SolarShadow::new(sim);
+        let earth = sim.body_by_name("Earth").unwrap();
+        let pos = station.pos(sim, time) - earth.pos;
+        let height = -pos.dot(&shadow.axis);
+        if -shadow.moon.dot(&shadow.axis) <= height {
+            return None;
+        }
+
+        let (axis, axis_rate) = (shadow.axis, shadow.axis_rate);
+        let rel = pos - shadow.moon;
+        let rel_vel = station.vel(sim, time) - earth.vel - shadow.moon_vel;
+        let offset = rel - axis * axis.dot(&rel);
+        let offset_rate = rel_vel
+            - axis_rate * axis.dot(&rel)
+            - axis * (axis_rate.dot(&rel) + axis.dot(&rel_vel));
+        Some(Self {
+            distance: offset.norm(),
+            penumbra: shadow.penumbra_radius(height),
+            umbra: shadow.umbra_radius(height),
+            receding: offset.dot(&offset_rate) > 0.0,
+        })
+    }
+
+    /// 0 outside of the penumbra, 1 in the penumbra, 2 in the umbra or the antumbra.
+    fn rank(place: &Option<Self>) -> usize {
+        match place {
+            Some(place) if place.distance < place.umbra.abs() => 2,
+            Some(place) if place.distance < place.penumbra => 1,
+            _ => 0,
+        }
+    }
+
+    /// The Sun's and the Moon's apparent radii, in the units of the fundamental plane.
+    fn radii(&self) -> (f64, f64) {
+        (
+            0.5 * (self.penumbra - self.umbra),
+            0.5 * (self.penumbra + self.umbra),
+        )
+    }
+
+    fn magnitude(&self) -> f64 {
+        let (sun, moon) = self.radii();
+        (sun + moon - self.distance) / (2.0 * sun)
+    }
+
+    fn obscuration(&self) -> f64 {
+        let (sun, moon) = self.radii();
+        disk_obscuration(sun, moon, self.distance)
+    }
+}
+
+/// The eclipse at its maximum, before all the contacts are known.
+struct Maximum {
+    kind: Eclipse,
+    contact: LocalContact,
+    magnitude: f64,
+    obscuration: f64,
+}
+
+fn local_contact(sim: &SimState, time: f64, station: &GroundStation) -> LocalContact {
+    let sun = station.horizontal(sim, time, "Sun").unwrap();
+    LocalContact {
+        date: sim_time_to_utc(time),
+        sun_altitude: sun.elevation.to_degrees(),
+    }
+}
+
+/// Finds the solar eclipses seen from `station` between `start` and `end` (simulation times),
+/// `sim` being the state of the system at `start`. The contacts are geometric, regardless of
+/// the horizon, but the eclipses during which the Sun stays below the horizon are left out.
+/// Contact times are accurate to about a second.
+pub fn local_solar_eclipses<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    station: &GroundStation,
+) -> Vec<LocalEclipse> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let _span = debug_span!("local_solar_eclipse_search").entered();
+
+    let mut result = vec![];
+    let place = ShadowPlace::new(&sim, time, station);
+    let mut current_rank = ShadowPlace::rank(&place);
+    let mut was_receding = place.is_some_and(|place| place.receding);
+    let mut contacts: [Option<LocalContact>; 4] = [None; 4];
+    let mut maximum: Option<Maximum> = None;
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        let place = ShadowPlace::new(&sim, time, station);
+        let new_rank = ShadowPlace::rank(&place);
+        let contact_at = |t: f64| local_contact(&propagator.propagated(&sim, t - time), t, station);
+
+        for level in current_rank + 1..=new_rank {
+            let t = refine::bisect(&propagator, &sim, time, step, 1.0, |state, t| {
+                ShadowPlace::rank(&ShadowPlace::new(state, t, station)) >= level
+            });
+            contacts[level - 1] = Some(contact_at(t));
+        }
+        for level in (new_rank + 1..=current_rank).rev() {
+            let t = refine::bisect(&propagator, &sim, time, step, 1.0, |state, t| {
+                ShadowPlace::rank(&ShadowPlace::new(state, t, station)) < level
+            });
+            contacts[4 - level] = Some(contact_at(t));
+        }
+
+        let receding = place.as_ref().is_some_and(|place| place.receding);
+        if receding && !was_receding && (new_rank > 0 || current_rank > 0) {
+            let t = refine::bisect(&propagator, &sim, time, step, 1.0, |state, t| {
+                ShadowPlace::new(state, t, station).is_some_and(|place| place.receding)
+            });
+            let state = propagator.propagated(&sim, t - time);
+            if let Some(place) = ShadowPlace::new(&state, t, station) {
+                let kind = if place.distance >= place.umbra.abs() {
+                    Eclipse::PartialSolar
+                } else if place.umbra > 0.0 {
+                    Eclipse::TotalSolar
+                } else {
+                    Eclipse::AnnularSolar
+                };
+                maximum = Some(Maximum {
+                    kind,
+                    contact: local_contact(&state, t, station),
+                    magnitude: place.magnitude(),
+                    obscuration: place.obscuration(),
+                });
+            }
+        }
+        was_receding = receding;
+
+        if new_rank == 0 && current_rank > 0 {
+            if let Some(eclipse) = finish_eclipse(&contacts, maximum) {
+                debug!(kind = ?eclipse.kind, maximum = %eclipse.maximum.date, "found eclipse");
+                result.push(eclipse);
+            }
+            contacts = [None; 4];
+            maximum = None;
+        }
+        current_rank = new_rank;
+    }
+
+    result
+}
+
+fn finish_eclipse(
+    contacts: &[Option<LocalContact>; 4],
+    maximum: Option<Maximum>,
+) -> Option<LocalEclipse> {
+    let maximum = maximum?;
+    let eclipse = LocalEclipse {
+        kind: maximum.kind,
+        maximum: maximum.contact,
+        magnitude: maximum.magnitude,
+        obscuration: maximum.obscuration,
+        contacts: LocalContacts {
+            c1: contacts[0]?,
+            c2: contacts[1],
+            c3: contacts[2],
+            c4: contacts[3]?,
+        },
+    };
+    let visible = [eclipse.contacts.c1, eclipse.maximum, eclipse.contacts.c4]
+        .iter()
+        .any(|contact| contact.sun_altitude > 0.0);
+    visible.then_some(eclipse)
+}
@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, debug_span};
+
+use super::{Eclipse, EclipseDetector, LunarShadow, Saros};
+use crate::{
+    refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Contact times of a lunar eclipse: P1/P4 with the penumbra, U1-U4 with the umbra.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LunarContacts {
+    pub p1: DateTime<Utc>,
+    pub u1: Option<DateTime<Utc>>,
+    pub u2: Option<DateTime<Utc>>,
+    pub u3: Option<DateTime<Utc>>,
+    pub u4: Option<DateTime<Utc>>,
+    pub p4: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LunarEclipse {
+    /// `PenumbralLunar`, `PartialLunar` or `TotalLunar`.
+    pub kind: Eclipse,
+    pub greatest: DateTime<Utc>,
+    pub umbral_magnitude: f64,
+    pub penumbral_magnitude: f64,
+    pub saros: Saros,
+    pub contacts: LunarContacts,
+}
+
+#[derive(Default)]
+struct Contacts {
+    times: [Option<f64>; 6],
+    greatest: Option<(f64, LunarShadow)>,
+}
+
+fn rank(eclipse: Option<Eclipse>) -> usize {
+    match eclipse {
+        Some(Eclipse::TotalLunar) => 3,
+        Some(Eclipse::PartialLunar) => 2,
+        Some(Eclipse::PenumbralLunar) => 1,
+        _ => 0,
+    }
+}
+
+/// Finds all lunar eclipses between `start` and `end` (simulation times), `sim` being the
+/// state of the system at `start`. Contact times are accurate to about a second.
+pub fn find_lunar_eclipses<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+) -> Vec<LunarEclipse> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut detector = EclipseDetector::new(propagator.max_step());
+    let mut time = start;
+    let _span = debug_span!("lunar_eclipse_search").entered();
+
+    let mut result = vec![];
+    let mut current_rank = 0;
+    let mut contacts = Contacts::default();
+    // the last three samples of the shadow, for finding the greatest eclipse
+    let mut samples: Vec<(f64, LunarShadow, SimState)> = Vec::with_capacity(3);
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        let sun = sim.body_by_name("Sun").unwrap();
+        let earth = sim.body_by_name("Earth").unwrap();
+        detector.save_light_dir(time, earth.pos - sun.pos);
+
+        let shadow = detector.lunar_shadow(&sim, time);
+        let new_rank = rank(shadow.and_then(|shadow| shadow.eclipse()));
+
+        for level in current_rank + 1..=new_rank {
+            let t = refine_contact(&propagator, &detector, &sim, time, step, |r| r >= level);
+            contacts.times[level - 1] = Some(t);
+        }
+        for level in (new_rank + 1..=current_rank).rev() {
+            let t = refine_contact(&propagator, &detector, &sim, time, step, |r| r < level);
+            contacts.times[6 - level] = Some(t);
+        }
+
+        match shadow {
+            Some(shadow) => {
+                if samples.len() == 3 {
+                    let _ = samples.remove(0);
+                }
+                samples.push((time, shadow, sim.clone()));
+                update_greatest(&mut contacts, &samples, &propagator, &detector);
+            }
+            None => samples.clear(),
+        }
+
+        if new_rank == 0 && current_rank > 0 {
+            if let Some(eclipse) = finish_eclipse(&contacts) {
+                debug!(kind = ?eclipse.kind, greatest = %eclipse.greatest, "found eclipse");
+                result.push(eclipse);
+            }
+            contacts = Contacts::default();
+        }
+        current_rank = new_rank;
+    }
+
+    result
+}
+
+/// Finds the moment within the last step at which the eclipse phase started satisfying `pred`.
+fn refine_contact<F: Fn(usize) -> bool>(
+    propagator: &Propagator,
+    detector: &EclipseDetector,
+    sim: &SimState,
+    time: f64,
+    step: f64,
+    pred: F,
+) -> f64 {
+    refine::bisect(propagator, sim, time, step, 1.0, |state, t| {
+        let eclipse = detector
+            .lunar_shadow(state, t)
+            .and_then(|shadow| shadow.eclipse());
+        pred(rank(eclipse))
+    })
+}
+
+fn update_greatest(
+    contacts: &mut Contacts,
+    samples: &[(f64, LunarShadow, SimState)],
+    propagator: &Propagator,
+    detector: &EclipseDetector,
+) {
+    let (t2, shadow2, _) = samples[samples.len() - 1];
+    if shadow2.eclipse().is_some() && is_greater(shadow2, contacts.greatest) {
+        contacts.greatest = Some((t2, shadow2));
+    }
+    if samples.len() < 3 {
+        return;
+    }
+    // The greatest eclipse is the minimum distance from the shadow axis. Its square is very
+    // close to a parabola in time, unlike the magnitudes, which have a kink there.
+    let (t0, shadow0, _) = samples[0];
+    let (t1, shadow1, ref sim1) = samples[1];
+    let (d0, d1, d2) = (
+        shadow0.axis_dist.powi(2),
+        shadow1.axis_dist.powi(2),
+        shadow2.axis_dist.powi(2),
+    );
+    if d1 > d0 || d1 > d2 {
+        return;
+    }
+    let (x0, x2) = (t0 - t1, t2 - t1);
+    let (slope0, slope2) = ((d1 - d0) / -x0, (d2 - d1) / x2);
+    let curvature = (slope2 - slope0) / (x2 - x0);
+    if curvature <= 0.0 {
+        return;
+    }
+    // d(x) = d1 + b * x + curvature * x^2 around t1
+    let b = slope0 - curvature * x0;
+    let offset = -b / (2.0 * curvature);
+
+    if let Some((time, shadow)) = refine_greatest(propagator, detector, sim1, t1, offset) {
+        if shadow.eclipse().is_some() && is_greater(shadow, contacts.greatest) {
+            contacts.greatest = Some((time, shadow));
+        }
+    }
+}
+
+fn is_greater(shadow: LunarShadow, greatest: Option<(f64, LunarShadow)>) -> bool {
+    match greatest {
+        Some((_, greatest)) => shadow.penumbral_magnitude() > greatest.penumbral_magnitude(),
+        None => true,
+    }
+}
+
+/// Corrects the estimate of the greatest eclipse, `offset` seconds from `time`, with a parabola
+/// through exactly computed distances from the shadow axis around it.
+fn refine_greatest(
+    propagator: &Propagator,
+    detector: &EclipseDetector,
+    sim: &SimState,
+    time: f64,
+    offset: f64,
+) -> Option<(f64, LunarShadow)> {
+    let shadow_at =
+        |offset: f64| detector.lunar_shadow(&propagator.propagated(sim, offset), time + offset);
+    let h = 30.0;
+    let d0 = shadow_at(offset - h)?.axis_dist.powi(2);
+    let d1 = shadow_at(offset)?.axis_dist.powi(2);
+    let d2 = shadow_at(offset + h)?.axis_dist.powi(2);
+    let curvature = d0 - 2.0 * d1 + d2;
+    if curvature <= 0.0 {
+        return None;
+    }
+    let offset = offset + (h * 0.5 * (d0 - d2) / curvature).clamp(-h, h);
+    Some((time + offset, shadow_at(offset)?))
+}
+
+fn finish_eclipse(contacts: &Contacts) -> Option<LunarEclipse> {
+    let times = contacts.times;
+    let (greatest, shadow) = contacts.greatest?;
+    let kind = if times[2].is_some() {
+        Eclipse::TotalLunar
+    } else if times[1].is_some() {
+        Eclipse::PartialLunar
+    } else {
+        Eclipse::PenumbralLunar
+    };
+    Some(LunarEclipse {
+        kind,
+        greatest: sim_time_to_utc(greatest),
+        umbral_magnitude: shadow.umbral_magnitude(),
+        penumbral_magnitude: shadow.penumbral_magnitude(),
+        saros: Saros::lunar(sim_time_to_utc(greatest)),
+        contacts: LunarContacts {
+            p1: sim_time_to_utc(times[0]?),
+            u1: times[1].map(sim_time_to_utc),
+            u2: times[2].map(sim_time_to_utc),
+            u3: times[3].map(sim_time_to_utc),
+            u4: times[4].map(sim_time_to_utc),
+            p4: sim_time_to_utc(times[5]?),
+        },
+    })
+}
@@ -0,0 +1,158 @@
+mod besselian;
+mod local;
+mod lunar;
+mod saros;
+mod screen;
+mod solar;
+
+pub use besselian::{besselian_elements, BesselianElements};
+pub use local::{local_solar_eclipses, LocalContact, LocalContacts, LocalEclipse};
+pub use lunar::{find_lunar_eclipses, LunarContacts, LunarEclipse};
+pub use saros::Saros;
+pub use screen::{eclipse_windows, screened, Syzygy};
+pub use solar::{
+    eclipse_path, find_solar_eclipses, EclipsePath, GroundPoint, SolarContacts, SolarEclipse,
+    SolarShadow,
+};
+
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::{SimState, SPEED_OF_LIGHT};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Eclipse {
+    PenumbralLunar,
+    PartialLunar,
+    TotalLunar,
+    PartialSolar,
+    TotalSolar,
+    AnnularSolar,
+}
+
+/// Fraction of the area of the Sun's disk covered by the Moon's, given their radii and the
+/// distance of their centers.
+fn disk_obscuration(sun: f64, moon: f64, distance: f64) -> f64 {
+    if distance >= sun + moon {
+        return 0.0;
+    }
+    if distance <= (sun - moon).abs() {
+        return (moon / sun).powi(2).min(1.0);
+    }
+    // the lens is made of a circular segment of each disk
+    let half_angle = |r1: f64, r2: f64| {
+        ((distance * distance + r1 * r1 - r2 * r2) / (2.0 * distance * r1)).acos()
+    };
+    let segment = |r: f64, angle: f64| r * r * (angle - 0.5 * (2.0 * angle).sin());
+    let area = segment(sun, half_angle(sun, moon)) + segment(moon, half_angle(moon, sun));
+    area / (PI * sun * sun)
+}
+
+pub struct EclipseDetector {
+    step: f64,
+    light_dirs: Vec<(f64, Vector3<f64>)>,
+}
+
+impl EclipseDetector {
+    pub fn new(step: f64) -> Self {
+        Self {
+            step,
+            light_dirs: Default::default(),
+        }
+    }
+
+    fn light_dir_for(&self, time: f64) -> Option<Vector3<f64>> {
+        match self
+            .light_dirs
+            .binary_search_by(|entry| entry.0.partial_cmp(&time).unwrap())
+        {
+            Err(0) => None,
+            Err(i) if i == self.light_dirs.len() => None,
+            Err(i) => {
+                let (t1, vec1) = self.light_dirs[i - 1];
+                let (t2, vec2) = self.light_dirs[i];
+                Some(vec1 + (vec2 - vec1) / (t2 - t1) * (time - t1))
+            }
+            Ok(i) => Some(self.light_dirs[i].1),
+        }
+    }
+
+    pub fn save_light_dir(&mut self, time: f64, dir: Vector3<f64>) {
+        self.light_dirs.push((time, dir));
+        // enough to cover the light travel time and a refinement within the previous step
+        if time - self.light_dirs[0].0 > 600.0 + 2.0 * self.step {
+            let _ = self.light_dirs.remove(0);
+        }
+    }
+
+    /// The Moon's position relative to the Earth's shadow at the given time, or `None` if the
+    /// Moon is on the day side of the Earth.
+    pub fn lunar_shadow(&self, sim: &SimState, time: f64) -> Option<LunarShadow> {
+        let sun = sim.body_by_name("Sun").unwrap();
+        let earth = sim.body_by_name("Earth").unwrap();
+        let moon = sim.body_by_name("Moon").unwrap();
+
+        // correction for shadow enlargement: https://eclipse.gsfc.nasa.gov/LEcat5/shadow.html
+        let re = earth.radius * 1.011;
+
+        let dist = earth.distance_from(sun);
+        let delay = dist / SPEED_OF_LIGHT;
+
+        let light_dir = self.light_dir_for(time - delay)?.normalize();
+
+        let moon_rel = moon.pos - earth.pos;
+        let h = moon_rel.dot(&light_dir);
+        if h <= 0.0 {
+            return None;
+        }
+        let r_vec = moon_rel - light_dir * h;
+
+        Some(LunarShadow {
+            axis_dist: r_vec.dot(&r_vec).sqrt(),
+            umbra_radius: re - h * (sun.radius - re) / dist,
+            penumbra_radius: re + h * (sun.radius + re) / dist,
+            moon_radius: moon.radius,
+        })
+    }
+
+    pub fn detect_eclipse(&self, sim: &SimState, time: f64) -> Option<Eclipse> {
+        self.lunar_shadow(sim, time)?.eclipse()
+    }
+}
+
+/// The Moon and the Earth's shadow cones, cut by the plane perpendicular to the shadow axis
+/// passing through the Moon's center. All distances are in km.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LunarShadow {
+    /// Distance of the Moon's center from the shadow axis.
+    pub axis_dist: f64,
+    pub umbra_radius: f64,
+    pub penumbra_radius: f64,
+    pub moon_radius: f64,
+}
+
+impl LunarShadow {
+    /// Fraction of the Moon's diameter immersed in the umbra.
+    pub fn umbral_magnitude(&self) -> f64 {
+        (self.umbra_radius + self.moon_radius - self.axis_dist) / (2.0 * self.moon_radius)
+    }
+
+    /// Fraction of the Moon's diameter immersed in the penumbra.
+    pub fn penumbral_magnitude(&self) -> f64 {
+        (self.penumbra_radius + self.moon_radius - self.axis_dist) / (2.0 * self.moon_radius)
+    }
+
+    pub fn eclipse(&self) -> Option<Eclipse> {
+        if self.axis_dist + self.moon_radius < self.umbra_radius {
+            Some(Eclipse::TotalLunar)
+        } else if self.axis_dist - self.moon_radius < self.umbra_radius {
+            Some(Eclipse::PartialLunar)
+        } else if self.axis_dist - self.moon_radius < self.penumbra_radius {
+            Some(Eclipse::PenumbralLunar)
+        } else {
+            None
+        }
+    }
+}
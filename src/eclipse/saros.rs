@@ -0,0 +1,86 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Mean length of the synodic month, in days.
+const SYNODIC_MONTH: f64 = 29.530588861;
+/// The Saros period: 223 synodic months, about 6585.32 days.
+const SAROS_LUNATIONS: i64 = 223;
+/// Each lunation moves an eclipse 38 series further, modulo the 223 lunations of the Saros.
+const SERIES_PER_LUNATION: i64 = 38;
+
+/// Eclipses of known Saros series and members: the date of the greatest eclipse (UTC), the
+/// series and the member. The first one of each table anchors the series numbers of all the
+/// others.
+const SOLAR_REFERENCES: &[(&str, u32, u32)] = &[
+    ("2024-04-08T18:17:00Z", 139, 30),
+    ("2009-07-22T02:35:00Z", 136, 37),
+    ("2017-08-21T18:25:00Z", 145, 22),
+    ("2019-07-02T19:23:00Z", 127, 58),
+    ("2020-12-14T16:13:00Z", 142, 23),
+    ("2021-06-10T10:41:00Z", 147, 23),
+    ("2023-10-14T17:59:00Z", 134, 44),
+    ("2026-08-12T17:46:00Z", 126, 48),
+];
+const LUNAR_REFERENCES: &[(&str, u32, u32)] = &[
+    ("2022-11-08T10:59:00Z", 136, 20),
+    ("2018-07-27T20:21:00Z", 129, 38),
+    ("2019-01-21T05:12:00Z", 134, 27),
+    ("2021-05-26T11:18:00Z", 121, 55),
+    ("2025-03-14T06:58:00Z", 123, 53),
+];
+
+/// The Saros series an eclipse belongs to, and its place in the series, counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Saros {
+    pub series: u32,
+    /// Only known for the series with a reference eclipse.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<u32>,
+}
+
+impl Saros {
+    /// The Saros of the solar eclipse greatest at `greatest`.
+    pub fn solar(greatest: DateTime<Utc>) -> Self {
+        Self::from_references(SOLAR_REFERENCES, greatest)
+    }
+
+    /// The Saros of the lunar eclipse greatest at `greatest`.
+    pub fn lunar(greatest: DateTime<Utc>) -> Self {
+        Self::from_references(LUNAR_REFERENCES, greatest)
+    }
+
+    fn from_references(references: &[(&str, u32, u32)], greatest: DateTime<Utc>) -> Self {
+        let lunations_since = |date: &str| {
+            let date = DateTime::<Utc>::from_str(date).unwrap();
+            let days = (greatest - date).num_seconds() as f64 / 86400.0;
+            (days / SYNODIC_MONTH).round() as i64
+        };
+        let (anchor, anchor_series, _) = references[0];
+        let series = (anchor_series as i64 - 1 + SERIES_PER_LUNATION * lunations_since(anchor))
+            .rem_euclid(SAROS_LUNATIONS)
+            + 1;
+        let member = references
+            .iter()
+            .find(|&&(_, ref_series, _)| ref_series as i64 == series)
+            .map(|&(date, _, member)| {
+                member as i64 + lunations_since(date).div_euclid(SAROS_LUNATIONS)
+            })
+            .filter(|&member| member > 0)
+            .map(|member| member as u32);
+        Self {
+            series: series as u32,
+            member,
+        }
+    }
+}
+
+impl fmt::Display for Saros {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.member {
+            Some(member) => write!(f, "Saros {}, member {}", self.series, member),
+            None => write!(f, "Saros {}", self.series),
+        }
+    }
+}
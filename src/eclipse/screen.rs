@@ -0,0 +1,80 @@
+use crate::{
+    simulation::{Propagator, SimState},
+    time::{jd_to_sim_time, EPOCH_JD},
+};
+
+/// Mean length of the synodic month, in days.
+const SYNODIC_MONTH: f64 = 29.530588861;
+/// JDE of the mean new Moon of 2000-01-06, lunation 0 of Meeus' series.
+const LUNATION_ZERO: f64 = 2451550.09766;
+/// No eclipse happens at a syzygy with the Moon's mean argument of latitude farther from a
+/// node than this, in terms of its sine; Meeus gives 0.36, with some margin added here.
+const MAX_SIN_LATITUDE: f64 = 0.4;
+/// How long before and after a mean syzygy a candidate eclipse is searched for, in seconds.
+/// The true syzygy is within 15 hours of the mean one, and the longest eclipses last about 6.
+const WINDOW_HALF_WIDTH: f64 = 1.5 * 86400.0;
+
+/// Which of the syzygies the eclipses happen at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syzygy {
+    /// Solar eclipses.
+    NewMoon,
+    /// Lunar eclipses.
+    FullMoon,
+}
+
+/// The periods between `start` and `end` (simulation times) in which eclipses are possible,
+/// found analytically from the mean phases of the Moon and its mean argument of latitude
+/// (Meeus, Astronomical Algorithms, chapters 49 and 54), without propagating anything.
+pub fn eclipse_windows(start: f64, end: f64, syzygy: Syzygy) -> Vec<(f64, f64)> {
+    let offset = match syzygy {
+        Syzygy::NewMoon => 0.0,
+        Syzygy::FullMoon => 0.5,
+    };
+    let lunation_at = |time: f64| (time / 86400.0 + EPOCH_JD - LUNATION_ZERO) / SYNODIC_MONTH;
+    let mut windows = vec![];
+    let mut lunation = lunation_at(start).floor() - 1.0;
+    while lunation < lunation_at(end) + 1.0 {
+        let k = lunation + offset;
+        let t = k / 1236.85;
+        let jde = LUNATION_ZERO + SYNODIC_MONTH * k + 0.00015437 * t * t - 0.00000015 * t * t * t;
+        let latitude = 160.7108 + 390.67050284 * k - 0.0016118 * t * t - 0.00000227 * t * t * t;
+        let time = jd_to_sim_time(jde);
+        let window = (
+            (time - WINDOW_HALF_WIDTH).max(start),
+            (time + WINDOW_HALF_WIDTH).min(end),
+        );
+        if latitude.to_radians().sin().abs() < MAX_SIN_LATITUDE && window.0 < window.1 {
+            windows.push(window);
+        }
+        lunation += 1.0;
+    }
+    windows
+}
+
+/// Runs `search` within each of `windows` (sorted, between `start` and `end`), `sim` being the
+/// state of the system at `start`, and only propagates the system between them, without
+/// checking anything. With `eclipse_windows` and the eclipse searches it finds the same
+/// eclipses as searching the whole period.
+pub fn screened<P, T, F>(
+    sim: &SimState,
+    start: f64,
+    propagator: P,
+    windows: &[(f64, f64)],
+    mut search: F,
+) -> Vec<T>
+where
+    P: Into<Propagator>,
+    F: FnMut(&SimState, f64, f64, Propagator) -> Vec<T>,
+{
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut found = vec![];
+    for &(from, to) in windows {
+        propagator.advance(&mut sim, from - time);
+        time = from;
+        found.extend(search(&sim, from, to, propagator.clone()));
+    }
+    found
+}
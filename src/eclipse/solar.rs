@@ -0,0 +1,461 @@
+use chrono::{DateTime, Utc};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, debug_span};
+
+use super::{disk_obscuration, Eclipse, Saros};
+use crate::{
+    frames::{self, Frame, EARTH_EQUATORIAL_RADIUS, EARTH_FLATTENING},
+    refine,
+    simulation::{Propagator, SimState, SPEED_OF_LIGHT},
+    time::sim_time_to_utc,
+};
+
+/// Rotation rate of the Earth, in rad/s.
+const EARTH_ROTATION_RATE: f64 = 7.292115e-5;
+
+/// Contact times of a solar eclipse with the Earth: P1/P4 when the penumbra first and last
+/// touches it, and the start and end of the central line, if there is one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SolarContacts {
+    pub p1: DateTime<Utc>,
+    pub central_start: Option<DateTime<Utc>>,
+    pub central_end: Option<DateTime<Utc>>,
+    pub p4: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SolarEclipse {
+    /// `PartialSolar`, `TotalSolar` or `AnnularSolar`; a hybrid eclipse is reported as what it
+    /// is at the greatest eclipse.
+    pub kind: Eclipse,
+    /// When the shadow axis passes closest to the Earth's center.
+    pub greatest: DateTime<Utc>,
+    /// The least distance of the shadow axis from the Earth's center, in equatorial radii,
+    /// negative when the axis passes south of it.
+    pub gamma: f64,
+    /// Where the eclipse is the greatest: on the central line at the greatest eclipse, or for a
+    /// partial eclipse on the Earth's limb closest to the shadow axis.
+    pub greatest_point: GroundPoint,
+    /// The ratio of the Moon's apparent diameter to the Sun's seen from `greatest_point` for a
+    /// central eclipse, the fraction of the Sun's diameter covered there for a partial one.
+    pub magnitude: f64,
+    /// Fraction of the Sun's disk area covered by the Moon seen from `greatest_point`.
+    pub obscuration: f64,
+    /// Width of the path of the umbra (or the antumbra) at the greatest eclipse, in km.
+    pub path_width: Option<f64>,
+    pub saros: Saros,
+    pub contacts: SolarContacts,
+}
+
+/// A point on the Earth's surface reached by the Moon's shadow, with the geodetic latitude and
+/// longitude in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GroundPoint {
+    pub date: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// The path of the umbra (or the antumbra) of a central solar eclipse on the Earth's surface.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EclipsePath {
+    pub central: Vec<GroundPoint>,
+    pub northern_limit: Vec<GroundPoint>,
+    pub southern_limit: Vec<GroundPoint>,
+}
+
+/// A point of the central line and the points of the northern and southern limits beside it,
+/// if they are on the Earth.
+type CentralPoints<T> = (T, Option<T>, Option<T>);
+
+/// The Moon's shadow cones. The positions are relative to the Earth's center, in the
+/// simulation frame and in km.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolarShadow {
+    /// Unit vector along the shadow axis, pointing away from the Sun.
+    pub axis: Vector3<f64>,
+    /// Rate of change of `axis`, in 1/s.
+    pub axis_rate: Vector3<f64>,
+    pub moon: Vector3<f64>,
+    pub moon_vel: Vector3<f64>,
+    pub moon_radius: f64,
+    /// Tangents of the half-angles of the penumbral and umbral cones.
+    pub tan_f1: f64,
+    pub tan_f2: f64,
+}
+
+impl SolarShadow {
+    /// The shadow cast by the Moon in the state `sim`, with the Sun where it was when the
+    /// light left it.
+    pub fn new(sim: &SimState) -> Self {
+        let sun = sim.body_by_name("Sun").unwrap();
+        let earth = sim.body_by_name("Earth").unwrap();
+        let moon = sim.body_by_name("Moon").unwrap();
+
+        let delay = moon.distance_from(sun) / SPEED_OF_LIGHT;
+        let from_sun = moon.pos - (sun.pos - sun.vel * delay);
+        let dist = from_sun.norm();
+        let axis = from_sun / dist;
+        let vel = moon.vel - sun.vel;
+        let tan = |sin: f64| sin / (1.0 - sin * sin).sqrt();
+        Self {
+            axis,
+            axis_rate: (vel - axis * axis.dot(&vel)) / dist,
+            moon: moon.pos - earth.pos,
+            moon_vel: moon.vel - earth.vel,
+            moon_radius: moon.radius,
+            tan_f1: tan((sun.radius + moon.radius) / dist),
+            tan_f2: tan((sun.radius - moon.radius) / dist),
+        }
+    }
+
+    /// Height of `point` above the fundamental plane, which passes through the Earth's center
+    /// perpendicular to the axis, towards the Moon.
+    fn height(&self, point: &Vector3<f64>) -> f64 {
+        -point.dot(&self.axis)
+    }
+
+    /// Radius of the penumbra `height` km above the fundamental plane.
+    pub fn penumbra_radius(&self, height: f64) -> f64 {
+        let depth = self.height(&self.moon) - height;
+        self.moon_radius * (1.0 + self.tan_f1 * self.tan_f1).sqrt() + depth * self.tan_f1
+    }
+
+    /// Radius of the umbra `height` km above the fundamental plane, negative beyond the apex
+    /// of the umbral cone, where the antumbra is.
+    pub fn umbra_radius(&self, height: f64) -> f64 {
+        let depth = self.height(&self.moon) - height;
+        self.moon_radius * (1.0 + self.tan_f2 * self.tan_f2).sqrt() - depth * self.tan_f2
+    }
+
+    /// The Sun's and the Moon's apparent radii seen from `height` km above the fundamental
+    /// plane, in km on it.
+    fn radii(&self, height: f64) -> (f64, f64) {
+        let (penumbra, umbra) = (self.penumbra_radius(height), self.umbra_radius(height));
+        (0.5 * (penumbra - umbra), 0.5 * (penumbra + umbra))
+    }
+
+    /// The point of the axis closest to the Earth's center.
+    pub fn axis_closest_point(&self) -> Vector3<f64> {
+        self.moon - self.axis * self.moon.dot(&self.axis)
+    }
+
+    /// Distance of the axis from the Earth's center at the simulation time `time`, with the
+    /// Earth stretched along the pole so that the ellipsoid becomes a sphere of the equatorial
+    /// radius.
+    fn stretched_axis_dist(&self, time: f64) -> f64 {
+        self.stretched_closest_point(time).norm()
+    }
+
+    /// The point of the axis closest to the Earth's center in the Earth-fixed frame stretched
+    /// like in `stretched_axis_dist`.
+    fn stretched_closest_point(&self, time: f64) -> Vector3<f64> {
+        let rotation = Frame::EarthFixed.rotation(time);
+        let moon = (rotation * self.moon).component_mul(&stretch());
+        let axis = (rotation * self.axis).component_mul(&stretch()).normalize();
+        moon - axis * moon.dot(&axis)
+    }
+
+    /// Where the line parallel to the axis, `offset` away from it, first meets the Earth's
+    /// surface at the simulation time `time`.
+    fn surface_point(&self, time: f64, offset: Vector3<f64>) -> Option<Vector3<f64>> {
+        let rotation = Frame::EarthFixed.rotation(time);
+        let start = rotation * (self.moon + offset);
+        let point = frames::ellipsoid_intersection(&start, &(rotation * self.axis))?;
+        Some(rotation.transpose() * point)
+    }
+
+    /// The eclipse seen from the part of the Earth most deeply in the shadow at the simulation
+    /// time `time`, if any.
+    pub fn eclipse(&self, time: f64) -> Option<Eclipse> {
+        if self.height(&self.moon) <= 0.0 {
+            return None;
+        }
+        if let Some(point) = self.surface_point(time, Vector3::zeros()) {
+            return if self.umbra_radius(self.height(&point)) > 0.0 {
+                Some(Eclipse::TotalSolar)
+            } else {
+                Some(Eclipse::AnnularSolar)
+            };
+        }
+        if self.stretched_axis_dist(time) < EARTH_EQUATORIAL_RADIUS + self.penumbra_radius(0.0) {
+            Some(Eclipse::PartialSolar)
+        } else {
+            None
+        }
+    }
+
+    /// The point of the central line and the points of the northern and southern limits of
+    /// the umbra (or the antumbra) at the simulation time `time`, or `None` if the eclipse
+    /// isn't central then. The limits are missing where they are off the Earth.
+    pub fn ground_points(&self, time: f64) -> Option<CentralPoints<GroundPoint>> {
+        let (central, north, south) = self.central_points(time)?;
+        Some((
+            ground_point(central, time),
+            north.map(|point| ground_point(point, time)),
+            south.map(|point| ground_point(point, time)),
+        ))
+    }
+
+    /// `ground_points` as positions relative to the Earth's center, in the simulation frame.
+    fn central_points(&self, time: f64) -> Option<CentralPoints<Vector3<f64>>> {
+        let central = self.surface_point(time, Vector3::zeros())?;
+
+        // the limits are perpendicular to the motion of the shadow over the ground
+        let pole = Frame::EarthFixed.to_sim(Vector3::z(), time);
+        let ground_vel = pole.cross(&central) * EARTH_ROTATION_RATE;
+        let axis_vel = self.moon_vel - self.axis_rate * self.moon.dot(&self.axis) - ground_vel;
+        let mut north = self.axis.cross(&axis_vel).normalize();
+        if north.dot(&pole) < 0.0 {
+            north = -north;
+        }
+        let limit = |side: Vector3<f64>| {
+            // the edge of the cone is found at the height of the central line first
+            let mut point = central;
+            for _ in 0..3 {
+                let radius = self.umbra_radius(self.height(&point)).abs();
+                point = self.surface_point(time, side * radius)?;
+            }
+            Some(point)
+        };
+        Some((central, limit(north), limit(-north)))
+    }
+
+    /// The point of greatest eclipse at the simulation time `time` (see
+    /// `SolarEclipse::greatest_point`), the magnitude and the obscuration there and the width
+    /// of the path.
+    fn greatest_eclipse(&self, time: f64) -> (GroundPoint, f64, f64, Option<f64>) {
+        match self.central_points(time) {
+            Some((central, north, south)) => {
+                let (sun, moon) = self.radii(self.height(&central));
+                let width = north
+                    .zip(south)
+                    .map(|(north, south)| (north - south).norm());
+                let obscuration = disk_obscuration(sun, moon, 0.0);
+                (ground_point(central, time), moon / sun, obscuration, width)
+            }
+            None => {
+                let closest = self.stretched_closest_point(time);
+                let (sun, moon) = self.radii(0.0);
+                let distance = closest.norm() - EARTH_EQUATORIAL_RADIUS;
+                let limb =
+                    (closest.normalize() * EARTH_EQUATORIAL_RADIUS).component_div(&stretch());
+                let magnitude = (sun + moon - distance) / (2.0 * sun);
+                let obscuration = disk_obscuration(sun, moon, distance);
+                let limb = Frame::EarthFixed.to_sim(limb, time);
+                (ground_point(limb, time), magnitude, obscuration, None)
+            }
+        }
+    }
+}
+
+/// Scales the Earth-fixed coordinates so that the ellipsoid becomes a sphere of the equatorial
+/// radius.
+fn stretch() -> Vector3<f64> {
+    Vector3::new(1.0, 1.0, 1.0 / (1.0 - EARTH_FLATTENING))
+}
+
+/// The place below `point` (relative to the Earth's center, in the simulation frame) at the
+/// simulation time `time`.
+fn ground_point(point: Vector3<f64>, time: f64) -> GroundPoint {
+    let (latitude, longitude) = frames::geodetic(&Frame::EarthFixed.from_sim(point, time));
+    GroundPoint {
+        date: sim_time_to_utc(time),
+        latitude: latitude.to_degrees(),
+        longitude: longitude.to_degrees(),
+    }
+}
+
+fn rank(eclipse: Option<Eclipse>) -> usize {
+    match eclipse {
+        Some(Eclipse::TotalSolar) | Some(Eclipse::AnnularSolar) => 2,
+        Some(Eclipse::PartialSolar) => 1,
+        _ => 0,
+    }
+}
+
+/// Finds all solar eclipses between `start` and `end` (simulation times), `sim` being the
+/// state of the system at `start`. Contact times are accurate to about a second.
+pub fn find_solar_eclipses<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+) -> Vec<SolarEclipse> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let _span = debug_span!("solar_eclipse_search").entered();
+
+    let mut result = vec![];
+    let mut current_rank = rank(SolarShadow::new(&sim).eclipse(time));
+    // P1, the start and end of the central line, and P4
+    let mut contacts: [Option<f64>; 4] = [None; 4];
+    let mut greatest: Option<(f64, SolarShadow)> = None;
+    // the last three samples of the shadow, for finding the greatest eclipse
+    let mut samples: Vec<(f64, SolarShadow, SimState)> = Vec::with_capacity(3);
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        let shadow = SolarShadow::new(&sim);
+        let new_rank = rank(shadow.eclipse(time));
+
+        for level in current_rank + 1..=new_rank {
+            let t = refine::bisect(&propagator, &sim, time, step, 1.0, |state, t| {
+                rank(SolarShadow::new(state).eclipse(t)) >= level
+            });
+            contacts[level - 1] = Some(t);
+        }
+        for level in (new_rank + 1..=current_rank).rev() {
+            let t = refine::bisect(&propagator, &sim, time, step, 1.0, |state, t| {
+                rank(SolarShadow::new(state).eclipse(t)) < level
+            });
+            contacts[4 - level] = Some(t);
+        }
+
+        if new_rank > 0 {
+            if samples.len() == 3 {
+                let _ = samples.remove(0);
+            }
+            samples.push((time, shadow, sim.clone()));
+            update_greatest(&mut greatest, &samples, &propagator);
+        } else {
+            samples.clear();
+        }
+
+        if new_rank == 0 && current_rank > 0 {
+            if let Some(eclipse) = finish_eclipse(&contacts, greatest) {
+                debug!(kind = ?eclipse.kind, greatest = %eclipse.greatest, "found eclipse");
+                result.push(eclipse);
+            }
+            contacts = [None; 4];
+            greatest = None;
+        }
+        current_rank = new_rank;
+    }
+
+    result
+}
+
+fn axis_dist(shadow: &SolarShadow) -> f64 {
+    shadow.axis_closest_point().norm()
+}
+
+fn update_greatest(
+    greatest: &mut Option<(f64, SolarShadow)>,
+    samples: &[(f64, SolarShadow, SimState)],
+    propagator: &Propagator,
+) {
+    let is_greater = |shadow: &SolarShadow, greatest: &Option<(f64, SolarShadow)>| {
+        greatest.is_none_or(|(_, other)| axis_dist(shadow) < axis_dist(&other))
+    };
+    let (t2, shadow2, _) = samples[samples.len() - 1];
+    if is_greater(&shadow2, greatest) {
+        *greatest = Some((t2, shadow2));
+    }
+    if samples.len() < 3 {
+        return;
+    }
+    // the square of the distance from the axis is very close to a parabola in time
+    let (t0, shadow0, _) = samples[0];
+    let (t1, shadow1, ref sim1) = samples[1];
+    let (d0, d1, d2) = (
+        axis_dist(&shadow0).powi(2),
+        axis_dist(&shadow1).powi(2),
+        axis_dist(&shadow2).powi(2),
+    );
+    if d1 > d0 || d1 > d2 {
+        return;
+    }
+    let (x0, x2) = (t0 - t1, t2 - t1);
+    let (slope0, slope2) = ((d1 - d0) / -x0, (d2 - d1) / x2);
+    let curvature = (slope2 - slope0) / (x2 - x0);
+    if curvature <= 0.0 {
+        return;
+    }
+    let b = slope0 - curvature * x0;
+    let offset = -b / (2.0 * curvature);
+
+    // corrected with a parabola through exactly computed distances around the estimate
+    let shadow_at = |offset: f64| SolarShadow::new(&propagator.propagated(sim1, offset));
+    let h = 30.0;
+    let d0 = axis_dist(&shadow_at(offset - h)).powi(2);
+    let d1 = axis_dist(&shadow_at(offset)).powi(2);
+    let d2 = axis_dist(&shadow_at(offset + h)).powi(2);
+    let curvature = d0 - 2.0 * d1 + d2;
+    if curvature <= 0.0 {
+        return;
+    }
+    let offset = offset + (h * 0.5 * (d0 - d2) / curvature).clamp(-h, h);
+    let shadow = shadow_at(offset);
+    if is_greater(&shadow, greatest) {
+        *greatest = Some((t1 + offset, shadow));
+    }
+}
+
+fn finish_eclipse(
+    contacts: &[Option<f64>; 4],
+    greatest: Option<(f64, SolarShadow)>,
+) -> Option<SolarEclipse> {
+    let (time, shadow) = greatest?;
+    let closest = shadow.axis_closest_point();
+    let north = Frame::Equatorial.from_sim(closest, time).z >= 0.0;
+    let gamma = closest.norm() / EARTH_EQUATORIAL_RADIUS;
+    let (greatest_point, magnitude, obscuration, path_width) = shadow.greatest_eclipse(time);
+    Some(SolarEclipse {
+        kind: shadow.eclipse(time).unwrap_or(Eclipse::PartialSolar),
+        greatest: sim_time_to_utc(time),
+        gamma: if north { gamma } else { -gamma },
+        greatest_point,
+        magnitude,
+        obscuration,
+        path_width,
+        saros: Saros::solar(sim_time_to_utc(time)),
+        contacts: SolarContacts {
+            p1: sim_time_to_utc(contacts[0]?),
+            central_start: contacts[1].map(sim_time_to_utc),
+            central_end: contacts[2].map(sim_time_to_utc),
+            p4: sim_time_to_utc(contacts[3]?),
+        },
+    })
+}
+
+/// Traces the central line and the limits of the umbra (or the antumbra) every `every`
+/// seconds between `start` and `end` (simulation times), `sim` being the state of the system
+/// at `start`. Only the times at which the eclipse is central add points to the path.
+pub fn eclipse_path<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    every: f64,
+    propagator: P,
+) -> EclipsePath {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut next = start;
+
+    let mut path = EclipsePath::default();
+    while next <= end {
+        time += propagator.step(&mut sim);
+        // the points within the last step, evaluated with single steps back from its end
+        while next <= time && next <= end {
+            let state = propagator.propagated(&sim, next - time);
+            if let Some((central, north, south)) = SolarShadow::new(&state).ground_points(next) {
+                path.central.push(central);
+                path.northern_limit.extend(north);
+                path.southern_limit.extend(south);
+            }
+            // the last point at the end of the path
+            next = if next < end {
+                (next + every).min(end)
+            } else {
+                next + every
+            };
+        }
+    }
+    path
+}
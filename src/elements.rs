@@ -0,0 +1,308 @@
+use std::{f64::consts::PI, fs, ops::Range, path::Path};
+
+use chrono::NaiveDate;
+use nalgebra::{Rotation3, Vector3};
+use tracing::warn;
+
+use crate::{
+    error::{Error, Result},
+    horizons,
+    simulation::{Body, SimState},
+    time::EPOCH_JD,
+};
+
+const AU: f64 = 149_597_870.7;
+const DAY: f64 = 86400.0;
+/// Propagating the elements of a small body further than this from their epoch without the
+/// planets' perturbations gets its position wrong by a lot.
+const MAX_UNPERTURBED_DAYS: f64 = 365.0;
+
+/// Heliocentric orbital elements referred to the ecliptic and equinox J2000, in the form
+/// working for elliptic, parabolic and hyperbolic orbits alike.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitalElements {
+    pub name: String,
+    /// Julian date (TT) of the osculation.
+    pub epoch: f64,
+    /// Perihelion distance, in AU.
+    pub perihelion_distance: f64,
+    pub eccentricity: f64,
+    /// Inclination, longitude of the ascending node and argument of the perihelion, in degrees.
+    pub inclination: f64,
+    pub node: f64,
+    pub argument_of_perihelion: f64,
+    /// Julian date (TT) of the perihelion passage.
+    pub perihelion_time: f64,
+}
+
+impl OrbitalElements {
+    /// Elements of an elliptic orbit given by its semi-major axis (AU) and mean anomaly
+    /// (degrees) at the epoch, `gm` being the Sun's (km³/s²).
+    #[allow(clippy::too_many_arguments)]
+    pub fn elliptic(
+        name: String,
+        epoch: f64,
+        semi_major_axis: f64,
+        eccentricity: f64,
+        (inclination, node, argument_of_perihelion): (f64, f64, f64),
+        mean_anomaly: f64,
+        gm: f64,
+    ) -> Self {
+        let mean_motion = (gm / (semi_major_axis * AU).powi(3)).sqrt() * DAY;
+        let mean_anomaly = (mean_anomaly.to_radians() + PI).rem_euclid(2.0 * PI) - PI;
+        Self {
+            name,
+            epoch,
+            perihelion_distance: semi_major_axis * (1.0 - eccentricity),
+            eccentricity,
+            inclination,
+            node,
+            argument_of_perihelion,
+            perihelion_time: epoch - mean_anomaly / mean_motion,
+        }
+    }
+
+    /// Heliocentric position and velocity (km, km/s) at the Julian date `jd`, moving on the
+    /// unperturbed orbit around a Sun with the given GM (km³/s²).
+    pub fn state_at(&self, jd: f64, gm: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let q = self.perihelion_distance * AU;
+        let e = self.eccentricity;
+        let t = (jd - self.perihelion_time) * DAY;
+        let (anomaly, r) = if (e - 1.0).abs() < 1e-9 {
+            // Barker's equation
+            let w = 3.0 * (gm / (2.0 * q * q * q)).sqrt() * t;
+            let root = (w * w / 4.0 + 1.0).sqrt();
+            let s = (w / 2.0 + root).cbrt() + (w / 2.0 - root).cbrt();
+            (2.0 * s.atan(), q * (1.0 + s * s))
+        } else if e < 1.0 {
+            let a = q / (1.0 - e);
+            let mean_anomaly = ((gm / (a * a * a)).sqrt() * t + PI).rem_euclid(2.0 * PI) - PI;
+            let mut ecc = mean_anomaly;
+            for _ in 0..50 {
+                ecc -= (ecc - e * ecc.sin() - mean_anomaly) / (1.0 - e * ecc.cos());
+            }
+            let anomaly = 2.0
+                * ((1.0 + e).sqrt() * (ecc / 2.0).sin())
+                    .atan2((1.0 - e).sqrt() * (ecc / 2.0).cos());
+            (anomaly, a * (1.0 - e * ecc.cos()))
+        } else {
+            let a = q / (e - 1.0);
+            let mean_anomaly = (gm / (a * a * a)).sqrt() * t;
+            let mut hyp = mean_anomaly.signum() * (2.0 * mean_anomaly.abs() / e + 1.8).ln();
+            for _ in 0..50 {
+                hyp -= (e * hyp.sinh() - hyp - mean_anomaly) / (e * hyp.cosh() - 1.0);
+            }
+            let anomaly = 2.0 * (((e + 1.0) / (e - 1.0)).sqrt() * (hyp / 2.0).tanh()).atan();
+            (anomaly, a * (e * hyp.cosh() - 1.0))
+        };
+
+        let speed = (gm / (q * (1.0 + e))).sqrt();
+        let pos = Vector3::new(anomaly.cos(), anomaly.sin(), 0.0) * r;
+        let vel = Vector3::new(-anomaly.sin(), e + anomaly.cos(), 0.0) * speed;
+        let rotation = Rotation3::from_axis_angle(&Vector3::z_axis(), self.node.to_radians())
+            * Rotation3::from_axis_angle(&Vector3::x_axis(), self.inclination.to_radians())
+            * Rotation3::from_axis_angle(
+                &Vector3::z_axis(),
+                self.argument_of_perihelion.to_radians(),
+            );
+        (rotation * pos, rotation * vel)
+    }
+}
+
+/// Parses orbital elements in the MPC's one-line formats of the asteroids (MPCORB.DAT) and of
+/// the comets (CometEls.txt), one body per line, or a JPL Horizons ELEMENTS table, whose body is
+/// named `name` unless its header says otherwise. `gm` is the Sun's GM (km³/s²).
+pub fn parse(text: &str, name: &str, gm: f64) -> Result<Vec<OrbitalElements>> {
+    if text.contains("EPOCH=") {
+        return parse_horizons(text, name, gm).map(|elements| vec![elements]);
+    }
+    // MPCORB.DAT starts with a header ending with a line of dashes
+    let data = match text.find("\n-----") {
+        Some(idx) => text[idx + 1..]
+            .split_once('\n')
+            .map_or("", |(_, rest)| rest),
+        None => text,
+    };
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            let is_comet = field(line, 14..18).chars().all(|c| c.is_ascii_digit())
+                && !field(line, 14..18).is_empty();
+            let elements = if is_comet {
+                parse_comet(line)
+            } else {
+                parse_asteroid(line, gm)
+            };
+            elements.ok_or_else(|| Error::Elements(format!("bad line {}: {}", idx + 1, line)))
+        })
+        .collect()
+}
+
+fn field(line: &str, range: Range<usize>) -> &str {
+    let end = range.end.min(line.len());
+    line.get(range.start.min(end)..end).unwrap_or("").trim()
+}
+
+fn number(line: &str, range: Range<usize>) -> Option<f64> {
+    field(line, range).parse().ok()
+}
+
+/// The Julian date of 0h on the given day, which can be fractional.
+fn julian_date(year: i32, month: u32, day: f64) -> Option<f64> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let j2000 = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    Some(EPOCH_JD + (first - j2000).num_days() as f64 + day - 1.0)
+}
+
+/// Decodes a packed date like "K24AH" (2024-10-17).
+fn packed_date(packed: &str) -> Option<f64> {
+    let digit = |c: char| match c {
+        '1'..='9' => c.to_digit(10),
+        'A'..='V' => Some(c as u32 - 'A' as u32 + 10),
+        _ => None,
+    };
+    let chars: Vec<char> = packed.chars().collect();
+    if chars.len() != 5 {
+        return None;
+    }
+    let century = match chars[0] {
+        'I'..='L' => 18 + (chars[0] as i32 - 'I' as i32),
+        _ => return None,
+    };
+    let year = century * 100 + packed[1..3].parse::<i32>().ok()?;
+    julian_date(year, digit(chars[3])?, digit(chars[4])? as f64)
+}
+
+fn parse_asteroid(line: &str, gm: f64) -> Option<OrbitalElements> {
+    let name = match field(line, 166..194) {
+        "" => field(line, 0..7),
+        name => name,
+    };
+    Some(OrbitalElements::elliptic(
+        name.to_owned(),
+        packed_date(field(line, 20..25))?,
+        number(line, 92..103)?,
+        number(line, 70..79)?,
+        (
+            number(line, 59..68)?,
+            number(line, 48..57)?,
+            number(line, 37..46)?,
+        ),
+        number(line, 26..35)?,
+        gm,
+    ))
+}
+
+fn parse_comet(line: &str) -> Option<OrbitalElements> {
+    let perihelion_time = julian_date(
+        field(line, 14..18).parse().ok()?,
+        field(line, 19..21).parse().ok()?,
+        number(line, 22..29)?,
+    )?;
+    let epoch = match field(line, 81..89) {
+        "" => perihelion_time,
+        epoch => julian_date(
+            epoch.get(0..4)?.parse().ok()?,
+            epoch.get(4..6)?.parse().ok()?,
+            epoch.get(6..8)?.parse().ok()?,
+        )?,
+    };
+    let name = match field(line, 102..158) {
+        "" => field(line, 0..12),
+        name => name,
+    };
+    Some(OrbitalElements {
+        name: name.to_owned(),
+        epoch,
+        perihelion_distance: number(line, 30..39)?,
+        eccentricity: number(line, 41..49)?,
+        inclination: number(line, 71..79)?,
+        node: number(line, 61..69)?,
+        argument_of_perihelion: number(line, 51..59)?,
+        perihelion_time,
+    })
+}
+
+/// Parses the osculating elements of a Horizons ELEMENTS table, given either with the
+/// perihelion distance and time (`QR`, `TP`) or with the semi-major axis and mean anomaly (`A`,
+/// `MA`).
+fn parse_horizons(text: &str, name: &str, gm: f64) -> Result<OrbitalElements> {
+    let values: Vec<(String, String)> = text
+        .lines()
+        .flat_map(|line| horizons::key_values(line.split('!').next().unwrap_or("")))
+        .collect();
+    let value = |key: &str| {
+        values
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.parse::<f64>().ok())
+    };
+    let required = |key: &str| {
+        value(key).ok_or_else(|| Error::Elements(format!("no {} in the elements table", key)))
+    };
+    let name = horizons::header_field(text, "Target body name:").unwrap_or_else(|| name.to_owned());
+    let epoch = required("EPOCH")?;
+    let eccentricity = required("EC")?;
+    let angles = (required("IN")?, required("OM")?, required("W")?);
+    match (value("QR"), value("TP")) {
+        (Some(perihelion_distance), Some(perihelion_time)) => Ok(OrbitalElements {
+            name,
+            epoch,
+            perihelion_distance,
+            eccentricity,
+            inclination: angles.0,
+            node: angles.1,
+            argument_of_perihelion: angles.2,
+            perihelion_time,
+        }),
+        _ => Ok(OrbitalElements::elliptic(
+            name,
+            epoch,
+            required("A")?,
+            eccentricity,
+            angles,
+            required("MA")?,
+            gm,
+        )),
+    }
+}
+
+/// Adds the small bodies whose elements are in the file at `path` (see `parse`) to `sim`, the
+/// state at the simulation time `time`, as test particles.
+pub fn load(path: &Path, sim: SimState, time: f64) -> Result<SimState> {
+    let load = move || -> Result<SimState> {
+        let sun = sim
+            .body_by_name("Sun")
+            .ok_or_else(|| Error::MissingBody("Sun".to_owned()))?
+            .clone();
+        let text = fs::read_to_string(path)?;
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        let elements = parse(&text, name, sun.gm)?;
+        let jd = time / DAY + EPOCH_JD;
+        let mut sim = sim;
+        for elements in elements {
+            if (jd - elements.epoch).abs() > MAX_UNPERTURBED_DAYS {
+                warn!(
+                    body = elements.name,
+                    days = (jd - elements.epoch).abs().round(),
+                    "propagating the elements far from their epoch without perturbations"
+                );
+            }
+            let (pos, vel) = elements.state_at(jd, sun.gm);
+            sim = sim.with_body(Body {
+                name: elements.name,
+                gm: 0.0,
+                pos: sun.pos + pos,
+                vel: sun.vel + vel,
+                radius: 0.0,
+                oblateness: None,
+            });
+        }
+        Ok(sim)
+    };
+    load().map_err(|err| err.in_file(path))
+}
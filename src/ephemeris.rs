@@ -0,0 +1,61 @@
+use std::{fs::File, io::Read, path::Path};
+
+use nalgebra::Vector3;
+
+use crate::{
+    de::DeEphemeris,
+    error::{Error, Result},
+    horizons::VectorTables,
+    spk::{self, Spk},
+};
+
+/// A reference ephemeris the propagated bodies can be compared with.
+pub trait Ephemeris {
+    /// Position and velocity (km, km/s, ecliptic J2000) of the named body relative to the
+    /// solar system barycenter at the given simulation time.
+    fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)>;
+}
+
+impl Ephemeris for Spk {
+    fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        let id = spk::naif_id(name)
+            .ok_or_else(|| Error::Spk(format!("no NAIF id known for {}", name)))?;
+        Spk::state(self, id, time)
+    }
+}
+
+impl Ephemeris for DeEphemeris {
+    fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        DeEphemeris::state(self, name, time)
+    }
+}
+
+/// The tables have to be centered on the solar system barycenter.
+impl Ephemeris for VectorTables {
+    fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        VectorTables::state(self, name, time)
+    }
+}
+
+/// Opens the ephemeris in `paths`, telling its format by their contents: an SPK kernel, Horizons
+/// vector tables or a DE ephemeris in either of JPL's formats.
+pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Box<dyn Ephemeris + Send + Sync>> {
+    let first = paths
+        .first()
+        .ok_or_else(|| Error::Argument("no ephemeris files given".into()))?
+        .as_ref();
+    let mut start = vec![];
+    File::open(first)
+        .and_then(|file| file.take(1 << 16).read_to_end(&mut start))
+        .map_err(|err| Error::from(err).in_file(first))?;
+    if start.starts_with(b"DAF/SPK ") {
+        match paths {
+            [path] => Ok(Box::new(Spk::open(path.as_ref())?)),
+            _ => Err(Error::Argument("give a single SPK kernel".into())),
+        }
+    } else if String::from_utf8_lossy(&start).contains("$$SOE") {
+        Ok(Box::new(VectorTables::open(paths)?))
+    } else {
+        Ok(Box::new(DeEphemeris::open(paths)?))
+    }
+}
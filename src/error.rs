@@ -0,0 +1,68 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// Everything that can go wrong when loading the inputs or saving the results.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "plot")]
+    #[error("couldn't draw the chart: {0}")]
+    Plot(String),
+    #[error("body \"{0}\" is missing")]
+    MissingBody(String),
+    #[error("invalid Horizons vector table: {0}")]
+    Horizons(String),
+    #[error("invalid SPK kernel: {0}")]
+    Spk(String),
+    #[error("invalid DE ephemeris: {0}")]
+    De(String),
+    #[error("invalid orbital elements: {0}")]
+    Elements(String),
+    #[error("invalid two-line elements: {0}")]
+    Tle(String),
+    #[error("invalid snapshot: {0}")]
+    Snapshot(String),
+    #[error("the run doesn't match the manifest: {0}")]
+    Manifest(String),
+    #[error("invalid checkpoint: {0}")]
+    Checkpoint(String),
+    #[error("invalid query: {0}")]
+    Query(String),
+    #[error(
+        "expected a date like 2020-01-31, 2020-01-31T12:00:00Z, 2020 or -0584-05-22, got \"{0}\""
+    )]
+    Date(String),
+    /// An invalid command line argument.
+    #[error("{0}")]
+    Argument(String),
+    #[error("{}: {source}", path.display())]
+    InFile {
+        path: PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Adds the path of the file the error happened in.
+    pub fn in_file<P: Into<PathBuf>>(self, path: P) -> Self {
+        Error::InFile {
+            path: path.into(),
+            source: Box::new(self),
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
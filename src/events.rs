@@ -0,0 +1,612 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, debug_span, info};
+
+use crate::{
+    bodies,
+    error::{Error, Result},
+    observer::{self, Observer, SunAngles, Visibility},
+    phase, refine,
+    simulation::{Propagator, SimState},
+    time::{self, sim_time_to_utc},
+};
+
+/// Precision of the reported event times, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+/// How far the Moon has to be from the boresight, in half-widths of the frame, for its
+/// visibility to be checked only once per sample interval.
+const QUIET_DISTANCE: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    BecomingVisible,
+    BecomingObscured,
+    LeavingFrame,
+    /// The Moon moving in front of the Earth disk.
+    StartingTransit,
+}
+
+impl EventKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            EventKind::BecomingVisible => "Moon becoming visible",
+            EventKind::BecomingObscured => "Moon becoming obscured",
+            EventKind::LeavingFrame => "Moon leaving frame",
+            EventKind::StartingTransit => "Moon starting to pass in front of the Earth",
+        }
+    }
+}
+
+/// A change of the Moon's visibility for an observer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub observer: String,
+    pub kind: EventKind,
+    pub date: DateTime<Utc>,
+    /// The Sun-Moon-observer angle, in radians. Missing from events saved by earlier
+    /// versions, which read it as 0.
+    #[serde(default)]
+    pub phase_angle: f64,
+    /// Fraction of the Moon's disk lit as seen by the observer.
+    #[serde(default)]
+    pub illuminated_fraction: f64,
+}
+
+/// The state of a visibility search at some point, from which it can be resumed.
+pub struct Checkpoint {
+    pub observer: String,
+    pub time: f64,
+    pub sim: SimState,
+    pub visibility: Visibility,
+    /// The events found so far.
+    pub events: Vec<Event>,
+}
+
+/// Everything but the bodies, which are stored in the `bodies::load` format after it.
+#[derive(Serialize, Deserialize)]
+struct CheckpointHeader {
+    observer: String,
+    time: f64,
+    visibility: Visibility,
+    #[serde(default)]
+    event: Vec<Event>,
+}
+
+impl Checkpoint {
+    /// The state at the start of a search.
+    pub fn new(observer: &dyn Observer, sim: &SimState, time: f64) -> Self {
+        Self {
+            observer: observer.name().to_owned(),
+            time,
+            sim: sim.clone(),
+            visibility: Visibility::OutOfFrame,
+            events: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let load = || -> Result<Self> {
+            let contents = fs::read_to_string(path)?;
+            let header: CheckpointHeader = toml::from_str(&contents)?;
+            Ok(Self {
+                observer: header.observer,
+                time: header.time,
+                sim: bodies::from_toml(&contents)?,
+                visibility: header.visibility,
+                events: header.event,
+            })
+        };
+        load().map_err(|err| err.in_file(path))
+    }
+
+    /// Writes the checkpoint to a temporary file first, so that an interrupted write doesn't
+    /// destroy the previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let header = CheckpointHeader {
+            observer: self.observer.clone(),
+            time: self.time,
+            visibility: self.visibility,
+            event: self.events.clone(),
+        };
+        let header = toml::to_string(&header)
+            .map_err(|err| Error::Checkpoint(err.to_string()).in_file(path))?;
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, header + "\n" + &bodies::to_toml(&self.sim))
+            .and_then(|()| fs::rename(&temp_path, path))
+            .map_err(|err| Error::from(err).in_file(path))
+    }
+}
+
+/// Statistics of an observer's visibility windows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VisibilitySummary {
+    pub observer: String,
+    pub windows: usize,
+    /// Total time the Moon was visible or in front of the Earth, in minutes.
+    pub minutes_visible: f64,
+    /// The start and end of the longest window.
+    pub longest_window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// The windows by the month they start in, from the first month with one to the last one.
+    pub monthly: Vec<MonthlyVisibility>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonthlyVisibility {
+    pub year: i32,
+    /// From 1 to 12.
+    pub month: u32,
+    pub windows: usize,
+    pub minutes_visible: f64,
+}
+
+/// Propagates `sim` from `start` to `end` (simulation times) and collects the changes of the
+/// Moon's visibility for `observer`.
+pub fn visibility_events<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    observer: &dyn Observer,
+) -> Vec<Event> {
+    let checkpoint = Checkpoint::new(observer, sim, start);
+    resume_visibility_events(
+        checkpoint,
+        end,
+        propagator,
+        observer,
+        (0.0, 0.0),
+        f64::INFINITY,
+        |_| Ok(()),
+    )
+    .expect("there are no checkpoints to fail saving")
+}
+
+/// Continues a visibility search from `checkpoint` until `end`, passing a new checkpoint to
+/// `on_checkpoint` every `checkpoint_interval` seconds of simulation time. The visibility only
+/// changes once the Moon is `margin` radians past a boundary, see
+/// `observer::moon_visibility_with_margin`, and is checked every `sample_interval` seconds
+/// while the Moon is far from the frame, see `Sampler`. Returns all the events, including the
+/// ones found before the checkpoint, or the first error returned by `on_checkpoint`.
+pub fn resume_visibility_events<P, F>(
+    checkpoint: Checkpoint,
+    end: f64,
+    propagator: P,
+    observer: &dyn Observer,
+    (margin, sample_interval): (f64, f64),
+    checkpoint_interval: f64,
+    mut on_checkpoint: F,
+) -> Result<Vec<Event>>
+where
+    P: Into<Propagator>,
+    F: FnMut(&Checkpoint) -> Result<()>,
+{
+    let Checkpoint {
+        mut sim,
+        mut time,
+        visibility: mut current_visibility,
+        mut events,
+        ..
+    } = checkpoint;
+    let mut propagator = propagator.into();
+    let mut next_checkpoint = time + checkpoint_interval;
+    let mut sampler = Sampler::new(sample_interval, time);
+    let _span = debug_span!("visibility_search", observer = observer.name()).entered();
+
+    while time < end {
+        time += propagator.step(&mut sim);
+
+        if let Some(last_check) = sampler.due(time) {
+            let visibility = observer::moon_visibility_with_margin(
+                observer,
+                &sim,
+                time,
+                current_visibility,
+                margin,
+            );
+            if visibility != current_visibility {
+                events.push(refine_event(
+                    &propagator,
+                    &sim,
+                    time,
+                    time - last_check,
+                    observer,
+                    margin,
+                    (current_visibility, visibility),
+                ));
+            }
+            current_visibility = visibility;
+            sampler.checked(observer, &sim, time, visibility);
+        }
+
+        if time >= next_checkpoint {
+            let checkpoint = Checkpoint {
+                observer: observer.name().to_owned(),
+                time,
+                sim,
+                visibility: current_visibility,
+                events,
+            };
+            info!(date = %sim_time_to_utc(time), "saving checkpoint");
+            on_checkpoint(&checkpoint)?;
+            sim = checkpoint.sim;
+            events = checkpoint.events;
+            next_checkpoint = time + checkpoint_interval;
+        }
+    }
+
+    Ok(events)
+}
+
+/// Propagates `sim` from `start` to `end` (simulation times) and collects the changes of the
+/// Moon's visibility for all of `observers` at once, in the order of their dates. The
+/// visibility only changes once the Moon is `margin` radians past a boundary, and is checked
+/// every `sample_interval` seconds while the Moon is far from an observer's frame. With a
+/// `backward` propagator, `end` is before `start` and the Moon is assumed out of frame at
+/// `start`.
+pub fn visibility_events_of<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    observers: &[&dyn Observer],
+    (margin, sample_interval): (f64, f64),
+) -> Vec<Event> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut current: Vec<Visibility> = vec![Visibility::OutOfFrame; observers.len()];
+    let mut samplers = vec![Sampler::new(sample_interval, start); observers.len()];
+    let _span = debug_span!("visibility_search", observers = observers.len()).entered();
+
+    let mut events = vec![];
+    while (end - time) * propagator.direction() > 0.0 {
+        time += propagator.step(&mut sim);
+
+        for ((&observer, current_visibility), sampler) in
+            observers.iter().zip(&mut current).zip(&mut samplers)
+        {
+            let Some(last_check) = sampler.due(time) else {
+                continue;
+            };
+            let visibility = observer::moon_visibility_with_margin(
+                observer,
+                &sim,
+                time,
+                *current_visibility,
+                margin,
+            );
+            if visibility != *current_visibility {
+                events.push(refine_event(
+                    &propagator,
+                    &sim,
+                    time,
+                    time - last_check,
+                    observer,
+                    margin,
+                    (*current_visibility, visibility),
+                ));
+            }
+            *current_visibility = visibility;
+            sampler.checked(observer, &sim, time, visibility);
+        }
+    }
+
+    // the events of different observers within a step are found out of order
+    events.sort_by_key(|event| event.date);
+    events
+}
+
+/// Decides when the visibility is checked: after every step while the Moon is in the frame or
+/// near it, but only once per `interval` seconds while it's more than `QUIET_DISTANCE`
+/// half-widths of the frame from the boresight. Nothing is missed as long as the Moon can't
+/// get from there into the frame within the interval.
+#[derive(Debug, Clone, Copy)]
+struct Sampler {
+    interval: f64,
+    /// The time of the last check, and whether the Moon was far from the frame then.
+    last_check: f64,
+    quiet: bool,
+}
+
+impl Sampler {
+    fn new(interval: f64, start: f64) -> Self {
+        Self {
+            interval,
+            last_check: start,
+            quiet: false,
+        }
+    }
+
+    /// The time of the last check, if the visibility has to be checked at `time`.
+    fn due(&self, time: f64) -> Option<f64> {
+        (!self.quiet || (time - self.last_check).abs() >= self.interval).then_some(self.last_check)
+    }
+
+    fn checked(
+        &mut self,
+        observer: &dyn Observer,
+        sim: &SimState,
+        time: f64,
+        visibility: Visibility,
+    ) {
+        self.last_check = time;
+        self.quiet = self.interval > 0.0
+            && visibility == Visibility::OutOfFrame
+            && far_from_frame(observer, sim, time);
+    }
+}
+
+fn far_from_frame(observer: &dyn Observer, sim: &SimState, time: f64) -> bool {
+    let moon = sim.body_by_name("Moon").unwrap();
+    let dir = moon.pos - observer.pos(sim, time);
+    match observer::frame_position(observer, sim, time, &dir) {
+        Some(position) => position.x.abs().max(position.y.abs()) > QUIET_DISTANCE,
+        None => true,
+    }
+}
+
+/// The change of the Moon's visibility for `observer` from `previous` to `visibility` within
+/// the `step` seconds before `time` (since the last check), `sim` being the state then.
+fn refine_event(
+    propagator: &Propagator,
+    sim: &SimState,
+    time: f64,
+    step: f64,
+    observer: &dyn Observer,
+    margin: f64,
+    (previous, visibility): (Visibility, Visibility),
+) -> Event {
+    let event_time = refine::bisect(propagator, sim, time, step, REFINE_TOLERANCE, |state, t| {
+        observer::moon_visibility_with_margin(observer, state, t, previous, margin) != previous
+    });
+    // the events are named by the visibility after them, which is the one before them when
+    // going backward
+    let after = if step > 0.0 { visibility } else { previous };
+    let kind = match after {
+        Visibility::Visible => EventKind::BecomingVisible,
+        Visibility::Obscured => EventKind::BecomingObscured,
+        Visibility::OutOfFrame => EventKind::LeavingFrame,
+        Visibility::Transiting => EventKind::StartingTransit,
+    };
+    let date = sim_time_to_utc(event_time);
+    debug!(observer = observer.name(), ?kind, %date, "visibility changed");
+    let state = propagator.propagated(sim, event_time - time);
+    let phase = phase::moon_phase_seen_from(&state, &observer.pos(&state, event_time));
+    Event {
+        observer: observer.name().to_owned(),
+        kind,
+        date,
+        phase_angle: phase.phase_angle,
+        illuminated_fraction: phase.illuminated_fraction,
+    }
+}
+
+/// The time between the Moon entering and leaving an observer's frame.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VisibilityWindow {
+    pub observer: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The events between `start` and `end`, the first and the last one included.
+    pub events: Vec<Event>,
+}
+
+/// Drops the visibilities lasting less than `min_duration` seconds from the events, e.g. the
+/// Moon entering the frame for a moment when it skims its edge. The event starting such a
+/// visibility is left out, and so is the one ending it if it goes back to the visibility from
+/// before.
+pub fn drop_short_visibilities(events: &[Event], min_duration: f64) -> Vec<Event> {
+    let visibility = |kind: EventKind| match kind {
+        EventKind::BecomingVisible => Visibility::Visible,
+        EventKind::BecomingObscured => Visibility::Obscured,
+        EventKind::LeavingFrame => Visibility::OutOfFrame,
+        EventKind::StartingTransit => Visibility::Transiting,
+    };
+    let mut kept: Vec<Event> = vec![];
+    for (i, event) in events.iter().enumerate() {
+        let next = events[i + 1..]
+            .iter()
+            .find(|next| next.observer == event.observer);
+        let short = next.is_some_and(|next| time::seconds(next.date - event.date) < min_duration);
+        let previous = kept
+            .iter()
+            .rev()
+            .find(|prev| prev.observer == event.observer)
+            .map_or(Visibility::OutOfFrame, |prev| visibility(prev.kind));
+        if !short && visibility(event.kind) != previous {
+            kept.push(event.clone());
+        }
+    }
+    kept
+}
+
+/// Summarizes the visibility windows of each observer, in the order they first appear.
+pub fn summarize(windows: &[VisibilityWindow]) -> Vec<VisibilitySummary> {
+    let mut summaries: Vec<VisibilitySummary> = vec![];
+    for window in windows {
+        let summary = match summaries
+            .iter_mut()
+            .position(|summary| summary.observer == window.observer)
+        {
+            Some(idx) => &mut summaries[idx],
+            None => {
+                summaries.push(VisibilitySummary {
+                    observer: window.observer.clone(),
+                    windows: 0,
+                    minutes_visible: 0.0,
+                    longest_window: None,
+                    monthly: vec![],
+                });
+                summaries.last_mut().unwrap()
+            }
+        };
+        let minutes_visible = window_minutes_visible(window);
+        summary.windows += 1;
+        summary.minutes_visible += minutes_visible;
+        let longer = summary
+            .longest_window
+            .is_none_or(|(start, end)| window.end - window.start > end - start);
+        if longer {
+            summary.longest_window = Some((window.start, window.end));
+        }
+
+        // months counted from year 0, filling in the ones without windows
+        let month_index = |year: i32, month: u32| year * 12 + month as i32 - 1;
+        let index = month_index(window.start.year(), window.start.month());
+        let first = summary
+            .monthly
+            .first()
+            .map_or(index, |first| month_index(first.year, first.month));
+        while index >= first + summary.monthly.len() as i32 {
+            let next = first + summary.monthly.len() as i32;
+            summary.monthly.push(MonthlyVisibility {
+                year: next.div_euclid(12),
+                month: next.rem_euclid(12) as u32 + 1,
+                windows: 0,
+                minutes_visible: 0.0,
+            });
+        }
+        let entry = summary.monthly.last_mut().unwrap();
+        entry.windows += 1;
+        entry.minutes_visible += minutes_visible;
+    }
+    summaries
+}
+
+/// The time the Moon was visible or in front of the Earth during `window`, in minutes.
+fn window_minutes_visible(window: &VisibilityWindow) -> f64 {
+    window
+        .events
+        .windows(2)
+        .filter(|pair| {
+            matches!(
+                pair[0].kind,
+                EventKind::BecomingVisible | EventKind::StartingTransit
+            )
+        })
+        .map(|pair| (pair[1].date - pair[0].date).num_milliseconds() as f64 / 60e3)
+        .sum()
+}
+
+/// Groups visibility events into windows, each observer's separately. A window still open at
+/// the end of the events is left out.
+pub fn visibility_windows(events: &[Event]) -> Vec<VisibilityWindow> {
+    let mut windows = vec![];
+    // the events of the window in progress of each observer
+    let mut open: Vec<(&str, Vec<Event>)> = vec![];
+    for event in events {
+        let current = match open
+            .iter_mut()
+            .find(|(observer, _)| *observer == event.observer)
+        {
+            Some((_, current)) => current,
+            None => {
+                open.push((&event.observer, vec![]));
+                &mut open.last_mut().unwrap().1
+            }
+        };
+        current.push(event.clone());
+        if event.kind == EventKind::LeavingFrame {
+            let events = std::mem::take(current);
+            windows.push(VisibilityWindow {
+                observer: event.observer.clone(),
+                start: events[0].date,
+                end: event.date,
+                events,
+            });
+        }
+    }
+    windows
+}
+
+/// A change of the visibility of another body than the Moon for an observer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodyEvent {
+    pub body: String,
+    pub observer: String,
+    /// The visibility after the change.
+    pub visibility: Visibility,
+    pub date: DateTime<Utc>,
+}
+
+/// Propagates `sim` from `start` to `end` (simulation times) and collects the changes of the
+/// visibility of the named body for `observer`, e.g. of a comet added with `elements::load`.
+pub fn body_visibility_events<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    observer: &dyn Observer,
+    body: &str,
+) -> Vec<BodyEvent> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut current = observer::body_visibility(observer, &sim, time, body);
+
+    let mut events = vec![];
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+        let visibility = observer::body_visibility(observer, &sim, time, body);
+        if visibility != current {
+            let event_time = refine::bisect(
+                &propagator,
+                &sim,
+                time,
+                step,
+                REFINE_TOLERANCE,
+                |state, t| observer::body_visibility(observer, state, t, body) != current,
+            );
+            let date = sim_time_to_utc(event_time);
+            debug!(body, ?visibility, %date, "visibility changed");
+            events.push(BodyEvent {
+                body: body.to_owned(),
+                observer: observer.name().to_owned(),
+                visibility,
+                date,
+            });
+            current = visibility;
+        }
+    }
+    events
+}
+
+/// The least angles between the Sun and the boresight of `observer` and between the Sun and the
+/// Moon, sampled every `every` seconds between `start` and `end` (simulation times), `sim`
+/// being the state of the system at `start`. Images taken with the Sun close to either are
+/// spoiled by glint or blooming.
+pub fn sun_proximity<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    every: f64,
+    propagator: P,
+    observer: &dyn Observer,
+) -> SunAngles {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut next = start;
+
+    let mut closest = observer::sun_angles(observer, &sim, start);
+    while next <= end {
+        let step = propagator.dense_step(&mut sim, time);
+        time = step.end_time();
+        while next <= time && next <= end {
+            let state = step.state_at(next);
+            let angles = observer::sun_angles(observer, &state, next);
+            closest.boresight = closest.boresight.min(angles.boresight);
+            closest.moon = closest.moon.min(angles.moon);
+            // the end of the window is always sampled
+            next = if next < end {
+                (next + every).min(end)
+            } else {
+                next + every
+            };
+        }
+    }
+    closest
+}
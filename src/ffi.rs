@@ -0,0 +1,188 @@
+//! A C interface to the propagation and the Moon visibility, declared in
+//! `include/eclipses.h`. Dates are given as UTC Unix times, in seconds. Functions returning
+//! a pointer return null on failure, and those returning an `int` a negative number; the
+//! reason is then given by `eclipses_last_error`.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::{
+    bodies,
+    error::{Error, Result},
+    observer::{self, Visibility},
+    simulation::{Propagator, SimState},
+    snapshots::Snapshots,
+    time::{sim_time_to_utc, utc_to_sim_time},
+};
+
+const STEP: f64 = 300.0;
+
+/// A simulated system and the time of its current state.
+pub struct EclipsesSim {
+    sim: SimState,
+    time: f64,
+    propagator: Propagator,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(err: Error) {
+    let message = CString::new(err.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Boxes the simulation for C, or records the error and returns null.
+fn new_sim(result: Result<(SimState, f64)>) -> *mut EclipsesSim {
+    match result {
+        Ok((sim, time)) => Box::into_raw(Box::new(EclipsesSim {
+            sim,
+            time,
+            propagator: Propagator::new(STEP),
+        })),
+        Err(err) => {
+            set_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn from_unix(time: f64) -> Result<DateTime<Utc>> {
+    // before 1970 `fract` is negative, while the nanoseconds have to count up from the second
+    let seconds = time.floor();
+    let nanos = ((time - seconds) * 1e9).min(999_999_999.0) as u32;
+    Utc.timestamp_opt(seconds as i64, nanos)
+        .single()
+        .ok_or_else(|| Error::Argument(format!("invalid Unix time: {}", time)))
+}
+
+fn to_unix(date: DateTime<Utc>) -> f64 {
+    date.timestamp() as f64 + f64::from(date.timestamp_subsec_nanos()) * 1e-9
+}
+
+/// The message of the last error in this thread, or null if there was none. It stays valid
+/// until the next call failing in the same thread.
+#[no_mangle]
+pub extern "C" fn eclipses_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// The built-in state of the solar system at the simulation epoch.
+#[no_mangle]
+pub extern "C" fn eclipses_sim_new() -> *mut EclipsesSim {
+    new_sim(Ok((bodies::solar_system(), 0.0)))
+}
+
+/// A state loaded from the contents of a snapshot file, which has to contain its time.
+///
+/// # Safety
+///
+/// `bytes` has to point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn eclipses_sim_from_snapshot(
+    bytes: *const u8,
+    len: usize,
+) -> *mut EclipsesSim {
+    if bytes.is_null() {
+        set_error(Error::Argument("no snapshot given".to_owned()));
+        return ptr::null_mut();
+    }
+    let contents = slice::from_raw_parts(bytes, len);
+    let mut snapshots = Snapshots::in_memory();
+    new_sim(snapshots.insert(contents).map(|time| {
+        let (_, sim) = snapshots.get_closest(time).unwrap();
+        (sim.clone(), time)
+    }))
+}
+
+/// Frees a state created by `eclipses_sim_new` or `eclipses_sim_from_snapshot`.
+///
+/// # Safety
+///
+/// `sim` has to be null or a state that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn eclipses_sim_free(sim: *mut EclipsesSim) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+/// The date of the current state.
+///
+/// # Safety
+///
+/// `sim` has to be a valid state.
+#[no_mangle]
+pub unsafe extern "C" fn eclipses_sim_time(sim: *const EclipsesSim) -> f64 {
+    to_unix(sim_time_to_utc((*sim).time))
+}
+
+/// Propagates the state to the given date, forwards or backwards. Returns 0 on success.
+///
+/// # Safety
+///
+/// `sim` has to be a valid state.
+#[no_mangle]
+pub unsafe extern "C" fn eclipses_propagate(sim: *mut EclipsesSim, time: f64) -> c_int {
+    let sim = &mut *sim;
+    match from_unix(time) {
+        Ok(date) => {
+            let target = utc_to_sim_time(date);
+            sim.propagator.advance(&mut sim.sim, target - sim.time);
+            sim.time = target;
+            0
+        }
+        Err(err) => {
+            set_error(err);
+            -1
+        }
+    }
+}
+
+/// How a built-in observer, like `himawari`, sees the Moon at the given date, propagating
+/// the state there: 0 if it's out of the frame, 1 if it's behind the Earth, 2 if it's visible
+/// and 3 if it's in front of the Earth disk.
+///
+/// # Safety
+///
+/// `sim` has to be a valid state and `observer` null or a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn eclipses_moon_visibility(
+    sim: *mut EclipsesSim,
+    observer: *const c_char,
+    time: f64,
+) -> c_int {
+    if observer.is_null() {
+        set_error(Error::Argument("no observer given".to_owned()));
+        return -1;
+    }
+    let name = CStr::from_ptr(observer).to_string_lossy();
+    let observer = match observer::by_name(&name) {
+        Some(observer) => observer,
+        None => {
+            set_error(Error::Argument(format!("unknown observer: {}", name)));
+            return -1;
+        }
+    };
+    if eclipses_propagate(sim, time) != 0 {
+        return -1;
+    }
+    let sim = &*sim;
+    match observer::moon_visibility(&*observer, &sim.sim, sim.time) {
+        Visibility::OutOfFrame => 0,
+        Visibility::Obscured => 1,
+        Visibility::Visible => 2,
+        Visibility::Transiting => 3,
+    }
+}
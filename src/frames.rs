@@ -0,0 +1,221 @@
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+use nalgebra::{Matrix3, Vector3};
+
+use crate::{
+    simulation::SimState,
+    time::{gmst, EPOCH_JD},
+};
+
+/// Obliquity of the ecliptic at J2000.
+pub const OBLIQUITY: f64 = 23.4392911 * PI / 180.0;
+
+const ARCSEC: f64 = PI / 180.0 / 3600.0;
+
+/// Equatorial radius of the WGS-84 ellipsoid, in km.
+pub const EARTH_EQUATORIAL_RADIUS: f64 = 6378.137;
+/// Flattening of the WGS-84 ellipsoid.
+pub const EARTH_FLATTENING: f64 = 1.0 / 298.257223563;
+
+/// Orientation of the coordinate axes a position can be expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// The axes of the simulation: the mean ecliptic and equinox of J2000.
+    Simulation,
+    /// The mean equator and equinox of J2000, which is the ICRF to within a few hundredths of
+    /// an arcsecond.
+    Equatorial,
+    /// The true equator and equinox of date.
+    TrueEquatorial,
+    /// The mean ecliptic and equinox of date.
+    Ecliptic,
+    /// Rotating with the Earth, like the ITRF. Precession and nutation follow IAU 1976/1980,
+    /// with only the largest nutation terms; polar motion is neglected.
+    EarthFixed,
+}
+
+/// Names accepted by `Frame::from_str`.
+pub const NAMES: &[&str] = &[
+    "simulation",
+    "equatorial",
+    "true-equatorial",
+    "ecliptic",
+    "earth-fixed",
+];
+
+impl FromStr for Frame {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simulation" => Ok(Frame::Simulation),
+            "equatorial" => Ok(Frame::Equatorial),
+            "true-equatorial" => Ok(Frame::TrueEquatorial),
+            "ecliptic" => Ok(Frame::Ecliptic),
+            "earth-fixed" => Ok(Frame::EarthFixed),
+            _ => Err(format!("unknown frame: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Frame::Simulation => "J2000 ecliptic",
+            Frame::Equatorial => "J2000 equatorial",
+            Frame::TrueEquatorial => "true equatorial of date",
+            Frame::Ecliptic => "ecliptic of date",
+            Frame::EarthFixed => "Earth-fixed",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Frame {
+    /// Matrix taking vectors from the simulation frame to this one at the given simulation
+    /// time.
+    pub fn rotation(&self, time: f64) -> Matrix3<f64> {
+        let equatorial = rot_x(-OBLIQUITY);
+        match self {
+            Frame::Simulation => Matrix3::identity(),
+            Frame::Equatorial => equatorial,
+            Frame::TrueEquatorial => nutation(time).0 * precession(time) * equatorial,
+            Frame::Ecliptic => rot_x(mean_obliquity(time)) * precession(time) * equatorial,
+            Frame::EarthFixed => {
+                let (nutation, equation_of_equinoxes) = nutation(time);
+                rot_z(gmst(time) + equation_of_equinoxes) * nutation * precession(time) * equatorial
+            }
+        }
+    }
+
+    /// Expresses a vector given in the simulation frame in this one.
+    pub fn from_sim(&self, v: Vector3<f64>, time: f64) -> Vector3<f64> {
+        self.rotation(time) * v
+    }
+
+    /// Expresses a vector given in this frame in the simulation one.
+    pub fn to_sim(&self, v: Vector3<f64>, time: f64) -> Vector3<f64> {
+        self.rotation(time).transpose() * v
+    }
+
+    /// Expresses a velocity given in the simulation frame in this one, including the motion
+    /// due to the rotation of the axes; `pos` is the position the velocity is taken at.
+    pub fn velocity_from_sim(
+        &self,
+        pos: Vector3<f64>,
+        vel: Vector3<f64>,
+        time: f64,
+    ) -> Vector3<f64> {
+        let rotation_rate = (self.rotation(time + 10.0) - self.rotation(time - 10.0)) / 20.0;
+        self.rotation(time) * vel + rotation_rate * pos
+    }
+}
+
+/// Position of the named body relative to `center` in the given frame, `sim` being the state
+/// of the system at `time`.
+pub fn position(
+    sim: &SimState,
+    time: f64,
+    body: &str,
+    center: &str,
+    frame: Frame,
+) -> Option<Vector3<f64>> {
+    let rel = sim.body_by_name(body)?.pos - sim.body_by_name(center)?.pos;
+    Some(frame.from_sim(rel, time))
+}
+
+/// Longitude and latitude of a vector, in radians, with the longitude in [0, 2π): the right
+/// ascension and declination in the equatorial frames.
+pub fn spherical(v: &Vector3<f64>) -> (f64, f64) {
+    let longitude = v.y.atan2(v.x).rem_euclid(2.0 * PI);
+    let latitude = (v.z / v.norm()).asin();
+    (longitude, latitude)
+}
+
+/// Geodetic latitude and longitude of an Earth-fixed position on the WGS-84 ellipsoid, in
+/// radians, with the longitude in (-π, π].
+pub fn geodetic(pos: &Vector3<f64>) -> (f64, f64) {
+    let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+    let latitude = pos.z.atan2((1.0 - e2) * pos.x.hypot(pos.y));
+    (latitude, pos.y.atan2(pos.x))
+}
+
+/// The first point at which the ray from the Earth-fixed `point` (relative to the Earth's
+/// center) along `dir` meets the WGS-84 ellipsoid, or `None` if it misses it.
+pub fn ellipsoid_intersection(point: &Vector3<f64>, dir: &Vector3<f64>) -> Option<Vector3<f64>> {
+    // stretched along the pole, the ellipsoid becomes a sphere of the equatorial radius
+    let stretch = Vector3::new(1.0, 1.0, 1.0 / (1.0 - EARTH_FLATTENING));
+    let p = point.component_mul(&stretch);
+    let d = dir.component_mul(&stretch);
+    let (a, b) = (d.dot(&d), p.dot(&d));
+    let c = p.dot(&p) - EARTH_EQUATORIAL_RADIUS * EARTH_EQUATORIAL_RADIUS;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    (t >= 0.0).then(|| point + dir * t)
+}
+
+pub fn equatorial_to_ecliptic(v: Vector3<f64>) -> Vector3<f64> {
+    rot_x(OBLIQUITY) * v
+}
+
+pub fn ecliptic_to_equatorial(v: Vector3<f64>) -> Vector3<f64> {
+    rot_x(-OBLIQUITY) * v
+}
+
+/// Julian centuries of TT since J2000.
+fn centuries(time: f64) -> f64 {
+    (time / 86400.0 + EPOCH_JD - 2451545.0) / 36525.0
+}
+
+/// Mean obliquity of the ecliptic of date (IAU 1980).
+fn mean_obliquity(time: f64) -> f64 {
+    let t = centuries(time);
+    OBLIQUITY + (-46.8150 * t - 0.00059 * t * t + 0.001813 * t * t * t) * ARCSEC
+}
+
+/// Precession from the mean equator and equinox of J2000 to those of date (IAU 1976).
+fn precession(time: f64) -> Matrix3<f64> {
+    let t = centuries(time);
+    let zeta = (2306.2181 * t + 0.30188 * t * t + 0.017998 * t * t * t) * ARCSEC;
+    let z = (2306.2181 * t + 1.09468 * t * t + 0.018203 * t * t * t) * ARCSEC;
+    let theta = (2004.3109 * t - 0.42665 * t * t - 0.041833 * t * t * t) * ARCSEC;
+    rot_z(-z) * rot_y(theta) * rot_z(-zeta)
+}
+
+/// Nutation from the mean equator and equinox of date to the true ones, with the four largest
+/// terms of the IAU 1980 series, and the equation of the equinoxes.
+fn nutation(time: f64) -> (Matrix3<f64>, f64) {
+    let t = centuries(time);
+    let node = (125.04452 - 1934.136261 * t).to_radians();
+    let sun = (280.4665 + 36000.7698 * t).to_radians();
+    let moon = (218.3165 + 481267.8813 * t).to_radians();
+    let d_psi = (-17.20 * node.sin() - 1.32 * (2.0 * sun).sin() - 0.23 * (2.0 * moon).sin()
+        + 0.21 * (2.0 * node).sin())
+        * ARCSEC;
+    let d_eps = (9.20 * node.cos() + 0.57 * (2.0 * sun).cos() + 0.10 * (2.0 * moon).cos()
+        - 0.09 * (2.0 * node).cos())
+        * ARCSEC;
+    let eps = mean_obliquity(time);
+    let matrix = rot_x(-(eps + d_eps)) * rot_z(-d_psi) * rot_x(eps);
+    (matrix, d_psi * (eps + d_eps).cos())
+}
+
+// Rotations of the coordinate axes by `angle` around x, y and z.
+
+fn rot_x(angle: f64) -> Matrix3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    Matrix3::new(1.0, 0.0, 0.0, 0.0, cos, sin, 0.0, -sin, cos)
+}
+
+fn rot_y(angle: f64) -> Matrix3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    Matrix3::new(cos, 0.0, -sin, 0.0, 1.0, 0.0, sin, 0.0, cos)
+}
+
+fn rot_z(angle: f64) -> Matrix3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    Matrix3::new(cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0)
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::eclipse::{Eclipse, EclipsePath, GroundPoint, SolarEclipse};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct FeatureCollection<P> {
+    pub features: Vec<Feature<P>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct Feature<P> {
+    pub properties: P,
+    pub geometry: Geometry,
+}
+
+/// Positions are `[longitude, latitude]` in degrees.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum Geometry {
+    MultiLineString(Vec<Vec<[f64; 2]>>),
+}
+
+/// Describes a line of an eclipse path.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathProperties {
+    /// `central`, `northern limit` or `southern limit`.
+    pub line: &'static str,
+    pub kind: Eclipse,
+    pub greatest: DateTime<Utc>,
+    pub gamma: f64,
+    pub magnitude: f64,
+    pub obscuration: f64,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// The central lines and the limits of the eclipse paths, as a feature per line.
+pub fn eclipse_paths(paths: &[(SolarEclipse, EclipsePath)]) -> FeatureCollection<PathProperties> {
+    let mut features = vec![];
+    for (eclipse, path) in paths {
+        let lines = [
+            ("central", &path.central),
+            ("northern limit", &path.northern_limit),
+            ("southern limit", &path.southern_limit),
+        ];
+        for (line, points) in lines {
+            let (first, last) = match (points.first(), points.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => continue,
+            };
+            features.push(Feature {
+                properties: PathProperties {
+                    line,
+                    kind: eclipse.kind,
+                    greatest: eclipse.greatest,
+                    gamma: eclipse.gamma,
+                    magnitude: eclipse.magnitude,
+                    obscuration: eclipse.obscuration,
+                    start: first.date,
+                    end: last.date,
+                },
+                geometry: Geometry::MultiLineString(split_at_antimeridian(points)),
+            });
+        }
+    }
+    FeatureCollection { features }
+}
+
+/// Splits a line into parts not crossing the antimeridian, as RFC 7946 recommends.
+fn split_at_antimeridian(points: &[GroundPoint]) -> Vec<Vec<[f64; 2]>> {
+    let mut parts = vec![vec![]];
+    for (i, point) in points.iter().enumerate() {
+        if let Some(previous) = i.checked_sub(1).map(|i| points[i]) {
+            let jump = point.longitude - previous.longitude;
+            if jump.abs() > 180.0 {
+                // where the line crosses, going the short way around
+                let edge = if jump > 0.0 { -180.0 } else { 180.0 };
+                let unwrapped = point.longitude - 360.0 * jump.signum();
+                let fraction = (edge - previous.longitude) / (unwrapped - previous.longitude);
+                let latitude = previous.latitude + (point.latitude - previous.latitude) * fraction;
+                parts.last_mut().unwrap().push([edge, latitude]);
+                parts.push(vec![[-edge, latitude]]);
+            }
+        }
+        parts
+            .last_mut()
+            .unwrap()
+            .push([point.longitude, point.latitude]);
+    }
+    parts
+}
@@ -0,0 +1,283 @@
+use std::{fs, path::Path};
+
+use nalgebra::Vector3;
+
+use crate::{
+    bodies,
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    simulation::{Body, SimState},
+    time::{jd_to_sim_time, tdb_minus_tt, EPOCH_JD},
+};
+
+const AU: f64 = 149_597_870.7;
+const DAY: f64 = 86400.0;
+
+/// A state vector of a JPL Horizons vector table, converted to km, km/s and the
+/// ecliptic J2000 frame.
+#[derive(Debug, Clone)]
+pub struct StateVector {
+    pub name: String,
+    pub center: String,
+    /// Julian date (TDB) of the vector.
+    pub jd: f64,
+    pub pos: Vector3<f64>,
+    pub vel: Vector3<f64>,
+    /// GM and radius, if Horizons listed them in the physical data header.
+    pub gm: Option<f64>,
+    pub radius: Option<f64>,
+}
+
+/// Parses the output of a Horizons VECTORS request, in either the plain text or the CSV
+/// format, returning its first state vector.
+pub fn parse(text: &str) -> Result<StateVector> {
+    parse_table(text).map(|mut vectors| vectors.swap_remove(0))
+}
+
+/// Parses all the state vectors of a Horizons vector table, like `parse`.
+pub fn parse_table(text: &str) -> Result<Vec<StateVector>> {
+    let (header, rest) =
+        split_once(text, "$$SOE").ok_or_else(|| Error::Horizons("no $$SOE marker".into()))?;
+    let (data, _) = split_once(rest, "$$EOE").unwrap_or((rest, ""));
+
+    let name = header_field(header, "Target body name:")
+        .ok_or_else(|| Error::Horizons("no target body name".into()))?;
+    let name = name
+        .split(" (")
+        .next()
+        .unwrap_or(&name)
+        .trim_end_matches(" Barycenter")
+        .to_owned();
+    let center = header_field(header, "Center body name:").unwrap_or_default();
+    let units = header_field(header, "Output units").unwrap_or_default();
+    // depending on the Horizons version, the plane is given as the frame or separately
+    let ecliptic = ["Reference frame", "Reference plane", "Coordinate system"]
+        .iter()
+        .filter_map(|key| header_field(header, key))
+        .any(|value| value.to_lowercase().contains("ecliptic"));
+
+    let mut gm = None;
+    let mut radius = None;
+    for (key, value) in header.lines().flat_map(key_values) {
+        let key = key.to_lowercase();
+        if gm.is_none() && key.contains("gm") && key.contains("km^3/s^2") && !key.contains("sigma")
+        {
+            gm = parse_number(&value);
+        } else if radius.is_none() && key.contains("mean radius") && key.contains("km") {
+            radius = parse_number(&value);
+        }
+    }
+
+    let mut vectors = vec![];
+    let mut lines = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    while let Some(first) = lines.next() {
+        let jd = parse_number(first.split(&[',', ' '][..]).next().unwrap_or(""))
+            .ok_or_else(|| Error::Horizons(format!("bad date line: {}", first)))?;
+
+        let components = if first.contains(',') {
+            first
+                .split(',')
+                .skip(2)
+                .take(6)
+                .map(parse_number)
+                .collect::<Option<Vec<_>>>()
+        } else {
+            // the components follow on the next lines as "X =... Y =... Z =..."
+            let mut record = vec![];
+            while let Some(line) =
+                lines.next_if(|line| !line.contains("A.D.") && !line.contains("B.C."))
+            {
+                record.push(line);
+            }
+            let pairs = key_values(&record.join(" "));
+            ["X", "Y", "Z", "VX", "VY", "VZ"]
+                .iter()
+                .map(|key| {
+                    pairs
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .and_then(|(_, v)| parse_number(v))
+                })
+                .collect::<Option<Vec<_>>>()
+        }
+        .filter(|components| components.len() == 6)
+        .ok_or_else(|| Error::Horizons("incomplete state vector".into()))?;
+
+        let mut pos = Vector3::new(components[0], components[1], components[2]);
+        let mut vel = Vector3::new(components[3], components[4], components[5]);
+        if units.contains("AU") {
+            pos *= AU;
+            vel *= AU;
+        }
+        if units.contains("-D") {
+            vel /= DAY;
+        }
+        if !ecliptic {
+            pos = equatorial_to_ecliptic(pos);
+            vel = equatorial_to_ecliptic(vel);
+        }
+
+        vectors.push(StateVector {
+            name: name.clone(),
+            center: center.clone(),
+            jd,
+            pos,
+            vel,
+            gm,
+            radius,
+        });
+    }
+    if vectors.is_empty() {
+        return Err(Error::Horizons("empty vector table".into()));
+    }
+    Ok(vectors)
+}
+
+/// Horizons vector tables of any number of bodies, one file per body, with the states in
+/// between their rows interpolated.
+pub struct VectorTables {
+    tables: Vec<Vec<StateVector>>,
+}
+
+impl VectorTables {
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let tables = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                fs::read_to_string(path)
+                    .map_err(Error::from)
+                    .and_then(|text| parse_table(&text))
+                    .map(|mut vectors| {
+                        vectors.sort_by(|a, b| a.jd.total_cmp(&b.jd));
+                        vectors
+                    })
+                    .map_err(|err| err.in_file(path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { tables })
+    }
+
+    /// Position and velocity of the named body at the given simulation time, relative to the
+    /// center of its table, by cubic Hermite interpolation between the nearest rows.
+    pub fn state(&self, name: &str, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        let table = self
+            .tables
+            .iter()
+            .find(|table| table[0].name == name)
+            .ok_or_else(|| Error::Horizons(format!("no table of {}", name)))?;
+        let jd = EPOCH_JD + (time + tdb_minus_tt(time)) / DAY;
+        let next = table.partition_point(|vector| vector.jd < jd);
+        if next == 0 || next == table.len() {
+            match table.get(next) {
+                Some(vector) if vector.jd == jd => return Ok((vector.pos, vector.vel)),
+                _ => {
+                    return Err(Error::Horizons(format!(
+                        "JD {} is outside of the table of {}",
+                        jd, name
+                    )))
+                }
+            }
+        }
+        let (a, b) = (&table[next - 1], &table[next]);
+        let h = (b.jd - a.jd) * DAY;
+        let s = (jd - a.jd) / (b.jd - a.jd);
+        let (s2, s3) = (s * s, s * s * s);
+        let pos = a.pos * (2.0 * s3 - 3.0 * s2 + 1.0)
+            + a.vel * h * (s3 - 2.0 * s2 + s)
+            + b.pos * (3.0 * s2 - 2.0 * s3)
+            + b.vel * h * (s3 - s2);
+        let vel = (a.pos - b.pos) * (6.0 * s2 - 6.0 * s) / h
+            + a.vel * (3.0 * s2 - 4.0 * s + 1.0)
+            + b.vel * (3.0 * s2 - 2.0 * s);
+        Ok((pos, vel))
+    }
+}
+
+/// Builds the initial state from Horizons vector tables, one file per body. Bodies whose GM or
+/// radius isn't given in the file take them from the built-in solar system; bodies unknown to
+/// it are massless. Returns the state along with the simulation time it corresponds to.
+pub fn load<P: AsRef<Path>>(paths: &[P]) -> Result<(SimState, f64)> {
+    let defaults = bodies::solar_system();
+    let mut sim = SimState::new();
+    let mut epoch: Option<(f64, String)> = None;
+
+    for path in paths {
+        let path = path.as_ref();
+        let vector = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|text| parse(&text))
+            .map_err(|err| err.in_file(path))?;
+        match &epoch {
+            Some((jd, center)) if (jd - vector.jd).abs() > 1e-9 || *center != vector.center => {
+                let err =
+                    Error::Horizons("epoch or center body differs from the other files".into());
+                return Err(err.in_file(path));
+            }
+            Some(_) => (),
+            None => epoch = Some((vector.jd, vector.center.clone())),
+        }
+
+        // bodies not known otherwise, like spacecraft, are propagated as test particles
+        let default = defaults.body_by_name(&vector.name);
+        let gm = vector.gm.or_else(|| default.map(|body| body.gm));
+        let radius = vector.radius.or_else(|| default.map(|body| body.radius));
+        sim = sim.with_body(Body {
+            name: vector.name,
+            gm: gm.unwrap_or(0.0),
+            pos: vector.pos,
+            vel: vector.vel,
+            radius: radius.unwrap_or(0.0),
+            oblateness: default.and_then(|body| body.oblateness),
+        });
+    }
+
+    let (jd, _) = epoch.ok_or_else(|| Error::Horizons("no files given".into()))?;
+    for name in bodies::REQUIRED {
+        if sim.body_by_name(name).is_none() {
+            return Err(Error::MissingBody(name.to_string()));
+        }
+    }
+    Ok((sim, jd_to_sim_time(jd)))
+}
+
+fn split_once<'a>(text: &'a str, marker: &str) -> Option<(&'a str, &'a str)> {
+    text.find(marker)
+        .map(|idx| (&text[..idx], &text[idx + marker.len()..]))
+}
+
+pub(crate) fn header_field(header: &str, key: &str) -> Option<String> {
+    header
+        .lines()
+        .find_map(|line| split_once(line, key))
+        .map(|(_, value)| {
+            let value = value.trim_start_matches(&[':', ' '][..]);
+            // drop the "{source: ...}" annotation
+            value.split('{').next().unwrap_or("").trim().to_owned()
+        })
+}
+
+/// Splits a line like "X =-1.0E+06 Y = 2.0E+05" into key-value pairs.
+pub(crate) fn key_values(line: &str) -> Vec<(String, String)> {
+    let mut result = vec![];
+    let mut rest = line;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_owned();
+        let after = rest[eq + 1..].trim_start();
+        let value_len = after.find(char::is_whitespace).unwrap_or(after.len());
+        result.push((key, after[..value_len].to_owned()));
+        rest = &after[value_len..];
+    }
+    result
+}
+
+/// Parses the leading number of a value such as "6371.01+-0.02".
+fn parse_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value.split("+-").next().unwrap_or(value);
+    value.parse().ok()
+}
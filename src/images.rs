@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{debug, debug_span};
+
+use crate::{
+    libration::{self, Libration},
+    observer::{self, FramePosition, Observer, PixelPosition},
+    simulation::{Propagator, SimState},
+    time::{self, sim_time_to_utc, utc_to_sim_time},
+};
+
+/// When a full disk imager takes its images: every `interval` seconds, counted from `offset`
+/// seconds after midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub interval: f64,
+    pub offset: f64,
+}
+
+impl Schedule {
+    /// Himawari's full disk observations start every 10 minutes, on the hour.
+    pub const HIMAWARI: Schedule = Schedule {
+        interval: 600.0,
+        offset: 0.0,
+    };
+
+    /// The first image taken at or after `date`.
+    pub fn next_image(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+        let unix_epoch = DateTime::<Utc>::UNIX_EPOCH;
+        let seconds = time::seconds(date - unix_epoch);
+        let index = ((seconds - self.offset) / self.interval).ceil();
+        unix_epoch + time::duration(index * self.interval + self.offset)
+    }
+}
+
+/// An image containing the Moon.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Image {
+    pub observer: String,
+    /// The nominal time of the image.
+    pub date: DateTime<Utc>,
+    /// Where in the frame the Moon is.
+    pub moon: FramePosition,
+    /// Where in the image the Moon is, if the observer's sensor is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels: Option<PixelPosition>,
+    /// Which side of the Moon the observer sees.
+    pub libration: Libration,
+}
+
+/// Finds the images taken by `observer` according to `schedule` between `start` and `end`
+/// (simulation times) that contain the Moon, `sim` being the state of the system at `start`.
+pub fn moon_images<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    observer: &dyn Observer,
+    schedule: Schedule,
+) -> Vec<Image> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut next_date = schedule.next_image(sim_time_to_utc(start));
+    let mut next = utc_to_sim_time(next_date);
+    let interval = time::duration(schedule.interval);
+    let _span = debug_span!("image_search", observer = observer.name()).entered();
+
+    let mut images = vec![];
+    while time < end {
+        let step = propagator.dense_step(&mut sim, time);
+        time = step.end_time();
+        // the images taken during the last step, interpolated within it
+        while next <= time && next <= end {
+            let state = step.state_at(next);
+            if let Some(moon) = observer::moon_frame_position(observer, &state, next) {
+                debug!(date = %next_date, x = moon.x, y = moon.y, "Moon in image");
+                images.push(Image {
+                    observer: observer.name().to_owned(),
+                    date: next_date,
+                    moon,
+                    pixels: observer::moon_pixel_position(observer, &state, next),
+                    libration: libration::libration(&state, next, &observer.pos(&state, next)),
+                });
+            }
+            next_date += interval;
+            next = utc_to_sim_time(next_date);
+        }
+    }
+
+    images
+}
@@ -0,0 +1,156 @@
+use std::{fmt, str::FromStr};
+
+use nalgebra::Vector3;
+
+use crate::simulation::SimState;
+
+/// A pair of bodies whose Lagrange points are computed. The Sun's partner is the Earth-Moon
+/// barycenter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum System {
+    SunEarth,
+    EarthMoon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Point {
+    L1,
+    L2,
+    L3,
+    L4,
+    L5,
+}
+
+/// A Lagrange point of a system, named like `sun-earth-l2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LagrangePoint {
+    pub system: System,
+    pub point: Point,
+}
+
+/// Names accepted by `LagrangePoint::from_str`.
+pub const NAMES: &[&str] = &[
+    "sun-earth-l1",
+    "sun-earth-l2",
+    "sun-earth-l3",
+    "sun-earth-l4",
+    "sun-earth-l5",
+    "earth-moon-l1",
+    "earth-moon-l2",
+    "earth-moon-l3",
+    "earth-moon-l4",
+    "earth-moon-l5",
+];
+
+impl FromStr for LagrangePoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (system, point) = s
+            .rsplit_once('-')
+            .ok_or_else(|| format!("unknown Lagrange point: {}", s))?;
+        let system = match system {
+            "sun-earth" => System::SunEarth,
+            "earth-moon" => System::EarthMoon,
+            _ => return Err(format!("unknown Lagrange point: {}", s)),
+        };
+        let point = match point {
+            "l1" => Point::L1,
+            "l2" => Point::L2,
+            "l3" => Point::L3,
+            "l4" => Point::L4,
+            "l5" => Point::L5,
+            _ => return Err(format!("unknown Lagrange point: {}", s)),
+        };
+        Ok(LagrangePoint { system, point })
+    }
+}
+
+impl fmt::Display for LagrangePoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let system = match self.system {
+            System::SunEarth => "Sun-Earth",
+            System::EarthMoon => "Earth-Moon",
+        };
+        write!(f, "{} {:?}", system, self.point)
+    }
+}
+
+/// Axes of the frame rotating with a two-body system: `x` from the primary to the secondary,
+/// `z` along the orbital angular momentum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotatingFrame {
+    pub barycenter: Vector3<f64>,
+    /// Distance between the bodies, in km.
+    pub distance: f64,
+    /// Mass of the secondary over the total mass.
+    pub mass_ratio: f64,
+    pub x: Vector3<f64>,
+    pub y: Vector3<f64>,
+    pub z: Vector3<f64>,
+}
+
+impl RotatingFrame {
+    pub fn new(sim: &SimState, system: System) -> Self {
+        let earth = sim.body_by_name("Earth").unwrap();
+        let moon = sim.body_by_name("Moon").unwrap();
+        let (primary, secondary) = match system {
+            System::SunEarth => {
+                let sun = sim.body_by_name("Sun").unwrap();
+                let gm = earth.gm + moon.gm;
+                let pos = (earth.pos * earth.gm + moon.pos * moon.gm) / gm;
+                let vel = (earth.vel * earth.gm + moon.vel * moon.gm) / gm;
+                ((sun.gm, sun.pos, sun.vel), (gm, pos, vel))
+            }
+            System::EarthMoon => (
+                (earth.gm, earth.pos, earth.vel),
+                (moon.gm, moon.pos, moon.vel),
+            ),
+        };
+        let (gm1, pos1, vel1) = primary;
+        let (gm2, pos2, vel2) = secondary;
+        let separation = pos2 - pos1;
+        let x = separation.normalize();
+        let z = separation.cross(&(vel2 - vel1)).normalize();
+        Self {
+            barycenter: (pos1 * gm1 + pos2 * gm2) / (gm1 + gm2),
+            distance: separation.norm(),
+            mass_ratio: gm2 / (gm1 + gm2),
+            x,
+            y: z.cross(&x),
+            z,
+        }
+    }
+}
+
+/// Position of a Lagrange point in the simulation frame. The bodies are treated as if they
+/// were on circular orbits at their current separation.
+pub fn position(sim: &SimState, point: LagrangePoint) -> Vector3<f64> {
+    let frame = RotatingFrame::new(sim, point.system);
+    let mu = frame.mass_ratio;
+    let (x, y) = match point.point {
+        Point::L1 => (collinear(mu, 1.0 - mu - (mu / 3.0).cbrt()), 0.0),
+        Point::L2 => (collinear(mu, 1.0 - mu + (mu / 3.0).cbrt()), 0.0),
+        Point::L3 => (collinear(mu, -1.0 - 5.0 * mu / 12.0), 0.0),
+        Point::L4 => (0.5 - mu, 0.75f64.sqrt()),
+        Point::L5 => (0.5 - mu, -0.75f64.sqrt()),
+    };
+    frame.barycenter + (frame.x * x + frame.y * y) * frame.distance
+}
+
+/// Solves for a collinear point on the x axis of the rotating frame, in units of the
+/// separation, with Newton's method starting from `guess`.
+fn collinear(mu: f64, guess: f64) -> f64 {
+    let mut x = guess;
+    for _ in 0..20 {
+        let (r1, r2) = (x + mu, x - 1.0 + mu);
+        let f = x - (1.0 - mu) * r1 / r1.abs().powi(3) - mu * r2 / r2.abs().powi(3);
+        let df = 1.0 + 2.0 * (1.0 - mu) / r1.abs().powi(3) + 2.0 * mu / r2.abs().powi(3);
+        let dx = f / df;
+        x -= dx;
+        if dx.abs() < 1e-15 {
+            break;
+        }
+    }
+    x
+}
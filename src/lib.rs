@@ -0,0 +1,63 @@
+//! Propagation of the solar system, and predictions of eclipses and of the Moon's visibility
+//! computed from it.
+//!
+//! Without the default features, the library builds for `wasm32-unknown-unknown`, e.g. with
+//! `cargo build --lib --target wasm32-unknown-unknown --no-default-features`. Propagating
+//! doesn't touch the file system there, and the snapshots can be given as the contents of
+//! their files with `Snapshots::in_memory` and `Snapshots::insert`.
+//!
+//! The `simd` feature, computing the forces with `std::simd`, needs a nightly compiler. The
+//! `double-double` feature propagates with about twice as many significant digits, and more
+//! slowly. The `gpu` feature computes the forces on thousands of test particles on a GPU that
+//! supports f64 shaders, falling back to the CPU without one.
+
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod apsides;
+pub mod bodies;
+pub mod cache;
+pub mod canon;
+pub mod catalog;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod de;
+pub mod eclipse;
+pub mod elements;
+pub mod ephemeris;
+pub mod error;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod frames;
+pub mod geojson;
+pub mod horizons;
+pub mod images;
+pub mod lagrange;
+pub mod libration;
+pub mod manifest;
+pub mod observer;
+pub mod output;
+pub mod phase;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod position;
+pub mod query;
+pub mod refine;
+pub mod rise;
+pub mod separation;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod simulation;
+pub mod snapshots;
+pub mod spk;
+pub mod time;
+pub mod trajectory;
+
+pub use eclipse::{Eclipse, EclipseDetector, LunarEclipse};
+pub use error::{Error, Result};
+pub use simulation::{
+    Body, DenseStep, IntegratorKind, Oblateness, Propagator, SimDerivative, SimState, StepControl,
+    SymplecticIntegrator,
+};
@@ -0,0 +1,75 @@
+use nalgebra::Vector3;
+use serde::Serialize;
+
+use crate::{
+    frames::{self, Frame},
+    simulation::SimState,
+    time::EPOCH_JD,
+};
+
+/// Inclination of the mean lunar equator to the ecliptic, in degrees.
+const LUNAR_EQUATOR_INCLINATION: f64 = 1.54242;
+
+/// Which side of the Moon an observer sees, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Libration {
+    /// Selenographic longitude of the point of the Moon's surface closest to the observer,
+    /// east positive, in (-180, 180].
+    pub longitude: f64,
+    /// Selenographic latitude of that point.
+    pub latitude: f64,
+    /// Position angle of the Moon's north pole, from the celestial north towards the east.
+    pub position_angle: f64,
+}
+
+/// The longitude of the mean ascending node of the Moon's orbit and the mean argument of
+/// latitude of the Moon on the ecliptic of date, in radians.
+fn node_and_argument_of_latitude(time: f64) -> (f64, f64) {
+    let t = (time / 86400.0 + EPOCH_JD - 2451545.0) / 36525.0;
+    let node = 125.0445479 - 1934.1362891 * t + 0.0020754 * t * t;
+    let argument = 93.2720950 + 483202.0175233 * t - 0.0036539 * t * t;
+    (node.to_radians(), argument.to_radians())
+}
+
+/// The optical libration of the Moon seen from `observer_pos` at the simulation time `time`.
+/// The Moon rotates uniformly with its equator following Cassini's laws; the physical
+/// libration, below 0.04°, is neglected.
+pub fn libration(sim: &SimState, time: f64, observer_pos: &Vector3<f64>) -> Libration {
+    let moon = sim.body_by_name("Moon").unwrap().pos - observer_pos;
+    let (node, argument) = node_and_argument_of_latitude(time);
+    let inclination = LUNAR_EQUATOR_INCLINATION.to_radians();
+
+    let (lon, lat) = frames::spherical(&Frame::Ecliptic.from_sim(moon, time));
+    let w = lon - node;
+    let a = (w.sin() * lat.cos() * inclination.cos() - lat.sin() * inclination.sin())
+        .atan2(w.cos() * lat.cos());
+    let latitude = (-w.sin() * lat.cos() * inclination.sin() - lat.sin() * inclination.cos())
+        .asin()
+        .to_degrees();
+    let longitude = (a - argument).to_degrees().rem_euclid(360.0);
+    let longitude = if longitude > 180.0 {
+        longitude - 360.0
+    } else {
+        longitude
+    };
+
+    // by Cassini's laws the pole is 90° ahead of the node of the orbit
+    let pole = Vector3::new(
+        -inclination.sin() * node.sin(),
+        inclination.sin() * node.cos(),
+        inclination.cos(),
+    );
+    let pole = Frame::TrueEquatorial.from_sim(Frame::Ecliptic.to_sim(pole, time), time);
+    let dir = Frame::TrueEquatorial.from_sim(moon, time).normalize();
+    let east = Vector3::z().cross(&dir).normalize();
+    let north = dir.cross(&east);
+    Libration {
+        longitude,
+        latitude,
+        position_angle: pole
+            .dot(&east)
+            .atan2(pole.dot(&north))
+            .to_degrees()
+            .rem_euclid(360.0),
+    }
+}
@@ -1,316 +1,3295 @@
-mod simulation;
-
-use std::str::FromStr;
+use std::{
+    env,
+    f64::consts::PI,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
 
-use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
-use nalgebra::Vector3;
-use numeric_algs::symplectic::{
-    integration::{Integrator, StepSize, SuzukiIntegrator},
-    State, StateDerivative,
+use chrono::{DateTime, Utc};
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    ArgAction, Args, Parser, Subcommand,
+};
+#[cfg(feature = "sqlite")]
+use eclipses::database::EventDatabase;
+use eclipses::{
+    apsides, bodies,
+    cache::{self, Cache},
+    canon, catalog,
+    de::DeEphemeris,
+    eclipse::{self, Syzygy},
+    elements, ephemeris,
+    events::{self, BodyEvent, Checkpoint, Event},
+    frames::{self, Frame},
+    geojson, horizons,
+    images::{self, Schedule},
+    lagrange::{self, LagrangePoint},
+    manifest::{self, FileChecksum, Manifest},
+    observer::{
+        self, GroundStation, HaloOrbit, LagrangeObserver, Observer, TleSatellite, Visibility,
+    },
+    output::{self, Format},
+    phase, position,
+    query::{self, Query, QueryEvent},
+    rise::{self, Twilight},
+    separation::{self, Conjunction, Viewpoint},
+    simulation::INTEGRATOR_NAMES,
+    snapshots::{self, Snapshots},
+    spk::Spk,
+    time::{self, sim_time_to_utc, utc_to_sim_time},
+    trajectory::Trajectory,
+    Error, IntegratorKind, Propagator, Result, SimState, StepControl,
 };
-use simulation::{Body, SimState};
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
 
-const STEP: f64 = 300.0;
 const YEAR: f64 = 365.25 * 24.0 * 3600.0;
+/// How far into the next part the results of a part of a sharded search are kept, in seconds;
+/// longer than any visibility window or eclipse.
+const SHARD_OVERLAP: f64 = 86400.0;
+
+/// Predicts eclipses and Moon appearances in satellite imagery
+#[derive(Parser)]
+#[command(name = "eclipses")]
+struct Cli {
+    #[command(flatten)]
+    model: ModelOptions,
+    #[command(flatten)]
+    period: PeriodOptions,
+    #[command(flatten)]
+    snapshots: SnapshotOptions,
+    /// Output format; csv is only supported by `predict`, `position` and `trajectory`, ics only
+    /// by `predict`
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        default_value = "text",
+        value_parser = PossibleValuesParser::new(output::FORMAT_NAMES)
+            .map(|format| format.parse::<Format>().unwrap()),
+    )]
+    output: Format,
+    /// Reuse the results of queries run before with the same options and model, saving them in
+    /// this directory
+    #[arg(long, global = true, value_name = "DIR")]
+    cache: Option<PathBuf>,
+    /// Also write a manifest of the run to this file: the arguments, the model, the snapshot
+    /// files and a checksum of the output
+    #[arg(long, global = true, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+    /// Run again with the arguments recorded in this manifest, ignoring the others, and check
+    /// that the model, the snapshots and the output are the same
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "manifest")]
+    verify_manifest: Option<PathBuf>,
+    /// Keep the found events in this SQLite database, and only search the parts of the period
+    /// that haven't been searched with it yet
+    #[cfg(feature = "sqlite")]
+    #[arg(long, global = true, value_name = "FILE")]
+    database: Option<PathBuf>,
+    /// Don't show the progress of long propagations
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    /// Log what the simulation is doing: -v for info, -vv for debug, -vvv for every step;
+    /// RUST_LOG takes precedence if set
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Lists lunar eclipses if no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Eclipse {
-    PenumbralLunar,
-    PartialLunar,
-    TotalLunar,
-    PartialSolar,
-    TotalSolar,
-    AnnularSolar,
+/// Options of the simulated model.
+#[derive(Args)]
+#[command(next_help_heading = "Model options")]
+struct ModelOptions {
+    /// Load the simulated bodies from a TOML or JSON file
+    #[arg(long, global = true, value_name = "FILE")]
+    bodies: Option<PathBuf>,
+    /// Build the initial state from JPL Horizons vector tables, one file per body
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "bodies")]
+    horizons: Vec<PathBuf>,
+    /// Integration step, e.g. 30s or 5min; at most --max-step
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        default_value = "300s",
+        conflicts_with = "adaptive"
+    )]
+    step: f64,
+    /// Use adaptive steps keeping the estimated position error per step below this value
+    #[arg(long, global = true, value_name = "KM")]
+    adaptive: Option<f64>,
+    /// Longest step, fixed or adaptive, e.g. 30min; events shorter than this can be missed
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        default_value = "30min"
+    )]
+    max_step: f64,
+    /// Integrator to propagate the bodies with
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        default_value = "suzuki",
+        value_parser = PossibleValuesParser::new(INTEGRATOR_NAMES)
+            .map(|name| name.parse::<IntegratorKind>().unwrap()),
+    )]
+    integrator: IntegratorKind,
+    /// Include the Earth's oblateness (J2) in its gravity field
+    #[arg(long, global = true)]
+    j2: bool,
+    /// Add the asteroids or comets whose orbital elements are in this file, in the MPC's
+    /// formats or as a JPL Horizons ELEMENTS table; can be repeated
+    #[arg(long, global = true, value_name = "FILE")]
+    elements: Vec<PathBuf>,
+    /// Add bodies from the built-in catalog, or the J2 terms of the other simulated bodies with
+    /// `oblateness`; can be repeated, before the command, whose own --include is different
+    #[arg(
+        long,
+        value_name = "GROUP",
+        value_parser = PossibleValuesParser::new(catalog::GROUPS),
+    )]
+    include: Vec<String>,
+    /// Include the post-Newtonian correction of the Sun's gravity
+    #[arg(long, global = true)]
+    relativistic: bool,
+    /// Radius of the Sun's disk casting the shadows, in km: 696000 by default, like in NASA's
+    /// canons, 695700 for the photosphere, or a few hundred km more for the edge of the
+    /// limb-darkened disk seen in eclipses, e.g. 696342; moves the contacts by seconds
+    #[arg(long, global = true, value_name = "KM")]
+    sun_radius: Option<f64>,
+    /// Raise the bodies seen from ground stations by the refraction of a standard atmosphere,
+    /// which makes them rise a few minutes earlier and set later
+    #[arg(long, global = true)]
+    refraction: bool,
+    /// Move the barycenter of the initial state to the origin and stop its motion
+    #[arg(long, global = true)]
+    recenter: bool,
+    /// Also move the barycenter back to the origin at this interval, e.g. 1year
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        requires = "recenter"
+    )]
+    recenter_every: Option<f64>,
+    /// Reset the bodies to their states in this reference ephemeris at --anchor-every: an SPK
+    /// kernel, DE files or Horizons vector tables centered on the solar system barycenter;
+    /// can be repeated
+    #[arg(long, global = true, value_name = "FILE")]
+    anchor: Vec<PathBuf>,
+    /// How often to reset the bodies to --anchor
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        default_value = "30days",
+        requires = "anchor"
+    )]
+    anchor_every: f64,
+    /// Add up the integration steps with compensated (Kahan) summation, which keeps the
+    /// round-off from building up over millions of steps at the cost of a few percent
+    #[arg(long, global = true)]
+    high_precision: bool,
+    /// Number of threads computing the forces; all cores by default
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<usize>,
+    /// Split the searched period into this many parts at the starts of months and search them
+    /// in parallel; the states they start from are taken from --snapshot-dir if given
+    #[arg(long, global = true, value_name = "N", default_value_t = 1)]
+    shards: usize,
 }
 
-struct EclipseDetector {
-    light_dirs: Vec<(f64, Vector3<f64>)>,
+/// The period the commands compute things in.
+#[derive(Args)]
+#[command(next_help_heading = "Period options")]
+struct PeriodOptions {
+    /// Start of the computed period (UTC), the simulation start by default
+    #[arg(
+        long,
+        global = true,
+        value_name = "DATE",
+        value_parser = parse_date,
+        allow_hyphen_values = true
+    )]
+    from: Option<f64>,
+    /// End of the computed period (UTC); 23 years after the start for `predict`, `eclipse` and
+    /// `validate`, a year after it otherwise
+    #[arg(
+        long,
+        global = true,
+        value_name = "DATE",
+        value_parser = parse_date,
+        allow_hyphen_values = true
+    )]
+    to: Option<f64>,
 }
 
-impl EclipseDetector {
-    fn new() -> Self {
-        Self {
-            light_dirs: Default::default(),
+#[derive(Args)]
+#[command(next_help_heading = "Snapshot options")]
+struct SnapshotOptions {
+    /// Start from the latest state saved in this directory before the computed period, and save
+    /// the states at the start of each month on the way there
+    #[arg(long, global = true, value_name = "DIR")]
+    snapshot_dir: Option<PathBuf>,
+    /// Don't save snapshots closer than this to existing ones, e.g. 3months
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        requires = "snapshot_dir",
+    )]
+    snapshot_min_interval: Option<f64>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Reports when the Moon is visible to an observer
+    Predict(Box<PredictArgs>),
+    /// Lists eclipses
+    Eclipse {
+        #[command(subcommand)]
+        kind: Option<EclipseKind>,
+        /// Only search around the syzygies found analytically to be close enough to a node of
+        /// the Moon's orbit, just propagating in between
+        #[arg(long, global = true)]
+        prescreen: bool,
+    },
+    /// Finds the next visibility windows or eclipses from now, or from --from, searching only
+    /// as far as needed
+    Next {
+        #[command(subcommand)]
+        what: NextKind,
+        /// How many to find
+        #[arg(
+            long,
+            short = 'n',
+            global = true,
+            value_name = "N",
+            default_value_t = 1
+        )]
+        count: usize,
+        /// Give up this long after the start, unless --to is given
+        #[arg(long, global = true, value_name = "INTERVAL", default_value = "100years", value_parser = parse_interval)]
+        within: f64,
+    },
+    /// Lists the principal phases of the Moon
+    Phase {
+        /// Also list the illuminated fraction at this interval, e.g. 6h
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        every: Option<f64>,
+    },
+    /// Lists the lunar perigees and apogees
+    Apsides,
+    /// Lists the rise, upper transit and set times of the bodies seen from a place, with the
+    /// refraction of a standard atmosphere and the upper limb on the horizon
+    Risetimes {
+        /// The place (degrees, east positive; altitude in km)
+        #[arg(long, value_name = "LAT,LON[,ALT]", allow_hyphen_values = true)]
+        station: String,
+        /// Comma-separated bodies to list
+        #[arg(
+            long,
+            value_name = "NAMES",
+            value_delimiter = ',',
+            default_value = "Sun,Moon"
+        )]
+        include: Vec<String>,
+        /// Also list the dawns and dusks of the civil, nautical and astronomical twilights
+        #[arg(long)]
+        twilight: bool,
+    },
+    /// Lists the positions of a body relative to another one
+    Ephemeris {
+        /// A simulated body or a Lagrange point, like sun-earth-l2
+        #[arg(long, value_name = "NAME", default_value = "Moon")]
+        body: String,
+        /// A simulated body or a Lagrange point
+        #[arg(long, value_name = "NAME", default_value = "Earth")]
+        center: String,
+        /// Interval between the listed positions, e.g. 6h or 1day
+        #[arg(long, value_name = "INTERVAL", default_value = "1day", value_parser = parse_interval)]
+        every: f64,
+        /// Axes to list the positions in
+        #[arg(
+            long,
+            value_name = "FRAME",
+            default_value = "simulation",
+            value_parser = PossibleValuesParser::new(frames::NAMES)
+                .map(|name| name.parse::<Frame>().unwrap()),
+        )]
+        frame: Frame,
+    },
+    /// Lists the right ascension, declination and distance of a body
+    Position {
+        #[arg(long, value_name = "NAME", default_value = "Moon")]
+        body: String,
+        #[command(flatten)]
+        sky: ViewpointArgs,
+        /// Interval between the listed positions, e.g. 6h or 1day
+        #[arg(long, value_name = "INTERVAL", default_value = "1day", value_parser = parse_interval)]
+        every: f64,
+        /// Refer to the true equator and equinox of date instead of J2000
+        #[arg(long)]
+        of_date: bool,
+    },
+    /// Lists the positions and velocities of bodies, e.g. as a CSV table with --output csv
+    Trajectory {
+        /// Comma-separated bodies to list, all by default
+        #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+        include: Vec<String>,
+        /// The body the states are relative to; the simulation origin by default
+        #[arg(long, value_name = "NAME")]
+        center: Option<String>,
+        /// Interval between the listed states, e.g. 6h or 1day
+        #[arg(long, value_name = "INTERVAL", default_value = "1day", value_parser = parse_interval)]
+        every: f64,
+        /// Axes to list the states in
+        #[arg(
+            long,
+            value_name = "FRAME",
+            default_value = "simulation",
+            value_parser = PossibleValuesParser::new(frames::NAMES)
+                .map(|name| name.parse::<Frame>().unwrap()),
+        )]
+        frame: Frame,
+        /// Write the table to a Parquet file instead of printing it
+        #[cfg(feature = "parquet")]
+        #[arg(long, value_name = "FILE")]
+        parquet: Option<PathBuf>,
+    },
+    /// Lists the closest approaches of two bodies in the sky
+    Separation {
+        #[arg(long, value_name = "NAME", default_value = "Moon")]
+        body: String,
+        #[arg(long, value_name = "NAME", default_value = "Sun")]
+        other: String,
+        #[command(flatten)]
+        sky: ViewpointArgs,
+        /// Also list the separation at this interval, e.g. 6h
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        every: Option<f64>,
+    },
+    /// Searches for events in the sky
+    Events {
+        #[command(subcommand)]
+        command: EventsCommand,
+    },
+    /// Manages the saved snapshots
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotsCommand,
+    },
+    /// Answers queries over HTTP, with JSON
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+        address: String,
+        /// Only serve the dates this close to the initial state, since propagating far takes
+        /// long
+        #[arg(long, value_name = "INTERVAL", default_value = "100years", value_parser = parse_interval)]
+        window: f64,
+    },
+    /// Draws a chart of a quantity over time, with the related events marked
+    #[cfg(feature = "plot")]
+    Plot {
+        #[arg(value_parser = PossibleValuesParser::new(eclipses::plot::QUANTITY_NAMES))]
+        quantity: String,
+        /// The PNG or SVG file to draw the chart to
+        #[arg(long, value_name = "FILE")]
+        file: PathBuf,
+        /// The built-in observer whose boresight the Moon's angle is measured from
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "himawari",
+            value_parser = PossibleValuesParser::new(observer::NAMES)
+        )]
+        observer: String,
+        /// Interval between the plotted values, e.g. 10min or 6h
+        #[arg(long, value_name = "INTERVAL", default_value = "1h", value_parser = parse_interval)]
+        every: f64,
+    },
+    /// Draws the Moon's track through a satellite's frame during its first pass after --from
+    #[cfg(feature = "plot")]
+    Frame {
+        /// The PNG or SVG file to draw the frame to
+        #[arg(long, value_name = "FILE")]
+        file: PathBuf,
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "himawari",
+            value_parser = PossibleValuesParser::new(observer::NAMES)
+        )]
+        observer: String,
+        /// Interval between the computed positions of the Moon
+        #[arg(long, value_name = "INTERVAL", default_value = "1min", value_parser = parse_interval)]
+        every: f64,
+        /// Interval between the labeled times on the track, counted from midnight UTC
+        #[arg(long, value_name = "INTERVAL", default_value = "10min", value_parser = parse_interval)]
+        tick: f64,
+    },
+    /// Animates the Moon's first pass through a satellite's frame after --from, with a frame
+    /// per image
+    #[cfg(feature = "plot")]
+    Animate {
+        /// The GIF file to write the animation to
+        #[arg(long, value_name = "FILE", required_unless_present = "frames")]
+        file: Option<PathBuf>,
+        /// Write a PNG file per image to this directory instead
+        #[arg(long, value_name = "DIR", conflicts_with = "file")]
+        frames: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "himawari",
+            value_parser = PossibleValuesParser::new(observer::NAMES)
+        )]
+        observer: String,
+        /// Interval between the images, Himawari's by default
+        #[arg(long, value_name = "INTERVAL", default_value = "10min", value_parser = parse_interval)]
+        image_interval: f64,
+        /// Time after midnight UTC the images are counted from
+        #[arg(long, value_name = "INTERVAL", default_value = "0", value_parser = parse_offset)]
+        image_offset: f64,
+        /// How long each image is shown in the animation, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        delay: u32,
+    },
+    /// Compares the propagated Earth and Moon with an SPK ephemeris
+    Validate {
+        /// Binary SPK kernel, e.g. de440.bsp
+        #[arg(long, value_name = "FILE", required_unless_present = "de")]
+        kernel: Option<PathBuf>,
+        /// A DE ephemeris in JPL's own format instead: a binary file, or the ASCII header and
+        /// data files, e.g. header.440 ascp01950.440
+        #[arg(long, value_name = "FILE", num_args = 1.., conflicts_with = "kernel")]
+        de: Vec<PathBuf>,
+        /// Interval between the comparisons
+        #[arg(long, value_name = "INTERVAL", default_value = "30days", value_parser = parse_interval)]
+        every: f64,
+    },
+    /// Compares two states body by body, e.g. snapshots of runs with different settings
+    Diff {
+        /// Snapshot or bodies file
+        first: PathBuf,
+        /// The state to compare it with
+        second: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct PredictArgs {
+    #[command(flatten)]
+    observer: ObserverArgs,
+    /// Put the --lagrange observer on a halo orbit around the point, with the given amplitudes
+    /// in and out of the orbital plane (km) and period (days)
+    #[arg(long, value_name = "AY,AZ,DAYS", requires = "lagrange")]
+    halo: Option<String>,
+    /// Half-width of the imager's frame instead of its own, e.g. for a sector scan
+    #[arg(long, value_name = "DEG", conflicts_with = "station")]
+    half_fov: Option<f64>,
+    /// Which way to scan from --from; backward goes into the past, until --to or 23 years
+    /// before, and still lists the events in chronological order
+    #[arg(
+        long,
+        value_name = "DIRECTION",
+        default_value = "forward",
+        value_parser = ["forward", "backward"],
+        conflicts_with_all = ["images", "checkpoint_interval", "resume"],
+    )]
+    direction: String,
+    /// Report which of the images taken every --image-interval contain the Moon, and where,
+    /// instead of the changes of its visibility
+    #[arg(long, conflicts_with_all = ["ics", "checkpoint_interval", "resume"])]
+    images: bool,
+    /// Interval between the images, Himawari's by default
+    #[arg(long, value_name = "INTERVAL", default_value = "10min", value_parser = parse_interval)]
+    image_interval: f64,
+    /// Time after midnight UTC the images are counted from
+    #[arg(long, value_name = "INTERVAL", default_value = "0", value_parser = parse_offset)]
+    image_offset: f64,
+    /// Only change the Moon's visibility once it's this far past the edge of the frame or of the
+    /// Earth, so that it doesn't flicker when the Moon skims one of them
+    #[arg(
+        long,
+        value_name = "DEG",
+        default_value_t = 0.0,
+        conflicts_with = "images"
+    )]
+    edge_margin: f64,
+    /// Only check the visibility this often while the Moon is far from the frame, and after
+    /// every step near it, which saves time with a small --step
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with = "images")]
+    sample_interval: Option<f64>,
+    /// Leave out the visibilities shorter than this, e.g. 1min
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with = "images")]
+    min_duration: Option<f64>,
+    /// Leave out the events at which less than this fraction of the Moon's disk is lit, e.g.
+    /// 0.2, since a thin crescent hardly shows in the images
+    #[arg(long, value_name = "FRACTION", conflicts_with = "images")]
+    min_illumination: Option<f64>,
+    /// Leave out the visibility windows during which the Sun comes within this angle of the
+    /// boresight or of the Moon, spoiling the images with glint or blooming
+    #[arg(long, value_name = "DEG", conflicts_with = "images")]
+    sun_exclusion: Option<f64>,
+    /// Print the number of visibility windows, the time the Moon is visible, the longest window
+    /// and a monthly histogram of each observer instead of the events
+    #[arg(long, conflicts_with = "images")]
+    summary: bool,
+    /// Also write the Moon visibility windows to an iCalendar file
+    #[arg(long, value_name = "FILE")]
+    ics: Option<PathBuf>,
+    /// Also write the events to a Parquet file
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "FILE")]
+    parquet: Option<PathBuf>,
+    /// Save the state of the search every this much simulated time, e.g. 1year, to be able to
+    /// resume it
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+    checkpoint_interval: Option<f64>,
+    /// File the checkpoints are saved to
+    #[arg(long, value_name = "FILE", default_value = "checkpoint.toml")]
+    checkpoint: PathBuf,
+    /// Resume the search from a checkpoint; the other options have to be the same as in the
+    /// interrupted run
+    #[arg(long, value_name = "FILE")]
+    resume: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct ObserverArgs {
+    /// A built-in imager; can be given several times to predict for all of them at once
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(observer::NAMES))]
+    observer: Vec<String>,
+    /// The observers listed in a TOML file, as `[[observer]]` tables with one of `name`, `tle`,
+    /// `station` or `lagrange` set like the options, and optionally `halo` and `half_fov`
+    #[arg(long, value_name = "FILE")]
+    observers: Option<PathBuf>,
+    /// A full disk imager on the orbit given by these two-line elements
+    #[arg(long, value_name = "FILE")]
+    tle: Option<PathBuf>,
+    /// A ground station (degrees, east positive; altitude in km), which sees the Moon when it's
+    /// above the horizon
+    #[arg(long, value_name = "LAT,LON[,ALT]", allow_hyphen_values = true)]
+    station: Option<String>,
+    /// An imager at a Lagrange point, looking at the Earth with a 2° wide frame
+    #[arg(
+        long,
+        value_name = "POINT",
+        value_parser = PossibleValuesParser::new(lagrange::NAMES)
+            .map(|name| name.parse::<LagrangePoint>().unwrap()),
+    )]
+    lagrange: Option<LagrangePoint>,
+}
+
+#[derive(Subcommand)]
+enum NextKind {
+    /// The Moon's visibility windows for an observer
+    Visibility {
+        #[command(flatten)]
+        observer: ObserverArgs,
+    },
+    /// Lunar eclipses
+    LunarEclipse,
+    /// Solar eclipses
+    SolarEclipse,
+}
+
+#[derive(Subcommand)]
+enum EclipseKind {
+    /// Lists lunar eclipses
+    Lunar {
+        /// Only list the eclipses of this Saros series
+        #[arg(long, value_name = "SERIES")]
+        saros: Option<u32>,
+        /// Only list the eclipses that can be seen from this place in darkness, and when
+        /// (degrees, east positive; altitude in km). The umbral phases have to be, or the
+        /// penumbral ones of penumbral eclipses
+        #[arg(long, value_name = "LAT,LON[,ALT]", allow_hyphen_values = true)]
+        station: Option<String>,
+        /// The twilight that has to be over for it to be dark
+        #[arg(
+            long,
+            value_name = "TWILIGHT",
+            default_value = "civil",
+            requires = "station",
+            value_parser = PossibleValuesParser::new(rise::TWILIGHT_NAMES)
+                .map(|name| name.parse::<Twilight>().unwrap()),
+        )]
+        darkness: Twilight,
+    },
+    /// Lists solar eclipses
+    Solar {
+        /// Also write the central lines and the limits of the paths of the central eclipses to
+        /// a GeoJSON file
+        #[arg(long, value_name = "FILE")]
+        geojson: Option<PathBuf>,
+        /// Interval between the points of the paths
+        #[arg(long, value_name = "INTERVAL", default_value = "1min", value_parser = parse_interval)]
+        path_every: f64,
+        /// List the local circumstances of the eclipses seen from this place instead (degrees,
+        /// east positive; altitude in km)
+        #[arg(
+            long,
+            value_name = "LAT,LON[,ALT]",
+            allow_hyphen_values = true,
+            conflicts_with = "geojson"
+        )]
+        station: Option<String>,
+        /// Also write the Besselian elements of the eclipses to a JSON file
+        #[arg(long, value_name = "FILE", conflicts_with = "station")]
+        besselian: Option<PathBuf>,
+        /// Also write the eclipses to a text file laid out like the tables of NASA's Five
+        /// Millennium Canon of Solar Eclipses, to compare them with it
+        #[arg(long, value_name = "FILE", conflicts_with = "station")]
+        canon: Option<PathBuf>,
+        /// Only list the eclipses of this Saros series
+        #[arg(long, value_name = "SERIES", conflicts_with = "station")]
+        saros: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum EventsCommand {
+    /// Lists the conjunctions of the Moon and the planets, and the occultations among them
+    Conjunctions {
+        /// Comma-separated bodies to consider, all but the Sun and the center by default
+        #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+        include: Vec<String>,
+        /// Only list the approaches closer than this
+        #[arg(long, value_name = "DEG", default_value_t = 1.0)]
+        max_separation: f64,
+        #[command(flatten)]
+        sky: ViewpointArgs,
+    },
+    /// Lists the transits of planets across the Sun, with contacts I-IV
+    Transits {
+        /// Comma-separated planets to consider
+        #[arg(
+            long,
+            value_name = "NAMES",
+            value_delimiter = ',',
+            default_value = "Mercury,Venus"
+        )]
+        include: Vec<String>,
+        #[command(flatten)]
+        sky: ViewpointArgs,
+    },
+    /// Lists the transits of artificial satellites across the Sun and the Moon seen from a
+    /// place on the Earth
+    Satellites {
+        /// File with the two-line elements of the satellites, like the catalogs of CelesTrak
+        #[arg(long, value_name = "FILE")]
+        tle: PathBuf,
+        /// The place the transits are seen from (degrees, east positive; altitude in km)
+        #[arg(long, value_name = "LAT,LON[,ALT]", allow_hyphen_values = true)]
+        station: String,
+        /// Comma-separated bodies the satellites can transit
+        #[arg(
+            long,
+            value_name = "NAMES",
+            value_delimiter = ',',
+            default_value = "Sun,Moon"
+        )]
+        include: Vec<String>,
+    },
+    /// Lists when a body, e.g. one added with --elements, enters and leaves a satellite's frame
+    /// and passes behind or in front of the Earth
+    Frame {
+        #[arg(long, value_name = "NAME")]
+        body: String,
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "himawari",
+            value_parser = PossibleValuesParser::new(observer::NAMES)
+        )]
+        observer: String,
+    },
+    /// Lists when a condition on the Moon seen by a satellite starts and stops holding, like
+    /// "in-frame and illuminated-fraction > 0.5 and sun-boresight > 20". The conditions are
+    /// in-frame, visible, obscured and transiting, the quantities (in degrees and km)
+    /// illuminated-fraction, phase-angle, sun-boresight, sun-moon and moon-distance, compared
+    /// with < or >, and they combine with and, or, not and parentheses
+    Query {
+        query: Query,
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "himawari",
+            value_parser = PossibleValuesParser::new(observer::NAMES)
+        )]
+        observer: String,
+    },
+}
+
+/// Where the sky is seen from.
+#[derive(Args)]
+struct ViewpointArgs {
+    /// The body the sky is seen from
+    #[arg(long, value_name = "NAME", default_value = "Earth")]
+    center: String,
+    /// See the sky from a place on the Earth instead (degrees, east positive; altitude in km)
+    #[arg(
+        long,
+        value_name = "LAT,LON[,ALT]",
+        allow_hyphen_values = true,
+        conflicts_with = "center"
+    )]
+    station: Option<String>,
+    /// Use the apparent positions of the bodies, corrected for the light travel time and
+    /// aberration
+    #[arg(long)]
+    apparent: bool,
+}
+
+impl ViewpointArgs {
+    /// The body the sky is seen from or from whose surface, and the ground station, if any.
+    fn parse(&self, sim: &SimState, refraction: bool) -> Result<(&str, Option<GroundStation>)> {
+        let station = self
+            .station
+            .as_deref()
+            .map(|station| parse_station(station, refraction))
+            .transpose()?;
+        let center = if station.is_some() {
+            "Earth"
+        } else {
+            &self.center
+        };
+        if sim.body_by_name(center).is_none() {
+            return Err(Error::MissingBody(center.to_owned()));
+        }
+        Ok((center, station))
+    }
+
+    /// Where the sky is seen from, for telling searches apart in the event database.
+    fn describe(&self, center: &str) -> String {
+        let place = match self.station {
+            Some(ref station) => format!("station {}", station),
+            None => center.to_owned(),
+        };
+        if self.apparent {
+            format!("{}, apparent", place)
+        } else {
+            place
         }
     }
 
-    fn light_dir_for(&self, time: f64) -> Option<Vector3<f64>> {
-        match self
-            .light_dirs
-            .binary_search_by(|entry| entry.0.partial_cmp(&time).unwrap())
-        {
-            Err(0) => None,
-            Err(i) if i == self.light_dirs.len() => None,
-            Err(i) => {
-                let (t1, vec1) = self.light_dirs[i - 1];
-                let (t2, vec2) = self.light_dirs[i];
-                Some(vec1 + (vec2 - vec1) / (t2 - t1) * (time - t1))
+    fn viewpoint<'a>(&self, center: &'a str, station: Option<&'a GroundStation>) -> Viewpoint<'a> {
+        let viewpoint = match station {
+            Some(station) => Viewpoint::observer(station),
+            None => Viewpoint::body(center),
+        };
+        viewpoint.apparent(self.apparent)
+    }
+}
+
+#[derive(Subcommand)]
+enum SnapshotsCommand {
+    /// Thins out old snapshots
+    Prune {
+        /// Keep one snapshot per this interval, e.g. 6months
+        #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+        keep_every: f64,
+        /// Only thin out the snapshots before this date
+        #[arg(long, value_name = "DATE", value_parser = parse_date)]
+        before: Option<f64>,
+    },
+    /// Only saves snapshots between --from and --to, propagating forward and backward from the
+    /// initial state in parallel, so that later queries in the period are fast
+    Generate {
+        /// Interval between the snapshots, which are at the starts of months
+        #[arg(
+            long,
+            value_name = "INTERVAL",
+            value_parser = parse_interval,
+            default_value = "1month"
+        )]
+        every: f64,
+    },
+}
+
+/// The initial state of the simulation and how to propagate it.
+struct Setup {
+    sim: SimState,
+    start: f64,
+    propagator: Propagator,
+    /// Advanced by the propagator's steps.
+    progress: ProgressBar,
+    snapshots: Option<Snapshots>,
+    /// Number of parts the searched periods are split into.
+    shards: usize,
+    /// What the results depend on besides the query: the initial state, the propagator and
+    /// the snapshots.
+    model: String,
+    /// Replaces the radius of the Sun in the states the commands start from, the snapshots'
+    /// included.
+    sun_radius: Option<f64>,
+    /// Whether the ground stations see the bodies refracted.
+    refraction: bool,
+    cache: Option<Cache>,
+    #[cfg(feature = "sqlite")]
+    database: Option<EventDatabase>,
+}
+
+impl Setup {
+    /// The state at the simulation time `time`, using the snapshots if there are any.
+    fn state_at(&mut self, time: f64) -> Result<SimState> {
+        let mut sim = self.sim.clone();
+        self.track(self.start, time);
+        self.propagator.set_time(self.start);
+        match self.snapshots {
+            Some(ref mut snapshots) => {
+                snapshots.propagate(&mut sim, self.start, time, &mut self.propagator)?;
+                warn_skipped(snapshots);
             }
-            Ok(i) => Some(self.light_dirs[i].1),
+            None => {
+                if time < self.start {
+                    snapshots::warn_backwards(&sim, self.start, time, &self.propagator);
+                }
+                self.propagator.advance(&mut sim, time - self.start)
+            }
+        }
+        if let Some(radius) = self.sun_radius {
+            let sun = sim
+                .body_index_by_name("Sun")
+                .ok_or_else(|| Error::MissingBody("Sun".to_owned()))?;
+            sim.get_body_mut(sun).radius = radius;
+        }
+        Ok(sim)
+    }
+
+    /// Runs `search` between `from` and `to` (simulation times), unless its results with the
+    /// same `key` and model are cached. With an event database, only the parts of the period
+    /// that haven't been searched with the same `key` are, and the results are stored in the
+    /// database and read back from it.
+    fn search<T, F>(
+        &mut self,
+        key: &str,
+        from: f64,
+        to: f64,
+        date: fn(&T) -> DateTime<Utc>,
+        search: F,
+    ) -> Result<Vec<T>>
+    where
+        T: Serialize + DeserializeOwned + Send,
+        F: Fn(&SimState, f64, f64, Propagator) -> Vec<T> + Sync,
+    {
+        let cache_key = format!("{} from {} to {} with {}", key, from, to, self.model);
+        if let Some(results) = self.cache.as_ref().and_then(|cache| cache.get(&cache_key)) {
+            return Ok(results);
+        }
+        let results = self.search_uncached(key, from, to, date, search)?;
+        if let Some(ref cache) = self.cache {
+            cache.put(&cache_key, &results)?;
         }
+        Ok(results)
     }
 
-    fn save_light_dir(&mut self, time: f64, dir: Vector3<f64>) {
-        self.light_dirs.push((time, dir));
-        if time - self.light_dirs[0].0 > 600.0 + STEP {
-            let _ = self.light_dirs.remove(0);
+    fn search_uncached<T, F>(
+        &mut self,
+        key: &str,
+        from: f64,
+        to: f64,
+        date: fn(&T) -> DateTime<Utc>,
+        search: F,
+    ) -> Result<Vec<T>>
+    where
+        T: Serialize + DeserializeOwned + Send,
+        F: Fn(&SimState, f64, f64, Propagator) -> Vec<T> + Sync,
+    {
+        #[cfg(feature = "sqlite")]
+        if let Some(mut database) = self.database.take() {
+            for (start, end) in database.missing(key, from, to)? {
+                let found = self.run_search(start, end, date, &search)?;
+                let found: Vec<(f64, &T)> = found
+                    .iter()
+                    .map(|item| (utc_to_sim_time(date(item)), item))
+                    .collect();
+                database.insert(key, start, end, &found)?;
+            }
+            let found = database.events(key, from, to);
+            self.database = Some(database);
+            return found;
         }
+        #[cfg(not(feature = "sqlite"))]
+        let _ = key;
+        self.run_search(from, to, date, &search)
     }
 
-    fn detect_eclipse(&self, sim: &SimState, time: f64) -> Option<Eclipse> {
-        let sun = sim.body_by_name("Sun").unwrap();
-        let earth = sim.body_by_name("Earth").unwrap();
-        let moon = sim.body_by_name("Moon").unwrap();
+    /// Runs `search` between `from` and `to`, split into `shards` parts searched in parallel.
+    /// The parts start at the starts of months, and the results within `SHARD_OVERLAP` of a
+    /// split are taken from the part before it, which is searched a bit further, so that they
+    /// don't depend on the state a part starts in.
+    fn run_search<T, F>(
+        &mut self,
+        from: f64,
+        to: f64,
+        date: fn(&T) -> DateTime<Utc>,
+        search: &F,
+    ) -> Result<Vec<T>>
+    where
+        T: Send,
+        F: Fn(&SimState, f64, f64, Propagator) -> Vec<T> + Sync,
+    {
+        let mut starts = vec![from];
+        for i in 1..self.shards {
+            let start = snapshots::next_month(from + (to - from) * i as f64 / self.shards as f64);
+            if start < to - SHARD_OVERLAP && start > starts[starts.len() - 1] + SHARD_OVERLAP {
+                starts.push(start);
+            }
+        }
+        if starts.len() == 1 {
+            let sim = self.state_at(from)?;
+            self.track(from, to);
+            return Ok(search(&sim, from, to, self.propagator.clone()));
+        }
 
-        // correction for shadow enlargement: https://eclipse.gsfc.nasa.gov/LEcat5/shadow.html
-        let re = earth.radius * 1.011;
+        // the states the parts start from, reached in one go unless there are snapshots
+        let mut states = vec![self.state_at(from)?];
+        for &start in &starts[1..] {
+            let state = match self.snapshots {
+                Some(_) => self.state_at(start)?,
+                None => {
+                    let mut state = states[states.len() - 1].clone();
+                    let previous = starts[states.len() - 1];
+                    self.track(previous, start);
+                    self.propagator.advance(&mut state, start - previous);
+                    state
+                }
+            };
+            states.push(state);
+        }
+        info!(parts = starts.len(), "searching in parallel");
 
-        let dist = earth.distance_from(sun);
-        let delay = dist / 299_792.458;
+        self.track(from, to);
+        let propagator = &self.propagator;
+        let parts: Vec<Vec<T>> = (0..starts.len())
+            .into_par_iter()
+            .map(|i| {
+                let start = starts[i];
+                let keep_from = if i == 0 {
+                    f64::NEG_INFINITY
+                } else {
+                    start + SHARD_OVERLAP
+                };
+                let (end, keep_to) = match starts.get(i + 1) {
+                    Some(&next) => ((next + 2.0 * SHARD_OVERLAP).min(to), next + SHARD_OVERLAP),
+                    None => (to, f64::INFINITY),
+                };
+                let mut propagator = propagator.clone();
+                propagator.set_time(start);
+                search(&states[i], start, end, propagator)
+                    .into_iter()
+                    .filter(|item| {
+                        let time = utc_to_sim_time(date(item));
+                        time >= keep_from && time < keep_to
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(parts.into_iter().flatten().collect())
+    }
 
-        let light_dir = self.light_dir_for(time - delay)?.normalize();
+    /// Shows the progress of propagating from `from` to `to` (simulation times) until the
+    /// propagator gets there.
+    fn track(&self, from: f64, to: f64) {
+        let direction = if to < from { -1.0 } else { 1.0 };
+        let style =
+            ProgressStyle::with_template("{date} [{bar:30}] {rate} simulated days/s, ETA {eta}")
+                .unwrap()
+                .with_key(
+                    "date",
+                    move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                        let date = sim_time_to_utc(from + direction * state.pos() as f64);
+                        let _ = write!(w, "{}", date.format("%Y-%m-%d"));
+                    },
+                )
+                .with_key("rate", |state: &ProgressState, w: &mut dyn fmt::Write| {
+                    let _ = write!(w, "{:.1}", state.per_sec() / 86400.0);
+                })
+                .progress_chars("=> ");
+        self.progress.set_style(style);
+        self.progress.set_length((to - from).abs() as u64);
+        self.progress.reset();
+    }
+}
 
-        // lunar eclipses
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    let format = cli.output;
+    if let Some(ref path) = cli.manifest {
+        let manifest = run_with_manifest(&arguments_without_manifest(), true)?;
+        return manifest.save(path);
+    }
+    if let Some(ref path) = cli.verify_manifest {
+        return verify_manifest(path);
+    }
+
+    if let Some(threads) = cli.model.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|err| Error::Argument(format!("couldn't start the thread pool: {}", err)))?;
+    }
+
+    let progress = if cli.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0)
+    };
+    init_logging(cli.verbose, &progress);
+    let mut setup = cli.model.setup(progress)?;
+    setup.snapshots = cli.snapshots.open()?;
+    if let Some(ref dir) = cli.snapshots.snapshot_dir {
+        setup.model += &format!(", snapshots in {}", dir.display());
+    }
+    setup.cache = cli.cache.as_deref().map(Cache::open).transpose()?;
+    #[cfg(feature = "sqlite")]
+    {
+        setup.database = cli
+            .database
+            .as_deref()
+            .map(EventDatabase::open)
+            .transpose()?;
+    }
+
+    let period_start = cli.period.from;
+    let from = period_start.unwrap_or(setup.start);
+    let period_end = cli.period.to;
+    let to = |default_length: f64| period_end.unwrap_or(from + default_length);
+
+    let default_command = Command::Eclipse {
+        kind: None,
+        prescreen: false,
+    };
+    match cli.command.unwrap_or(default_command) {
+        Command::Predict(args) => {
+            let length = if args.direction == "backward" {
+                -23.0 * YEAR
+            } else {
+                23.0 * YEAR
+            };
+            predict(*args, &mut setup, from, to(length), format)
+        }
+        Command::Eclipse {
+            kind:
+                Some(EclipseKind::Solar {
+                    station: Some(station),
+                    ..
+                }),
+            prescreen,
+        } => {
+            check_format(format, "eclipse")?;
+            let ground_station = parse_station(&station, setup.refraction)?;
+            let eclipses = setup.search(
+                &eclipse_key(
+                    &format!("solar eclipses seen from station {}", station),
+                    prescreen,
+                ),
+                from,
+                to(23.0 * YEAR),
+                |eclipse: &eclipse::LocalEclipse| eclipse.maximum.date,
+                |sim, start, end, propagator| {
+                    let search = |sim: &SimState, start, end, propagator: Propagator| {
+                        eclipse::local_solar_eclipses(sim, start, end, propagator, &ground_station)
+                    };
+                    search_eclipses(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        prescreen,
+                        Syzygy::NewMoon,
+                        search,
+                    )
+                },
+            )?;
+            print_local_eclipses(&eclipses, format)
+        }
+        Command::Eclipse {
+            kind:
+                Some(EclipseKind::Solar {
+                    geojson,
+                    path_every,
+                    station: None,
+                    besselian,
+                    canon,
+                    saros,
+                }),
+            prescreen,
+        } => {
+            check_format(format, "eclipse")?;
+            let mut eclipses = setup.search(
+                &eclipse_key("solar-eclipses", prescreen),
+                from,
+                to(23.0 * YEAR),
+                |eclipse: &eclipse::SolarEclipse| eclipse.greatest,
+                |sim, start, end, propagator| {
+                    let search = eclipse::find_solar_eclipses::<Propagator>;
+                    search_eclipses(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        prescreen,
+                        Syzygy::NewMoon,
+                        search,
+                    )
+                },
+            )?;
+            if let Some(series) = saros {
+                eclipses.retain(|eclipse| eclipse.saros.series == series);
+            }
+            print_solar_eclipses(&eclipses, format)?;
+            if let Some(path) = besselian {
+                write_besselian_elements(&path, &eclipses, &mut setup, from)?;
+            }
+            if let Some(path) = canon {
+                let mut table = vec![];
+                canon::write_solar_eclipses(&mut table, &eclipses)?;
+                fs::write(&path, table).map_err(|err| Error::from(err).in_file(&path))?;
+            }
+            match geojson {
+                Some(path) => write_eclipse_paths(&path, &eclipses, &mut setup, from, path_every),
+                None => Ok(()),
+            }
+        }
+        Command::Eclipse { kind, prescreen } => {
+            check_format(format, "eclipse")?;
+            let (saros, station, darkness) = match kind {
+                Some(EclipseKind::Lunar {
+                    saros,
+                    station,
+                    darkness,
+                }) => (saros, station, darkness),
+                _ => (None, None, Twilight::Civil),
+            };
+            let mut eclipses = setup.search(
+                &eclipse_key("lunar-eclipses", prescreen),
+                from,
+                to(23.0 * YEAR),
+                |eclipse: &eclipse::LunarEclipse| eclipse.greatest,
+                |sim, start, end, propagator| {
+                    let search = eclipse::find_lunar_eclipses::<Propagator>;
+                    search_eclipses(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        prescreen,
+                        Syzygy::FullMoon,
+                        search,
+                    )
+                },
+            )?;
+            if let Some(series) = saros {
+                eclipses.retain(|eclipse| eclipse.saros.series == series);
+            }
+            match station {
+                Some(station) => {
+                    let station = parse_station(&station, true)?;
+                    print_dark_lunar_eclipses(
+                        &eclipses, &mut setup, from, &station, darkness, format,
+                    )
+                }
+                None => print_lunar_eclipses(&eclipses, format),
+            }
+        }
+        Command::Next {
+            what,
+            count,
+            within,
+        } => {
+            let from = period_start.unwrap_or_else(|| utc_to_sim_time(Utc::now()));
+            next(
+                what,
+                &mut setup,
+                from,
+                period_end.unwrap_or(from + within),
+                count,
+                format,
+            )
+        }
+        Command::Phase { every } => {
+            check_format(format, "phase")?;
+            if every.is_some() && format != Format::Text {
+                return Err(Error::Argument(
+                    "--every is only supported with text output".to_owned(),
+                ));
+            }
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            print_phases(sim, from, to(YEAR), every, setup.propagator, format)
+        }
+        Command::Apsides => {
+            check_format(format, "apsides")?;
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            print_apsides(&sim, from, to(YEAR), setup.propagator, format)
+        }
+        Command::Risetimes {
+            station,
+            include,
+            twilight,
+        } => {
+            check_format(format, "risetimes")?;
+            if let Some(name) = include
+                .iter()
+                .find(|name| setup.sim.body_by_name(name).is_none())
+            {
+                return Err(Error::MissingBody(name.clone()));
+            }
+            let station = parse_station(&station, true)?;
+            let bodies: Vec<&str> = include.iter().map(String::as_str).collect();
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let events = rise::rise_times(
+                &sim,
+                from,
+                to(YEAR),
+                setup.propagator,
+                &station,
+                &bodies,
+                twilight,
+            );
+            print_rise_times(&events, format)
+        }
+        Command::Ephemeris {
+            body,
+            center,
+            every,
+            frame,
+        } => {
+            check_format(format, "ephemeris")?;
+            for name in [&body, &center].iter() {
+                if position_of(&setup.sim, name).is_none() {
+                    return Err(Error::MissingBody(name.to_string()));
+                }
+            }
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let ephemeris = Ephemeris {
+                body,
+                center,
+                from,
+                to: to(YEAR),
+                every,
+                frame,
+            };
+            ephemeris.print(sim, setup.propagator, format)
+        }
+        Command::Position {
+            body,
+            sky,
+            every,
+            of_date,
+        } => {
+            if format == Format::Ics {
+                return Err(Error::Argument(
+                    "position doesn't support ics output".to_owned(),
+                ));
+            }
+            let (center, station) = sky.parse(&setup.sim, setup.refraction)?;
+            if setup.sim.body_by_name(&body).is_none() {
+                return Err(Error::MissingBody(body));
+            }
+            let viewpoint = sky.viewpoint(center, station.as_ref());
+            let frame = if of_date {
+                Frame::TrueEquatorial
+            } else {
+                Frame::Equatorial
+            };
+            let mut sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let mut positions = vec![];
+            let mut time = from;
+            while time <= to(YEAR) {
+                positions.push(position::sky_position(&sim, time, viewpoint, &body, frame));
+                setup.propagator.advance(&mut sim, every);
+                time += every;
+            }
+            let stdout = io::stdout();
+            output::write_positions(&mut stdout.lock(), &positions, format)?;
+            Ok(())
+        }
+        Command::Trajectory {
+            include,
+            center,
+            every,
+            frame,
+            #[cfg(feature = "parquet")]
+            parquet,
+        } => {
+            if format == Format::Ics {
+                return Err(Error::Argument(
+                    "trajectory doesn't support ics output".to_owned(),
+                ));
+            }
+            let bodies: Vec<String> = if include.is_empty() {
+                setup.sim.bodies().map(|body| body.name.clone()).collect()
+            } else {
+                include
+            };
+            if let Some(name) = bodies
+                .iter()
+                .chain(&center)
+                .find(|name| setup.sim.body_by_name(name).is_none())
+            {
+                return Err(Error::MissingBody(name.clone()));
+            }
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let trajectory = Trajectory {
+                bodies,
+                center,
+                frame,
+                every,
+            };
+            let states = trajectory.sample(&sim, from, to(YEAR), setup.propagator);
+            #[cfg(feature = "parquet")]
+            if let Some(path) = parquet {
+                return eclipses::columnar::write_states(&path, &states);
+            }
+            let stdout = io::stdout();
+            output::write_states(&mut stdout.lock(), &states, format)?;
+            Ok(())
+        }
+        Command::Separation {
+            body,
+            other,
+            sky,
+            every,
+        } => {
+            check_format(format, "separation")?;
+            if every.is_some() && format != Format::Text {
+                return Err(Error::Argument(
+                    "--every is only supported with text output".to_owned(),
+                ));
+            }
+            let (center, station) = sky.parse(&setup.sim, setup.refraction)?;
+            for name in [&body, &other].iter() {
+                if setup.sim.body_by_name(name).is_none() {
+                    return Err(Error::MissingBody(name.to_string()));
+                }
+            }
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let query = SeparationQuery {
+                body,
+                other,
+                viewpoint: sky.viewpoint(center, station.as_ref()),
+                from,
+                to: to(YEAR),
+                every,
+            };
+            query.print(sim, setup.propagator, format)
+        }
+        Command::Events {
+            command:
+                EventsCommand::Conjunctions {
+                    include,
+                    max_separation,
+                    sky,
+                },
+        } => {
+            check_format(format, "events conjunctions")?;
+            let (center, station) = sky.parse(&setup.sim, setup.refraction)?;
+            let include: Vec<String> = if include.is_empty() {
+                setup
+                    .sim
+                    .bodies()
+                    .map(|body| body.name.clone())
+                    .filter(|name| name != "Sun" && name != center)
+                    .collect()
+            } else {
+                include
+            };
+            if let Some(name) = include
+                .iter()
+                .find(|name| setup.sim.body_by_name(name).is_none())
+            {
+                return Err(Error::MissingBody(name.clone()));
+            }
+            let viewpoint = sky.viewpoint(center, station.as_ref());
+            let names: Vec<&str> = include.iter().map(String::as_str).collect();
+            let key = format!(
+                "conjunctions of {} within {} deg seen from {}",
+                include.join(","),
+                max_separation,
+                sky.describe(center)
+            );
+            let conjunctions = setup.search(
+                &key,
+                from,
+                to(YEAR),
+                |conjunction: &Conjunction| conjunction.date,
+                |sim, start, end, propagator| {
+                    separation::conjunctions(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        viewpoint,
+                        &names,
+                        max_separation.to_radians(),
+                    )
+                },
+            )?;
+            print_conjunctions(&conjunctions, format)
+        }
+        Command::Events {
+            command: EventsCommand::Transits { include, sky },
+        } => {
+            check_format(format, "events transits")?;
+            let (center, station) = sky.parse(&setup.sim, setup.refraction)?;
+            if let Some(name) = include
+                .iter()
+                .find(|name| setup.sim.body_by_name(name).is_none())
+            {
+                return Err(Error::MissingBody(name.clone()));
+            }
+            let viewpoint = sky.viewpoint(center, station.as_ref());
+            let names: Vec<&str> = include.iter().map(String::as_str).collect();
+            let key = format!(
+                "transits of {} seen from {}",
+                include.join(","),
+                sky.describe(center)
+            );
+            let transits = setup.search(
+                &key,
+                from,
+                to(YEAR),
+                |transit: &Conjunction| transit.date,
+                |sim, start, end, propagator| {
+                    separation::transits(sim, start, end, propagator, viewpoint, &names)
+                },
+            )?;
+            print_conjunctions(&transits, format)
+        }
+        Command::Events {
+            command:
+                EventsCommand::Satellites {
+                    tle,
+                    station,
+                    include,
+                },
+        } => {
+            check_format(format, "events satellites")?;
+            if let Some(name) = include
+                .iter()
+                .find(|name| setup.sim.body_by_name(name).is_none())
+            {
+                return Err(Error::MissingBody(name.clone()));
+            }
+            let satellites = TleSatellite::load_all(&tle)?;
+            let elements = fs::read(&tle).map_err(|err| Error::from(err).in_file(&tle))?;
+            let ground = parse_station(&station, setup.refraction)?;
+            let names: Vec<&str> = include.iter().map(String::as_str).collect();
+            let key = format!(
+                "transits of satellites with elements {:08x} across {} seen from station {}",
+                crc32fast::hash(&elements),
+                include.join(","),
+                station
+            );
+            let transits = setup.search(
+                &key,
+                from,
+                to(YEAR),
+                |transit: &Conjunction| transit.date,
+                |sim, start, end, propagator| {
+                    separation::satellite_transits(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        &ground,
+                        &satellites,
+                        &names,
+                    )
+                },
+            )?;
+            print_conjunctions(&transits, format)
+        }
+        Command::Events {
+            command: EventsCommand::Frame { body, observer },
+        } => {
+            check_format(format, "events frame")?;
+            if setup.sim.body_by_name(&body).is_none() {
+                return Err(Error::MissingBody(body));
+            }
+            let observer = observer::by_name(&observer).unwrap();
+            let key = format!("visibility of {} for {}", body, observer.name());
+            let events = setup.search(
+                &key,
+                from,
+                to(YEAR),
+                |event: &BodyEvent| event.date,
+                |sim, start, end, propagator| {
+                    events::body_visibility_events(
+                        sim,
+                        start,
+                        end,
+                        propagator,
+                        observer.as_ref(),
+                        &body,
+                    )
+                },
+            )?;
+            print_body_events(&events, format)
+        }
+        Command::Events {
+            command: EventsCommand::Query { query, observer },
+        } => {
+            check_format(format, "events query")?;
+            let observer = observer::by_name(&observer).unwrap();
+            let key = format!("query \"{}\" for {}", query, observer.name());
+            let events = setup.search(
+                &key,
+                from,
+                to(YEAR),
+                |event: &QueryEvent| event.date,
+                |sim, start, end, propagator| {
+                    query::query_events(sim, start, end, propagator, observer.as_ref(), &query)
+                },
+            )?;
+            print_query_events(&events, &query, format)
+        }
+        Command::Snapshots {
+            command: SnapshotsCommand::Prune { keep_every, before },
+        } => {
+            let default_dir = Path::new(snapshots::DEFAULT_DIR);
+            let mut snapshots = match setup.snapshots {
+                Some(snapshots) => snapshots,
+                // not creating the directory just to find it empty
+                None if !default_dir.is_dir() => {
+                    println!("No snapshots in {}", default_dir.display());
+                    return Ok(());
+                }
+                None => open_snapshots(default_dir)?,
+            };
+            let deleted = snapshots.prune(keep_every, before.unwrap_or(f64::INFINITY))?;
+            println!("Deleted {} snapshots", deleted.len());
+            Ok(())
+        }
+        Command::Snapshots {
+            command: SnapshotsCommand::Generate { every },
+        } => {
+            let (from, to) = period_start.zip(period_end).ok_or_else(|| {
+                Error::Argument("snapshots generate needs --from and --to".to_owned())
+            })?;
+            let dir = cli
+                .snapshots
+                .snapshot_dir
+                .unwrap_or_else(|| PathBuf::from(snapshots::DEFAULT_DIR));
+            generate_snapshots(&setup, &dir, from, to, every)?;
+            println!(
+                "Saved the snapshots from {} to {} in {}",
+                sim_time_to_utc(from).format("%Y-%m-%d"),
+                sim_time_to_utc(to).format("%Y-%m-%d"),
+                dir.display()
+            );
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Command::Serve { address, window } => {
+            let server = eclipses::server::Server::new(
+                setup.sim,
+                setup.start,
+                setup.propagator,
+                setup.snapshots,
+            )
+            .with_window(window);
+            server.run(&address)
+        }
+        #[cfg(feature = "plot")]
+        Command::Plot {
+            quantity,
+            file,
+            observer,
+            every,
+        } => {
+            eclipses::plot::check_path(&file)?;
+            let observer = observer::by_name(&observer).unwrap();
+            let quantity = eclipses::plot::Quantity::by_name(&quantity, observer.as_ref()).unwrap();
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(YEAR));
+            let chart = quantity.chart(&sim, from, to(YEAR), every, setup.propagator);
+            chart.draw(&file)
+        }
+        #[cfg(feature = "plot")]
+        Command::Frame {
+            file,
+            observer,
+            every,
+            tick,
+        } => {
+            eclipses::plot::check_path(&file)?;
+            let pass = first_pass(&mut setup, &observer, from, to(YEAR), every)?;
+            let ticks = Schedule {
+                interval: tick,
+                offset: 0.0,
+            };
+            pass.draw(&file, ticks)
+        }
+        #[cfg(feature = "plot")]
+        Command::Animate {
+            file,
+            frames,
+            observer,
+            image_interval,
+            image_offset,
+            delay,
+        } => {
+            if file
+                .as_ref()
+                .is_some_and(|file| file.extension() != Some("gif".as_ref()))
+            {
+                return Err(Error::Argument(
+                    "animations can only be saved as .gif".to_owned(),
+                ));
+            }
+            // fine enough for the Moon's position between the images
+            let pass = first_pass(&mut setup, &observer, from, to(YEAR), 60.0)?;
+            let schedule = Schedule {
+                interval: image_interval,
+                offset: image_offset,
+            };
+            match (file, frames) {
+                (Some(file), _) => pass.animate(&file, schedule, delay),
+                (None, Some(dir)) => {
+                    let paths = pass.draw_images(&dir, schedule)?;
+                    println!("Wrote {} images to {}", paths.len(), dir.display());
+                    Ok(())
+                }
+                (None, None) => unreachable!("clap requires --file or --frames"),
+            }
+        }
+        Command::Diff { first, second } => {
+            check_format(format, "diff")?;
+            diff_states(&first, &second, format)
+        }
+        Command::Validate { kernel, de, every } => {
+            if format != Format::Text {
+                return Err(Error::Argument(
+                    "validate only supports text output".to_owned(),
+                ));
+            }
+            let reference: Box<dyn ephemeris::Ephemeris> = match kernel {
+                Some(kernel) => Box::new(Spk::open(&kernel)?),
+                None => Box::new(DeEphemeris::open(&de)?),
+            };
+            let sim = setup.state_at(from)?;
+            setup.track(from, to(23.0 * YEAR));
+            validate(
+                sim,
+                from,
+                to(23.0 * YEAR),
+                setup.propagator,
+                reference.as_ref(),
+                every,
+                &setup.progress,
+            );
+            setup.progress.finish_and_clear();
+            Ok(())
+        }
+    }
+}
 
-        let shadow_cone_height = re * dist / (sun.radius - re);
-        let moon_rel = moon.pos - earth.pos;
+impl ModelOptions {
+    fn step_control(&self) -> StepControl {
+        match self.adaptive {
+            Some(tolerance) => StepControl::Adaptive {
+                tolerance,
+                min_step: 1.0,
+                max_step: self.max_step,
+            },
+            None => StepControl::Fixed(self.step),
+        }
+    }
 
-        let a = (re / shadow_cone_height).asin();
+    fn setup(&self, progress: ProgressBar) -> Result<Setup> {
+        // the searches check their conditions once a step
+        if self.adaptive.is_none() && self.step > self.max_step {
+            return Err(Error::Argument(format!(
+                "a step of {} s could miss events shorter than --max-step ({} s)",
+                self.step, self.max_step
+            )));
+        }
+        let (sim, start) = if !self.horizons.is_empty() {
+            horizons::load(&self.horizons)?
+        } else if let Some(ref path) = self.bodies {
+            (bodies::load(path)?, 0.0)
+        } else {
+            (bodies::solar_system(), 0.0)
+        };
+        let sim = self
+            .include
+            .iter()
+            .try_fold(sim, |sim, group| catalog::include(sim, group, start))?;
+        let sim = self
+            .elements
+            .iter()
+            .try_fold(sim, |sim, path| elements::load(path, sim, start))?;
 
-        let h = moon_rel.dot(&light_dir);
-        let r_vec = moon_rel - light_dir * h;
-        let r = r_vec.dot(&r_vec).sqrt() / a.cos();
-        let h2 = shadow_cone_height - h + r * a.sin();
+        let sim = if self.j2 {
+            sim.with_oblateness("Earth", bodies::earth_oblateness())
+        } else {
+            sim
+        };
 
-        if h > 0.0 && (r + moon.radius) / h2 < re / shadow_cone_height {
-            return Some(Eclipse::TotalLunar);
+        let mut sim = if self.relativistic {
+            sim.with_relativity("Sun")
+        } else {
+            sim
+        };
+        if self.recenter {
+            let (pos, vel) = sim.recenter();
+            info!(
+                offset = pos.norm(),
+                velocity = vel.norm(),
+                "moved the barycenter to the origin"
+            );
         }
 
-        if h > 0.0 && (r - moon.radius) / h2 < re / shadow_cone_height {
-            return Some(Eclipse::PartialLunar);
+        let step = self.step_control();
+        let model = format!(
+            "initial state {} at {}, {:?}, {:?}, recentered every {:?}",
+            cache::fingerprint(&sim),
+            start,
+            self.integrator,
+            step,
+            self.recenter_every
+        );
+        let model = if self.anchor.is_empty() {
+            model
+        } else {
+            format!(
+                "{}, anchored to {:?} every {}",
+                model, self.anchor, self.anchor_every
+            )
+        };
+        let model = if cfg!(feature = "double-double") {
+            format!("{}, double-double precision", model)
+        } else if self.high_precision {
+            format!("{}, compensated summation", model)
+        } else {
+            model
+        };
+        let model = match self.sun_radius {
+            Some(radius) => format!("{}, Sun radius {} km", model, radius),
+            None => model,
+        };
+        let model = if self.refraction {
+            format!("{}, refraction", model)
+        } else {
+            model
+        };
+        // the parts start from different states than a single search reaches there
+        let model = if self.shards > 1 {
+            format!("{}, {} shards", model, self.shards)
+        } else {
+            model
+        };
+        let bar = progress.clone();
+        let propagator = Propagator::new(step);
+        let propagator = match self.recenter_every {
+            Some(interval) => propagator.recenter_every(interval),
+            None => propagator,
+        };
+        let propagator = if self.anchor.is_empty() {
+            propagator
+        } else {
+            let reference = ephemeris::open(&self.anchor)?;
+            propagator.anchored(Arc::from(reference), self.anchor_every)
+        };
+        let propagator = if self.high_precision {
+            propagator.compensated()
+        } else {
+            propagator
+        };
+        let propagator = propagator
+            .with_integrator(self.integrator)
+            .on_step(move |step| {
+                if !bar.is_finished() {
+                    bar.inc(step as u64);
+                    if Some(bar.position()) >= bar.length() {
+                        bar.finish_and_clear();
+                    }
+                }
+            });
+        Ok(Setup {
+            sim,
+            start,
+            propagator,
+            progress,
+            snapshots: None,
+            shards: self.shards,
+            model,
+            sun_radius: self.sun_radius,
+            refraction: self.refraction,
+            cache: None,
+            #[cfg(feature = "sqlite")]
+            database: None,
+        })
+    }
+}
+
+impl SnapshotOptions {
+    fn open(&self) -> Result<Option<Snapshots>> {
+        match self.snapshot_dir {
+            Some(ref dir) => Ok(Some(
+                open_snapshots(dir)?.with_min_interval(self.snapshot_min_interval.unwrap_or(0.0)),
+            )),
+            None => Ok(None),
         }
+    }
+}
 
-        None
+impl ObserverArgs {
+    /// The observers given on the command line or listed in the --observers file; `halo` and
+    /// `half_fov` (in degrees) only apply to the former.
+    fn specs(&self, halo: Option<&str>, half_fov: Option<f64>) -> Result<Vec<ObserverSpec>> {
+        if let Some(ref path) = self.observers {
+            let contents =
+                fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+            let list: ObserverList =
+                toml::from_str(&contents).map_err(|err| Error::from(err).in_file(path))?;
+            // the elements files are relative to the list
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let mut specs = list.observer;
+            for spec in &mut specs {
+                spec.tle = spec.tle.as_ref().map(|tle| dir.join(tle));
+                if let Some(ref name) = spec.name {
+                    if !observer::NAMES.contains(&name.as_str()) {
+                        return Err(Error::Argument(format!("unknown observer {}", name)));
+                    }
+                }
+            }
+            if specs.is_empty() {
+                return Err(Error::Argument(format!(
+                    "{} lists no observers",
+                    path.display()
+                )));
+            }
+            return Ok(specs);
+        }
+        let spec = ObserverSpec {
+            name: None,
+            tle: self.tle.clone(),
+            station: self.station.clone(),
+            lagrange: self.lagrange,
+            halo: halo.map(str::to_owned),
+            half_fov,
+        };
+        if self.observer.is_empty() {
+            return Ok(vec![spec]);
+        }
+        Ok(self
+            .observer
+            .iter()
+            .map(|name| ObserverSpec {
+                name: Some(name.clone()),
+                ..spec.clone()
+            })
+            .collect())
     }
 }
 
-fn main() {
-    let epoch = DateTime::<Utc>::from_str("2000-01-01T00:00:00Z").unwrap();
-
-    let mut sim = SimState::new()
-        .with_body(Body {
-            name: "Sun".to_owned(),
-            gm: 132712440041.93938,
-            pos: Vector3::new(
-                -1.068108951496322E+06,
-                -4.177210908491462E+05,
-                3.086887010002915E+04,
-            ),
-            vel: Vector3::new(
-                9.305302656256911E-03,
-                -1.283177282717393E-02,
-                -1.631700118015769E-04,
-            ),
-            radius: 696000.0,
-        })
-        .with_body(Body {
-            name: "Mercury".to_owned(),
-            gm: 22031.86855,
-            pos: Vector3::new(
-                -2.212073002393702E+07,
-                -6.682435921338345E+07,
-                -3.461577076477692E+06,
-            ),
-            vel: Vector3::new(
-                3.666229234452722E+01,
-                -1.230266984222893E+01,
-                -4.368336206255391E+00,
-            ),
-            radius: 2440.0,
-        })
-        .with_body(Body {
-            name: "Venus".to_owned(),
-            gm: 324858.592,
-            pos: Vector3::new(
-                -1.085736592234813E+08,
-                -3.784241757371509E+06,
-                6.190088659339075E+06,
-            ),
-            vel: Vector3::new(
-                8.984650886248794E-01,
-                -3.517203951420625E+01,
-                -5.320225928762774E-01,
-            ),
-            radius: 6052.0,
-        })
-        .with_body(Body {
-            name: "Earth".to_owned(),
-            gm: 398600.435436,
-            pos: Vector3::new(
-                -2.627903751048988E+07,
-                1.445101984929515E+08,
-                3.025245352813601E+04,
-            ),
-            vel: Vector3::new(
-                -2.983052803412253E+01,
-                -5.220465675237847E+00,
-                -1.014855999592612E-04,
-            ),
-            radius: 6371.0,
-        })
-        .with_body(Body {
-            name: "Moon".to_owned(),
-            gm: 4902.800066,
-            pos: Vector3::new(
-                -2.659668775178492E+07,
-                1.442683153167126E+08,
-                6.680827660505474E+04,
-            ),
-            vel: Vector3::new(
-                -2.926974096801152E+01,
-                -6.020397935372383E+00,
-                -1.740818643718001E-03,
-            ),
-            radius: 1737.0,
-        })
-        .with_body(Body {
-            name: "Mars".to_owned(),
-            gm: 42828.375214,
-            pos: Vector3::new(
-                2.069270543147017E+08,
-                -3.560689745239088E+06,
-                -5.147936537447235E+06,
-            ),
-            vel: Vector3::new(
-                1.304308833322233E+00,
-                2.628158890420931E+01,
-                5.188465740839767E-01,
-            ),
-            radius: 3390.0,
-        })
-        .with_body(Body {
-            name: "Jupiter".to_owned(),
-            gm: 126686531.900,
-            pos: Vector3::new(
-                5.978410555886381E+08,
-                4.387048655696349E+08,
-                -1.520164176015472E+07,
-            ),
-            vel: Vector3::new(
-                -7.892632213479861E+00,
-                1.115034525890079E+01,
-                1.305100448596264E-01,
-            ),
-            radius: 69911.0,
-        })
-        .with_body(Body {
-            name: "Saturn".to_owned(),
-            gm: 37931206.159,
-            pos: Vector3::new(
-                9.576383364792708E+08,
-                9.821475307689621E+08,
-                -5.518981181311160E+07,
-            ),
-            vel: Vector3::new(
-                -7.419580382572883E+00,
-                6.725982471305630E+00,
-                1.775012039800541E-01,
-            ),
-            radius: 58232.0,
+/// The contents of an --observers file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObserverList {
+    #[serde(default)]
+    observer: Vec<ObserverSpec>,
+}
+
+/// One observer, as it's given on the command line.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObserverSpec {
+    name: Option<String>,
+    tle: Option<PathBuf>,
+    station: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lagrange")]
+    lagrange: Option<LagrangePoint>,
+    halo: Option<String>,
+    /// In degrees.
+    half_fov: Option<f64>,
+}
+
+fn deserialize_lagrange<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<LagrangePoint>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+impl ObserverSpec {
+    /// Identifies the observer for the cached results.
+    fn describe(&self) -> Result<String> {
+        let observer = if let Some(ref path) = self.tle {
+            let elements = fs::read(path).map_err(|err| Error::from(err).in_file(path))?;
+            format!("elements {:08x}", crc32fast::hash(&elements))
+        } else if let Some(ref station) = self.station {
+            format!("station {}", station)
+        } else if let Some(point) = self.lagrange {
+            format!(
+                "{} on halo orbit {}",
+                point,
+                self.halo.as_deref().unwrap_or("none")
+            )
+        } else if let Some(ref name) = self.name {
+            name.clone()
+        } else {
+            return Err(Error::Argument(
+                "an observer needs one of name, tle, station or lagrange".to_owned(),
+            ));
+        };
+        Ok(match self.half_fov {
+            Some(half_fov) => format!("{}, half FOV {}°", observer, half_fov),
+            None => observer,
         })
-        .with_body(Body {
-            name: "Uranus".to_owned(),
-            gm: 5793951.322,
-            pos: Vector3::new(
-                2.157706590772995E+09,
-                -2.055242872276605E+09,
-                -3.559274281048691E+07,
-            ),
-            vel: Vector3::new(
-                4.646953838324629E+00,
-                4.614361336011624E+00,
-                -4.301369677250144E-02,
-            ),
-            radius: 25362.0,
+    }
+
+    fn observer(&self, refraction: bool) -> Result<Box<dyn Observer>> {
+        let half_fov = self.half_fov.map(f64::to_radians);
+        if let Some(ref path) = self.tle {
+            let satellite = TleSatellite::load(path)?;
+            match half_fov {
+                Some(half_fov) => Ok(Box::new(satellite.with_half_fov(half_fov))),
+                None => Ok(Box::new(satellite)),
+            }
+        } else if let Some(ref station) = self.station {
+            Ok(Box::new(parse_station(station, refraction)?))
+        } else if let Some(point) = self.lagrange {
+            let mut observer = LagrangeObserver::new(&point.to_string(), point);
+            if let Some(half_fov) = half_fov {
+                observer = observer.with_half_fov(half_fov);
+            }
+            match self.halo {
+                Some(ref halo) => Ok(Box::new(observer.with_orbit(parse_halo(halo)?))),
+                None => Ok(Box::new(observer)),
+            }
+        } else if let Some(ref name) = self.name {
+            Ok(match half_fov {
+                Some(half_fov) => observer::by_name_with_half_fov(name, half_fov).unwrap(),
+                None => observer::by_name(name).unwrap(),
+            })
+        } else {
+            Err(Error::Argument(
+                "an observer needs one of name, tle, station or lagrange".to_owned(),
+            ))
+        }
+    }
+}
+
+/// Saves the states at the starts of months between `from` and `to`, at least about `every`
+/// seconds apart, propagating the part before the initial state backwards on another thread.
+fn generate_snapshots(setup: &Setup, dir: &Path, from: f64, to: f64, every: f64) -> Result<()> {
+    // the months are 28 to 31 days long
+    let open = || open_snapshots(dir).map(|snapshots| snapshots.with_min_interval(0.9 * every));
+    let (sim, start) = (&setup.sim, setup.start);
+    let propagator = &setup.propagator;
+    setup.track(from.min(start), to);
+
+    let (backward, forward) = rayon::join(
+        || -> Result<()> {
+            if from >= start {
+                return Ok(());
+            }
+            let mut snapshots = open()?;
+            let mut sim = sim.clone();
+            snapshots::propagate_back(
+                &mut snapshots,
+                &mut sim,
+                start,
+                from,
+                &mut propagator.clone(),
+            )
+        },
+        || -> Result<()> {
+            let mut snapshots = open()?;
+            let mut sim = sim.clone();
+            let mut propagator = propagator.clone();
+            // month by month, so that the gaps between any existing snapshots are filled
+            let mut time = start;
+            let mut month = snapshots::next_month(snapshots::previous_month(from.max(start)));
+            while month <= to {
+                snapshots.propagate(&mut sim, time, month, &mut propagator)?;
+                time = month;
+                month = snapshots::next_month(month);
+            }
+            Ok(())
+        },
+    );
+    setup.progress.finish_and_clear();
+    backward.and(forward)
+}
+
+/// The command line arguments, without the program name and any `--manifest` option.
+fn arguments_without_manifest() -> Vec<String> {
+    let mut arguments = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--manifest" {
+            args.next();
+        } else if !arg.starts_with("--manifest=") {
+            arguments.push(arg);
+        }
+    }
+    arguments
+}
+
+/// Runs the program with `arguments` in a child process, returning what it prints, which is
+/// passed through as well if `echo` is set.
+fn run_captured(arguments: &[String], echo: bool) -> Result<Vec<u8>> {
+    let mut child = process::Command::new(env::current_exe()?)
+        .args(arguments)
+        .stdout(process::Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("the output is piped");
+    let mut output = vec![];
+    let mut buffer = [0u8; 8192];
+    loop {
+        let len = stdout.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        if echo {
+            io::stdout().write_all(&buffer[..len])?;
+        }
+        output.extend_from_slice(&buffer[..len]);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::Argument(format!("the run failed with {}", status)));
+    }
+    Ok(output)
+}
+
+/// Runs the program with `arguments` like `run_captured`, and records the run.
+fn run_with_manifest(arguments: &[String], echo: bool) -> Result<Manifest> {
+    let cli =
+        Cli::try_parse_from(iter::once("eclipses").chain(arguments.iter().map(String::as_str)))
+            .map_err(|err| Error::Argument(err.to_string()))?;
+    let mut setup = cli.model.setup(ProgressBar::hidden())?;
+    if let Some(ref dir) = cli.snapshots.snapshot_dir {
+        setup.model += &format!(", snapshots in {}", dir.display());
+    }
+    let output = run_captured(arguments, echo)?;
+    let snapshots = match cli.snapshots.snapshot_dir {
+        Some(ref dir) => manifest::snapshot_files(dir)?,
+        None => vec![],
+    };
+    Ok(Manifest {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        arguments: arguments.to_vec(),
+        initial_state: cache::fingerprint(&setup.sim),
+        integrator: format!("{:?}", cli.model.integrator),
+        step: format!("{:?}", cli.model.step_control()),
+        model: setup.model,
+        snapshots,
+        output: FileChecksum::new("stdout", &output),
+    })
+}
+
+fn verify_manifest(path: &Path) -> Result<()> {
+    let recorded = Manifest::load(path)?;
+    let current = run_with_manifest(&recorded.arguments, false)?;
+    let differences = recorded.differences(&current);
+    if !differences.is_empty() {
+        return Err(Error::Manifest(differences.join("; ")));
+    }
+    println!(
+        "Reproduced the output of {} bytes with checksum {}",
+        current.output.size, current.output.checksum
+    );
+    Ok(())
+}
+
+/// Only `predict`, `position` and `trajectory` write CSV, and only `predict` iCalendar.
+fn check_format(format: Format, command: &str) -> Result<()> {
+    match format {
+        Format::Text | Format::Json => Ok(()),
+        _ => Err(Error::Argument(format!(
+            "{} only supports text and json output",
+            command
+        ))),
+    }
+}
+
+fn parse_number(value: &str, option: &str) -> Result<f64> {
+    value
+        .parse()
+        .map_err(|_| Error::Argument(format!("{} has to be a number, got \"{}\"", option, value)))
+}
+
+fn open_snapshots(dir: &Path) -> Result<Snapshots> {
+    let mut snapshots = Snapshots::open(dir).map_err(|err| err.in_file(dir))?;
+    warn_skipped(&mut snapshots);
+    Ok(snapshots)
+}
+
+fn warn_skipped(snapshots: &mut Snapshots) {
+    for (path, err) in snapshots.take_skipped() {
+        warn!("skipping snapshot {}: {}", path.display(), err);
+    }
+}
+
+/// Writes to stderr, hiding the progress bar while doing it.
+struct LogWriter(ProgressBar);
+
+impl io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+fn init_logging(verbose: u8, progress: &ProgressBar) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,eclipses={}", level)));
+    let progress = progress.clone();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(move || LogWriter(progress.clone()))
+        .init();
+}
+
+/// Parses a time interval like 90days, 2weeks, 6months or 1year into seconds. Months and
+/// years are the average ones of the Julian calendar.
+fn parse_interval(value: &str) -> Result<f64> {
+    let split = value
+        .find(|c: char| c.is_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let unit = match unit.trim_end_matches('s') {
+        "" => Some(1.0),
+        "min" => Some(60.0),
+        "h" | "hour" => Some(3600.0),
+        "d" | "day" => Some(86400.0),
+        "w" | "week" => Some(7.0 * 86400.0),
+        "month" => Some(YEAR / 12.0),
+        "y" | "year" => Some(YEAR),
+        _ => None,
+    };
+    match (number.trim().parse::<f64>(), unit) {
+        (Ok(number), Some(unit)) if number > 0.0 => Ok(number * unit),
+        _ => Err(Error::Argument(
+            "expected an interval like 30days, 6months or 1year".to_owned(),
+        )),
+    }
+}
+
+/// The first pass of the Moon through the frame of the named built-in observer between `from`
+/// and `to`, with the Moon's position every `every` seconds.
+#[cfg(feature = "plot")]
+fn first_pass(
+    setup: &mut Setup,
+    observer: &str,
+    from: f64,
+    to: f64,
+    every: f64,
+) -> Result<eclipses::plot::FramePass> {
+    let observer = observer::by_name(observer).unwrap();
+    let sim = setup.state_at(from)?;
+    setup.track(from, to);
+    eclipses::plot::moon_pass(
+        &sim,
+        from,
+        to,
+        every,
+        setup.propagator.clone(),
+        observer.as_ref(),
+    )
+    .ok_or_else(|| {
+        Error::Argument(format!(
+            "the Moon doesn't pass through {}'s frame in the given period",
+            observer.name()
+        ))
+    })
+}
+
+fn parse_halo(value: &str) -> Result<HaloOrbit> {
+    let numbers = value
+        .split(',')
+        .map(|number| parse_number(number.trim(), "--halo"))
+        .collect::<Result<Vec<f64>>>()?;
+    match numbers[..] {
+        [amplitude_y, amplitude_z, days] if days > 0.0 => Ok(HaloOrbit {
+            amplitude_y,
+            amplitude_z,
+            period: days * 86400.0,
+            phase_y: 0.0,
+            phase_z: 0.5 * std::f64::consts::PI,
+        }),
+        _ => Err(Error::Argument(format!(
+            "--halo has to be AY,AZ,DAYS, got \"{}\"",
+            value
+        ))),
+    }
+}
+
+/// Like `parse_interval`, but also accepts zero.
+fn parse_offset(value: &str) -> Result<f64> {
+    if value.parse() == Ok(0.0) {
+        Ok(0.0)
+    } else {
+        parse_interval(value)
+    }
+}
+
+fn parse_date(value: &str) -> Result<f64> {
+    Ok(utc_to_sim_time(time::parse_date(value)?))
+}
+
+fn parse_station(value: &str, refraction: bool) -> Result<GroundStation> {
+    let coords = value
+        .split(',')
+        .map(|coord| parse_number(coord.trim(), "--station"))
+        .collect::<Result<Vec<f64>>>()?;
+    let station = match coords[..] {
+        [lat, lon] => GroundStation::new(value, lat, lon, 0.0),
+        [lat, lon, alt] => GroundStation::new(value, lat, lon, alt),
+        _ => {
+            return Err(Error::Argument(format!(
+                "--station has to be LAT,LON or LAT,LON,ALT, got \"{}\"",
+                value
+            )))
+        }
+    };
+    Ok(if refraction {
+        station.with_refraction()
+    } else {
+        station
+    })
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn predict(args: PredictArgs, setup: &mut Setup, from: f64, to: f64, format: Format) -> Result<()> {
+    if args.summary {
+        check_format(format, "predict --summary")?;
+    }
+    let specs = args.observer.specs(args.halo.as_deref(), args.half_fov)?;
+    let observers = specs
+        .iter()
+        .map(|spec| spec.observer(setup.refraction))
+        .collect::<Result<Vec<_>>>()?;
+    let observers: Vec<&dyn Observer> = observers.iter().map(|observer| &**observer).collect();
+    let observer = observers[0];
+    if observers.len() > 1
+        && (args.images || args.resume.is_some() || args.checkpoint_interval.is_some())
+    {
+        return Err(Error::Argument(
+            "--images and checkpoints need a single observer".to_owned(),
+        ));
+    }
+    if args.images {
+        #[cfg(feature = "parquet")]
+        if args.parquet.is_some() {
+            return Err(Error::Argument(
+                "--parquet can't be used with --images".to_owned(),
+            ));
+        }
+        let schedule = Schedule {
+            interval: args.image_interval,
+            offset: args.image_offset,
+        };
+        let sim = setup.state_at(from)?;
+        setup.track(from, to);
+        let images =
+            images::moon_images(&sim, from, to, setup.propagator.clone(), observer, schedule);
+        let stdout = io::stdout();
+        output::write_images(&mut stdout.lock(), &images, format)?;
+        return Ok(());
+    }
+    let checkpoints = args
+        .checkpoint_interval
+        .map(|interval| (interval, args.checkpoint.as_path()));
+    let margin = args.edge_margin.to_radians();
+    let sampling = (margin, args.sample_interval.unwrap_or(0.0));
+    let backward = args.direction == "backward";
+    let events = if backward {
+        if to >= from {
+            return Err(Error::Argument(
+                "--direction backward needs --to before --from".to_owned(),
+            ));
+        }
+        #[cfg(feature = "sqlite")]
+        if setup.database.is_some() {
+            warn!("the event database isn't used when searching backward");
+        }
+        let sim = setup.state_at(from)?;
+        setup.track(from, to);
+        let propagator = setup.propagator.clone().backward();
+        events::visibility_events_of(&sim, from, to, propagator, &observers, sampling)
+    } else if args.resume.is_none() && checkpoints.is_none() {
+        let mut description = specs
+            .iter()
+            .map(ObserverSpec::describe)
+            .collect::<Result<Vec<_>>>()?
+            .join("; ");
+        if margin != 0.0 {
+            description += &format!(", edge margin {}°", args.edge_margin);
+        }
+        if let Some(interval) = args.sample_interval {
+            description += &format!(", sampled every {} s", interval);
+        }
+        setup.search(
+            &format!("visibility for {}", description),
+            from,
+            to,
+            |event: &Event| event.date,
+            |sim, start, end, propagator| {
+                events::visibility_events_of(sim, start, end, propagator, &observers, sampling)
+            },
+        )?
+    } else {
+        #[cfg(feature = "sqlite")]
+        if setup.database.is_some() {
+            warn!("the event database isn't used with checkpoints");
+        }
+        let checkpoint = match args.resume {
+            Some(ref path) => {
+                let checkpoint = Checkpoint::load(path)?;
+                if checkpoint.observer != observer.name() {
+                    return Err(Error::Argument(format!(
+                        "the checkpoint is for observer {}, not {}",
+                        checkpoint.observer,
+                        observer.name()
+                    )));
+                }
+                checkpoint
+            }
+            None => Checkpoint::new(observer, &setup.state_at(from)?, from),
+        };
+        setup.track(checkpoint.time, to);
+        generate(
+            checkpoint,
+            to,
+            setup.propagator.clone(),
+            observer,
+            sampling,
+            checkpoints,
+        )?
+    };
+    write_visibility_events(args, setup, &observers, events, from.min(to), format)
+}
+
+/// Filters the visibility events of `observers` found since `from` as asked and writes them
+/// out.
+fn write_visibility_events(
+    args: PredictArgs,
+    setup: &mut Setup,
+    observers: &[&dyn Observer],
+    mut events: Vec<Event>,
+    from: f64,
+    format: Format,
+) -> Result<()> {
+    if let Some(min_duration) = args.min_duration {
+        events = events::drop_short_visibilities(&events, min_duration);
+    }
+    if let Some(exclusion) = args.sun_exclusion {
+        exclude_sun_glint(&mut events, setup, observers, from, exclusion.to_radians())?;
+    }
+    if let Some(min_illumination) = args.min_illumination {
+        events.retain(|event| event.illuminated_fraction >= min_illumination);
+    }
+    let stdout = io::stdout();
+    if args.summary {
+        let summaries = events::summarize(&events::visibility_windows(&events));
+        output::write_summaries(&mut stdout.lock(), &summaries, format)?;
+    } else {
+        output::write_events(&mut stdout.lock(), &events, format)?;
+    }
+
+    if let Some(path) = args.ics {
+        let windows = events::visibility_windows(&events);
+        File::create(&path)
+            .and_then(|mut file| {
+                output::write_ics_windows(&mut BufWriter::new(&mut file), &windows)
+            })
+            .map_err(|err| Error::from(err).in_file(path))?;
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(path) = args.parquet {
+        eclipses::columnar::write_events(&path, &events)?;
+    }
+    Ok(())
+}
+
+/// The key of an eclipse search, which starts from different states when prescreened.
+fn eclipse_key(key: &str, prescreen: bool) -> String {
+    if prescreen {
+        format!("{}, prescreened", key)
+    } else {
+        key.to_owned()
+    }
+}
+
+/// Runs an eclipse `search` between `start` and `end`, only around the `syzygy`s where
+/// eclipses are possible if `prescreen` is set.
+fn search_eclipses<T>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: Propagator,
+    prescreen: bool,
+    syzygy: Syzygy,
+    search: impl Fn(&SimState, f64, f64, Propagator) -> Vec<T>,
+) -> Vec<T> {
+    if prescreen {
+        let windows = eclipse::eclipse_windows(start, end, syzygy);
+        eclipse::screened(sim, start, propagator, &windows, search)
+    } else {
+        search(sim, start, end, propagator)
+    }
+}
+
+/// Finds the first `count` visibility windows or eclipses after `from`, or the ones before
+/// `limit` (simulation times) if there are fewer.
+fn next(
+    what: NextKind,
+    setup: &mut Setup,
+    from: f64,
+    limit: f64,
+    count: usize,
+    format: Format,
+) -> Result<()> {
+    match what {
+        NextKind::Visibility { observer } => {
+            let spec = match observer.specs(None, None)?.as_slice() {
+                [spec] => spec.clone(),
+                _ => {
+                    return Err(Error::Argument(
+                        "next visibility needs a single observer".to_owned(),
+                    ))
+                }
+            };
+            let observer = spec.observer(setup.refraction)?;
+            let key = format!("visibility for {}", spec.describe()?);
+            let windows = search_first(count, from, limit, 30.0 * 86400.0, |to| {
+                let events = setup.search(
+                    &key,
+                    from,
+                    to,
+                    |event: &Event| event.date,
+                    |sim, start, end, propagator| {
+                        events::visibility_events(sim, start, end, propagator, &*observer)
+                    },
+                )?;
+                Ok(events::visibility_windows(&events))
+            })?;
+            let events: Vec<Event> = windows
+                .into_iter()
+                .flat_map(|window| window.events)
+                .collect();
+            let stdout = io::stdout();
+            output::write_events(&mut stdout.lock(), &events, format)?;
+            Ok(())
+        }
+        NextKind::LunarEclipse => {
+            check_format(format, "next")?;
+            let eclipses = search_first(count, from, limit, YEAR, |to| {
+                setup.search(
+                    "lunar-eclipses",
+                    from,
+                    to,
+                    |eclipse: &eclipse::LunarEclipse| eclipse.greatest,
+                    |sim, start, end, propagator| {
+                        eclipse::find_lunar_eclipses(sim, start, end, propagator)
+                    },
+                )
+            })?;
+            print_lunar_eclipses(&eclipses, format)
+        }
+        NextKind::SolarEclipse => {
+            check_format(format, "next")?;
+            let eclipses = search_first(count, from, limit, YEAR, |to| {
+                setup.search(
+                    "solar-eclipses",
+                    from,
+                    to,
+                    |eclipse: &eclipse::SolarEclipse| eclipse.greatest,
+                    |sim, start, end, propagator| {
+                        eclipse::find_solar_eclipses(sim, start, end, propagator)
+                    },
+                )
+            })?;
+            print_solar_eclipses(&eclipses, format)
+        }
+    }
+}
+
+/// Calls `search` with the end of the searched period, starting `length` after `from` and
+/// doubling it until there are `count` results or the period reaches `limit`, and returns the
+/// first `count` results.
+fn search_first<T>(
+    count: usize,
+    from: f64,
+    limit: f64,
+    mut length: f64,
+    mut search: impl FnMut(f64) -> Result<Vec<T>>,
+) -> Result<Vec<T>> {
+    loop {
+        let to = (from + length).min(limit);
+        let mut found = search(to)?;
+        if found.len() >= count || to >= limit {
+            found.truncate(count);
+            return Ok(found);
+        }
+        info!(found = found.len(), until = %sim_time_to_utc(to), "searching further");
+        length *= 2.0;
+    }
+}
+
+/// Removes the events of the visibility windows during which the Sun comes within `exclusion`
+/// radians of the boresight or of the Moon.
+fn exclude_sun_glint(
+    events: &mut Vec<Event>,
+    setup: &mut Setup,
+    observers: &[&dyn Observer],
+    from: f64,
+    exclusion: f64,
+) -> Result<()> {
+    let mut sim = setup.state_at(from)?;
+    let mut time = from;
+    let mut excluded = vec![];
+    let mut windows = events::visibility_windows(events);
+    // the windows of different observers overlap, but the propagation only goes forward
+    windows.sort_by_key(|window| window.start);
+    for window in windows {
+        let Some(&observer) = observers
+            .iter()
+            .find(|observer| observer.name() == window.observer)
+        else {
+            continue;
+        };
+        let (start, end) = (utc_to_sim_time(window.start), utc_to_sim_time(window.end));
+        setup.track(time, end);
+        setup.propagator.advance(&mut sim, start - time);
+        time = start;
+        let closest =
+            events::sun_proximity(&sim, start, end, 60.0, setup.propagator.clone(), observer);
+        if closest.boresight < exclusion || closest.moon < exclusion {
+            info!(
+                start = %window.start,
+                end = %window.end,
+                boresight = closest.boresight.to_degrees(),
+                moon = closest.moon.to_degrees(),
+                "excluding a window with the Sun too close"
+            );
+            excluded.push((window.observer, window.start, window.end));
+        }
+    }
+    events.retain(|event| {
+        !excluded.iter().any(|(observer, start, end)| {
+            event.observer == *observer && event.date >= *start && event.date <= *end
         })
-        .with_body(Body {
-            name: "Neptune".to_owned(),
-            gm: 6835099.97,
-            pos: Vector3::new(
-                2.513785451779509E+09,
-                -3.739265135509532E+09,
-                1.907027540535474E+07,
-            ),
-            vel: Vector3::new(
-                4.475107938022004E+00,
-                3.062850546988970E+00,
-                -1.667293921151841E-01,
-            ),
-            radius: 24624.0,
-        });
+    });
+    Ok(())
+}
 
-    let mut integrator = SuzukiIntegrator::new(STEP);
-    let mut time = 0.0;
-    let mut current_eclipse = None;
+fn print_lunar_eclipses(eclipses: &[eclipse::LunarEclipse], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(eclipses);
+    }
+    for eclipse in eclipses {
+        println!(
+            "{:?}: greatest = {}, umbral magnitude = {:.4}, penumbral magnitude = {:.4}, {}",
+            eclipse.kind,
+            eclipse.greatest,
+            eclipse.umbral_magnitude,
+            eclipse.penumbral_magnitude,
+            eclipse.saros
+        );
+        let contacts = eclipse.contacts;
+        println!("  P1: {}", contacts.p1);
+        let umbral = [
+            ("U1", contacts.u1),
+            ("U2", contacts.u2),
+            ("U3", contacts.u3),
+            ("U4", contacts.u4),
+        ];
+        for (name, contact) in umbral.iter() {
+            if let Some(date) = contact {
+                println!("  {}: {}", name, date);
+            }
+        }
+        println!("  P4: {}\n", contacts.p4);
+    }
+    Ok(())
+}
+
+/// Lists the `eclipses` that can be seen from `station` with the Moon up and `twilight` over,
+/// with when they can.
+fn print_dark_lunar_eclipses(
+    eclipses: &[eclipse::LunarEclipse],
+    setup: &mut Setup,
+    from: f64,
+    station: &GroundStation,
+    twilight: Twilight,
+    format: Format,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        eclipse: &'a eclipse::LunarEclipse,
+        visible_start: DateTime<Utc>,
+        visible_end: DateTime<Utc>,
+    }
 
-    let mut eclipse_detector = EclipseDetector::new();
+    let mut sim = setup.state_at(from)?;
+    let mut time = from;
+    let mut entries = vec![];
+    for eclipse in eclipses {
+        let contacts = eclipse.contacts;
+        let (first, last) = match (contacts.u1, contacts.u4) {
+            (Some(u1), Some(u4)) => (u1, u4),
+            _ => (contacts.p1, contacts.p4),
+        };
+        let (start, end) = (utc_to_sim_time(first), utc_to_sim_time(last));
+        setup.track(time, end);
+        setup.propagator.advance(&mut sim, start - time);
+        time = start;
+        let visible = rise::visible_in_darkness(
+            &sim,
+            start,
+            end,
+            setup.propagator.clone(),
+            station,
+            "Moon",
+            twilight,
+        );
+        if let Some((visible_start, visible_end)) = visible {
+            entries.push(Entry {
+                eclipse,
+                visible_start,
+                visible_end,
+            });
+        }
+    }
+    if format == Format::Json {
+        return print_json(&entries);
+    }
+    for entry in entries {
+        let eclipse = entry.eclipse;
+        println!(
+            "{:?}: greatest = {}, umbral magnitude = {:.4}, penumbral magnitude = {:.4}, {}",
+            eclipse.kind,
+            eclipse.greatest,
+            eclipse.umbral_magnitude,
+            eclipse.penumbral_magnitude,
+            eclipse.saros
+        );
+        println!(
+            "  Visible in darkness: {} to {}\n",
+            entry.visible_start, entry.visible_end
+        );
+    }
+    Ok(())
+}
 
-    while time < 23.0 * YEAR {
-        integrator.propagate_in_place(
-            &mut sim,
-            SimState::position_derivative,
-            SimState::momentum_derivative,
-            StepSize::UseDefault,
+fn print_solar_eclipses(eclipses: &[eclipse::SolarEclipse], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(eclipses);
+    }
+    for eclipse in eclipses {
+        println!(
+            "{:?}: greatest = {}, gamma = {:.4}, magnitude = {:.4}, obscuration = {:.4}, {}",
+            eclipse.kind,
+            eclipse.greatest,
+            eclipse.gamma,
+            eclipse.magnitude,
+            eclipse.obscuration,
+            eclipse.saros
         );
-        time += STEP;
-
-        let sun = sim.body_by_name("Sun").unwrap();
-        let earth = sim.body_by_name("Earth").unwrap();
-
-        eclipse_detector.save_light_dir(time, earth.pos - sun.pos);
-
-        let new_eclipse = eclipse_detector.detect_eclipse(&sim, time);
-
-        if new_eclipse != current_eclipse {
-            // find the actual transition moment with 1 sec precision
-            let mut time2 = time;
-            let mut sim2 = sim.clone();
-            let step2 = 1.0;
-            loop {
-                integrator.propagate_in_place(
-                    &mut sim2,
-                    SimState::position_derivative,
-                    SimState::momentum_derivative,
-                    StepSize::Step(-step2),
+        let contacts = eclipse.contacts;
+        println!("  P1: {}", contacts.p1);
+        if let (Some(start), Some(end)) = (contacts.central_start, contacts.central_end) {
+            println!("  Central line: {} to {}", start, end);
+        }
+        println!("  P4: {}\n", contacts.p4);
+    }
+    Ok(())
+}
+
+fn print_local_eclipses(eclipses: &[eclipse::LocalEclipse], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(eclipses);
+    }
+    let print_contact = |name: &str, contact: &eclipse::LocalContact| {
+        println!(
+            "  {}: {}, Sun altitude = {:.1}°",
+            name, contact.date, contact.sun_altitude
+        );
+    };
+    for eclipse in eclipses {
+        println!(
+            "{:?}: magnitude = {:.4}, obscuration = {:.4}",
+            eclipse.kind, eclipse.magnitude, eclipse.obscuration
+        );
+        let contacts = eclipse.contacts;
+        print_contact("C1", &contacts.c1);
+        if let Some(ref c2) = contacts.c2 {
+            print_contact("C2", c2);
+        }
+        print_contact("Maximum", &eclipse.maximum);
+        if let Some(ref c3) = contacts.c3 {
+            print_contact("C3", c3);
+        }
+        print_contact("C4", &contacts.c4);
+        println!();
+    }
+    Ok(())
+}
+
+/// Traces the paths of the central eclipses among `eclipses` every `every` seconds and writes
+/// them to a GeoJSON file at `path`.
+fn write_eclipse_paths(
+    path: &Path,
+    eclipses: &[eclipse::SolarEclipse],
+    setup: &mut Setup,
+    from: f64,
+    every: f64,
+) -> Result<()> {
+    let mut sim = setup.state_at(from)?;
+    let mut time = from;
+    let mut paths = vec![];
+    for eclipse in eclipses {
+        let contacts = eclipse.contacts;
+        let (start, end) = match (contacts.central_start, contacts.central_end) {
+            (Some(start), Some(end)) => (utc_to_sim_time(start), utc_to_sim_time(end)),
+            _ => continue,
+        };
+        setup.track(time, end);
+        setup.propagator.advance(&mut sim, start - time);
+        time = start;
+        let found = eclipse::eclipse_path(&sim, start, end, every, setup.propagator.clone());
+        paths.push((*eclipse, found));
+    }
+    let json = serde_json::to_string_pretty(&geojson::eclipse_paths(&paths))?;
+    fs::write(path, json).map_err(|err| Error::from(err).in_file(path))
+}
+
+/// Writes the Besselian elements of `eclipses` to a JSON file at `path`.
+fn write_besselian_elements(
+    path: &Path,
+    eclipses: &[eclipse::SolarEclipse],
+    setup: &mut Setup,
+    from: f64,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        eclipse: &'a eclipse::SolarEclipse,
+        elements: eclipse::BesselianElements,
+    }
+
+    let mut sim = setup.state_at(from)?;
+    let mut time = from;
+    let mut entries = vec![];
+    for eclipse in eclipses {
+        let start = utc_to_sim_time(eclipse.contacts.p1);
+        setup.track(time, utc_to_sim_time(eclipse.contacts.p4));
+        setup.propagator.advance(&mut sim, start - time);
+        time = start;
+        let elements = eclipse::besselian_elements(&sim, start, eclipse, setup.propagator.clone());
+        entries.push(Entry { eclipse, elements });
+    }
+    let json = serde_json::to_string_pretty(&entries)?;
+    fs::write(path, json).map_err(|err| Error::from(err).in_file(path))
+}
+
+fn print_phases(
+    mut sim: SimState,
+    from: f64,
+    to: f64,
+    every: Option<f64>,
+    mut propagator: Propagator,
+    format: Format,
+) -> Result<()> {
+    let phases = phase::principal_phases(&sim, from, to, propagator.clone());
+    if format == Format::Json {
+        return print_json(&phases);
+    }
+
+    let mut lines: Vec<(DateTime<Utc>, String)> = phases
+        .iter()
+        .map(|event| (event.date, event.kind.description().to_owned()))
+        .collect();
+    if let Some(every) = every {
+        let mut time = from;
+        while time <= to {
+            let phase = phase::moon_phase(&sim);
+            let description = format!(
+                "illuminated fraction = {:.3}, phase angle = {:.2}°",
+                phase.illuminated_fraction,
+                phase.phase_angle.to_degrees()
+            );
+            lines.push((sim_time_to_utc(time), description));
+            propagator.advance(&mut sim, every);
+            time += every;
+        }
+        lines.sort_by_key(|(date, _)| *date);
+    }
+    for (date, description) in lines {
+        println!("{}: {}", date, description);
+    }
+    Ok(())
+}
+
+fn print_apsides(
+    sim: &SimState,
+    from: f64,
+    to: f64,
+    propagator: Propagator,
+    format: Format,
+) -> Result<()> {
+    let apsides = apsides::lunar_apsides(sim, from, to, propagator);
+    if format == Format::Json {
+        return print_json(&apsides);
+    }
+    for apsis in apsides {
+        println!(
+            "{:?}: date = {}, distance = {:.0} km, angular diameter = {:.2}'",
+            apsis.kind,
+            apsis.date,
+            apsis.distance,
+            apsis.angular_diameter.to_degrees() * 60.0
+        );
+    }
+    Ok(())
+}
+
+fn print_rise_times(events: &[rise::RiseEvent], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(events);
+    }
+    for event in events {
+        let direction = match event.kind {
+            rise::RiseKind::Transit => format!("elevation = {:.1}°", event.elevation),
+            _ => format!("azimuth = {:.1}°", event.azimuth),
+        };
+        println!(
+            "{}: {} {}, {}",
+            event.date,
+            event.body,
+            event.kind.description(),
+            direction
+        );
+    }
+    Ok(())
+}
+
+/// Position of a body or a Lagrange point, like sun-earth-l2.
+fn position_of(sim: &SimState, name: &str) -> Option<Vector3<f64>> {
+    match name.parse::<LagrangePoint>() {
+        Ok(point) => Some(lagrange::position(sim, point)),
+        Err(_) => sim.body_by_name(name).map(|body| body.pos),
+    }
+}
+
+/// The angular separation of `body` and `other` seen from `viewpoint`.
+struct SeparationQuery<'a> {
+    body: String,
+    other: String,
+    viewpoint: Viewpoint<'a>,
+    from: f64,
+    to: f64,
+    every: Option<f64>,
+}
+
+impl SeparationQuery<'_> {
+    fn print(&self, mut sim: SimState, mut propagator: Propagator, format: Format) -> Result<()> {
+        let conjunctions = separation::conjunctions(
+            &sim,
+            self.from,
+            self.to,
+            propagator.clone(),
+            self.viewpoint,
+            &[&self.body, &self.other],
+            PI,
+        );
+        if format == Format::Json {
+            return print_json(&conjunctions);
+        }
+
+        let mut lines: Vec<(DateTime<Utc>, String)> = conjunctions
+            .iter()
+            .map(|conjunction| {
+                let description = format!(
+                    "closest approach, separation = {:.4}°",
+                    conjunction.separation.to_degrees()
                 );
-                time2 -= step2;
-                if time - time2 > STEP {
-                    panic!("wtf");
-                }
-                let eclipse = eclipse_detector.detect_eclipse(&sim2, time2);
-                if eclipse == current_eclipse {
-                    break;
+                (conjunction.date, description)
+            })
+            .collect();
+        if let Some(every) = self.every {
+            let mut time = self.from;
+            while time <= self.to {
+                let separation =
+                    separation::separation(&sim, time, self.viewpoint, &self.body, &self.other);
+                let description = format!("separation = {:.4}°", separation.to_degrees());
+                lines.push((sim_time_to_utc(time), description));
+                propagator.advance(&mut sim, every);
+                time += every;
+            }
+            lines.sort_by_key(|(date, _)| *date);
+        }
+        for (date, description) in lines {
+            println!("{}: {}", date, description);
+        }
+        Ok(())
+    }
+}
+
+fn print_body_events(events: &[BodyEvent], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(events);
+    }
+    for event in events {
+        let description = match event.visibility {
+            Visibility::Visible => "visible",
+            Visibility::Obscured => "behind the Earth",
+            Visibility::OutOfFrame => "out of frame",
+            Visibility::Transiting => "in front of the Earth",
+        };
+        println!(
+            "{}: {} {} for {}",
+            event.date, event.body, description, event.observer
+        );
+    }
+    Ok(())
+}
+
+fn print_query_events(events: &[QueryEvent], query: &Query, format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(events);
+    }
+    for event in events {
+        let change = if event.holds {
+            "starts holding"
+        } else {
+            "stops holding"
+        };
+        println!(
+            "{}: \"{}\" {} for {}",
+            event.date, query, change, event.observer
+        );
+    }
+    Ok(())
+}
+
+fn print_conjunctions(conjunctions: &[Conjunction], format: Format) -> Result<()> {
+    if format == Format::Json {
+        return print_json(conjunctions);
+    }
+    for conjunction in conjunctions {
+        let separation = conjunction.separation.to_degrees();
+        match conjunction.occultation {
+            Some(contacts) => {
+                let description = if conjunction.far == "Sun" {
+                    format!("transit of {} across the Sun", conjunction.near)
+                } else {
+                    format!("occultation of {} by {}", conjunction.far, conjunction.near)
+                };
+                println!(
+                    "{}: {}, separation = {:.4}°",
+                    conjunction.date, description, separation
+                );
+                println!("    I:   {}", contacts.first);
+                if let (Some(second), Some(third)) = (contacts.second, contacts.third) {
+                    println!("    II:  {}", second);
+                    println!("    III: {}", third);
                 }
+                println!("    IV:  {}", contacts.fourth);
             }
+            None => println!(
+                "{}: conjunction of {} and {}, separation = {:.4}°",
+                conjunction.date, conjunction.near, conjunction.far, separation
+            ),
+        }
+    }
+    Ok(())
+}
 
-            let date = epoch + Duration::seconds(time2 as i64);
-            // correction TT -> UT
-            let t = date.year() as f64 + (date.month() as f64 - 0.5) / 12.0 - 2000.0;
-            let delta_t = if date.year() < 2005 {
-                63.86 + 0.3345 * t - 0.060374 * t * t + 0.0017275 * t * t * t
-            } else {
-                62.92 + 0.32217 * t + 0.005589 * t * t
+/// Positions of `body` relative to `center` at regular intervals.
+struct Ephemeris {
+    body: String,
+    center: String,
+    from: f64,
+    to: f64,
+    every: f64,
+    frame: Frame,
+}
+
+#[derive(Serialize)]
+struct EphemerisEntry {
+    date: DateTime<Utc>,
+    /// In km.
+    position: [f64; 3],
+    distance: f64,
+}
+
+impl Ephemeris {
+    fn print(&self, mut sim: SimState, mut propagator: Propagator, format: Format) -> Result<()> {
+        let mut entries = vec![];
+        let mut time = self.from;
+        while time <= self.to {
+            let pos =
+                position_of(&sim, &self.body).unwrap() - position_of(&sim, &self.center).unwrap();
+            let pos = self.frame.from_sim(pos, time);
+            entries.push(EphemerisEntry {
+                date: sim_time_to_utc(time),
+                position: [pos.x, pos.y, pos.z],
+                distance: pos.norm(),
+            });
+            propagator.advance(&mut sim, self.every);
+            time += self.every;
+        }
+
+        if format == Format::Json {
+            return print_json(&entries);
+        }
+        for entry in entries {
+            let [x, y, z] = entry.position;
+            println!(
+                "{}: x = {:.1} km, y = {:.1} km, z = {:.1} km, distance = {:.1} km",
+                entry.date, x, y, z, entry.distance
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Searches for the visibility events, saving checkpoints at the given interval to the given
+/// file if `checkpoints` is set. `sampling` is the edge margin and the sample interval.
+fn generate(
+    checkpoint: Checkpoint,
+    end: f64,
+    propagator: Propagator,
+    observer: &dyn Observer,
+    sampling: (f64, f64),
+    checkpoints: Option<(f64, &Path)>,
+) -> Result<Vec<Event>> {
+    let (interval, checkpoint_path) = checkpoints.unwrap_or((f64::INFINITY, Path::new("")));
+    events::resume_visibility_events(
+        checkpoint,
+        end,
+        propagator,
+        observer,
+        sampling,
+        interval,
+        |checkpoint| checkpoint.save(checkpoint_path),
+    )
+}
+
+/// A body in both of the compared states.
+#[derive(Serialize)]
+struct BodyDifference {
+    body: String,
+    /// Of the second state from the first, in km and km/s.
+    position: [f64; 3],
+    velocity: [f64; 3],
+    distance: f64,
+    speed: f64,
+}
+
+#[derive(Serialize)]
+struct StateDifference {
+    first_date: Option<DateTime<Utc>>,
+    second_date: Option<DateTime<Utc>>,
+    /// Of the second state from the first, in seconds, if both times are known.
+    time_difference: Option<f64>,
+    bodies: Vec<BodyDifference>,
+    only_in_first: Vec<String>,
+    only_in_second: Vec<String>,
+}
+
+fn diff_states(first_path: &Path, second_path: &Path, format: Format) -> Result<()> {
+    let (first_time, first) = snapshots::read(first_path)?;
+    let (second_time, second) = snapshots::read(second_path)?;
+    let bodies = first
+        .bodies()
+        .filter_map(|body| {
+            let other = second.body_by_name(&body.name)?;
+            let position = other.pos - body.pos;
+            let velocity = other.vel - body.vel;
+            Some(BodyDifference {
+                body: body.name.clone(),
+                position: position.into(),
+                velocity: velocity.into(),
+                distance: position.norm(),
+                speed: velocity.norm(),
+            })
+        })
+        .collect();
+    let only_in = |sim: &SimState, other: &SimState| -> Vec<String> {
+        sim.bodies()
+            .filter(|body| other.body_by_name(&body.name).is_none())
+            .map(|body| body.name.clone())
+            .collect()
+    };
+    let diff = StateDifference {
+        first_date: first_time.map(sim_time_to_utc),
+        second_date: second_time.map(sim_time_to_utc),
+        time_difference: first_time
+            .zip(second_time)
+            .map(|(first, second)| second - first),
+        bodies,
+        only_in_first: only_in(&first, &second),
+        only_in_second: only_in(&second, &first),
+    };
+    if format == Format::Json {
+        return print_json(&diff);
+    }
+
+    for (path, date) in [
+        (first_path, diff.first_date),
+        (second_path, diff.second_date),
+    ] {
+        match date {
+            Some(date) => println!("{}: {}", path.display(), date),
+            None => println!("{}: time unknown", path.display()),
+        }
+    }
+    if let Some(difference) = diff.time_difference {
+        println!("Time difference: {:.3} s", difference);
+    }
+    println!(
+        "{:<12} {:>16} {:>16}",
+        "Body", "Position (km)", "Velocity (km/s)"
+    );
+    for body in &diff.bodies {
+        println!(
+            "{:<12} {:>16.6} {:>16.9}",
+            body.body, body.distance, body.speed
+        );
+    }
+    if !diff.only_in_first.is_empty() {
+        println!("Only in the first: {}", diff.only_in_first.join(", "));
+    }
+    if !diff.only_in_second.is_empty() {
+        println!("Only in the second: {}", diff.only_in_second.join(", "));
+    }
+    Ok(())
+}
+
+fn validate(
+    mut sim: SimState,
+    start: f64,
+    end: f64,
+    mut propagator: Propagator,
+    reference: &dyn ephemeris::Ephemeris,
+    every: f64,
+    progress: &ProgressBar,
+) {
+    let mut time = start;
+    let mut next_sample = start;
+    let mut max_errors = [0.0f64; 3];
+    let mut squared_errors = [0.0f64; 3];
+    let mut samples = 0;
+
+    loop {
+        if time >= next_sample {
+            let reference = reference
+                .state("Earth", time)
+                .and_then(|earth| Ok((earth, reference.state("Moon", time)?)));
+            let ((earth_ref, _), (moon_ref, _)) = match reference {
+                Ok(reference) => reference,
+                Err(err) => {
+                    warn!("stopping: {}", err);
+                    break;
+                }
             };
-            let date = date - Duration::seconds(delta_t as i64);
-            //println!("{:#?}", sim.body_by_name("Earth").unwrap());
-            //println!("{:#?}", sim.body_by_name("Moon").unwrap());
-            //println!("{:#?}", sim.body_by_name("Sun").unwrap());
-            if let Some(eclipse) = new_eclipse {
-                println!("{:?}: date = {}", eclipse, date);
-            } else {
-                println!("Eclipse ends: date = {}\n", date);
+            let earth = sim.body_by_name("Earth").unwrap();
+            let moon = sim.body_by_name("Moon").unwrap();
+            let errors = [
+                (earth.pos - earth_ref).norm(),
+                (moon.pos - moon_ref).norm(),
+                ((moon.pos - earth.pos) - (moon_ref - earth_ref)).norm(),
+            ];
+            progress.suspend(|| {
+                println!(
+                    "{}: Earth error = {:.3} km, Moon error = {:.3} km, geocentric Moon error = {:.3} km",
+                    sim_time_to_utc(time),
+                    errors[0],
+                    errors[1],
+                    errors[2]
+                )
+            });
+            for ((max, squared), error) in max_errors
+                .iter_mut()
+                .zip(&mut squared_errors)
+                .zip(errors.iter())
+            {
+                *max = max.max(*error);
+                *squared += error * error;
             }
+            samples += 1;
+            next_sample += every;
+        }
+        if time >= end {
+            break;
         }
-        current_eclipse = new_eclipse;
+
+        time += propagator.step(&mut sim);
+    }
+
+    println!(
+        "\nMaximum errors: Earth = {:.3} km, Moon = {:.3} km, geocentric Moon = {:.3} km",
+        max_errors[0], max_errors[1], max_errors[2]
+    );
+    if samples > 0 {
+        let rms = squared_errors.map(|squared| (squared / samples as f64).sqrt());
+        println!(
+            "RMS errors: Earth = {:.3} km, Moon = {:.3} km, geocentric Moon = {:.3} km",
+            rms[0], rms[1], rms[2]
+        );
     }
 }
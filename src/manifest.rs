@@ -0,0 +1,111 @@
+use std::{ffi::OsStr, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// What the results of a run depend on, with a checksum of the results, so that they can be
+/// checked to be reproduced later, e.g. before publishing them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the program.
+    pub version: String,
+    /// The command line, without the program name.
+    pub arguments: Vec<String>,
+    /// `cache::fingerprint` of the initial state, with the bodies loaded from any catalog.
+    pub initial_state: String,
+    pub integrator: String,
+    pub step: String,
+    /// The whole model as the cached results are keyed by, with the flags changing it.
+    pub model: String,
+    /// The files in the snapshot directory after the run.
+    pub snapshots: Vec<FileChecksum>,
+    pub output: FileChecksum,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub path: String,
+    pub size: u64,
+    /// CRC-32 of the contents.
+    pub checksum: String,
+}
+
+impl FileChecksum {
+    pub fn new(path: &str, contents: &[u8]) -> Self {
+        Self {
+            path: path.to_owned(),
+            size: contents.len() as u64,
+            checksum: format!("{:08x}", crc32fast::hash(contents)),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let load = || -> Result<Self> { Ok(serde_json::from_str(&fs::read_to_string(path)?)?) };
+        load().map_err(|err| err.in_file(path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n").map_err(|err| Error::from(err).in_file(path))
+    }
+
+    /// How `other`, from running again, differs from the recorded run. Snapshots saved by the
+    /// recorded run which are there again are expected to be the same; new ones don't matter.
+    pub fn differences(&self, other: &Manifest) -> Vec<String> {
+        let mut differences = vec![];
+        let fields = [
+            ("version", &self.version, &other.version),
+            ("initial state", &self.initial_state, &other.initial_state),
+            ("integrator", &self.integrator, &other.integrator),
+            ("step", &self.step, &other.step),
+            ("model", &self.model, &other.model),
+        ];
+        for (name, recorded, current) in fields {
+            if recorded != current {
+                differences.push(format!("{} {} instead of {}", name, current, recorded));
+            }
+        }
+        for snapshot in &self.snapshots {
+            match other
+                .snapshots
+                .iter()
+                .find(|other| other.path == snapshot.path)
+            {
+                None => differences.push(format!("snapshot {} is missing", snapshot.path)),
+                Some(other) if other != snapshot => {
+                    differences.push(format!("snapshot {} has changed", snapshot.path))
+                }
+                Some(_) => (),
+            }
+        }
+        if self.output != other.output {
+            differences.push(format!(
+                "output of {} bytes with checksum {} instead of {} bytes with checksum {}",
+                other.output.size, other.output.checksum, self.output.size, self.output.checksum
+            ));
+        }
+        differences
+    }
+}
+
+/// Checksums of the snapshot files in `dir`, sorted by their paths.
+pub fn snapshot_files(dir: &Path) -> Result<Vec<FileChecksum>> {
+    let list = || -> Result<Vec<FileChecksum>> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new("toml")) {
+                files.push(FileChecksum::new(
+                    &path.display().to_string(),
+                    &fs::read(&path)?,
+                ));
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    };
+    list().map_err(|err| err.in_file(dir))
+}
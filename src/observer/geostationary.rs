@@ -0,0 +1,66 @@
+use nalgebra::Vector3;
+
+use super::{Observer, Sensor, FULL_DISK_HALF_FOV};
+use crate::{frames::Frame, simulation::SimState};
+
+const GEO_RADIUS: f64 = 42164.0;
+
+/// A satellite parked over the equator at a fixed longitude, imaging the full Earth disk.
+pub struct Geostationary {
+    name: String,
+    longitude: f64,
+    half_fov: f64,
+    sensor: Option<Sensor>,
+}
+
+impl Geostationary {
+    /// `longitude` is in degrees, positive towards the east. The frame is 17.4° wide, enough
+    /// for the whole Earth disk, unless set with `with_half_fov`.
+    pub fn new(name: &str, longitude: f64) -> Self {
+        Self {
+            name: name.to_owned(),
+            longitude: longitude.to_radians(),
+            half_fov: FULL_DISK_HALF_FOV,
+            sensor: None,
+        }
+    }
+
+    /// In radians.
+    pub fn with_half_fov(mut self, half_fov: f64) -> Self {
+        self.half_fov = half_fov;
+        self
+    }
+
+    pub fn with_sensor(mut self, sensor: Sensor) -> Self {
+        self.sensor = Some(sensor);
+        self
+    }
+
+    fn offset_from_earth(&self, time: f64) -> Vector3<f64> {
+        let (sin, cos) = self.longitude.sin_cos();
+        Frame::EarthFixed.to_sim(Vector3::new(GEO_RADIUS * cos, GEO_RADIUS * sin, 0.0), time)
+    }
+}
+
+impl Observer for Geostationary {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pos(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let earth = sim.body_by_name("Earth").unwrap();
+        earth.pos + self.offset_from_earth(time)
+    }
+
+    fn looking_dir(&self, _sim: &SimState, time: f64) -> Vector3<f64> {
+        -self.offset_from_earth(time).normalize()
+    }
+
+    fn half_fov(&self) -> f64 {
+        self.half_fov
+    }
+
+    fn sensor(&self) -> Option<Sensor> {
+        self.sensor
+    }
+}
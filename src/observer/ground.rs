@@ -0,0 +1,152 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+
+use super::Observer;
+use crate::{
+    frames::{Frame, EARTH_EQUATORIAL_RADIUS, EARTH_FLATTENING},
+    simulation::SimState,
+};
+
+/// Direction of a body as seen from a ground station, in radians.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Horizontal {
+    /// Measured from the north towards the east.
+    pub azimuth: f64,
+    /// Above the horizon, raised by the refraction if the station has it.
+    pub elevation: f64,
+}
+
+/// The refraction (radians) raising a body at the geometric `elevation` (radians), from
+/// Sæmundsson's formula for 1010 hPa and 10 °C. Below -1°, where the formula breaks down, it
+/// fades out with the cotangent of the elevation, so that the apparent elevation keeps growing
+/// with the geometric one.
+pub fn refraction(elevation: f64) -> f64 {
+    let limit = -1.0f64.to_radians();
+    let degrees = elevation.max(limit).to_degrees();
+    // the constant makes it vanish at the zenith
+    let arcmin = 1.02 / (degrees + 10.3 / (degrees + 5.11)).to_radians().tan() + 0.0019279;
+    let refraction = (arcmin / 60.0).to_radians();
+    if elevation < limit {
+        refraction * limit.tan() / elevation.tan()
+    } else {
+        refraction
+    }
+}
+
+/// An observer on the Earth's surface. Its "frame" is the whole sky above the horizon.
+pub struct GroundStation {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    refraction: bool,
+}
+
+impl GroundStation {
+    /// Geodetic `latitude` and `longitude` in degrees (east positive), `altitude` in km above
+    /// the WGS-84 ellipsoid.
+    pub fn new(name: &str, latitude: f64, longitude: f64, altitude: f64) -> Self {
+        Self {
+            name: name.to_owned(),
+            latitude: latitude.to_radians(),
+            longitude: longitude.to_radians(),
+            altitude,
+            refraction: false,
+        }
+    }
+
+    /// The station seeing the bodies where the atmosphere bends their light to (see
+    /// `refraction`).
+    pub fn with_refraction(mut self) -> Self {
+        self.refraction = true;
+        self
+    }
+
+    /// Topocentric direction of the named body.
+    pub fn horizontal(&self, sim: &SimState, time: f64, body: &str) -> Option<Horizontal> {
+        let target = sim.body_by_name(body)?.pos;
+        let dir = self.apparent_dir(sim, time, &(target - self.pos(sim, time)));
+        Some(self.horizontal_dir(&dir, time))
+    }
+
+    /// Like `horizontal`, but without the refraction, which e.g. the twilights are defined
+    /// without.
+    pub fn geometric_horizontal(
+        &self,
+        sim: &SimState,
+        time: f64,
+        body: &str,
+    ) -> Option<Horizontal> {
+        let target = sim.body_by_name(body)?.pos;
+        Some(self.horizontal_dir(&(target - self.pos(sim, time)), time))
+    }
+
+    fn horizontal_dir(&self, dir: &Vector3<f64>, time: f64) -> Horizontal {
+        let dir = dir.normalize();
+        let (east, north, up) = self.local_axes(time);
+        Horizontal {
+            azimuth: dir.dot(&east).atan2(dir.dot(&north)).rem_euclid(2.0 * PI),
+            elevation: dir.dot(&up).asin(),
+        }
+    }
+
+    /// Position relative to the Earth's center in the Earth-fixed frame.
+    fn earth_fixed_pos(&self) -> Vector3<f64> {
+        let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.sin_cos();
+        let n = EARTH_EQUATORIAL_RADIUS / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        Vector3::new(
+            (n + self.altitude) * cos_lat * cos_lon,
+            (n + self.altitude) * cos_lat * sin_lon,
+            (n * (1.0 - e2) + self.altitude) * sin_lat,
+        )
+    }
+
+    /// East, north and zenith unit vectors in the simulation frame.
+    fn local_axes(&self, time: f64) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.sin_cos();
+        let east = Vector3::new(-sin_lon, cos_lon, 0.0);
+        let north = Vector3::new(-sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat);
+        let up = Vector3::new(cos_lat * cos_lon, cos_lat * sin_lon, sin_lat);
+        let to_sim = Frame::EarthFixed.rotation(time).transpose();
+        (to_sim * east, to_sim * north, to_sim * up)
+    }
+}
+
+impl Observer for GroundStation {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pos(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let earth = sim.body_by_name("Earth").unwrap();
+        earth.pos + Frame::EarthFixed.to_sim(self.earth_fixed_pos(), time)
+    }
+
+    fn looking_dir(&self, _sim: &SimState, time: f64) -> Vector3<f64> {
+        self.local_axes(time).2
+    }
+
+    fn apparent_dir(&self, _sim: &SimState, time: f64, dir: &Vector3<f64>) -> Vector3<f64> {
+        let up = self.local_axes(time).2;
+        let level = dir - up * dir.dot(&up);
+        if !self.refraction || level.norm() == 0.0 {
+            return *dir;
+        }
+        let elevation = (dir.dot(&up) / dir.norm()).asin();
+        let apparent = elevation + refraction(elevation);
+        (level.normalize() * apparent.cos() + up * apparent.sin()) * dir.norm()
+    }
+
+    fn half_fov(&self) -> f64 {
+        0.5 * PI
+    }
+
+    /// The horizon, which bounds the frame, already hides everything below it.
+    fn earth_obscuration(&self, _distance: f64) -> f64 {
+        0.0
+    }
+}
@@ -0,0 +1,128 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+
+use super::{Observer, Sensor};
+use crate::{
+    lagrange::{self, LagrangePoint, Point, RotatingFrame, System},
+    simulation::SimState,
+};
+
+/// A periodic orbit around a Lagrange point: independent oscillations perpendicular to the
+/// line between the bodies, in and out of their orbital plane. With equal periods and a phase
+/// difference of 90° it's a halo orbit seen along that line, otherwise a Lissajous figure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HaloOrbit {
+    /// In km.
+    pub amplitude_y: f64,
+    pub amplitude_z: f64,
+    /// In seconds.
+    pub period: f64,
+    /// Phases of the two oscillations at the epoch, in radians.
+    pub phase_y: f64,
+    pub phase_z: f64,
+}
+
+impl HaloOrbit {
+    fn offset(&self, frame: &RotatingFrame, time: f64) -> Vector3<f64> {
+        let angle = 2.0 * PI * time / self.period;
+        frame.y * self.amplitude_y * (angle + self.phase_y).sin()
+            + frame.z * self.amplitude_z * (angle + self.phase_z).sin()
+    }
+}
+
+/// An imager parked at a Lagrange point, possibly on an orbit around it, looking at the
+/// Earth.
+pub struct LagrangeObserver {
+    name: String,
+    point: LagrangePoint,
+    orbit: Option<HaloOrbit>,
+    half_fov: f64,
+    sensor: Option<Sensor>,
+}
+
+impl LagrangeObserver {
+    /// The frame is 2° wide unless set with `with_half_fov`.
+    pub fn new(name: &str, point: LagrangePoint) -> Self {
+        Self {
+            name: name.to_owned(),
+            point,
+            orbit: None,
+            half_fov: 1f64.to_radians(),
+            sensor: None,
+        }
+    }
+
+    /// DSCOVR's EPIC camera, with a 0.61° field of view imaged on 2048×2048 pixels.
+    ///
+    /// The orbit around L1 is an idealized one with the real amplitudes and period, but not
+    /// fitted to the actual spacecraft. It's good for finding the seasons in which the Moon can
+    /// cross the Earth disk, not the exact dates.
+    pub fn dscovr() -> Self {
+        let half_fov = 0.305f64.to_radians();
+        Self::new(
+            "DSCOVR",
+            LagrangePoint {
+                system: System::SunEarth,
+                point: Point::L1,
+            },
+        )
+        .with_orbit(HaloOrbit {
+            amplitude_y: 264_000.0,
+            amplitude_z: 148_000.0,
+            period: 178.0 * 86400.0,
+            phase_y: 0.0,
+            phase_z: 0.5 * PI,
+        })
+        .with_half_fov(half_fov)
+        .with_sensor(Sensor {
+            pixels: 2048,
+            pixel_size: 2.0 * half_fov / 2048.0,
+        })
+    }
+
+    pub fn with_orbit(mut self, orbit: HaloOrbit) -> Self {
+        self.orbit = Some(orbit);
+        self
+    }
+
+    /// In radians.
+    pub fn with_half_fov(mut self, half_fov: f64) -> Self {
+        self.half_fov = half_fov;
+        self
+    }
+
+    pub fn with_sensor(mut self, sensor: Sensor) -> Self {
+        self.sensor = Some(sensor);
+        self
+    }
+}
+
+impl Observer for LagrangeObserver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pos(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let pos = lagrange::position(sim, self.point);
+        match self.orbit {
+            Some(ref orbit) => {
+                pos + orbit.offset(&RotatingFrame::new(sim, self.point.system), time)
+            }
+            None => pos,
+        }
+    }
+
+    fn looking_dir(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let earth = sim.body_by_name("Earth").unwrap();
+        (earth.pos - self.pos(sim, time)).normalize()
+    }
+
+    fn half_fov(&self) -> f64 {
+        self.half_fov
+    }
+
+    fn sensor(&self) -> Option<Sensor> {
+        self.sensor
+    }
+}
@@ -0,0 +1,316 @@
+mod geostationary;
+mod ground;
+mod lagrange;
+mod tle;
+
+pub use geostationary::Geostationary;
+pub use ground::{refraction, GroundStation, Horizontal};
+pub use lagrange::{HaloOrbit, LagrangeObserver};
+pub use tle::TleSatellite;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{frames::OBLIQUITY, simulation::SimState};
+
+/// Mean radius of the Earth, in km.
+const EARTH_RADIUS: f64 = 6371.0;
+
+/// Half-width of the frame of a geostationary full disk imager, unless set otherwise.
+const FULL_DISK_HALF_FOV: f64 = 8.7 * std::f64::consts::PI / 180.0;
+
+/// Names of the built-in observers, as accepted by `by_name`.
+pub const NAMES: &[&str] = &["himawari", "goes-east", "goes-west", "meteosat", "dscovr"];
+
+/// Something looking at the sky from a position tied to the simulated Earth.
+pub trait Observer: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Position of the observer in the simulation frame, `time` seconds after the epoch.
+    fn pos(&self, sim: &SimState, time: f64) -> Vector3<f64>;
+
+    /// Velocity of the observer in the simulation frame. By default the observer is assumed to
+    /// be carried along by the Earth, with its position relative to the Earth changing only
+    /// with time.
+    fn vel(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let earth = sim.body_by_name("Earth").unwrap();
+        earth.vel + 0.5 * (self.pos(sim, time + 1.0) - self.pos(sim, time - 1.0))
+    }
+
+    /// Unit vector along the sensor boresight.
+    fn looking_dir(&self, sim: &SimState, time: f64) -> Vector3<f64>;
+
+    /// The direction in which `dir` (relative to the observer) is seen, which differs only
+    /// for observers under the atmosphere.
+    fn apparent_dir(&self, _sim: &SimState, _time: f64, dir: &Vector3<f64>) -> Vector3<f64> {
+        *dir
+    }
+
+    /// Half-width of the (square) sensor frame, in radians.
+    fn half_fov(&self) -> f64;
+
+    /// Angular distance from the Earth's center within which the Earth hides the Moon, for an
+    /// observer `distance` km from the Earth's center.
+    fn earth_obscuration(&self, distance: f64) -> f64 {
+        (EARTH_RADIUS / distance).min(1.0).asin()
+    }
+
+    /// The pixel grid of the images, if known.
+    fn sensor(&self) -> Option<Sensor> {
+        None
+    }
+}
+
+/// The pixel grid of a square image centered on the boresight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensor {
+    /// Width and height of the image.
+    pub pixels: u32,
+    /// Angular size of a pixel, in radians.
+    pub pixel_size: f64,
+}
+
+pub fn by_name(name: &str) -> Option<Box<dyn Observer>> {
+    if name == "dscovr" {
+        return Some(Box::new(LagrangeObserver::dscovr()));
+    }
+    Some(Box::new(geostationary_by_name(name)?))
+}
+
+/// The built-in observer with the frame `half_fov` radians wide instead of its own. The pixel
+/// size of its sensor stays the same.
+pub fn by_name_with_half_fov(name: &str, half_fov: f64) -> Option<Box<dyn Observer>> {
+    if name == "dscovr" {
+        return Some(Box::new(LagrangeObserver::dscovr().with_half_fov(half_fov)));
+    }
+    Some(Box::new(
+        geostationary_by_name(name)?.with_half_fov(half_fov),
+    ))
+}
+
+fn geostationary_by_name(name: &str) -> Option<Geostationary> {
+    let observer = match name {
+        // the full disk products with 2 km pixels at the sub-satellite point (3 km for SEVIRI)
+        "himawari" => Geostationary::new("Himawari-9", 140.7).with_sensor(Sensor {
+            pixels: 5500,
+            pixel_size: (65536.0 / 20466275.0f64).to_radians(),
+        }),
+        "goes-east" => Geostationary::new("GOES-East", -75.2).with_sensor(Sensor {
+            pixels: 5424,
+            pixel_size: 56e-6,
+        }),
+        "goes-west" => Geostationary::new("GOES-West", -137.2).with_sensor(Sensor {
+            pixels: 5424,
+            pixel_size: 56e-6,
+        }),
+        "meteosat" => Geostationary::new("Meteosat", 0.0).with_sensor(Sensor {
+            pixels: 3712,
+            pixel_size: (65536.0 / 13642337.0f64).to_radians(),
+        }),
+        _ => return None,
+    };
+    Some(observer)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Visibility {
+    OutOfFrame,
+    /// Behind the Earth.
+    Obscured,
+    Visible,
+    /// In front of the Earth disk.
+    Transiting,
+}
+
+pub fn moon_visibility(observer: &dyn Observer, sim: &SimState, time: f64) -> Visibility {
+    classify(observer, sim, time, "Moon", (0.0, 0.0))
+}
+
+/// Like `moon_visibility`, for any of the simulated bodies, e.g. a comet.
+pub fn body_visibility(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+    body: &str,
+) -> Visibility {
+    classify(observer, sim, time, body, (0.0, 0.0))
+}
+
+/// Like `moon_visibility`, but the Moon keeps the `current` visibility until it's `margin`
+/// radians past the edge of the frame or of the Earth disk, so that it doesn't chatter when
+/// the Moon skims one of them.
+pub fn moon_visibility_with_margin(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+    current: Visibility,
+    margin: f64,
+) -> Visibility {
+    let (frame_margin, earth_margin) = match current {
+        Visibility::OutOfFrame => (-margin, 0.0),
+        Visibility::Visible => (margin, -margin),
+        Visibility::Obscured | Visibility::Transiting => (margin, margin),
+    };
+    classify(observer, sim, time, "Moon", (frame_margin, earth_margin))
+}
+
+/// The body's visibility with the frame and the Earth disk widened by the given angles.
+fn classify(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+    body: &str,
+    (frame_margin, earth_margin): (f64, f64),
+) -> Visibility {
+    let earth = sim.body_by_name("Earth").unwrap();
+    let target = sim.body_by_name(body).unwrap();
+
+    let pos = observer.pos(sim, time);
+    let to_target = target.pos - pos;
+    let to_earth = earth.pos - pos;
+
+    if !within_frame(observer, sim, time, &to_target, frame_margin) {
+        return Visibility::OutOfFrame;
+    }
+
+    let ang_to_earth = to_target.angle(&to_earth);
+    if ang_to_earth >= observer.earth_obscuration(to_earth.norm()) + earth_margin {
+        Visibility::Visible
+    } else if to_target.norm() > to_earth.norm() {
+        Visibility::Obscured
+    } else {
+        Visibility::Transiting
+    }
+}
+
+/// Position of a direction in an observer's frame, as the angles from the boresight along
+/// the frame axes divided by the half-width of the frame. The frame is oriented with the
+/// celestial north up; `x` grows to the right and `y` downwards, like image coordinates, and
+/// both are within (-1, 1) inside the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FramePosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl FramePosition {
+    pub fn is_inside(&self) -> bool {
+        self.x.abs() < 1.0 && self.y.abs() < 1.0
+    }
+}
+
+/// Position of `dir` (relative to the observer) in the observer's frame, or `None` if it
+/// points behind the observer.
+pub fn frame_position(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+    dir: &Vector3<f64>,
+) -> Option<FramePosition> {
+    let north = Vector3::new(0.0, OBLIQUITY.sin(), OBLIQUITY.cos());
+    let dir = observer.apparent_dir(sim, time, dir);
+    let z = observer.looking_dir(sim, time);
+    let x = z.cross(&north).normalize();
+    let y = z.cross(&x);
+
+    let depth = dir.dot(&z);
+    if depth <= 0.0 {
+        return None;
+    }
+    let half_fov = observer.half_fov();
+    Some(FramePosition {
+        x: dir.dot(&x).atan2(depth) / half_fov,
+        y: dir.dot(&y).atan2(depth) / half_fov,
+    })
+}
+
+/// Where the Moon is in an image, with (0, 0) at the top left corner of the top left pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PixelPosition {
+    pub column: f64,
+    pub row: f64,
+    /// Apparent diameter of the Moon, in pixels.
+    pub diameter: f64,
+}
+
+/// Converts a position in the frame of an observer with the given sensor to pixels. The
+/// pixels are assumed to be evenly spaced in the angles of `FramePosition`.
+pub fn pixel_position(
+    position: FramePosition,
+    half_fov: f64,
+    sensor: Sensor,
+    angular_diameter: f64,
+) -> PixelPosition {
+    let center = 0.5 * f64::from(sensor.pixels);
+    let scale = half_fov / sensor.pixel_size;
+    PixelPosition {
+        column: center + position.x * scale,
+        row: center + position.y * scale,
+        diameter: angular_diameter / sensor.pixel_size,
+    }
+}
+
+/// Position of the Moon in the observer's frame, or `None` if it isn't visible there.
+pub fn moon_frame_position(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+) -> Option<FramePosition> {
+    match moon_visibility(observer, sim, time) {
+        Visibility::Visible | Visibility::Transiting => (),
+        Visibility::OutOfFrame | Visibility::Obscured => return None,
+    }
+    let moon = sim.body_by_name("Moon").unwrap();
+    frame_position(observer, sim, time, &(moon.pos - observer.pos(sim, time)))
+}
+
+/// Position and size of the Moon in the observer's images, or `None` if it isn't visible or
+/// the observer's sensor isn't known.
+pub fn moon_pixel_position(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+) -> Option<PixelPosition> {
+    let sensor = observer.sensor()?;
+    let position = moon_frame_position(observer, sim, time)?;
+    let moon = sim.body_by_name("Moon").unwrap();
+    let distance = (moon.pos - observer.pos(sim, time)).norm();
+    let angular_diameter = 2.0 * (moon.radius / distance).asin();
+    Some(pixel_position(
+        position,
+        observer.half_fov(),
+        sensor,
+        angular_diameter,
+    ))
+}
+
+/// Angles seen by an observer between the Sun and the sensor boresight, and between the Sun and
+/// the Moon, in radians.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SunAngles {
+    pub boresight: f64,
+    pub moon: f64,
+}
+
+pub fn sun_angles(observer: &dyn Observer, sim: &SimState, time: f64) -> SunAngles {
+    let sun = sim.body_by_name("Sun").unwrap();
+    let moon = sim.body_by_name("Moon").unwrap();
+    let pos = observer.pos(sim, time);
+    let to_sun = sun.pos - pos;
+    SunAngles {
+        boresight: to_sun.angle(&observer.looking_dir(sim, time)),
+        moon: to_sun.angle(&(moon.pos - pos)),
+    }
+}
+
+fn within_frame(
+    observer: &dyn Observer,
+    sim: &SimState,
+    time: f64,
+    dir: &Vector3<f64>,
+    margin: f64,
+) -> bool {
+    let limit = 1.0 + margin / observer.half_fov();
+    frame_position(observer, sim, time, dir)
+        .is_some_and(|position| position.x.abs() < limit && position.y.abs() < limit)
+}
@@ -0,0 +1,245 @@
+use std::{f64::consts::PI, fs, path::Path, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use nalgebra::Vector3;
+
+use super::{Observer, FULL_DISK_HALF_FOV};
+use crate::{
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    simulation::SimState,
+    time::{self, utc_to_sim_time},
+};
+
+// WGS-72 constants, which the elements are fitted with
+const EARTH_RADIUS: f64 = 6378.135;
+const MU: f64 = 398600.8;
+const J2: f64 = 0.001082616;
+
+/// A satellite whose orbit is given by NORAD two-line elements.
+///
+/// The mean elements are propagated with the secular J2 rates and the decay of the mean motion
+/// given in the elements, which is enough to follow the inclination and drift of a
+/// geostationary satellite between fresh element sets and a low one for a few days. The
+/// periodic terms, the rest of SGP4's drag model and the deep-space lunisolar perturbations of
+/// SDP4 aren't modeled, and the TEME frame of the elements is treated as J2000.
+#[derive(Debug, Clone)]
+pub struct TleSatellite {
+    name: String,
+    /// Simulation time of the elements.
+    epoch: f64,
+    inclination: f64,
+    raan: f64,
+    eccentricity: f64,
+    arg_perigee: f64,
+    mean_anomaly: f64,
+    /// Semi-major axis, in km.
+    semi_major_axis: f64,
+    /// Secular rates of the node, perigee and mean anomaly, in rad/s.
+    raan_rate: f64,
+    arg_perigee_rate: f64,
+    mean_motion: f64,
+    /// Half the rate of change of the mean motion, in rad/s².
+    mean_motion_decay: f64,
+    half_fov: f64,
+}
+
+impl TleSatellite {
+    pub fn load(path: &Path) -> Result<Self> {
+        fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|text| Self::parse(&text))
+            .map_err(|err| err.in_file(path))
+    }
+
+    /// Loads a catalog of element sets, like the ones published by CelesTrak.
+    pub fn load_all(path: &Path) -> Result<Vec<Self>> {
+        fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|text| Self::parse_all(&text))
+            .map_err(|err| err.in_file(path))
+    }
+
+    /// Parses consecutive element sets, each with or without the name line.
+    pub fn parse_all(text: &str) -> Result<Vec<Self>> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let mut satellites = vec![];
+        let mut rest = &lines[..];
+        while !rest.is_empty() {
+            let len = if rest[0].starts_with("1 ") && rest.len() > 1 && rest[1].starts_with("2 ") {
+                2
+            } else {
+                3
+            };
+            let set = &rest[..len.min(rest.len())];
+            satellites.push(Self::parse(&set.join("\n"))?);
+            rest = &rest[set.len()..];
+        }
+        Ok(satellites)
+    }
+
+    /// Parses a single element set, with or without the name line.
+    pub fn parse(text: &str) -> Result<Self> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let (name, line1, line2) = match lines[..] {
+            [line1, line2] => (None, line1, line2),
+            [name, line1, line2] => (Some(name.trim_start_matches("0 ").trim()), line1, line2),
+            _ => {
+                return Err(Error::Tle(
+                    "expected two element lines, optionally with a name".into(),
+                ))
+            }
+        };
+        for (number, line) in [('1', line1), ('2', line2)].iter() {
+            if line.len() < 69 || !line.starts_with(*number) {
+                return Err(Error::Tle(format!("malformed element line {}", number)));
+            }
+            if !checksum_ok(line) {
+                return Err(Error::Tle(format!("bad checksum of line {}", number)));
+            }
+        }
+
+        let field = |line: &str, from: usize, to: usize| -> Result<f64> {
+            let text = line[from - 1..to].trim();
+            text.parse()
+                .map_err(|_| Error::Tle(format!("bad element field \"{}\"", text)))
+        };
+
+        let year = field(line1, 19, 20)? as i32;
+        let year = if year < 57 { 2000 + year } else { 1900 + year };
+        let day = field(line1, 21, 32)?;
+        let epoch = DateTime::<Utc>::from_str(&format!("{}-01-01T00:00:00Z", year)).unwrap()
+            + time::duration((day - 1.0) * 86400.0);
+
+        let inclination = field(line2, 9, 16)?.to_radians();
+        let raan = field(line2, 18, 25)?.to_radians();
+        let eccentricity = field(line2, 27, 33)? * 1e-7;
+        let arg_perigee = field(line2, 35, 42)?.to_radians();
+        let mean_anomaly = field(line2, 44, 51)?.to_radians();
+        let revs_per_day = field(line2, 53, 63)?;
+        let decay = field(line1, 34, 43)? * 2.0 * PI / (86400.0 * 86400.0);
+
+        // recover the Brouwer mean motion from the Kozai one given in the elements, like SGP4
+        let ke = (MU / EARTH_RADIUS.powi(3)).sqrt();
+        let n0 = revs_per_day * 2.0 * PI / 86400.0;
+        let cos_i = inclination.cos();
+        let beta2 = 1.0 - eccentricity * eccentricity;
+        let k = 0.75 * J2 * (3.0 * cos_i * cos_i - 1.0) / beta2.powf(1.5);
+        let a1 = (ke / n0).powf(2.0 / 3.0);
+        let d1 = k / (a1 * a1);
+        let a0 = a1 * (1.0 - d1 / 3.0 - d1 * d1 - 134.0 / 81.0 * d1 * d1 * d1);
+        let d0 = k / (a0 * a0);
+        let n = n0 / (1.0 + d0);
+        let a = a0 / (1.0 - d0);
+
+        let p = a * beta2;
+        let j2_term = J2 / (p * p) * n;
+
+        Ok(Self {
+            name: name
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("NORAD {}", line1[2..7].trim())),
+            epoch: utc_to_sim_time(epoch),
+            inclination,
+            raan,
+            eccentricity,
+            arg_perigee,
+            mean_anomaly,
+            semi_major_axis: a * EARTH_RADIUS,
+            raan_rate: -1.5 * j2_term * cos_i,
+            arg_perigee_rate: 0.75 * j2_term * (5.0 * cos_i * cos_i - 1.0),
+            mean_motion: n + 0.75 * j2_term * beta2.sqrt() * (3.0 * cos_i * cos_i - 1.0),
+            mean_motion_decay: decay,
+            half_fov: FULL_DISK_HALF_FOV,
+        })
+    }
+
+    /// The frame of a full disk imager is assumed unless set with this, in radians.
+    pub fn with_half_fov(mut self, half_fov: f64) -> Self {
+        self.half_fov = half_fov;
+        self
+    }
+
+    /// An upper bound of the rate at which the satellite can move across the sky of a place on
+    /// the Earth, in rad/s.
+    pub fn max_angular_rate(&self) -> f64 {
+        let perigee = self.semi_major_axis * (1.0 - self.eccentricity);
+        let speed = (MU * (2.0 / perigee - 1.0 / self.semi_major_axis)).sqrt();
+        // the highest places on the Earth, carried along by its rotation at below 0.5 km/s
+        (speed + 0.5) / (perigee - EARTH_RADIUS - 10.0).max(1.0)
+    }
+
+    /// Position relative to the Earth's center, in the simulation frame.
+    fn offset_from_earth(&self, time: f64) -> Vector3<f64> {
+        let dt = time - self.epoch;
+        let raan = self.raan + self.raan_rate * dt;
+        let arg_perigee = self.arg_perigee + self.arg_perigee_rate * dt;
+        let mean_anomaly =
+            (self.mean_anomaly + self.mean_motion * dt + self.mean_motion_decay * dt * dt)
+                % (2.0 * PI);
+
+        let e = self.eccentricity;
+        let mut ecc_anomaly = mean_anomaly;
+        for _ in 0..10 {
+            ecc_anomaly -= (ecc_anomaly - e * ecc_anomaly.sin() - mean_anomaly)
+                / (1.0 - e * ecc_anomaly.cos());
+        }
+        let x = self.semi_major_axis * (ecc_anomaly.cos() - e);
+        let y = self.semi_major_axis * (1.0 - e * e).sqrt() * ecc_anomaly.sin();
+
+        // perifocal -> equatorial
+        let (sin_o, cos_o) = raan.sin_cos();
+        let (sin_w, cos_w) = arg_perigee.sin_cos();
+        let (sin_i, cos_i) = self.inclination.sin_cos();
+        let p = Vector3::new(
+            cos_o * cos_w - sin_o * sin_w * cos_i,
+            sin_o * cos_w + cos_o * sin_w * cos_i,
+            sin_w * sin_i,
+        );
+        let q = Vector3::new(
+            -cos_o * sin_w - sin_o * cos_w * cos_i,
+            -sin_o * sin_w + cos_o * cos_w * cos_i,
+            cos_w * sin_i,
+        );
+        equatorial_to_ecliptic(x * p + y * q)
+    }
+}
+
+impl Observer for TleSatellite {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pos(&self, sim: &SimState, time: f64) -> Vector3<f64> {
+        let earth = sim.body_by_name("Earth").unwrap();
+        earth.pos + self.offset_from_earth(time)
+    }
+
+    fn looking_dir(&self, _sim: &SimState, time: f64) -> Vector3<f64> {
+        -self.offset_from_earth(time).normalize()
+    }
+
+    fn half_fov(&self) -> f64 {
+        self.half_fov
+    }
+}
+
+/// Element lines end with the sum of their digits modulo 10, minus signs counting as 1.
+fn checksum_ok(line: &str) -> bool {
+    let sum: u32 = line[..68]
+        .chars()
+        .map(|c| match c {
+            '-' => 1,
+            _ => c.to_digit(10).unwrap_or(0),
+        })
+        .sum();
+    line[68..69].parse() == Ok(sum % 10)
+}
@@ -0,0 +1,453 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    events::{Event, EventKind, VisibilitySummary, VisibilityWindow},
+    images::Image,
+    position::SkyPosition,
+    trajectory::StateVector,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+    Ics,
+}
+
+pub const FORMAT_NAMES: &[&str] = &["text", "json", "csv", "ics"];
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "ics" => Ok(Format::Ics),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+pub fn write_events<W: Write>(out: &mut W, events: &[Event], format: Format) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(out, events),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, events)?;
+            writeln!(out)
+        }
+        Format::Csv => write_csv(out, events),
+        Format::Ics => write_ics(out, events),
+    }
+}
+
+/// Width of the longest bar of the monthly histograms, in characters.
+const HISTOGRAM_WIDTH: f64 = 40.0;
+
+/// Writes the visibility statistics of each observer; only text and JSON are supported.
+pub fn write_summaries<W: Write>(
+    out: &mut W,
+    summaries: &[VisibilitySummary],
+    format: Format,
+) -> io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *out, summaries)?;
+        return writeln!(out);
+    }
+    for summary in summaries {
+        writeln!(
+            out,
+            "{}: {} windows, {:.1} minutes visible",
+            summary.observer, summary.windows, summary.minutes_visible
+        )?;
+        if let Some((start, end)) = summary.longest_window {
+            writeln!(
+                out,
+                "  longest window: {} to {} ({:.1} min)",
+                start,
+                end,
+                (end - start).num_milliseconds() as f64 / 60e3
+            )?;
+        }
+        let most = summary
+            .monthly
+            .iter()
+            .map(|month| month.minutes_visible)
+            .fold(0.0, f64::max);
+        for month in &summary.monthly {
+            let bar = if most > 0.0 {
+                (month.minutes_visible / most * HISTOGRAM_WIDTH).round() as usize
+            } else {
+                0
+            };
+            writeln!(
+                out,
+                "  {}-{:02}: {:3} windows, {:7.1} min {}",
+                month.year,
+                month.month,
+                month.windows,
+                month.minutes_visible,
+                "#".repeat(bar)
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes the images containing the Moon, with its position in each.
+pub fn write_images<W: Write>(out: &mut W, images: &[Image], format: Format) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for image in images {
+                write!(
+                    out,
+                    "{}: image {}: Moon at x = {:.4}, y = {:.4}",
+                    image.observer, image.date, image.moon.x, image.moon.y
+                )?;
+                if let Some(pixels) = image.pixels {
+                    write!(
+                        out,
+                        ", pixel = ({:.1}, {:.1}), diameter = {:.1} px",
+                        pixels.column, pixels.row, pixels.diameter
+                    )?;
+                }
+                let libration = image.libration;
+                writeln!(
+                    out,
+                    ", libration = ({:.2}°, {:.2}°), pole at {:.1}°",
+                    libration.longitude, libration.latitude, libration.position_angle
+                )?;
+            }
+            Ok(())
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, images)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            writeln!(
+                out,
+                "observer,date,x,y,column,row,diameter,libration_lon,libration_lat,position_angle"
+            )?;
+            for image in images {
+                let pixels = match image.pixels {
+                    Some(pixels) => format!("{},{},{}", pixels.column, pixels.row, pixels.diameter),
+                    None => ",,".to_owned(),
+                };
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{}",
+                    csv_field(&image.observer),
+                    image.date.to_rfc3339(),
+                    image.moon.x,
+                    image.moon.y,
+                    pixels,
+                    image.libration.longitude,
+                    image.libration.latitude,
+                    image.libration.position_angle
+                )?;
+            }
+            Ok(())
+        }
+        Format::Ics => {
+            write_ics_header(out)?;
+            for image in images {
+                let stamp = ics_date(&image.date);
+                write_ics_lines(
+                    out,
+                    &[
+                        "BEGIN:VEVENT".to_owned(),
+                        format!("UID:{}-image-{}@eclipses", stamp, image.observer),
+                        format!("DTSTAMP:{}", stamp),
+                        format!("DTSTART:{}", stamp),
+                        format!("DTEND:{}", stamp),
+                        format!(
+                            "SUMMARY:{}",
+                            ics_text(&format!("Moon in the {} image", image.observer))
+                        ),
+                        "END:VEVENT".to_owned(),
+                    ],
+                )?;
+            }
+            write_ics_lines(out, &["END:VCALENDAR".to_owned()])
+        }
+    }
+}
+
+/// Writes the positions of a body in the sky. The CSV columns are in degrees and km.
+pub fn write_positions<W: Write>(
+    out: &mut W,
+    positions: &[SkyPosition],
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for position in positions {
+                writeln!(
+                    out,
+                    "{}: RA = {}, Dec = {}, distance = {:.1} km",
+                    position.date,
+                    sexagesimal(
+                        position.right_ascension.to_degrees() / 15.0,
+                        "h",
+                        "m",
+                        "s",
+                        2
+                    ),
+                    sexagesimal(position.declination.to_degrees(), "°", "′", "″", 1),
+                    position.distance
+                )?;
+            }
+            Ok(())
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, positions)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            writeln!(out, "date,right_ascension,declination,distance")?;
+            for position in positions {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    position.date.to_rfc3339(),
+                    position.right_ascension.to_degrees(),
+                    position.declination.to_degrees(),
+                    position.distance
+                )?;
+            }
+            Ok(())
+        }
+        Format::Ics => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "positions can't be written as a calendar",
+        )),
+    }
+}
+
+/// Writes the states of bodies, one row per body and time in CSV, in km and km/s.
+pub fn write_states<W: Write>(
+    out: &mut W,
+    states: &[StateVector],
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for state in states {
+                let [x, y, z] = state.position;
+                let [vx, vy, vz] = state.velocity;
+                writeln!(
+                    out,
+                    "{}: {}: x = {:.1} km, y = {:.1} km, z = {:.1} km, \
+                     vx = {:.6} km/s, vy = {:.6} km/s, vz = {:.6} km/s",
+                    state.date, state.body, x, y, z, vx, vy, vz
+                )?;
+            }
+            Ok(())
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, states)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            writeln!(out, "date,body,x,y,z,vx,vy,vz")?;
+            for state in states {
+                let [x, y, z] = state.position;
+                let [vx, vy, vz] = state.velocity;
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{}",
+                    state.date.to_rfc3339(),
+                    csv_field(&state.body),
+                    x,
+                    y,
+                    z,
+                    vx,
+                    vy,
+                    vz
+                )?;
+            }
+            Ok(())
+        }
+        Format::Ics => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "states can't be written as a calendar",
+        )),
+    }
+}
+
+/// Formats `value` like 12h34m56.78s, with `decimals` digits of the last unit.
+fn sexagesimal(value: f64, unit: &str, minute: &str, second: &str, decimals: usize) -> String {
+    let sign = if value < 0.0 { "-" } else { "" };
+    let scale = 10f64.powi(decimals as i32);
+    let total = (value.abs() * 3600.0 * scale).round() / scale;
+    let whole = (total / 3600.0).floor();
+    let minutes = ((total - whole * 3600.0) / 60.0).floor();
+    let seconds = total - whole * 3600.0 - minutes * 60.0;
+    format!(
+        "{}{:02}{}{:02}{}{:0width$.decimals$}{}",
+        sign,
+        whole,
+        unit,
+        minutes,
+        minute,
+        seconds,
+        second,
+        width = decimals + 3,
+        decimals = decimals
+    )
+}
+
+fn write_text<W: Write>(out: &mut W, events: &[Event]) -> io::Result<()> {
+    for event in events {
+        writeln!(
+            out,
+            "{}: {}: date = {}, illuminated = {:.1}%, phase angle = {:.1}°",
+            event.observer,
+            event.kind.description(),
+            event.date,
+            event.illuminated_fraction * 100.0,
+            event.phase_angle.to_degrees()
+        )?;
+        if event.kind == EventKind::LeavingFrame {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(out: &mut W, events: &[Event]) -> io::Result<()> {
+    writeln!(out, "observer,event,date,illuminated_fraction,phase_angle")?;
+    for event in events {
+        writeln!(
+            out,
+            "{},{:?},{},{},{}",
+            csv_field(&event.observer),
+            event.kind,
+            event.date.to_rfc3339(),
+            event.illuminated_fraction,
+            event.phase_angle.to_degrees()
+        )?;
+    }
+    Ok(())
+}
+
+fn write_ics<W: Write>(out: &mut W, events: &[Event]) -> io::Result<()> {
+    write_ics_header(out)?;
+    for event in events {
+        let stamp = ics_date(&event.date);
+        write_ics_lines(
+            out,
+            &[
+                "BEGIN:VEVENT".to_owned(),
+                format!("UID:{}-{:?}-{}@eclipses", stamp, event.kind, event.observer),
+                format!("DTSTAMP:{}", stamp),
+                format!("DTSTART:{}", stamp),
+                format!("DTEND:{}", stamp),
+                format!(
+                    "SUMMARY:{}",
+                    ics_text(&format!("{}: {}", event.observer, event.kind.description()))
+                ),
+                "END:VEVENT".to_owned(),
+            ],
+        )?;
+    }
+    write_ics_lines(out, &["END:VCALENDAR".to_owned()])
+}
+
+/// Writes the visibility windows as an iCalendar file, one VEVENT per window.
+pub fn write_ics_windows<W: Write>(out: &mut W, windows: &[VisibilityWindow]) -> io::Result<()> {
+    write_ics_header(out)?;
+    for window in windows {
+        let start = ics_date(&window.start);
+        let description: Vec<_> = window
+            .events
+            .iter()
+            .map(|event| {
+                format!(
+                    "{}: {}",
+                    event.date.format("%H:%M:%S UTC"),
+                    event.kind.description()
+                )
+            })
+            .collect();
+        write_ics_lines(
+            out,
+            &[
+                "BEGIN:VEVENT".to_owned(),
+                format!("UID:{}-{}@eclipses", start, window.observer),
+                format!("DTSTAMP:{}", start),
+                format!("DTSTART:{}", start),
+                format!("DTEND:{}", ics_date(&window.end)),
+                format!(
+                    "SUMMARY:{}",
+                    ics_text(&format!("Moon in the {} frame", window.observer))
+                ),
+                format!("DESCRIPTION:{}", ics_text(&description.join("\n"))),
+                "END:VEVENT".to_owned(),
+            ],
+        )?;
+    }
+    write_ics_lines(out, &["END:VCALENDAR".to_owned()])
+}
+
+fn write_ics_header<W: Write>(out: &mut W) -> io::Result<()> {
+    write_ics_lines(
+        out,
+        &[
+            "BEGIN:VCALENDAR".to_owned(),
+            "VERSION:2.0".to_owned(),
+            "PRODID:-//eclipses//Moon visibility//EN".to_owned(),
+        ],
+    )
+}
+
+/// Writes content lines terminated with CRLF, folded at 75 octets as RFC 5545 requires.
+fn write_ics_lines<W: Write>(out: &mut W, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        let mut rest = line.as_str();
+        let mut limit = 75;
+        while rest.len() > limit {
+            let mut split = limit;
+            while !rest.is_char_boundary(split) {
+                split -= 1;
+            }
+            write!(out, "{}\r\n ", &rest[..split])?;
+            rest = &rest[split..];
+            // the leading space of a continuation line counts towards its length
+            limit = 74;
+        }
+        write!(out, "{}\r\n", rest)?;
+    }
+    Ok(())
+}
+
+fn ics_date(date: &DateTime<Utc>) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Quotes a CSV field if it has to be, as in RFC 4180, e.g. the "LAT,LON" name of a ground
+/// station.
+fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,122 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use nalgebra::Vector3;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Precision of the reported phase instants, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+
+/// The Moon's illumination as seen from the Earth's center, or another observer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoonPhase {
+    /// The Sun-Moon-observer angle, in radians: 0 at full moon, π at new moon.
+    pub phase_angle: f64,
+    /// Fraction of the disk that is lit, between 0 and 1.
+    pub illuminated_fraction: f64,
+    /// Ecliptic longitude of the Moon minus that of the Sun seen by the observer, in [0, 2π).
+    pub elongation: f64,
+}
+
+pub fn moon_phase(sim: &SimState) -> MoonPhase {
+    moon_phase_seen_from(sim, &sim.body_by_name("Earth").unwrap().pos)
+}
+
+/// The Moon's illumination as seen from `observer_pos`.
+pub fn moon_phase_seen_from(sim: &SimState, observer_pos: &Vector3<f64>) -> MoonPhase {
+    let sun = sim.body_by_name("Sun").unwrap();
+    let moon = sim.body_by_name("Moon").unwrap();
+
+    let phase_angle = (sun.pos - moon.pos).angle(&(observer_pos - moon.pos));
+    let longitude = |v: Vector3<f64>| v.y.atan2(v.x);
+    let elongation = (longitude(moon.pos - observer_pos) - longitude(sun.pos - observer_pos))
+        .rem_euclid(2.0 * PI);
+    MoonPhase {
+        phase_angle,
+        illuminated_fraction: 0.5 * (1.0 + phase_angle.cos()),
+        elongation,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PhaseKind {
+    NewMoon,
+    FirstQuarter,
+    FullMoon,
+    LastQuarter,
+}
+
+impl PhaseKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            PhaseKind::NewMoon => "new moon",
+            PhaseKind::FirstQuarter => "first quarter",
+            PhaseKind::FullMoon => "full moon",
+            PhaseKind::LastQuarter => "last quarter",
+        }
+    }
+
+    /// The phase that was last passed at the given elongation.
+    fn from_elongation(elongation: f64) -> Self {
+        match (elongation / (0.5 * PI)) as u32 {
+            0 => PhaseKind::NewMoon,
+            1 => PhaseKind::FirstQuarter,
+            2 => PhaseKind::FullMoon,
+            _ => PhaseKind::LastQuarter,
+        }
+    }
+}
+
+/// The instant of a principal phase, when the elongation is a multiple of 90°.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PhaseEvent {
+    pub kind: PhaseKind,
+    pub date: DateTime<Utc>,
+}
+
+/// Finds the principal phases of the Moon between `start` and `end` (simulation times), `sim`
+/// being the state of the system at `start`.
+pub fn principal_phases<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+) -> Vec<PhaseEvent> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut current = PhaseKind::from_elongation(moon_phase(&sim).elongation);
+    let mut events = vec![];
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        let kind = PhaseKind::from_elongation(moon_phase(&sim).elongation);
+        if kind != current {
+            let event_time = refine::bisect(
+                &propagator,
+                &sim,
+                time,
+                step,
+                REFINE_TOLERANCE,
+                |state, _| PhaseKind::from_elongation(moon_phase(state).elongation) == kind,
+            );
+            if event_time <= end {
+                let date = sim_time_to_utc(event_time);
+                debug!(?kind, %date, "found phase");
+                events.push(PhaseEvent { kind, date });
+            }
+            current = kind;
+        }
+    }
+
+    events
+}
@@ -0,0 +1,352 @@
+use std::{
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use plotters::{coord::Shift, prelude::*};
+
+use super::{check_path, plot_error};
+use crate::{
+    error::{Error, Result},
+    images::Schedule,
+    observer::{self, FramePosition, Observer, Sensor, Visibility},
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Size of the frame views, in pixels.
+const SIZE: (u32, u32) = (800, 800);
+/// Number of vertices of the polygon drawn as the Earth disk.
+const OUTLINE_POINTS: usize = 180;
+const EARTH_COLOR: RGBColor = RGBColor(130, 170, 220);
+const VISIBLE_COLOR: RGBColor = RGBColor(220, 20, 60);
+const OBSCURED_COLOR: RGBColor = RGBColor(150, 150, 150);
+
+/// Where the Moon is in an observer's frame at some time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub date: DateTime<Utc>,
+    pub position: FramePosition,
+    /// Apparent radius of the Moon, in the units of `position`.
+    pub radius: f64,
+    pub visibility: Visibility,
+}
+
+/// The Moon's track through an observer's frame, from entering it to leaving it.
+pub struct FramePass {
+    pub observer: String,
+    pub half_fov: f64,
+    pub sensor: Option<Sensor>,
+    pub track: Vec<TrackPoint>,
+    /// The Earth's center in the frame in the middle of the pass, or `None` if the Earth is
+    /// behind the observer.
+    pub earth: Option<FramePosition>,
+    /// Radius of the disk hiding the Moon, in the units of `FramePosition`.
+    pub earth_radius: f64,
+}
+
+/// Finds the first pass of the Moon through the frame of `observer` between `start` and `end`
+/// (simulation times), `sim` being the state of the system at `start`, with the Moon's
+/// position every `every` seconds. Returns `None` if the Moon doesn't enter the frame.
+pub fn moon_pass<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    every: f64,
+    propagator: P,
+    observer: &dyn Observer,
+) -> Option<FramePass> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut next = start;
+
+    let mut track = vec![];
+    let mut earth = vec![];
+    'search: while next <= end {
+        time += propagator.step(&mut sim);
+        // the positions within the last step, evaluated with single steps back from its end
+        while next <= time && next <= end {
+            let state = propagator.propagated(&sim, next - time);
+            let visibility = observer::moon_visibility(observer, &state, next);
+            if visibility == Visibility::OutOfFrame {
+                if !track.is_empty() {
+                    break 'search;
+                }
+            } else {
+                let pos = observer.pos(&state, next);
+                let moon = state.body_by_name("Moon").unwrap();
+                let earth_pos = state.body_by_name("Earth").unwrap().pos;
+                let to_earth = earth_pos - pos;
+                // a Moon within the frame is in front of the observer
+                let position = observer::frame_position(observer, &state, next, &(moon.pos - pos))
+                    .expect("the Moon is in the frame");
+                track.push(TrackPoint {
+                    date: sim_time_to_utc(next),
+                    position,
+                    radius: (moon.radius / (moon.pos - pos).norm()).asin() / observer.half_fov(),
+                    visibility,
+                });
+                earth.push((
+                    observer::frame_position(observer, &state, next, &to_earth),
+                    observer.earth_obscuration(to_earth.norm()) / observer.half_fov(),
+                ));
+            }
+            next += every;
+        }
+    }
+
+    let (earth, earth_radius) = earth.get(earth.len() / 2).copied()?;
+    Some(FramePass {
+        observer: observer.name().to_owned(),
+        half_fov: observer.half_fov(),
+        sensor: observer.sensor(),
+        track,
+        earth,
+        earth_radius,
+    })
+}
+
+impl FramePass {
+    /// The Moon at `date`, interpolated between the points of the track, or `None` if the
+    /// date is outside of the pass.
+    pub fn point_at(&self, date: DateTime<Utc>) -> Option<TrackPoint> {
+        let index = self.track.iter().position(|point| point.date >= date)?;
+        let after = self.track[index];
+        if index == 0 {
+            return (after.date == date).then_some(after);
+        }
+        let before = self.track[index - 1];
+        let fraction = (date - before.date).num_milliseconds() as f64
+            / (after.date - before.date).num_milliseconds() as f64;
+        let nearest = if fraction < 0.5 { before } else { after };
+        Some(TrackPoint {
+            date,
+            position: FramePosition {
+                x: before.position.x + (after.position.x - before.position.x) * fraction,
+                y: before.position.y + (after.position.y - before.position.y) * fraction,
+            },
+            radius: before.radius + (after.radius - before.radius) * fraction,
+            visibility: nearest.visibility,
+        })
+    }
+
+    /// Draws the frame with the Earth disk and the Moon's track to a PNG or SVG file,
+    /// depending on the extension of `path`. The track is labeled at the times of `ticks`,
+    /// e.g. at the times of the images. With a known sensor, the axes are in pixels.
+    pub fn draw(&self, path: &Path, ticks: Schedule) -> Result<()> {
+        check_path(path)?;
+        let result = if path.extension() == Some("png".as_ref()) {
+            self.draw_on(
+                &BitMapBackend::new(path, SIZE).into_drawing_area(),
+                Some(ticks),
+                None,
+            )
+        } else {
+            self.draw_on(
+                &SVGBackend::new(path, SIZE).into_drawing_area(),
+                Some(ticks),
+                None,
+            )
+        };
+        result.map_err(|err| err.in_file(path))
+    }
+
+    /// The times of the images taken according to `schedule` during the pass.
+    pub fn images(&self, schedule: Schedule) -> Vec<DateTime<Utc>> {
+        let last = self.track[self.track.len() - 1].date;
+        let mut images = vec![];
+        let mut image = schedule.next_image(self.track[0].date);
+        while image <= last {
+            images.push(image);
+            image = schedule.next_image(image + Duration::milliseconds(1));
+        }
+        images
+    }
+
+    /// Writes an animated GIF to `path` showing the Moon in each image taken according to
+    /// `schedule` for `delay` milliseconds.
+    pub fn animate(&self, path: &Path, schedule: Schedule, delay: u32) -> Result<()> {
+        let images = self.images(schedule);
+        if images.is_empty() {
+            return Err(Error::Argument(
+                "no images are taken during the pass".to_owned(),
+            ));
+        }
+        let result = BitMapBackend::gif(path, SIZE, delay)
+            .map_err(|err| Error::Plot(err.to_string()))
+            .and_then(|backend| {
+                let root = backend.into_drawing_area();
+                images
+                    .iter()
+                    .try_for_each(|&image| self.draw_on(&root, None, Some(image)))
+            });
+        result.map_err(|err| err.in_file(path))
+    }
+
+    /// Writes a PNG file for each image taken according to `schedule` to `dir`, named after
+    /// the time of the image, and returns their paths.
+    pub fn draw_images(&self, dir: &Path, schedule: Schedule) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir).map_err(|err| Error::from(err).in_file(dir))?;
+        let mut paths = vec![];
+        for image in self.images(schedule) {
+            let path = dir.join(image.format("%Y%m%dT%H%M%SZ.png").to_string());
+            self.draw_on(
+                &BitMapBackend::new(&path, SIZE).into_drawing_area(),
+                None,
+                Some(image),
+            )
+            .map_err(|err| err.in_file(&path))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Converts a frame position to the coordinates of the drawing.
+    fn coordinates(&self, position: FramePosition) -> (f64, f64) {
+        match self.sensor {
+            Some(sensor) => {
+                let pixels = observer::pixel_position(position, self.half_fov, sensor, 0.0);
+                (pixels.column, pixels.row)
+            }
+            None => (position.x, position.y),
+        }
+    }
+
+    /// The outline of a disk in the frame, in the coordinates of the drawing.
+    fn disk(&self, center: FramePosition, radius: f64) -> Vec<(f64, f64)> {
+        (0..OUTLINE_POINTS)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f64 / OUTLINE_POINTS as f64;
+                self.coordinates(FramePosition {
+                    x: center.x + radius * angle.cos(),
+                    y: center.y + radius * angle.sin(),
+                })
+            })
+            .collect()
+    }
+
+    /// Draws the frame, labeling the track at the times of `ticks`, or showing the Moon at
+    /// the time of `image` over a faded track.
+    fn draw_on<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        ticks: Option<Schedule>,
+        image: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let (low, high) = match self.sensor {
+            Some(sensor) => (0.0, f64::from(sensor.pixels)),
+            None => (-1.0, 1.0),
+        };
+        let margin = 0.05 * (high - low);
+        let (first, last) = (self.track[0].date, self.track[self.track.len() - 1].date);
+        let title = match image {
+            Some(date) => format!(
+                "The Moon in {}'s frame, {} UTC",
+                self.observer,
+                date.format("%Y-%m-%d %H:%M:%S")
+            ),
+            None => format!(
+                "The Moon in {}'s frame, {} – {} UTC",
+                self.observer,
+                first.format("%Y-%m-%d %H:%M"),
+                last.format("%H:%M")
+            ),
+        };
+
+        root.fill(&WHITE).map_err(plot_error)?;
+        // rows grow downwards, like in the images
+        let mut chart = ChartBuilder::on(root)
+            .caption(title, ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(low - margin..high + margin, high + margin..low - margin)
+            .map_err(plot_error)?;
+        let (x_desc, y_desc) = match self.sensor {
+            Some(_) => ("column", "row"),
+            None => ("x", "y"),
+        };
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_desc(x_desc)
+            .y_desc(y_desc)
+            .draw()
+            .map_err(plot_error)?;
+
+        if let Some(earth) = self.earth {
+            chart
+                .draw_series([Polygon::new(
+                    self.disk(earth, self.earth_radius),
+                    EARTH_COLOR.filled(),
+                )])
+                .map_err(plot_error)?
+                .label("Earth")
+                .legend(|(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 20, y + 5)], EARTH_COLOR.filled())
+                });
+        }
+        chart
+            .draw_series([Rectangle::new([(low, low), (high, high)], BLACK)])
+            .map_err(plot_error)?;
+
+        let faded = if image.is_some() { 0.3 } else { 1.0 };
+        for pair in self.track.windows(2) {
+            let color = match pair[1].visibility {
+                Visibility::Obscured => OBSCURED_COLOR,
+                _ => VISIBLE_COLOR,
+            };
+            let line = pair.iter().map(|point| self.coordinates(point.position));
+            chart
+                .draw_series(LineSeries::new(line, color.mix(faded).stroke_width(2)))
+                .map_err(plot_error)?;
+        }
+        for (label, color) in [
+            ("Moon", VISIBLE_COLOR),
+            ("Moon behind the Earth", OBSCURED_COLOR),
+        ] {
+            chart
+                .draw_series(LineSeries::new(std::iter::empty(), color))
+                .map_err(plot_error)?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+        }
+
+        if let Some(moon) = image.and_then(|date| self.point_at(date)) {
+            let color = match moon.visibility {
+                Visibility::Obscured => OBSCURED_COLOR,
+                _ => VISIBLE_COLOR,
+            };
+            chart
+                .draw_series([Polygon::new(
+                    self.disk(moon.position, moon.radius),
+                    color.filled(),
+                )])
+                .map_err(plot_error)?;
+        }
+        let ticks = ticks.map(|ticks| self.images(ticks)).unwrap_or_default();
+        for tick in ticks {
+            if let Some(point) = self.point_at(tick) {
+                let point = self.coordinates(point.position);
+                let label = tick.format("%H:%M").to_string();
+                chart
+                    .draw_series([Circle::new(point, 3, BLACK.filled())])
+                    .map_err(plot_error)?;
+                chart
+                    .draw_series([Text::new(label, point, ("sans-serif", 14))])
+                    .map_err(plot_error)?;
+            }
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(plot_error)?;
+        root.present().map_err(plot_error)
+    }
+}
@@ -0,0 +1,327 @@
+mod frame;
+
+pub use frame::{moon_pass, FramePass, TrackPoint};
+
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use plotters::{coord::Shift, prelude::*};
+
+use crate::{
+    apsides, eclipse,
+    eclipse::EclipseDetector,
+    error::{Error, Result},
+    events,
+    observer::Observer,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Size of the charts, in pixels.
+const SIZE: (u32, u32) = (1200, 600);
+/// Colors of the series, in order.
+const SERIES_COLORS: [RGBColor; 2] = [RGBColor(220, 20, 60), RGBColor(40, 40, 40)];
+/// Colors of the markers, by the order their labels first appear in.
+const MARKER_COLORS: [RGBColor; 4] = [
+    RGBColor(0, 90, 200),
+    RGBColor(0, 150, 0),
+    RGBColor(200, 0, 200),
+    RGBColor(230, 120, 0),
+];
+
+pub const QUANTITY_NAMES: [&str; 3] = ["moon-boresight", "moon-distance", "eclipse-magnitude"];
+
+/// What can be charted over time.
+#[derive(Clone, Copy)]
+pub enum Quantity<'a> {
+    /// The angle between the observer's looking direction and the direction to the Moon.
+    MoonBoresight(&'a dyn Observer),
+    /// The distance between the centers of the Earth and the Moon.
+    MoonDistance,
+    /// The umbral and penumbral magnitudes of lunar eclipses, zero outside of them.
+    EclipseMagnitude,
+}
+
+/// Values of a quantity at a series of dates.
+pub struct Series {
+    pub label: String,
+    pub points: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// An event marked on a chart with a vertical line.
+pub struct Marker {
+    pub date: DateTime<Utc>,
+    /// Markers with the same label are drawn in the same color.
+    pub label: String,
+}
+
+/// A chart of some series over time, with markers.
+pub struct Chart {
+    pub title: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+    pub markers: Vec<Marker>,
+}
+
+impl<'a> Quantity<'a> {
+    /// The quantity with the given name from `QUANTITY_NAMES`; `observer` is only used for
+    /// the boresight angle.
+    pub fn by_name(name: &str, observer: &'a dyn Observer) -> Option<Self> {
+        match name {
+            "moon-boresight" => Some(Quantity::MoonBoresight(observer)),
+            "moon-distance" => Some(Quantity::MoonDistance),
+            "eclipse-magnitude" => Some(Quantity::EclipseMagnitude),
+            _ => None,
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Quantity::MoonBoresight(observer) => {
+                format!("Angle between {}'s boresight and the Moon", observer.name())
+            }
+            Quantity::MoonDistance => "Earth–Moon distance".to_owned(),
+            Quantity::EclipseMagnitude => "Lunar eclipse magnitude".to_owned(),
+        }
+    }
+
+    fn y_label(&self) -> &'static str {
+        match self {
+            Quantity::MoonBoresight(_) => "angle (°)",
+            Quantity::MoonDistance => "distance (km)",
+            Quantity::EclipseMagnitude => "magnitude",
+        }
+    }
+
+    fn series_labels(&self) -> &'static [&'static str] {
+        match self {
+            Quantity::MoonBoresight(_) => &["angle"],
+            Quantity::MoonDistance => &["distance"],
+            Quantity::EclipseMagnitude => &["umbral", "penumbral"],
+        }
+    }
+
+    fn values(&self, detector: &EclipseDetector, sim: &SimState, time: f64) -> Vec<f64> {
+        let moon = sim.body_by_name("Moon").unwrap();
+        match self {
+            Quantity::MoonBoresight(observer) => {
+                let dir = moon.pos - observer.pos(sim, time);
+                vec![observer.looking_dir(sim, time).angle(&dir).to_degrees()]
+            }
+            Quantity::MoonDistance => {
+                let earth = sim.body_by_name("Earth").unwrap();
+                vec![moon.distance_from(earth)]
+            }
+            Quantity::EclipseMagnitude => match detector.lunar_shadow(sim, time) {
+                Some(shadow) => vec![
+                    shadow.umbral_magnitude().max(0.0),
+                    shadow.penumbral_magnitude().max(0.0),
+                ],
+                None => vec![0.0, 0.0],
+            },
+        }
+    }
+
+    /// Samples the quantity every `every` seconds between `start` and `end` (simulation
+    /// times), `sim` being the state of the system at `start`.
+    pub fn sample<P: Into<Propagator>>(
+        &self,
+        sim: &SimState,
+        start: f64,
+        end: f64,
+        every: f64,
+        propagator: P,
+    ) -> Vec<Series> {
+        let mut sim = sim.clone();
+        let mut propagator = propagator.into();
+        let mut detector = EclipseDetector::new(propagator.max_step());
+        let save_light_dir = |detector: &mut EclipseDetector, sim: &SimState, time: f64| {
+            let sun = sim.body_by_name("Sun").unwrap();
+            let earth = sim.body_by_name("Earth").unwrap();
+            detector.save_light_dir(time, earth.pos - sun.pos);
+        };
+        save_light_dir(&mut detector, &sim, start);
+
+        let mut series: Vec<Series> = self
+            .series_labels()
+            .iter()
+            .map(|label| Series {
+                label: label.to_string(),
+                points: vec![],
+            })
+            .collect();
+        let mut time = start;
+        let mut next = start;
+        while next <= end {
+            time += propagator.step(&mut sim);
+            save_light_dir(&mut detector, &sim, time);
+            // the samples within the last step, evaluated with single steps back from its end
+            while next <= time && next <= end {
+                let state = propagator.propagated(&sim, next - time);
+                let date = sim_time_to_utc(next);
+                for (series, value) in series.iter_mut().zip(self.values(&detector, &state, next)) {
+                    series.points.push((date, value));
+                }
+                next += every;
+            }
+        }
+        series
+    }
+
+    /// The events worth marking on a chart of the quantity: the visibility events for the
+    /// boresight angle, the apsides for the distance and the greatest eclipses for the
+    /// magnitude.
+    pub fn markers<P: Into<Propagator>>(
+        &self,
+        sim: &SimState,
+        start: f64,
+        end: f64,
+        propagator: P,
+    ) -> Vec<Marker> {
+        match self {
+            Quantity::MoonBoresight(observer) => {
+                events::visibility_events(sim, start, end, propagator, *observer)
+                    .into_iter()
+                    .map(|event| Marker {
+                        date: event.date,
+                        label: event.kind.description().to_owned(),
+                    })
+                    .collect()
+            }
+            Quantity::MoonDistance => apsides::lunar_apsides(sim, start, end, propagator)
+                .into_iter()
+                .map(|apsis| Marker {
+                    date: apsis.date,
+                    label: format!("{:?}", apsis.kind),
+                })
+                .collect(),
+            Quantity::EclipseMagnitude => eclipse::find_lunar_eclipses(sim, start, end, propagator)
+                .into_iter()
+                .map(|eclipse| Marker {
+                    date: eclipse.greatest,
+                    label: "Greatest eclipse".to_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    /// A chart of the quantity between `start` and `end`, with the events marked.
+    pub fn chart<P: Into<Propagator>>(
+        &self,
+        sim: &SimState,
+        start: f64,
+        end: f64,
+        every: f64,
+        propagator: P,
+    ) -> Chart {
+        let propagator = propagator.into();
+        Chart {
+            title: self.title(),
+            y_label: self.y_label().to_owned(),
+            series: self.sample(sim, start, end, every, propagator.clone()),
+            markers: self.markers(sim, start, end, propagator),
+        }
+    }
+}
+
+impl Chart {
+    /// Draws the chart to a PNG or SVG file, depending on the extension of `path`.
+    pub fn draw(&self, path: &Path) -> Result<()> {
+        check_path(path)?;
+        let result = if path.extension() == Some("png".as_ref()) {
+            self.draw_on(BitMapBackend::new(path, SIZE).into_drawing_area())
+        } else {
+            self.draw_on(SVGBackend::new(path, SIZE).into_drawing_area())
+        };
+        result.map_err(|err| err.in_file(path))
+    }
+
+    fn draw_on<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()> {
+        let points = || self.series.iter().flat_map(|series| &series.points);
+        let (first, last) = match (points().map(|p| p.0).min(), points().map(|p| p.0).max()) {
+            (Some(first), Some(last)) if first < last => (first, last),
+            _ => return Err(Error::Argument("nothing to plot".to_owned())),
+        };
+        let min = points().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max = points().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        let margin = ((max - min) * 0.05).max(1e-3);
+        let (min, max) = (min - margin, max + margin);
+
+        let date_format = if last - first < Duration::days(10) {
+            "%Y-%m-%d %H:%M"
+        } else {
+            "%Y-%m-%d"
+        };
+
+        root.fill(&WHITE).map_err(plot_error)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&self.title, ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(80)
+            .build_cartesian_2d(first..last, min..max)
+            .map_err(plot_error)?;
+        chart
+            .configure_mesh()
+            .x_label_formatter(&|date| date.format(date_format).to_string())
+            .y_desc(&self.y_label)
+            .draw()
+            .map_err(plot_error)?;
+
+        let mut marker_labels: Vec<&str> = vec![];
+        for marker in &self.markers {
+            let (index, first) = match marker_labels
+                .iter()
+                .position(|&label| label == marker.label)
+            {
+                Some(index) => (index, false),
+                None => {
+                    marker_labels.push(&marker.label);
+                    (marker_labels.len() - 1, true)
+                }
+            };
+            let color = MARKER_COLORS[index % MARKER_COLORS.len()];
+            let line = LineSeries::new([(marker.date, min), (marker.date, max)], color);
+            let drawn = chart.draw_series(line).map_err(plot_error)?;
+            // one legend entry per kind of marker
+            if first {
+                drawn
+                    .label(&marker.label)
+                    .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+            }
+        }
+        for (index, series) in self.series.iter().enumerate() {
+            let color = SERIES_COLORS[index % SERIES_COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(series.points.iter().copied(), color))
+                .map_err(plot_error)?
+                .label(&series.label)
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(plot_error)?;
+        root.present().map_err(plot_error)
+    }
+}
+
+/// Checks that a chart can be drawn to `path`, judging by its extension.
+pub fn check_path(path: &Path) -> Result<()> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png" | "svg") => Ok(()),
+        _ => Err(Error::Argument(format!(
+            "charts can only be saved as .png or .svg, not {}",
+            path.display()
+        ))),
+    }
+}
+
+pub(crate) fn plot_error<E: std::error::Error + Send + Sync>(
+    err: DrawingAreaErrorKind<E>,
+) -> Error {
+    Error::Plot(err.to_string())
+}
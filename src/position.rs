@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    frames::{self, Frame},
+    separation::Viewpoint,
+    simulation::SimState,
+    time::sim_time_to_utc,
+};
+
+/// Where a body is in the sky.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SkyPosition {
+    pub date: DateTime<Utc>,
+    /// In radians.
+    pub right_ascension: f64,
+    /// In radians.
+    pub declination: f64,
+    /// In km.
+    pub distance: f64,
+}
+
+/// Position of the named body seen from `viewpoint`, `sim` being the state of the system at
+/// `time`. The coordinates refer to the equator of `frame`, which should be one of the
+/// equatorial frames.
+pub fn sky_position(
+    sim: &SimState,
+    time: f64,
+    viewpoint: Viewpoint,
+    body: &str,
+    frame: Frame,
+) -> SkyPosition {
+    let (rel, _) = viewpoint.relative(sim, time, body);
+    let (right_ascension, declination) = frames::spherical(&frame.from_sim(rel, time));
+    SkyPosition {
+        date: sim_time_to_utc(time),
+        right_ascension,
+        declination,
+        distance: rel.norm(),
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, Not},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    observer::{self, Observer, Visibility},
+    phase, refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Precision of the reported event times, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+
+/// Where the Moon is relative to an observer's frame and the Earth disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// In the frame, whether or not the Earth hides it.
+    InFrame,
+    Visible,
+    Obscured,
+    Transiting,
+}
+
+/// Names accepted by `Condition::from_str`.
+pub const CONDITION_NAMES: &[&str] = &["in-frame", "visible", "obscured", "transiting"];
+
+impl Condition {
+    fn holds(&self, observer: &dyn Observer, sim: &SimState, time: f64) -> bool {
+        let visibility = observer::moon_visibility(observer, sim, time);
+        match self {
+            Condition::InFrame => visibility != Visibility::OutOfFrame,
+            Condition::Visible => visibility == Visibility::Visible,
+            Condition::Obscured => visibility == Visibility::Obscured,
+            Condition::Transiting => visibility == Visibility::Transiting,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Condition::InFrame => "in-frame",
+            Condition::Visible => "visible",
+            Condition::Obscured => "obscured",
+            Condition::Transiting => "transiting",
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "in-frame" => Ok(Condition::InFrame),
+            "visible" => Ok(Condition::Visible),
+            "obscured" => Ok(Condition::Obscured),
+            "transiting" => Ok(Condition::Transiting),
+            _ => Err(format!("unknown condition: {}", s)),
+        }
+    }
+}
+
+/// A quantity seen by an observer that can be compared with a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    /// Fraction of the Moon's disk that is lit.
+    IlluminatedFraction,
+    /// The Sun-Moon-observer angle, in degrees.
+    PhaseAngle,
+    /// Angle between the Sun and the boresight, in degrees.
+    SunBoresight,
+    /// Angle between the Sun and the Moon, in degrees.
+    SunMoon,
+    /// Distance of the Moon, in km.
+    MoonDistance,
+}
+
+/// Names accepted by `Quantity::from_str`.
+pub const QUANTITY_NAMES: &[&str] = &[
+    "illuminated-fraction",
+    "phase-angle",
+    "sun-boresight",
+    "sun-moon",
+    "moon-distance",
+];
+
+impl Quantity {
+    pub fn value(&self, observer: &dyn Observer, sim: &SimState, time: f64) -> f64 {
+        let pos = observer.pos(sim, time);
+        match self {
+            Quantity::IlluminatedFraction => {
+                phase::moon_phase_seen_from(sim, &pos).illuminated_fraction
+            }
+            Quantity::PhaseAngle => phase::moon_phase_seen_from(sim, &pos)
+                .phase_angle
+                .to_degrees(),
+            Quantity::SunBoresight => observer::sun_angles(observer, sim, time)
+                .boresight
+                .to_degrees(),
+            Quantity::SunMoon => observer::sun_angles(observer, sim, time).moon.to_degrees(),
+            Quantity::MoonDistance => (sim.body_by_name("Moon").unwrap().pos - pos).norm(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Quantity::IlluminatedFraction => "illuminated-fraction",
+            Quantity::PhaseAngle => "phase-angle",
+            Quantity::SunBoresight => "sun-boresight",
+            Quantity::SunMoon => "sun-moon",
+            Quantity::MoonDistance => "moon-distance",
+        }
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "illuminated-fraction" => Ok(Quantity::IlluminatedFraction),
+            "phase-angle" => Ok(Quantity::PhaseAngle),
+            "sun-boresight" => Ok(Quantity::SunBoresight),
+            "sun-moon" => Ok(Quantity::SunMoon),
+            "moon-distance" => Ok(Quantity::MoonDistance),
+            _ => Err(format!("unknown quantity: {}", s)),
+        }
+    }
+}
+
+/// A compound condition on what an observer sees, parsed from text like
+/// "in-frame and illuminated-fraction > 0.5 and sun-boresight > 20°", or built with `&`, `|`
+/// and `!`. `not` binds tighter than `and`, which binds tighter than `or`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Is(Condition),
+    Above(Quantity, f64),
+    Below(Quantity, f64),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+impl Query {
+    pub fn holds(&self, observer: &dyn Observer, sim: &SimState, time: f64) -> bool {
+        match self {
+            Query::Is(condition) => condition.holds(observer, sim, time),
+            Query::Above(quantity, value) => quantity.value(observer, sim, time) > *value,
+            Query::Below(quantity, value) => quantity.value(observer, sim, time) < *value,
+            Query::Not(query) => !query.holds(observer, sim, time),
+            Query::And(first, second) => {
+                first.holds(observer, sim, time) && second.holds(observer, sim, time)
+            }
+            Query::Or(first, second) => {
+                first.holds(observer, sim, time) || second.holds(observer, sim, time)
+            }
+        }
+    }
+}
+
+impl BitAnd for Query {
+    type Output = Query;
+
+    fn bitand(self, other: Query) -> Query {
+        Query::And(Box::new(self), Box::new(other))
+    }
+}
+
+impl BitOr for Query {
+    type Output = Query;
+
+    fn bitor(self, other: Query) -> Query {
+        Query::Or(Box::new(self), Box::new(other))
+    }
+}
+
+impl Not for Query {
+    type Output = Query;
+
+    fn not(self) -> Query {
+        Query::Not(Box::new(self))
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let query = parser.or()?;
+        match parser.advance() {
+            None => Ok(query),
+            Some(token) => Err(Error::Query(format!("unexpected \"{}\"", token))),
+        }
+    }
+}
+
+/// Writes the query back in the syntax it's parsed from, with only the needed parentheses.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Query::Is(condition) => f.write_str(condition.name()),
+            Query::Above(quantity, value) => write!(f, "{} > {}", quantity.name(), value),
+            Query::Below(quantity, value) => write!(f, "{} < {}", quantity.name(), value),
+            Query::Not(query) => match **query {
+                Query::And(..) | Query::Or(..) => write!(f, "not ({})", query),
+                _ => write!(f, "not {}", query),
+            },
+            Query::And(first, second) => {
+                let operand = |query: &Query| match query {
+                    Query::Or(..) => format!("({})", query),
+                    _ => query.to_string(),
+                };
+                write!(f, "{} and {}", operand(first), operand(second))
+            }
+            Query::Or(first, second) => write!(f, "{} or {}", first, second),
+        }
+    }
+}
+
+/// Splits a query into the words, numbers, comparison operators and parentheses, lowercased.
+/// Degree signs are dropped.
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '°');
+        let Some(first) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        let len = match first {
+            '<' | '>' | '(' | ')' => 1,
+            _ => rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '.' || c == '_'))
+                .unwrap_or(rest.len()),
+        };
+        if len == 0 {
+            return Err(Error::Query(format!("unexpected \"{}\"", first)));
+        }
+        let (token, tail) = rest.split_at(len);
+        tokens.push(token.to_lowercase());
+        rest = tail;
+    }
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    next: usize,
+}
+
+impl Parser {
+    fn advance(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.next)?;
+        self.next += 1;
+        Some(token)
+    }
+
+    /// Skips the next token if it's `keyword`.
+    fn skip(&mut self, keyword: &str) -> bool {
+        let found = self
+            .tokens
+            .get(self.next)
+            .is_some_and(|token| token == keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Query> {
+        let mut query = self.and()?;
+        while self.skip("or") {
+            query = query | self.and()?;
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query> {
+        let mut query = self.not()?;
+        while self.skip("and") {
+            query = query & self.not()?;
+        }
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query> {
+        if self.skip("not") {
+            Ok(!self.not()?)
+        } else {
+            self.term()
+        }
+    }
+
+    fn term(&mut self) -> Result<Query> {
+        let token = self
+            .advance()
+            .ok_or_else(|| Error::Query("unexpected end".into()))?
+            .to_owned();
+        if token == "(" {
+            let query = self.or()?;
+            if !self.skip(")") {
+                return Err(Error::Query("missing \")\"".into()));
+            }
+            return Ok(query);
+        }
+        if let Ok(condition) = token.parse() {
+            return Ok(Query::Is(condition));
+        }
+        let quantity: Quantity = token.parse().map_err(|_| {
+            Error::Query(format!(
+                "expected one of {} or a comparison of one of {}, got \"{}\"",
+                CONDITION_NAMES.join(", "),
+                QUANTITY_NAMES.join(", "),
+                token
+            ))
+        })?;
+        let operator = self.advance().map(str::to_owned);
+        let value = self.advance().and_then(|value| value.parse().ok());
+        match (operator.as_deref(), value) {
+            (Some(">"), Some(value)) => Ok(Query::Above(quantity, value)),
+            (Some("<"), Some(value)) => Ok(Query::Below(quantity, value)),
+            _ => Err(Error::Query(format!(
+                "expected {} to be compared with a number, like {} > 20",
+                token, token
+            ))),
+        }
+    }
+}
+
+/// A change of whether a query holds for an observer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryEvent {
+    pub observer: String,
+    /// Whether the query holds after the change.
+    pub holds: bool,
+    pub date: DateTime<Utc>,
+}
+
+/// Propagates `sim` from `start` to `end` (simulation times) and collects the changes of
+/// whether `query` holds for `observer`. It's checked once a step, so it can miss holding for
+/// less than a step; `and` and `or` skip their second part when the first one settles it.
+pub fn query_events<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    observer: &dyn Observer,
+    query: &Query,
+) -> Vec<QueryEvent> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut holds = query.holds(observer, &sim, time);
+
+    let mut events = vec![];
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+        if query.holds(observer, &sim, time) != holds {
+            let event_time = refine::bisect(
+                &propagator,
+                &sim,
+                time,
+                step,
+                REFINE_TOLERANCE,
+                |state, t| query.holds(observer, state, t) != holds,
+            );
+            holds = !holds;
+            if event_time <= end {
+                let date = sim_time_to_utc(event_time);
+                debug!(holds, %date, "query changed");
+                events.push(QueryEvent {
+                    observer: observer.name().to_owned(),
+                    holds,
+                    date,
+                });
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Query {
+        text.parse().unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        match text.parse::<Query>() {
+            Err(Error::Query(message)) => message,
+            other => panic!("{:?} parsed as {:?}", text, other),
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for text in [
+            "in-frame",
+            "not visible",
+            "not not visible",
+            "in-frame and illuminated-fraction > 0.5 and sun-boresight > 20",
+            "visible or obscured and transiting",
+            "(visible or obscured) and transiting",
+            "not (visible or obscured) and phase-angle < 90.5",
+            "not (in-frame and not transiting) or moon-distance > 384400",
+            "in-frame and (sun-moon < -1.5 or not (visible and obscured))",
+        ] {
+            let query = parse(text);
+            assert_eq!(query.to_string(), text);
+            assert_eq!(parse(&query.to_string()), query);
+        }
+    }
+
+    #[test]
+    fn display_keeps_the_structure() {
+        let visible = || Query::Is(Condition::Visible);
+        let obscured = || Query::Is(Condition::Obscured);
+        let transiting = || Query::Is(Condition::Transiting);
+        for query in [
+            !(visible() & obscured()),
+            !(visible() | obscured()),
+            (visible() | obscured()) & transiting(),
+            transiting() & (visible() | obscured()),
+            !!visible() | !(obscured() & !transiting()),
+        ] {
+            assert_eq!(parse(&query.to_string()), query);
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let visible = || Query::Is(Condition::Visible);
+        let obscured = || Query::Is(Condition::Obscured);
+        let transiting = || Query::Is(Condition::Transiting);
+        assert_eq!(
+            parse("not visible and obscured or transiting"),
+            (!visible() & obscured()) | transiting()
+        );
+        assert_eq!(
+            parse("visible or obscured and not transiting"),
+            visible() | (obscured() & !transiting())
+        );
+    }
+
+    #[test]
+    fn degree_signs() {
+        let expected = Query::Above(Quantity::SunBoresight, 20.0);
+        assert_eq!(parse("sun-boresight > 20°"), expected);
+        assert_eq!(parse("sun-boresight > 20 °"), expected);
+        assert_eq!(parse("Sun-Boresight>20°"), expected);
+        assert_eq!(
+            parse("sun-moon < 5° and in-frame"),
+            Query::Below(Quantity::SunMoon, 5.0) & Query::Is(Condition::InFrame)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("(visible or obscured"), "missing \")\"");
+        assert!(error("cloudy").contains("got \"cloudy\""));
+        assert!(error("sun-moon").contains("compared with a number"));
+        assert!(error("sun-moon >").contains("compared with a number"));
+        assert!(error("sun-moon > far").contains("compared with a number"));
+        assert!(error("sun-moon 20").contains("compared with a number"));
+        assert_eq!(error("visible obscured"), "unexpected \"obscured\"");
+        assert_eq!(error("visible )"), "unexpected \")\"");
+        assert_eq!(error("sun-moon = 20"), "unexpected \"=\"");
+        assert_eq!(error(""), "unexpected end");
+        assert_eq!(error("  "), "unexpected end");
+        assert_eq!(error("visible and"), "unexpected end");
+    }
+}
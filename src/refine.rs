@@ -0,0 +1,33 @@
+use crate::simulation::{Propagator, SimState};
+
+/// Finds the moment within `(time - step, time]` at which `pred` becomes true, to within
+/// `tolerance` seconds. `sim` is the state at `time`, where `pred` holds; it is assumed not
+/// to hold at `time - step`. A negative `step`, made by a backward propagator, makes it search
+/// within `[time, time - step)` for the moment `pred` stops holding. The intermediate states
+/// are obtained by propagating `sim` backwards with `propagator`'s integrator, so the search
+/// doesn't depend on the step used for the scan.
+pub fn bisect<F>(
+    propagator: &Propagator,
+    sim: &SimState,
+    time: f64,
+    step: f64,
+    tolerance: f64,
+    mut pred: F,
+) -> f64
+where
+    F: FnMut(&SimState, f64) -> bool,
+{
+    // offsets back from `time`: `pred` holds at `near`, doesn't at `far`
+    let mut near = 0.0;
+    let mut far = step;
+    while (far - near).abs() > tolerance {
+        let mid = 0.5 * (near + far);
+        let state = propagator.propagated(sim, -mid);
+        if pred(&state, time - mid) {
+            near = mid;
+        } else {
+            far = mid;
+        }
+    }
+    time - near
+}
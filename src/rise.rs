@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    observer::{GroundStation, Observer},
+    refine,
+    simulation::{Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Precision of the reported times, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+
+/// The twilights, each ending when the center of the Sun is this far below the horizon,
+/// without refraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Twilight {
+    Civil,
+    Nautical,
+    Astronomical,
+}
+
+/// Names accepted by `Twilight::from_str`.
+pub const TWILIGHT_NAMES: &[&str] = &["civil", "nautical", "astronomical"];
+
+impl Twilight {
+    const ALL: [Twilight; 3] = [Twilight::Civil, Twilight::Nautical, Twilight::Astronomical];
+
+    /// The elevation of the Sun the twilight ends at, in radians.
+    pub fn sun_elevation(&self) -> f64 {
+        match self {
+            Twilight::Civil => -6f64.to_radians(),
+            Twilight::Nautical => -12f64.to_radians(),
+            Twilight::Astronomical => -18f64.to_radians(),
+        }
+    }
+}
+
+impl FromStr for Twilight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "civil" => Ok(Twilight::Civil),
+            "nautical" => Ok(Twilight::Nautical),
+            "astronomical" => Ok(Twilight::Astronomical),
+            _ => Err(format!("unknown twilight: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum RiseKind {
+    Rise,
+    Transit,
+    Set,
+    /// The Sun rising above the elevation the twilight ends at.
+    Dawn(Twilight),
+    /// The Sun setting below it.
+    Dusk(Twilight),
+}
+
+impl RiseKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            RiseKind::Rise => "rises",
+            RiseKind::Transit => "transits",
+            RiseKind::Set => "sets",
+            RiseKind::Dawn(Twilight::Civil) => "civil dawn",
+            RiseKind::Dawn(Twilight::Nautical) => "nautical dawn",
+            RiseKind::Dawn(Twilight::Astronomical) => "astronomical dawn",
+            RiseKind::Dusk(Twilight::Civil) => "civil dusk",
+            RiseKind::Dusk(Twilight::Nautical) => "nautical dusk",
+            RiseKind::Dusk(Twilight::Astronomical) => "astronomical dusk",
+        }
+    }
+}
+
+/// A rise, an upper transit across the meridian or a set of a body seen from a ground station.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RiseEvent {
+    pub body: String,
+    pub kind: RiseKind,
+    pub date: DateTime<Utc>,
+    /// Direction of the center of the body then, in degrees: measured from the north towards
+    /// the east, and above the horizon.
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+/// Where a body is relative to the horizon and to the meridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Place {
+    /// Whether the upper limb is above the horizon.
+    up: bool,
+    east: bool,
+}
+
+impl Place {
+    fn new(station: &GroundStation, sim: &SimState, time: f64, body: &str) -> Self {
+        let horizontal = station.horizontal(sim, time, body).unwrap();
+        let target = sim.body_by_name(body).unwrap();
+        let distance = (target.pos - station.pos(sim, time)).norm();
+        let semi_diameter = (target.radius / distance).min(1.0).asin();
+        Self {
+            up: horizontal.elevation + semi_diameter > 0.0,
+            east: horizontal.azimuth.sin() > 0.0,
+        }
+    }
+}
+
+/// The number of twilight ends the Sun is below: 0 in the daytime and the twilights, 3 at
+/// night.
+fn darkness(station: &GroundStation, sim: &SimState, time: f64) -> usize {
+    let elevation = station
+        .geometric_horizontal(sim, time, "Sun")
+        .unwrap()
+        .elevation;
+    Twilight::ALL
+        .iter()
+        .filter(|twilight| elevation < twilight.sun_elevation())
+        .count()
+}
+
+/// Finds the rises, upper transits and sets of `bodies` seen from `station` between `start`
+/// and `end` (simulation times), `sim` being the state of the system at `start`, and the dawns
+/// and dusks of the twilights too if `twilight` is set. A body rises and sets with its upper
+/// limb on the horizon, raised by the refraction at its center if the station has it. The
+/// elevations are checked once a step, so a body that is up for less than a step can be
+/// missed.
+pub fn rise_times<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    station: &GroundStation,
+    bodies: &[&str],
+    twilight: bool,
+) -> Vec<RiseEvent> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut places: Vec<Place> = bodies
+        .iter()
+        .map(|body| Place::new(station, &sim, time, body))
+        .collect();
+    let mut dark = darkness(station, &sim, time);
+    let mut events = vec![];
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        if twilight {
+            let new = darkness(station, &sim, time);
+            let dusk = new > dark;
+            for &twilight in &Twilight::ALL[dark.min(new)..dark.max(new)] {
+                let event_time = refine::bisect(
+                    &propagator,
+                    &sim,
+                    time,
+                    step,
+                    REFINE_TOLERANCE,
+                    |state, t| {
+                        let sun = station.geometric_horizontal(state, t, "Sun").unwrap();
+                        (sun.elevation < twilight.sun_elevation()) == dusk
+                    },
+                );
+                if event_time <= end {
+                    let state = propagator.propagated(&sim, event_time - time);
+                    let sun = station
+                        .geometric_horizontal(&state, event_time, "Sun")
+                        .unwrap();
+                    let kind = if dusk {
+                        RiseKind::Dusk(twilight)
+                    } else {
+                        RiseKind::Dawn(twilight)
+                    };
+                    let date = sim_time_to_utc(event_time);
+                    debug!(?kind, %date, "found twilight");
+                    events.push(RiseEvent {
+                        body: "Sun".to_owned(),
+                        kind,
+                        date,
+                        azimuth: sun.azimuth.to_degrees(),
+                        elevation: sun.elevation.to_degrees(),
+                    });
+                }
+            }
+            dark = new;
+        }
+
+        for (body, place) in bodies.iter().zip(&mut places) {
+            let new = Place::new(station, &sim, time, body);
+            let mut found = |kind: RiseKind, pred: &dyn Fn(&Place) -> bool| {
+                let event_time = refine::bisect(
+                    &propagator,
+                    &sim,
+                    time,
+                    step,
+                    REFINE_TOLERANCE,
+                    |state, t| pred(&Place::new(station, state, t, body)),
+                );
+                if event_time <= end {
+                    let state = propagator.propagated(&sim, event_time - time);
+                    let horizontal = station.horizontal(&state, event_time, body).unwrap();
+                    let date = sim_time_to_utc(event_time);
+                    debug!(body, ?kind, %date, "found rise time");
+                    events.push(RiseEvent {
+                        body: body.to_string(),
+                        kind,
+                        date,
+                        azimuth: horizontal.azimuth.to_degrees(),
+                        elevation: horizontal.elevation.to_degrees(),
+                    });
+                }
+            };
+            if new.up && !place.up {
+                found(RiseKind::Rise, &|place| place.up);
+            } else if !new.up && place.up {
+                found(RiseKind::Set, &|place| !place.up);
+            }
+            // at the lower transit, the body crosses the meridian going east
+            if place.east && !new.east {
+                found(RiseKind::Transit, &|place| !place.east);
+            }
+            *place = new;
+        }
+    }
+
+    events.sort_by_key(|event| event.date);
+    events
+}
+
+/// The part of the period between `start` and `end` (simulation times), `sim` being the state
+/// at `start`, in which `body` is up seen from `station` and `twilight` has ended: from the
+/// first to the last moment, if there are any.
+pub fn visible_in_darkness<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    station: &GroundStation,
+    body: &str,
+    twilight: Twilight,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut up = Place::new(station, sim, start, body).up;
+    let mut dark = darkness(station, sim, start) > twilight as usize;
+    let mut span: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    let mut since = (up && dark).then(|| sim_time_to_utc(start));
+    for event in rise_times(sim, start, end, propagator, station, &[body], true) {
+        match event.kind {
+            RiseKind::Rise => up = true,
+            RiseKind::Set => up = false,
+            RiseKind::Dusk(kind) if kind == twilight => dark = true,
+            RiseKind::Dawn(kind) if kind == twilight => dark = false,
+            _ => continue,
+        }
+        match since {
+            None if up && dark => since = Some(event.date),
+            Some(first) if !(up && dark) => {
+                span = Some((span.map_or(first, |(first, _)| first), event.date));
+                since = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(first) = since {
+        span = Some((span.map_or(first, |(first, _)| first), sim_time_to_utc(end)));
+    }
+    span
+}
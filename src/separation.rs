@@ -0,0 +1,457 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    observer::{GroundStation, Observer, TleSatellite},
+    simulation::{apparent_position, DenseStep, Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// Precision of the reported times, in seconds.
+const REFINE_TOLERANCE: f64 = 1.0;
+/// Precision of the reported times of the satellite transits, which last about a second.
+const SATELLITE_TOLERANCE: f64 = 0.01;
+/// Shortest step of the scan for satellite transits, in seconds.
+const MIN_SATELLITE_SCAN: f64 = 0.05;
+/// An upper bound of the rate at which the Sun and the Moon move across the sky, in rad/s.
+const MAX_BODY_RATE: f64 = 2e-4;
+
+#[derive(Clone, Copy)]
+enum Place<'a> {
+    Body(&'a str),
+    Observer(&'a dyn Observer),
+}
+
+/// Where the sky is seen from, and how.
+#[derive(Clone, Copy)]
+pub struct Viewpoint<'a> {
+    place: Place<'a>,
+    apparent: bool,
+}
+
+impl<'a> Viewpoint<'a> {
+    /// The center of the named body.
+    pub fn body(name: &'a str) -> Self {
+        Self {
+            place: Place::Body(name),
+            apparent: false,
+        }
+    }
+
+    pub fn observer(observer: &'a dyn Observer) -> Self {
+        Self {
+            place: Place::Observer(observer),
+            apparent: false,
+        }
+    }
+
+    /// Whether to see the bodies at their apparent positions, corrected for the light travel
+    /// time and aberration, instead of the geometric ones.
+    pub fn apparent(self, apparent: bool) -> Self {
+        Self { apparent, ..self }
+    }
+
+    /// Position and velocity of the viewpoint.
+    pub fn state(&self, sim: &SimState, time: f64) -> (Vector3<f64>, Vector3<f64>) {
+        match self.place {
+            Place::Body(name) => {
+                let body = sim.body_by_name(name).unwrap();
+                (body.pos, body.vel)
+            }
+            Place::Observer(observer) => (observer.pos(sim, time), observer.vel(sim, time)),
+        }
+    }
+
+    /// Position of the named body relative to the viewpoint, and its rate of change.
+    pub fn relative(&self, sim: &SimState, time: f64, name: &str) -> (Vector3<f64>, Vector3<f64>) {
+        let body = sim.body_by_name(name).unwrap();
+        let (pos, vel) = self.state(sim, time);
+        if !self.apparent {
+            return (body.pos - pos, body.vel - vel);
+        }
+        // the corrections change slowly enough for the motion over a second to be uniform
+        let at =
+            |dt: f64| apparent_position(body.pos + body.vel * dt, body.vel, pos + vel * dt, vel);
+        (at(0.0), 0.5 * (at(1.0) - at(-1.0)))
+    }
+}
+
+/// Contact times of a disk passing in front of another one: I and IV when the disks touch
+/// externally, II and III when the smaller one is entirely inside the larger one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Contacts {
+    pub first: DateTime<Utc>,
+    pub second: Option<DateTime<Utc>>,
+    pub third: Option<DateTime<Utc>>,
+    pub fourth: DateTime<Utc>,
+}
+
+/// The closest approach of two bodies in the sky.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conjunction {
+    /// The nearer of the two bodies.
+    pub near: String,
+    pub far: String,
+    pub date: DateTime<Utc>,
+    /// In radians.
+    pub separation: f64,
+    /// Set if the disks of the bodies overlap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occultation: Option<Contacts>,
+}
+
+/// Direction from `viewpoint` to the named body, and its rate of change.
+fn direction(
+    sim: &SimState,
+    time: f64,
+    viewpoint: Viewpoint,
+    name: &str,
+) -> (Vector3<f64>, Vector3<f64>) {
+    let (rel_pos, rel_vel) = viewpoint.relative(sim, time, name);
+    let unit = rel_pos.normalize();
+    (unit, (rel_vel - unit * unit.dot(&rel_vel)) / rel_pos.norm())
+}
+
+/// Whether the angle between bodies `a` and `b` seen from `viewpoint` is growing.
+fn separating(sim: &SimState, time: f64, viewpoint: Viewpoint, a: &str, b: &str) -> bool {
+    let (u, du) = direction(sim, time, viewpoint, a);
+    let (w, dw) = direction(sim, time, viewpoint, b);
+    // the separation grows when its cosine decreases
+    du.dot(&w) + u.dot(&dw) < 0.0
+}
+
+/// Separation of the bodies and their apparent radii, seen from `viewpoint`.
+fn disks(sim: &SimState, time: f64, viewpoint: Viewpoint, a: &str, b: &str) -> (f64, f64, f64) {
+    let (rel_a, _) = viewpoint.relative(sim, time, a);
+    let (rel_b, _) = viewpoint.relative(sim, time, b);
+    let radius = |name: &str, rel: Vector3<f64>| {
+        let body = sim.body_by_name(name).unwrap();
+        (body.radius / rel.norm()).min(1.0).asin()
+    };
+    (rel_a.angle(&rel_b), radius(a, rel_a), radius(b, rel_b))
+}
+
+/// The angle between the named bodies seen from `viewpoint`, in radians.
+pub fn separation(sim: &SimState, time: f64, viewpoint: Viewpoint, a: &str, b: &str) -> f64 {
+    disks(sim, time, viewpoint, a, b).0
+}
+
+/// Finds the local minima of the angular separation of every pair of the named bodies seen
+/// from `viewpoint` between `start` and `end` (simulation times), `sim` being the state of the
+/// system at `start`. Only the minima below `max_separation` (radians) are returned. The
+/// bodies have to exist in `sim`.
+pub fn conjunctions<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    viewpoint: Viewpoint,
+    bodies: &[&str],
+    max_separation: f64,
+) -> Vec<Conjunction> {
+    let pairs: Vec<(&str, &str)> = bodies
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &a)| bodies[i + 1..].iter().map(move |&b| (a, b)))
+        .collect();
+    closest_approaches(
+        sim,
+        start,
+        end,
+        propagator,
+        viewpoint,
+        &pairs,
+        max_separation,
+    )
+}
+
+/// Finds the transits of the named planets across the Sun seen from `viewpoint` between
+/// `start` and `end` (simulation times), `sim` being the state of the system at `start`.
+pub fn transits<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    viewpoint: Viewpoint,
+    planets: &[&str],
+) -> Vec<Conjunction> {
+    let pairs: Vec<(&str, &str)> = planets.iter().map(|&planet| (planet, "Sun")).collect();
+    let approaches = closest_approaches(sim, start, end, propagator, viewpoint, &pairs, PI);
+    approaches
+        .into_iter()
+        .filter(|approach| approach.far == "Sun" && approach.occultation.is_some())
+        .collect()
+}
+
+fn closest_approaches<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    viewpoint: Viewpoint,
+    pairs: &[(&str, &str)],
+    max_separation: f64,
+) -> Vec<Conjunction> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut was_separating: Vec<bool> = pairs
+        .iter()
+        .map(|&(a, b)| separating(&sim, time, viewpoint, a, b))
+        .collect();
+    let mut result = vec![];
+
+    while time < end {
+        let step = propagator.step(&mut sim);
+        time += step;
+
+        for (&(a, b), was_separating) in pairs.iter().zip(&mut was_separating) {
+            let now_separating = separating(&sim, time, viewpoint, a, b);
+            if now_separating && !*was_separating {
+                // the minimum is within the last step; search it from the step's start
+                let state = propagator.propagated(&sim, -step);
+                let minimum = edge(&propagator, &state, time - step, step, |state, t| {
+                    !separating(state, t, viewpoint, a, b)
+                });
+                if minimum <= end {
+                    if let Some(conjunction) = closest_approach(
+                        &propagator,
+                        &sim,
+                        time,
+                        minimum,
+                        viewpoint,
+                        (a, b),
+                        max_separation,
+                    ) {
+                        result.push(conjunction);
+                    }
+                }
+            }
+            *was_separating = now_separating;
+        }
+    }
+
+    result.sort_by_key(|conjunction| conjunction.date);
+    result
+}
+
+/// Describes the closest approach of `bodies` at `minimum`, if it's closer than
+/// `max_separation`. `sim` is the state at `time`, shortly after `minimum`.
+fn closest_approach(
+    propagator: &Propagator,
+    sim: &SimState,
+    time: f64,
+    minimum: f64,
+    viewpoint: Viewpoint,
+    (a, b): (&str, &str),
+    max_separation: f64,
+) -> Option<Conjunction> {
+    let state = propagator.propagated(sim, minimum - time);
+    let (separation, radius_a, radius_b) = disks(&state, minimum, viewpoint, a, b);
+    if separation > max_separation {
+        return None;
+    }
+    let occultation = if separation < radius_a + radius_b {
+        let contact = |step: f64, inner: bool| {
+            let time = edge(propagator, &state, minimum, step, |state, t| {
+                let (separation, radius_a, radius_b) = disks(state, t, viewpoint, a, b);
+                if inner {
+                    separation < (radius_a - radius_b).abs()
+                } else {
+                    separation < radius_a + radius_b
+                }
+            });
+            sim_time_to_utc(time)
+        };
+        let step = propagator.max_step();
+        let inside = separation < (radius_a - radius_b).abs();
+        Some(Contacts {
+            first: contact(-step, false),
+            second: inside.then(|| contact(-step, true)),
+            third: inside.then(|| contact(step, true)),
+            fourth: contact(step, false),
+        })
+    } else {
+        None
+    };
+
+    let distance = |name: &str| viewpoint.relative(&state, minimum, name).0.norm();
+    let (near, far) = if distance(a) < distance(b) {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    let date = sim_time_to_utc(minimum);
+    debug!(near, far, %date, separation, "found conjunction");
+    Some(Conjunction {
+        near: near.to_owned(),
+        far: far.to_owned(),
+        date,
+        separation,
+        occultation,
+    })
+}
+
+/// Finds the transits of `satellites` across the disks of the named bodies, e.g. the Sun and the
+/// Moon, seen from `station` between `start` and `end` (simulation times), `sim` being the
+/// state of the system at `start`. Only the transits with the body above the horizon count.
+/// The satellites are points, and the scan never steps further than they can move across the
+/// sky before reaching a disk, so that none of the transits is missed.
+pub fn satellite_transits<P: Into<Propagator>>(
+    sim: &SimState,
+    start: f64,
+    end: f64,
+    propagator: P,
+    station: &GroundStation,
+    satellites: &[TleSatellite],
+    bodies: &[&str],
+) -> Vec<Conjunction> {
+    let mut sim = sim.clone();
+    let mut propagator = propagator.into();
+    let mut time = start;
+    let mut result = vec![];
+    while time < end {
+        let step = propagator.dense_step(&mut sim, time);
+        time = step.end_time();
+        for satellite in satellites {
+            for &body in bodies {
+                result.extend(transits_within(&step, end, station, satellite, body));
+            }
+        }
+    }
+    result.sort_by_key(|transit| transit.date);
+    result
+}
+
+/// Separation of `satellite` from the named body seen from `station` and the apparent radius
+/// of the body.
+fn satellite_disk(
+    sim: &SimState,
+    time: f64,
+    station: &GroundStation,
+    satellite: &TleSatellite,
+    body: &str,
+) -> (f64, f64) {
+    let pos = station.pos(sim, time);
+    let target = sim.body_by_name(body).unwrap();
+    let to_target = target.pos - pos;
+    let radius = (target.radius / to_target.norm()).min(1.0).asin();
+    ((satellite.pos(sim, time) - pos).angle(&to_target), radius)
+}
+
+/// The transits of `satellite` across the named body starting within `step`, before `end`.
+/// The state is interpolated within the step, and extrapolated for the moment a transit
+/// spills over its end.
+fn transits_within(
+    step: &DenseStep,
+    end: f64,
+    station: &GroundStation,
+    satellite: &TleSatellite,
+    body: &str,
+) -> Vec<Conjunction> {
+    let disk = |t: f64| satellite_disk(&step.state_at(t), t, station, satellite, body);
+    let gap = |t: f64| {
+        let (separation, radius) = disk(t);
+        separation - radius
+    };
+    let inside = |t: f64| gap(t) < 0.0;
+    let rate = satellite.max_angular_rate() + MAX_BODY_RATE;
+    let stop = step.end_time().min(end);
+
+    let mut transits = vec![];
+    let mut t = step.start_time();
+    // a transit in progress at the start was found with the previous step
+    let mut was_inside = inside(t);
+    while t < stop {
+        let previous = t;
+        t = (t + (gap(t).abs() / rate).max(MIN_SATELLITE_SCAN)).min(stop);
+        if inside(t) && !was_inside {
+            let first = bisect_time(previous, t, inside);
+            let mut last = t;
+            while inside(last) {
+                last += MIN_SATELLITE_SCAN;
+            }
+            let fourth = bisect_time(last, last - MIN_SATELLITE_SCAN, inside);
+            let minimum = golden_section(first, fourth, gap);
+            let state = step.state_at(minimum);
+            let up = station
+                .horizontal(&state, minimum, body)
+                .is_some_and(|horizontal| horizontal.elevation > 0.0);
+            if up {
+                let date = sim_time_to_utc(minimum);
+                debug!(satellite = satellite.name(), body, %date, "found satellite transit");
+                transits.push(Conjunction {
+                    near: satellite.name().to_owned(),
+                    far: body.to_owned(),
+                    date,
+                    separation: disk(minimum).0,
+                    occultation: Some(Contacts {
+                        first: sim_time_to_utc(first),
+                        second: None,
+                        third: None,
+                        fourth: sim_time_to_utc(fourth),
+                    }),
+                });
+            }
+            t = last;
+        }
+        was_inside = inside(t);
+    }
+    transits
+}
+
+/// The moment between `outside` and `inside` at which `pred` starts to hold, assuming it holds
+/// at `inside` and doesn't at `outside`.
+fn bisect_time<F: Fn(f64) -> bool>(mut outside: f64, mut inside: f64, pred: F) -> f64 {
+    while (inside - outside).abs() > SATELLITE_TOLERANCE {
+        let mid = 0.5 * (outside + inside);
+        if pred(mid) {
+            inside = mid;
+        } else {
+            outside = mid;
+        }
+    }
+    inside
+}
+
+/// The minimum of a function with a single one between `a` and `b`.
+fn golden_section<F: Fn(f64) -> f64>(mut a: f64, mut b: f64, f: F) -> f64 {
+    let ratio = 0.5 * (5f64.sqrt() - 1.0);
+    while b - a > SATELLITE_TOLERANCE {
+        let (c, d) = (b - ratio * (b - a), a + ratio * (b - a));
+        if f(c) < f(d) {
+            b = d;
+        } else {
+            a = c;
+        }
+    }
+    0.5 * (a + b)
+}
+
+/// Finds the last moment `pred` holds going from `time`, at which it holds, in steps of `step`
+/// (negative to search backwards). `sim` is the state at `time`, and the states at the other
+/// moments are propagated from it in single steps.
+fn edge<F>(propagator: &Propagator, sim: &SimState, time: f64, step: f64, mut pred: F) -> f64
+where
+    F: FnMut(&SimState, f64) -> bool,
+{
+    let mut near = 0.0;
+    let mut far = step;
+    while pred(&propagator.propagated(sim, far), time + far) {
+        near = far;
+        far += step;
+    }
+    while (far - near).abs() > REFINE_TOLERANCE {
+        let mid = 0.5 * (near + far);
+        if pred(&propagator.propagated(sim, mid), time + mid) {
+            near = mid;
+        } else {
+            far = mid;
+        }
+    }
+    time + near
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, io::Cursor};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response};
+use tracing::{info, warn};
+
+use crate::{
+    eclipse,
+    error::{Error, Result},
+    events,
+    frames::Frame,
+    observer, position,
+    separation::Viewpoint,
+    simulation::{Propagator, SimState},
+    snapshots::{self, Snapshots},
+    time::{self, utc_to_sim_time},
+};
+
+/// How often the states passed on the way to a requested date are kept in memory.
+const WARM_INTERVAL: f64 = 30.0 * 86400.0;
+/// The longest period a single request can ask for.
+const MAX_PERIOD: f64 = 25.0 * 365.25 * 86400.0;
+/// The most positions a single request can ask for.
+const MAX_POSITIONS: usize = 10_000;
+/// How far from the initial state the requested dates can be by default.
+const DEFAULT_WINDOW: f64 = 100.0 * 365.25 * 86400.0;
+
+/// A small HTTP API answering queries with JSON:
+///
+/// - `/visibility?observer=himawari&start=DATE&end=DATE`: the Moon visibility events of a
+///   built-in observer,
+/// - `/eclipses?start=DATE&end=DATE`: the lunar eclipses,
+/// - `/position?body=Moon&center=Earth&start=DATE[&end=DATE&every=DAYS][&apparent=true]`:
+///   the right ascension, declination and distance of a body.
+///
+/// The states passed when propagating to the requested dates are kept, in memory or in the
+/// snapshots, so later requests only propagate from the closest one. Dates further from the
+/// initial state than the window are rejected, so that no request keeps the server busy for
+/// hours.
+pub struct Server {
+    propagator: Propagator,
+    /// Sorted by time, starting with the initial state.
+    states: Vec<(f64, SimState)>,
+    snapshots: Option<Snapshots>,
+    /// In seconds.
+    window: f64,
+}
+
+#[derive(Serialize)]
+struct ErrorMessage {
+    error: String,
+}
+
+impl Server {
+    /// A server propagating `sim`, the state at `start`, with `propagator`, and using the
+    /// snapshots if there are any.
+    pub fn new(
+        sim: SimState,
+        start: f64,
+        propagator: Propagator,
+        snapshots: Option<Snapshots>,
+    ) -> Self {
+        Self {
+            propagator,
+            states: vec![(start, sim)],
+            snapshots,
+            window: DEFAULT_WINDOW,
+        }
+    }
+
+    /// Serves the dates up to `window` seconds from the initial state, instead of 100 years.
+    pub fn with_window(mut self, window: f64) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Answers the requests coming to `address`, like `127.0.0.1:8080`, until the process is
+    /// stopped.
+    pub fn run(mut self, address: &str) -> Result<()> {
+        let server = tiny_http::Server::http(address)
+            .map_err(|err| Error::Argument(format!("couldn't listen on {}: {}", address, err)))?;
+        info!(address, "serving");
+        for request in server.incoming_requests() {
+            let response = self.respond(&request);
+            if let Err(err) = request.respond(response) {
+                warn!(%err, "couldn't send a response");
+            }
+        }
+        Ok(())
+    }
+
+    fn respond(&mut self, request: &Request) -> Response<Cursor<Vec<u8>>> {
+        info!(method = %request.method(), url = request.url(), "request");
+        if *request.method() != Method::Get {
+            return json(405, &message("only GET requests are supported"));
+        }
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        let query: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let result = match path {
+            "/visibility" => self.visibility(&query),
+            "/eclipses" => self.eclipses(&query),
+            "/position" => self.position(&query),
+            _ => return json(404, &message("unknown endpoint")),
+        };
+        match result {
+            Ok(body) => Response::from_data(body).with_header(content_type()),
+            Err(err) => json(400, &message(&err.to_string())),
+        }
+    }
+
+    fn visibility(&mut self, query: &HashMap<String, String>) -> Result<Vec<u8>> {
+        let name = required(query, "observer")?;
+        let observer = observer::by_name(name)
+            .ok_or_else(|| Error::Argument(format!("unknown observer: {}", name)))?;
+        let (start, end) = period(query)?;
+        self.check_window(end)?;
+        let sim = self.state_at(start)?;
+        let events =
+            events::visibility_events(&sim, start, end, self.propagator.clone(), &*observer);
+        Ok(serde_json::to_vec(&events)?)
+    }
+
+    fn eclipses(&mut self, query: &HashMap<String, String>) -> Result<Vec<u8>> {
+        let (start, end) = period(query)?;
+        self.check_window(end)?;
+        let sim = self.state_at(start)?;
+        let eclipses = eclipse::find_lunar_eclipses(&sim, start, end, self.propagator.clone());
+        Ok(serde_json::to_vec(&eclipses)?)
+    }
+
+    fn position(&mut self, query: &HashMap<String, String>) -> Result<Vec<u8>> {
+        let body = query.get("body").map_or("Moon", String::as_str);
+        let center = query.get("center").map_or("Earth", String::as_str);
+        let start = date(required(query, "start")?)?;
+        let end = query.get("end").map(|end| date(end)).transpose()?;
+        let every = match query.get("every") {
+            Some(days) => number(days, "every")? * 86400.0,
+            None => 86400.0,
+        };
+        let apparent = query.get("apparent").is_some_and(|value| value == "true");
+        let end = end.unwrap_or(start);
+        if !(every.is_finite() && every > 0.0) || (end - start) / every > MAX_POSITIONS as f64 {
+            return Err(Error::Argument(format!(
+                "every has to be positive, and at most {} positions can be requested at once",
+                MAX_POSITIONS
+            )));
+        }
+        self.check_window(end)?;
+
+        let mut sim = self.state_at(start)?;
+        for name in [body, center] {
+            if sim.body_by_name(name).is_none() {
+                return Err(Error::MissingBody(name.to_owned()));
+            }
+        }
+        let viewpoint = Viewpoint::body(center).apparent(apparent);
+        let mut positions = vec![];
+        let mut time = start;
+        loop {
+            positions.push(position::sky_position(
+                &sim,
+                time,
+                viewpoint,
+                body,
+                Frame::Equatorial,
+            ));
+            if time + every > end {
+                break;
+            }
+            self.propagator.advance(&mut sim, every);
+            time += every;
+        }
+        Ok(serde_json::to_vec(&positions)?)
+    }
+
+    /// Fails if `time` is outside the window around the initial state.
+    fn check_window(&self, time: f64) -> Result<()> {
+        let origin = self.states[0].0;
+        if (time - origin).abs() > self.window {
+            return Err(Error::Argument(format!(
+                "only dates within {:.0} years of {} are served",
+                self.window / (365.25 * 86400.0),
+                time::sim_time_to_utc(origin).format("%Y-%m-%d")
+            )));
+        }
+        Ok(())
+    }
+
+    /// The state at `time`, propagated from the closest earlier one kept, or from the
+    /// earliest one if there is none.
+    fn state_at(&mut self, time: f64) -> Result<SimState> {
+        self.check_window(time)?;
+        if let Some(ref mut snapshots) = self.snapshots {
+            let (start, sim) = &self.states[0];
+            let mut sim = sim.clone();
+            snapshots.propagate(&mut sim, *start, time, &mut self.propagator)?;
+            return Ok(sim);
+        }
+
+        let index = self
+            .states
+            .iter()
+            .take_while(|(state_time, _)| *state_time <= time)
+            .count();
+        let (mut current, mut sim) = self.states[index.saturating_sub(1)].clone();
+        if time < current {
+            snapshots::warn_backwards(&sim, current, time, &self.propagator);
+            self.propagator.advance(&mut sim, time - current);
+            return Ok(sim);
+        }
+        let mut index = index;
+        while time - current > WARM_INTERVAL {
+            self.propagator.advance(&mut sim, WARM_INTERVAL);
+            current += WARM_INTERVAL;
+            self.states.insert(index, (current, sim.clone()));
+            index += 1;
+        }
+        self.propagator.advance(&mut sim, time - current);
+        Ok(sim)
+    }
+}
+
+fn required<'a>(query: &'a HashMap<String, String>, name: &str) -> Result<&'a str> {
+    query
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| Error::Argument(format!("missing parameter: {}", name)))
+}
+
+fn date(value: &str) -> Result<f64> {
+    Ok(utc_to_sim_time(time::parse_date(value)?))
+}
+
+fn number(value: &str, name: &str) -> Result<f64> {
+    value
+        .parse()
+        .map_err(|_| Error::Argument(format!("{} has to be a number, got \"{}\"", name, value)))
+}
+
+/// The `start` and `end` of the requested period.
+fn period(query: &HashMap<String, String>) -> Result<(f64, f64)> {
+    let start = date(required(query, "start")?)?;
+    let end = date(required(query, "end")?)?;
+    if end < start || end - start > MAX_PERIOD {
+        return Err(Error::Argument(
+            "the end has to be after the start, by at most 25 years".to_owned(),
+        ));
+    }
+    Ok((start, end))
+}
+
+fn message(text: &str) -> ErrorMessage {
+    ErrorMessage {
+        error: text.to_owned(),
+    }
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(serde_json::to_vec(body).expect("messages can be serialized"))
+        .with_status_code(status)
+        .with_header(content_type())
+}
+
+fn content_type() -> Header {
+    Header::from_bytes("Content-Type", "application/json").unwrap()
+}
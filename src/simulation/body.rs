@@ -1,18 +1,67 @@
 use super::{Position, Velocity};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Body {
     pub name: String,
 
+    /// Zero for test particles.
+    #[serde(default)]
     pub gm: f64,
     pub pos: Position,
     pub vel: Velocity,
 
+    #[serde(default)]
     pub radius: f64,
+    /// If set, the gravity field includes the J2 term of the body's flattening.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oblateness: Option<Oblateness>,
+}
+
+/// The second zonal harmonic of a body's gravity field.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Oblateness {
+    pub j2: f64,
+    /// Reference radius of `j2`, in km.
+    pub radius: f64,
+    /// Unit vector along the rotation axis, in the simulation frame.
+    pub pole: Vector3<f64>,
+}
+
+impl Oblateness {
+    /// Acceleration caused by the J2 term of a body with the given GM at `rel` relative to its
+    /// center.
+    pub fn accel(&self, gm: f64, rel: Vector3<f64>) -> Vector3<f64> {
+        let r2 = rel.dot(&rel);
+        let r = r2.sqrt();
+        let z = rel.dot(&self.pole);
+        let factor = 1.5 * self.j2 * gm * self.radius * self.radius / (r2 * r2 * r);
+        factor * ((5.0 * z * z / r2 - 1.0) * rel - 2.0 * z * self.pole)
+    }
 }
 
 impl Body {
+    /// A body that moves in the gravity field of the others, but doesn't attract them, like a
+    /// spacecraft or a small asteroid.
+    pub fn test_particle(name: &str, pos: Position, vel: Velocity) -> Body {
+        Body {
+            name: name.to_owned(),
+            gm: 0.0,
+            pos,
+            vel,
+            radius: 0.0,
+            oblateness: None,
+        }
+    }
+
+    pub fn is_massless(&self) -> bool {
+        self.gm == 0.0
+    }
+
     pub fn distance_from(&self, other: &Body) -> f64 {
         let diff = self.pos - other.pos;
         diff.dot(&diff).sqrt()
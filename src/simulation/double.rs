@@ -0,0 +1,28 @@
+//! The error-free transformations double-double arithmetic is built from, a number being kept
+//! as the unevaluated sum of a high and a low-order `f64`.
+
+/// The rounded sum of `a` and `b` and its rounding error (Knuth's TwoSum).
+pub fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_part = sum - a;
+    let error = (a - (sum - b_part)) + (b - b_part);
+    (sum, error)
+}
+
+/// The rounded product of `a` and `b` and its rounding error.
+pub fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let product = a * b;
+    (product, a.mul_add(b, -product))
+}
+
+/// Renormalizes `high + low`, `low` being at most about an ulp of `high`.
+pub fn quick_two_sum(high: f64, low: f64) -> (f64, f64) {
+    let sum = high + low;
+    (sum, low - (sum - high))
+}
+
+/// `(high, low) + (term_high, term_low)` in double-double arithmetic.
+pub fn add(high: f64, low: f64, term_high: f64, term_low: f64) -> (f64, f64) {
+    let (sum, error) = two_sum(high, term_high);
+    quick_two_sum(sum, error + low + term_low)
+}
@@ -0,0 +1,215 @@
+use std::{
+    convert::TryInto,
+    sync::{mpsc, OnceLock},
+};
+
+use nalgebra::Vector3;
+use tracing::{info, warn};
+use wgpu::util::DeviceExt;
+
+use super::Body;
+
+/// Below this many test particles, the transfers to and from the GPU cost more than computing
+/// their accelerations on the CPU.
+pub(super) const MIN_PARTICLES: usize = 1024;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Sums the Newtonian pulls of the sources, given as the position and the GM, on the test
+/// particles. Every particle has its own invocation, summing over all the sources.
+const SHADER: &str = r#"
+@group(0) @binding(0) var<storage, read> sources: array<f64>;
+@group(0) @binding(1) var<storage, read> particles: array<f64>;
+@group(0) @binding(2) var<storage, read_write> accels: array<f64>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= arrayLength(&particles) / 3u {
+        return;
+    }
+    let pos = vec3<f64>(particles[3u * i], particles[3u * i + 1u], particles[3u * i + 2u]);
+    var accel = vec3<f64>(0.0lf, 0.0lf, 0.0lf);
+    for (var s = 0u; s < arrayLength(&sources) / 4u; s++) {
+        let diff = vec3<f64>(sources[4u * s], sources[4u * s + 1u], sources[4u * s + 2u]) - pos;
+        let dist_sq = dot(diff, diff);
+        accel += diff * (sources[4u * s + 3u] / (dist_sq * sqrt(dist_sq)));
+    }
+    accels[3u * i] = accel.x;
+    accels[3u * i + 1u] = accel.y;
+    accels[3u * i + 2u] = accel.z;
+}
+"#;
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+/// The GPU, set up on first use, or `None` if there's no adapter computing in f64.
+fn gpu() -> Option<&'static Gpu> {
+    static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+    GPU.get_or_init(|| match Gpu::new() {
+        Ok(gpu) => Some(gpu),
+        Err(reason) => {
+            warn!("computing the forces on the CPU: {}", reason);
+            None
+        }
+    })
+    .as_ref()
+}
+
+impl Gpu {
+    fn new() -> Result<Self, String> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok_or("no GPU found")?;
+        let name = adapter.get_info().name;
+        // in f32, the differences of positions some AU from the origin would be off by km
+        if !adapter.features().contains(wgpu::Features::SHADER_F64) {
+            return Err(format!("{} doesn't compute in f64", name));
+        }
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::SHADER_F64,
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|err| err.to_string())?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("accels"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("accels"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        info!(
+            adapter = name,
+            "computing the forces on test particles on the GPU"
+        );
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn accels(&self, sources: &[f64], particles: &[f64]) -> Option<Vec<f64>> {
+        let storage = |label, contents: &[f64], usage| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: &to_bytes(contents),
+                    usage: wgpu::BufferUsages::STORAGE | usage,
+                })
+        };
+        let sources = storage("sources", sources, wgpu::BufferUsages::empty());
+        let particles = storage("particles", particles, wgpu::BufferUsages::empty());
+        let size = particles.size();
+        let accels = storage(
+            "accels",
+            &vec![0.0; size as usize / 8],
+            wgpu::BufferUsages::COPY_SRC,
+        );
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sources.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: accels.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let count = (size / 24) as u32;
+            pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&accels, 0, &readback, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = receiver.recv().ok()? {
+            warn!("couldn't read the accelerations back from the GPU: {}", err);
+            return None;
+        }
+        let accels = from_bytes(&slice.get_mapped_range());
+        readback.unmap();
+        Some(accels)
+    }
+}
+
+/// The Newtonian accelerations of the test particles among `bodies`, in the order of their
+/// indices, computed on the GPU, or `None` if there's none to compute them on.
+pub(super) fn particle_accels(bodies: &[Body], sources: &[usize]) -> Option<Vec<Vector3<f64>>> {
+    let gpu = gpu()?;
+    let sources: Vec<f64> = sources
+        .iter()
+        .flat_map(|&source| {
+            let body = &bodies[source];
+            [body.pos.x, body.pos.y, body.pos.z, body.gm]
+        })
+        .collect();
+    let particles: Vec<f64> = bodies
+        .iter()
+        .filter(|body| body.is_massless())
+        .flat_map(|body| body.pos.iter().copied())
+        .collect();
+    let accels = gpu.accels(&sources, &particles)?;
+    Some(
+        accels
+            .chunks_exact(3)
+            .map(Vector3::from_column_slice)
+            .collect(),
+    )
+}
+
+fn to_bytes(values: &[f64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .collect()
+}
+
+fn from_bytes(bytes: &[u8]) -> Vec<f64> {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| f64::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
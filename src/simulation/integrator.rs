@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use numeric_algs::symplectic::{
+    integration::{Integrator, StepSize},
+    State,
+};
+
+use super::{SimDerivative, SimState};
+
+/// Names accepted by `IntegratorKind::from_str`.
+pub const INTEGRATOR_NAMES: &[&str] = &["suzuki", "leapfrog", "yoshida4", "yoshida8"];
+
+/// The available symplectic integrators. All of them are compositions of leapfrog steps; the
+/// higher order ones need more force evaluations per step, but allow much longer steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegratorKind {
+    /// 4th order, 5 force evaluations per step.
+    Suzuki,
+    /// 2nd order, 1 force evaluation per step.
+    Leapfrog,
+    /// 4th order, 3 force evaluations per step.
+    Yoshida4,
+    /// 8th order, 15 force evaluations per step.
+    Yoshida8,
+}
+
+impl IntegratorKind {
+    /// Creates an integrator of this kind with the given default step.
+    pub fn integrator(self, step: f64) -> SymplecticIntegrator {
+        SymplecticIntegrator::new(self, step)
+    }
+
+    pub fn order(self) -> i32 {
+        match self {
+            IntegratorKind::Leapfrog => 2,
+            IntegratorKind::Suzuki | IntegratorKind::Yoshida4 => 4,
+            IntegratorKind::Yoshida8 => 8,
+        }
+    }
+
+    /// Lengths of the consecutive leapfrog substeps, as fractions of the whole step.
+    fn weights(self) -> Vec<f64> {
+        match self {
+            IntegratorKind::Leapfrog => vec![1.0],
+            IntegratorKind::Suzuki => {
+                let p = 1.0 / (4.0 - 4.0f64.cbrt());
+                vec![p, p, 1.0 - 4.0 * p, p, p]
+            }
+            IntegratorKind::Yoshida4 => {
+                let w1 = 1.0 / (2.0 - 2.0f64.cbrt());
+                vec![w1, 1.0 - 2.0 * w1, w1]
+            }
+            IntegratorKind::Yoshida8 => {
+                // solution D from H. Yoshida, Phys. Lett. A 150 (1990), 262
+                let w = [
+                    0.914_844_246_229_740,
+                    0.253_693_336_566_229,
+                    -1.444_852_236_860_48,
+                    -0.158_240_635_368_243,
+                    1.938_139_137_622_76,
+                    -1.960_610_232_975_49,
+                    0.102_799_849_391_985,
+                ];
+                let w0 = 1.0 - 2.0 * w.iter().sum::<f64>();
+                w.iter()
+                    .copied()
+                    .chain(Some(w0))
+                    .chain(w.iter().rev().copied())
+                    .collect()
+            }
+        }
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "suzuki" => Ok(IntegratorKind::Suzuki),
+            "leapfrog" => Ok(IntegratorKind::Leapfrog),
+            "yoshida4" => Ok(IntegratorKind::Yoshida4),
+            "yoshida8" => Ok(IntegratorKind::Yoshida8),
+            _ => Err(format!("unknown integrator: {}", s)),
+        }
+    }
+}
+
+/// A symplectic integrator of a kind chosen at runtime.
+#[derive(Clone)]
+pub struct SymplecticIntegrator {
+    default_step: f64,
+    /// Coefficients of the alternating position and momentum shifts, starting and ending with
+    /// a position shift.
+    position_coeffs: Vec<f64>,
+    momentum_coeffs: Vec<f64>,
+    /// Reused by `propagate_buffered` for the derivatives of every substep.
+    position_buffer: SimDerivative,
+    momentum_buffer: SimDerivative,
+}
+
+impl SymplecticIntegrator {
+    pub fn new(kind: IntegratorKind, step: f64) -> Self {
+        let weights = kind.weights();
+        // drift-kick-drift leapfrogs, with the adjacent drifts merged
+        let mut position_coeffs = vec![0.5 * weights[0]];
+        for pair in weights.windows(2) {
+            position_coeffs.push(0.5 * (pair[0] + pair[1]));
+        }
+        position_coeffs.push(0.5 * weights[weights.len() - 1]);
+        Self {
+            default_step: step,
+            position_coeffs,
+            momentum_coeffs: weights,
+            position_buffer: SimDerivative::default(),
+            momentum_buffer: SimDerivative::default(),
+        }
+    }
+
+    pub fn set_default_step(&mut self, step: f64) {
+        self.default_step = step;
+    }
+
+    /// Propagates `sim` by `step` seconds like `propagate_in_place`, but writing the derivatives
+    /// into buffers kept by the integrator instead of allocating new ones at every substep.
+    pub fn propagate_buffered(&mut self, sim: &mut SimState, step: f64) {
+        for (c, d) in self.position_coeffs.iter().zip(&self.momentum_coeffs) {
+            sim.position_derivative_into(&mut self.position_buffer);
+            sim.shift_position_in_place(&self.position_buffer, step * c);
+            sim.momentum_derivative_into(&mut self.momentum_buffer);
+            sim.shift_momentum_in_place(&self.momentum_buffer, step * d);
+        }
+        let last = self.position_coeffs[self.position_coeffs.len() - 1];
+        sim.position_derivative_into(&mut self.position_buffer);
+        sim.shift_position_in_place(&self.position_buffer, step * last);
+    }
+}
+
+impl<S: State> Integrator<S> for SymplecticIntegrator {
+    fn propagate_in_place<D1, D2>(
+        &mut self,
+        start: &mut S,
+        pos_diff_eq: D1,
+        momentum_diff_eq: D2,
+        step_size: StepSize,
+    ) where
+        D1: Fn(&S) -> S::PositionDerivative,
+        D2: Fn(&S) -> S::MomentumDerivative,
+    {
+        let h = match step_size {
+            StepSize::UseDefault => self.default_step,
+            StepSize::Step(x) => x,
+        };
+
+        for (c, d) in self.position_coeffs.iter().zip(&self.momentum_coeffs) {
+            start.shift_position_in_place(&pos_diff_eq(start), h * c);
+            start.shift_momentum_in_place(&momentum_diff_eq(start), h * d);
+        }
+        let last = self.position_coeffs[self.position_coeffs.len() - 1];
+        start.shift_position_in_place(&pos_diff_eq(start), h * last);
+    }
+}
@@ -1,33 +1,156 @@
 mod body;
+mod dense;
+#[cfg(feature = "double-double")]
+mod double;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod integrator;
+mod propagator;
+#[cfg(feature = "simd")]
+mod simd;
 
-pub use body::Body;
-use nalgebra::{DVector, Vector3};
-use num::Zero;
+pub use body::{Body, Oblateness};
+pub use dense::DenseStep;
+pub use integrator::{IntegratorKind, SymplecticIntegrator, INTEGRATOR_NAMES};
+use nalgebra::{Matrix3xX, MatrixSliceMut3xX, Vector3};
 use numeric_algs::symplectic::{State, StateDerivative};
+pub use propagator::{Propagator, StepControl};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use std::fmt;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 type Position = Vector3<f64>;
 type Velocity = Vector3<f64>;
+/// One per body.
+type LowParts = Vec<Vector3<f64>>;
 
-const DIM: usize = 3;
+/// In km/s.
+pub const SPEED_OF_LIGHT: f64 = 299_792.458;
+/// Below this many bodies, distributing the force computation among threads costs more than
+/// it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_BODIES: usize = 16;
 
-#[derive(Clone)]
+/// Position of a body at `pos` moving with `vel` relative to an observer at `observer_pos`
+/// moving with `observer_vel`, as the observer sees it: where the body was when the light left
+/// it, assuming uniform motion, in the direction shifted by the aberration.
+pub fn apparent_position(
+    pos: Position,
+    vel: Velocity,
+    observer_pos: Position,
+    observer_vel: Velocity,
+) -> Vector3<f64> {
+    let mut rel = pos - observer_pos;
+    for _ in 0..3 {
+        let delay = rel.norm() / SPEED_OF_LIGHT;
+        rel = pos - vel * delay - observer_pos;
+    }
+    let distance = rel.norm();
+    let dir = rel / distance;
+    let beta = observer_vel / SPEED_OF_LIGHT;
+    (dir + beta - dir * dir.dot(&beta)).normalize() * distance
+}
+
+/// Serialized as a `relativistic` field with the name of the relativistic source, if any, and
+/// an array of bodies named `body`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "SerializedState", into = "SerializedState")]
 pub struct SimState {
     bodies: Vec<Body>,
+    /// Indices of the bodies that aren't massless, the only ones exerting any force.
+    sources: Vec<usize>,
+    /// Index of the body whose field includes the post-Newtonian correction.
+    relativistic_source: Option<usize>,
+    /// With compensated summation, the low-order parts of the positions and the velocities,
+    /// which adding up the steps lost to rounding.
+    low: Option<(LowParts, LowParts)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    relativistic: Option<String>,
+    body: Vec<Body>,
+}
+
+impl From<SerializedState> for SimState {
+    fn from(state: SerializedState) -> Self {
+        let sim = state
+            .body
+            .into_iter()
+            .fold(SimState::new(), |sim, mut body| {
+                if let Some(oblateness) = &mut body.oblateness {
+                    oblateness.pole = oblateness.pole.normalize();
+                }
+                sim.with_body(body)
+            });
+        match state.relativistic {
+            Some(name) => sim.with_relativity(&name),
+            None => sim,
+        }
+    }
+}
+
+impl From<SimState> for SerializedState {
+    fn from(sim: SimState) -> Self {
+        SerializedState {
+            relativistic: sim
+                .relativistic_source
+                .map(|idx| sim.bodies[idx].name.clone()),
+            body: sim.bodies,
+        }
+    }
 }
 
 impl SimState {
     pub fn new() -> Self {
-        Self { bodies: Vec::new() }
+        Self {
+            bodies: Vec::new(),
+            sources: Vec::new(),
+            relativistic_source: None,
+            low: None,
+        }
     }
 
     pub fn with_body(mut self, body: Body) -> Self {
+        if !body.is_massless() {
+            self.sources.push(self.bodies.len());
+        }
         self.bodies.push(body);
         self
     }
 
+    /// Sets the J2 term of the named body's gravity field, if there is such a body.
+    pub fn with_oblateness(mut self, name: &str, oblateness: Oblateness) -> Self {
+        if let Some(body) = self.bodies.iter_mut().find(|body| body.name == name) {
+            body.oblateness = Some(oblateness);
+        }
+        self
+    }
+
+    /// Adds the 1PN (Schwarzschild) correction of the named body's field to the accelerations
+    /// of the other bodies. In practice only the Sun's correction is worth including.
+    pub fn with_relativity(mut self, name: &str) -> Self {
+        self.relativistic_source = self.body_index_by_name(name);
+        self
+    }
+
+    /// Makes the shifts of the positions and velocities add up with Kahan's compensated
+    /// summation, which keeps the round-off from building up over millions of steps, or in
+    /// double-double arithmetic with the `double-double` feature, which also computes the
+    /// forces from the low-order parts of the positions. The low-order parts aren't saved with
+    /// the state.
+    pub fn compensate(&mut self) {
+        let count = self.bodies.len();
+        if self.low.as_ref().map(|(pos, _)| pos.len()) != Some(count) {
+            let zeros = vec![Vector3::zeros(); count];
+            self.low = Some((zeros.clone(), zeros));
+        }
+    }
+
     pub fn body_index_by_name(&self, name: &str) -> Option<usize> {
         self.bodies
             .iter()
@@ -40,37 +163,201 @@ impl SimState {
         self.bodies.iter().find(|body| body.name == name)
     }
 
-    pub fn position_derivative(&self) -> SimDerivative {
-        let mut derivative = Vec::with_capacity(self.bodies.len() * DIM);
+    /// Position and velocity of the barycenter of the bodies.
+    pub fn barycenter(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let mut gm = 0.0;
+        let mut pos = Vector3::zeros();
+        let mut vel = Vector3::zeros();
         for body in &self.bodies {
-            for i in 0..DIM {
-                derivative.push(body.vel[i]);
-            }
+            gm += body.gm;
+            pos += body.pos * body.gm;
+            vel += body.vel * body.gm;
         }
-        SimDerivative(DVector::from_vec(derivative))
+        (pos / gm, vel / gm)
+    }
+
+    /// Moves all the bodies so that the barycenter is at rest at the origin. Returns the
+    /// previous position and velocity of the barycenter.
+    pub fn recenter(&mut self) -> (Vector3<f64>, Vector3<f64>) {
+        let (pos, vel) = self.barycenter();
+        for body in &mut self.bodies {
+            body.pos -= pos;
+            body.vel -= vel;
+        }
+        (pos, vel)
+    }
+
+    /// The angle between the directions to the named bodies as seen from `observer_pos`, in
+    /// radians, or `None` if one of them is missing.
+    pub fn angular_separation(
+        &self,
+        observer_pos: &Vector3<f64>,
+        body_a: &str,
+        body_b: &str,
+    ) -> Option<f64> {
+        let a = self.body_by_name(body_a)?;
+        let b = self.body_by_name(body_b)?;
+        Some((a.pos - observer_pos).angle(&(b.pos - observer_pos)))
+    }
+
+    pub fn position_derivative(&self) -> SimDerivative {
+        let mut derivative = SimDerivative::default();
+        self.position_derivative_into(&mut derivative);
+        derivative
     }
 
     pub fn momentum_derivative(&self) -> SimDerivative {
-        let mut derivative = Vec::with_capacity(self.bodies.len() * DIM);
-        for _ in 0..DIM * self.bodies.len() {
-            derivative.push(0.0);
+        let mut derivative = SimDerivative::default();
+        self.momentum_derivative_into(&mut derivative);
+        derivative
+    }
+
+    /// Writes the position derivative into `derivative`, reusing its memory if it's of the
+    /// right size.
+    pub fn position_derivative_into(&self, derivative: &mut SimDerivative) {
+        derivative.resize(self.bodies.len());
+        for (mut column, body) in derivative.0.column_iter_mut().zip(&self.bodies) {
+            column.copy_from(&body.vel);
         }
-        for (i, body) in self.bodies.iter().enumerate() {
-            let mut accel: Vector3<f64> = Zero::zero();
-            for (i2, body2) in self.bodies.iter().enumerate() {
-                if i2 == i {
-                    continue;
+        derivative.1 = self.low.as_ref().map(|(_, vel)| {
+            let mut low = derivative
+                .1
+                .take()
+                .unwrap_or_else(|| Matrix3xX::zeros(vel.len()));
+            for (mut column, vel) in low.column_iter_mut().zip(vel) {
+                column.copy_from(vel);
+            }
+            low
+        });
+    }
+
+    /// Writes the momentum derivative into `derivative`, reusing its memory if it's of the
+    /// right size.
+    pub fn momentum_derivative_into(&self, derivative: &mut SimDerivative) {
+        let count = self.bodies.len();
+        derivative.resize(count);
+        derivative.1 = None;
+        #[cfg(feature = "gpu")]
+        if self.momentum_derivative_on_gpu(derivative) {
+            return;
+        }
+        #[cfg(feature = "parallel")]
+        if count >= PARALLEL_MIN_BODIES && rayon::current_num_threads() > 1 {
+            let chunk = count.div_ceil(rayon::current_num_threads());
+            derivative
+                .0
+                .as_mut_slice()
+                .par_chunks_mut(3 * chunk)
+                .enumerate()
+                .for_each(|(idx, columns)| {
+                    let accel = MatrixSliceMut3xX::from_slice(columns, columns.len() / 3);
+                    self.accels(idx * chunk, accel);
+                });
+            return;
+        }
+        self.accels(
+            0,
+            MatrixSliceMut3xX::from_slice(derivative.0.as_mut_slice(), count),
+        );
+    }
+
+    /// Writes the gravitational accelerations of the bodies from the one with index `start` on
+    /// into the columns of `accel`. The Newtonian pull of each source is added to all of them
+    /// at once, four at a time with the `simd` feature; bodies with zero GM don't attract
+    /// anything, so with m massive bodies and n test particles this is O((n + m) * m) instead
+    /// of O((n + m)^2).
+    fn accels(&self, start: usize, mut accel: MatrixSliceMut3xX<f64>) {
+        let targets = start..start + accel.ncols();
+        accel.fill(0.0);
+        #[cfg(all(feature = "simd", not(feature = "double-double")))]
+        simd::add_newtonian_accels(&self.bodies, &self.sources, start, &mut accel);
+        #[cfg(any(not(feature = "simd"), feature = "double-double"))]
+        for &source in &self.sources {
+            let gm = self.bodies[source].gm;
+            let source_position = self.bodies[source].pos;
+            for ((mut accel, target), i) in accel
+                .column_iter_mut()
+                .zip(&self.bodies[targets.clone()])
+                .zip(targets.clone())
+            {
+                if i != source {
+                    let diff = source_position - target.pos;
+                    #[cfg(feature = "double-double")]
+                    let diff = match self.low {
+                        Some((ref low, _)) => diff + (low[source] - low[i]),
+                        None => diff,
+                    };
+                    let dist_sq = diff.norm_squared();
+                    accel.axpy(gm / (dist_sq * dist_sq.sqrt()), &diff, 1.0);
                 }
-                let diff = body2.pos - body.pos;
-                let dist = body.distance_from(body2);
-                let part_accel = body2.gm / (dist * dist);
-                accel += part_accel * diff / dist;
             }
-            for j in 0..DIM {
-                derivative[i * DIM + j] = accel[j];
+        }
+
+        self.add_perturbations(start, accel);
+    }
+
+    /// Adds the non-Newtonian parts of the accelerations of the bodies from the one with index
+    /// `start` on to the columns of `accel`.
+    fn add_perturbations(&self, start: usize, mut accel: MatrixSliceMut3xX<f64>) {
+        for (mut accel, i) in accel.column_iter_mut().zip(start..) {
+            for &source in &self.sources {
+                if source != i && self.is_perturbed(i, source) {
+                    accel += self.perturbation(i, source);
+                }
+            }
+        }
+    }
+
+    /// With enough test particles, writes the momentum derivative into `derivative` with the
+    /// Newtonian accelerations of the particles computed on the GPU, and those of the massive
+    /// bodies and all the perturbations on the CPU. Returns false, leaving it to the CPU, if
+    /// there's no GPU computing in f64 or the positions are compensated.
+    #[cfg(feature = "gpu")]
+    fn momentum_derivative_on_gpu(&self, derivative: &mut SimDerivative) -> bool {
+        let particles = self.bodies.len() - self.sources.len();
+        if particles < gpu::MIN_PARTICLES || self.sources.is_empty() || self.low.is_some() {
+            return false;
+        }
+        let Some(accels) = gpu::particle_accels(&self.bodies, &self.sources) else {
+            return false;
+        };
+        let mut accels = accels.into_iter();
+        for (i, column) in derivative.0.as_mut_slice().chunks_mut(3).enumerate() {
+            let mut accel = MatrixSliceMut3xX::from_slice(column, 1);
+            if self.bodies[i].is_massless() {
+                accel.set_column(0, &accels.next().unwrap());
+                self.add_perturbations(i, accel);
+            } else {
+                self.accels(i, accel);
             }
         }
-        SimDerivative(DVector::from_vec(derivative))
+        true
+    }
+
+    fn is_perturbed(&self, i: usize, source: usize) -> bool {
+        self.bodies[i].oblateness.is_some()
+            || self.bodies[source].oblateness.is_some()
+            || self.relativistic_source == Some(source)
+    }
+
+    /// The non-Newtonian part of the acceleration of the body with index `i` caused by the
+    /// body with index `source`: the J2 terms of their fields and the 1PN correction.
+    fn perturbation(&self, i: usize, source: usize) -> Vector3<f64> {
+        let body = &self.bodies[i];
+        let body2 = &self.bodies[source];
+        let mut accel = Vector3::zeros();
+        let diff = body2.pos - body.pos;
+        if let Some(oblateness) = body2.oblateness {
+            accel += oblateness.accel(body2.gm, -diff);
+        }
+        // reaction to the pull of our own J2 on the other body, which a massless body doesn't pull
+        if let Some(oblateness) = body.oblateness.filter(|_| !body.is_massless()) {
+            accel -= body2.gm / body.gm * oblateness.accel(body.gm, diff);
+        }
+        if self.relativistic_source == Some(source) {
+            accel += schwarzschild_accel(body2.gm, body.pos - body2.pos, body.vel - body2.vel);
+        }
+        accel
     }
 
     pub fn bodies(&self) -> impl Iterator<Item = &Body> {
@@ -80,6 +367,19 @@ impl SimState {
     pub fn get_body(&self, idx: usize) -> &Body {
         &self.bodies[idx]
     }
+
+    /// The body can't be made massless, the sources of the forces being found when adding it.
+    pub fn get_body_mut(&mut self, idx: usize) -> &mut Body {
+        &mut self.bodies[idx]
+    }
+}
+
+/// The 1PN correction to the acceleration of a test body at `pos` moving with `vel` relative
+/// to a body with the given GM.
+fn schwarzschild_accel(gm: f64, pos: Vector3<f64>, vel: Vector3<f64>) -> Vector3<f64> {
+    let r = pos.norm();
+    let c2 = SPEED_OF_LIGHT * SPEED_OF_LIGHT;
+    gm / (c2 * r * r * r) * ((4.0 * gm / r - vel.dot(&vel)) * pos + 4.0 * pos.dot(&vel) * vel)
 }
 
 impl State for SimState {
@@ -87,22 +387,61 @@ impl State for SimState {
     type MomentumDerivative = SimDerivative;
 
     fn shift_position_in_place(&mut self, dir: &SimDerivative, amount: f64) {
-        for (i, body) in self.bodies.iter_mut().enumerate() {
-            for j in 0..DIM {
-                body.pos[j] += dir.0[i * DIM + j] * amount;
+        if let Some((ref mut low, _)) = self.low {
+            let zeros = Matrix3xX::zeros(dir.0.ncols());
+            let dir_low = dir.1.as_ref().unwrap_or(&zeros);
+            for (((body, low), dir), dir_low) in self
+                .bodies
+                .iter_mut()
+                .zip(low)
+                .zip(dir.0.column_iter())
+                .zip(dir_low.column_iter())
+            {
+                compensated_add((&mut body.pos, low), (dir.into(), dir_low.into()), amount);
             }
+            return;
+        }
+        for (body, dir) in self.bodies.iter_mut().zip(dir.0.column_iter()) {
+            body.pos += dir * amount;
         }
     }
 
     fn shift_momentum_in_place(&mut self, dir: &SimDerivative, amount: f64) {
-        for (i, body) in self.bodies.iter_mut().enumerate() {
-            for j in 0..DIM {
-                body.vel[j] += dir.0[i * DIM + j] * amount;
+        if let Some((_, ref mut low)) = self.low {
+            for ((body, low), dir) in self.bodies.iter_mut().zip(low).zip(dir.0.column_iter()) {
+                compensated_add((&mut body.vel, low), (dir.into(), Vector3::zeros()), amount);
             }
+            return;
+        }
+        for (body, dir) in self.bodies.iter_mut().zip(dir.0.column_iter()) {
+            body.vel += dir * amount;
         }
     }
 }
 
+/// Adds `dir * amount` to `sum`, each given by its high and low-order parts: with Kahan's
+/// summation, or exactly but for the final rounding in double-double arithmetic with the
+/// `double-double` feature.
+fn compensated_add(
+    (sum, low): (&mut Vector3<f64>, &mut Vector3<f64>),
+    (dir, dir_low): (Vector3<f64>, Vector3<f64>),
+    amount: f64,
+) {
+    #[cfg(not(feature = "double-double"))]
+    {
+        let term = dir * amount + (dir_low * amount + *low);
+        let new_sum = *sum + term;
+        *low = term - (new_sum - *sum);
+        *sum = new_sum;
+    }
+    #[cfg(feature = "double-double")]
+    for axis in 0..3 {
+        let (term, error) = double::two_product(dir[axis], amount);
+        (sum[axis], low[axis]) =
+            double::add(sum[axis], low[axis], term, error + dir_low[axis] * amount);
+    }
+}
+
 impl fmt::Debug for SimState {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         for (i, body) in self.bodies.iter().enumerate() {
@@ -112,14 +451,42 @@ impl fmt::Debug for SimState {
     }
 }
 
+/// Derivatives of the positions or the velocities of the bodies, one column per body, and
+/// their low-order parts if there are any, see `SimState::compensate`.
 #[derive(Clone)]
-pub struct SimDerivative(DVector<f64>);
+pub struct SimDerivative(Matrix3xX<f64>, Option<Matrix3xX<f64>>);
+
+impl SimDerivative {
+    fn resize(&mut self, count: usize) {
+        if self.0.ncols() != count {
+            self.0 = Matrix3xX::zeros(count);
+            self.1 = None;
+        }
+    }
+
+    fn map<F: Fn(Matrix3xX<f64>) -> Matrix3xX<f64>>(self, f: F) -> SimDerivative {
+        SimDerivative(f(self.0), self.1.map(f))
+    }
+}
+
+impl Default for SimDerivative {
+    fn default() -> Self {
+        SimDerivative(Matrix3xX::zeros(0), None)
+    }
+}
+
+fn add_low(low: Option<Matrix3xX<f64>>, other: Option<Matrix3xX<f64>>) -> Option<Matrix3xX<f64>> {
+    match (low, other) {
+        (Some(low), Some(other)) => Some(low + other),
+        (low, other) => low.or(other),
+    }
+}
 
 impl Add<SimDerivative> for SimDerivative {
     type Output = SimDerivative;
 
     fn add(self, other: SimDerivative) -> SimDerivative {
-        SimDerivative(self.0 + other.0)
+        SimDerivative(self.0 + other.0, add_low(self.1, other.1))
     }
 }
 
@@ -127,7 +494,7 @@ impl Sub<SimDerivative> for SimDerivative {
     type Output = SimDerivative;
 
     fn sub(self, other: SimDerivative) -> SimDerivative {
-        SimDerivative(self.0 - other.0)
+        self + -other
     }
 }
 
@@ -135,7 +502,7 @@ impl Mul<f64> for SimDerivative {
     type Output = SimDerivative;
 
     fn mul(self, other: f64) -> SimDerivative {
-        SimDerivative(self.0 * other)
+        self.map(|matrix| matrix * other)
     }
 }
 
@@ -143,7 +510,7 @@ impl Div<f64> for SimDerivative {
     type Output = SimDerivative;
 
     fn div(self, other: f64) -> SimDerivative {
-        SimDerivative(self.0 / other)
+        self.map(|matrix| matrix / other)
     }
 }
 
@@ -151,7 +518,7 @@ impl Neg for SimDerivative {
     type Output = SimDerivative;
 
     fn neg(self) -> SimDerivative {
-        SimDerivative(-self.0)
+        self.map(|matrix| -matrix)
     }
 }
 
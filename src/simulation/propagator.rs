@@ -0,0 +1,324 @@
+use std::sync::Arc;
+
+use tracing::{debug, trace};
+
+use super::{DenseStep, IntegratorKind, SimState, SymplecticIntegrator};
+use crate::ephemeris::Ephemeris;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepControl {
+    /// Constant step, in seconds.
+    Fixed(f64),
+    /// Step chosen by step doubling, so that the estimated position error of any body stays
+    /// below `tolerance` km per step.
+    Adaptive {
+        tolerance: f64,
+        min_step: f64,
+        max_step: f64,
+    },
+}
+
+impl StepControl {
+    /// The longest step that can be taken.
+    pub fn max_step(&self) -> f64 {
+        match *self {
+            StepControl::Fixed(step) => step,
+            StepControl::Adaptive { max_step, .. } => max_step,
+        }
+    }
+}
+
+impl From<f64> for StepControl {
+    fn from(step: f64) -> Self {
+        StepControl::Fixed(step)
+    }
+}
+
+impl From<f64> for Propagator {
+    fn from(step: f64) -> Self {
+        Propagator::new(step)
+    }
+}
+
+impl From<StepControl> for Propagator {
+    fn from(control: StepControl) -> Self {
+        Propagator::new(control)
+    }
+}
+
+/// Advances the simulation with a symplectic integrator (Suzuki by default), using either fixed
+/// or adaptive steps.
+#[derive(Clone)]
+pub struct Propagator {
+    kind: IntegratorKind,
+    integrator: SymplecticIntegrator,
+    control: StepControl,
+    next_step: f64,
+    on_step: Option<Arc<dyn Fn(f64) + Send + Sync>>,
+    /// How often to move the barycenter back to the origin, and the time since it was.
+    recenter_interval: Option<f64>,
+    since_recenter: f64,
+    /// 1 if `step` goes forward in time, -1 if it goes backward.
+    direction: f64,
+    /// The ephemeris to reset the bodies to and how often, and the time since they were.
+    anchor: Option<(Arc<dyn Ephemeris + Send + Sync>, f64)>,
+    since_anchor: f64,
+    /// Simulation time of the propagated state, as far as the propagator was told.
+    time: f64,
+    compensated: bool,
+}
+
+impl Propagator {
+    pub fn new<C: Into<StepControl>>(control: C) -> Self {
+        let control = control.into();
+        let next_step = control.max_step();
+        Self {
+            kind: IntegratorKind::Suzuki,
+            integrator: IntegratorKind::Suzuki.integrator(next_step),
+            control,
+            next_step,
+            on_step: None,
+            recenter_interval: None,
+            since_recenter: 0.0,
+            direction: 1.0,
+            anchor: None,
+            since_anchor: 0.0,
+            time: 0.0,
+            compensated: cfg!(feature = "double-double"),
+        }
+    }
+
+    pub fn with_integrator(mut self, kind: IntegratorKind) -> Self {
+        self.kind = kind;
+        self.integrator = kind.integrator(self.next_step);
+        self
+    }
+
+    /// Calls `callback` with the length of every step made by `step` and `advance`, e.g. to
+    /// report the progress of a long propagation. Clones of the propagator share the callback.
+    pub fn on_step<F: Fn(f64) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.on_step = Some(Arc::new(callback));
+        self
+    }
+
+    /// Moves the barycenter of the system back to the origin after every `interval` seconds
+    /// propagated by `step` and `advance`, so that the rounding errors don't make the whole
+    /// system drift away over long runs.
+    pub fn recenter_every(mut self, interval: f64) -> Self {
+        self.recenter_interval = Some(interval);
+        self
+    }
+
+    /// Resets the bodies `reference` knows to their states in it after every `interval`
+    /// seconds propagated by `step` and `advance`, so that the errors of the model don't build
+    /// up over long runs. The propagator has to be told the time of the states with `set_time`.
+    pub fn anchored(mut self, reference: Arc<dyn Ephemeris + Send + Sync>, interval: f64) -> Self {
+        self.anchor = Some((reference, interval));
+        self
+    }
+
+    /// Adds up the steps with compensated summation, see `SimState::compensate`. With the
+    /// `double-double` feature, all propagators do.
+    pub fn compensated(mut self) -> Self {
+        self.compensated = true;
+        self
+    }
+
+    /// Sets the simulation time of the state propagated next, which the propagator keeps
+    /// track of afterwards.
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    /// Makes `step` go backward in time, returning negative lengths, e.g. to search for events
+    /// in the past.
+    pub fn backward(mut self) -> Self {
+        self.direction = -1.0;
+        self
+    }
+
+    /// 1 if `step` goes forward in time, -1 if it goes backward.
+    pub fn direction(&self) -> f64 {
+        self.direction
+    }
+
+    pub fn max_step(&self) -> f64 {
+        self.control.max_step()
+    }
+
+    /// The state `offset` seconds (possibly negative) away from `sim`, reached in a single step.
+    pub fn propagated(&self, sim: &SimState, offset: f64) -> SimState {
+        let mut sim = sim.clone();
+        if self.compensated {
+            sim.compensate();
+        }
+        self.kind
+            .integrator(offset)
+            .propagate_buffered(&mut sim, offset);
+        sim
+    }
+
+    /// Rough estimate of the position error, in km, of propagating `sim` by `duration`
+    /// seconds: the Richardson estimate of the error of the longest step times the number of
+    /// steps.
+    pub fn estimated_error(&self, sim: &SimState, duration: f64) -> f64 {
+        let step = self.max_step().copysign(duration);
+        let full = self.propagated(sim, step);
+        let halves = self.propagated(&self.propagated(sim, 0.5 * step), 0.5 * step);
+        let error =
+            max_position_difference(&full, &halves) / (2.0f64.powi(self.kind.order()) - 1.0);
+        error * (duration / step).abs()
+    }
+
+    /// Propagates `sim` by exactly `duration` seconds, backwards if it's negative.
+    pub fn advance(&mut self, sim: &mut SimState, duration: f64) {
+        if duration * self.direction < 0.0 {
+            self.retreat(sim, duration);
+            return;
+        }
+        let mut remaining = duration;
+        while remaining.abs() > self.next_step {
+            remaining -= self.step(sim);
+        }
+        *sim = self.propagated(sim, remaining);
+        self.time += remaining;
+        if let Some(ref on_step) = self.on_step {
+            on_step(remaining.abs());
+        }
+    }
+
+    /// Propagates `sim` by `duration` seconds against the direction of `step`, with steps of
+    /// the longest length. The integrators are symmetric, so going back is as accurate as
+    /// going forward.
+    fn retreat(&mut self, sim: &mut SimState, duration: f64) {
+        let step = self.max_step().copysign(duration);
+        let mut remaining = duration;
+        while remaining.abs() > step.abs() {
+            self.propagate(sim, step);
+            remaining -= step;
+            self.time += step;
+            if let Some(ref on_step) = self.on_step {
+                on_step(step.abs());
+            }
+        }
+        *sim = self.propagated(sim, remaining);
+        self.time += remaining;
+        if let Some(ref on_step) = self.on_step {
+            on_step(remaining.abs());
+        }
+    }
+
+    /// Makes a single step, forward unless the propagator is `backward`, and returns its
+    /// length in seconds, negative when going backward.
+    pub fn step(&mut self, sim: &mut SimState) -> f64 {
+        let step = self.direction * self.controlled_step(sim);
+        trace!(step, "integration step");
+        self.time += step;
+        if let Some(ref on_step) = self.on_step {
+            on_step(step.abs());
+        }
+        if let Some(interval) = self.recenter_interval {
+            self.since_recenter += step.abs();
+            if self.since_recenter >= interval {
+                self.since_recenter = 0.0;
+                let (pos, vel) = sim.recenter();
+                debug!(
+                    drift = pos.norm(),
+                    velocity = vel.norm(),
+                    "recentered on the barycenter"
+                );
+            }
+        }
+        if let Some((ref reference, interval)) = self.anchor {
+            self.since_anchor += step.abs();
+            if self.since_anchor >= interval {
+                self.since_anchor = 0.0;
+                anchor(sim, reference.as_ref(), self.time);
+            }
+        }
+        step
+    }
+
+    /// Makes a single step like `step` from the simulation time `time`, returning the step
+    /// with its ends to interpolate the states within it.
+    pub fn dense_step(&mut self, sim: &mut SimState, time: f64) -> DenseStep {
+        let start = sim.clone();
+        let step = self.step(sim);
+        DenseStep::new(time, start, time + step, sim.clone())
+    }
+
+    fn controlled_step(&mut self, sim: &mut SimState) -> f64 {
+        let (tolerance, min_step, max_step) = match self.control {
+            StepControl::Fixed(step) => {
+                self.propagate_along(sim, step);
+                return step;
+            }
+            StepControl::Adaptive {
+                tolerance,
+                min_step,
+                max_step,
+            } => (tolerance, min_step, max_step),
+        };
+
+        loop {
+            let step = self.next_step;
+            let mut full = sim.clone();
+            self.propagate_along(&mut full, step);
+            let mut halves = sim.clone();
+            self.propagate_along(&mut halves, 0.5 * step);
+            self.propagate_along(&mut halves, 0.5 * step);
+
+            // Richardson estimate of the error of the two half steps
+            let order = self.kind.order();
+            let error = max_position_difference(&full, &halves) / (2.0f64.powi(order) - 1.0);
+            let factor = if error > 0.0 {
+                (0.9 * (tolerance / error).powf(1.0 / f64::from(order + 1))).clamp(0.2, 2.0)
+            } else {
+                2.0
+            };
+            self.next_step = (step * factor).max(min_step).min(max_step);
+
+            if error <= tolerance || step <= min_step {
+                *sim = halves;
+                return step;
+            }
+            trace!(step, error, "rejected adaptive step");
+        }
+    }
+
+    /// Makes a step of `step` seconds in the direction of the propagator.
+    fn propagate_along(&mut self, sim: &mut SimState, step: f64) {
+        self.propagate(sim, self.direction * step);
+    }
+
+    fn propagate(&mut self, sim: &mut SimState, step: f64) {
+        if self.compensated {
+            sim.compensate();
+        }
+        self.integrator.propagate_buffered(sim, step);
+    }
+}
+
+/// Resets the bodies of `sim` to their states in `reference` at the simulation time `time`,
+/// leaving the ones it doesn't have or doesn't cover then.
+fn anchor(sim: &mut SimState, reference: &dyn Ephemeris, time: f64) {
+    let mut correction = 0.0f64;
+    let mut anchored = 0;
+    for idx in 0..sim.bodies().count() {
+        let body = sim.get_body_mut(idx);
+        if let Ok((pos, vel)) = reference.state(&body.name, time) {
+            correction = correction.max((body.pos - pos).norm());
+            body.pos = pos;
+            body.vel = vel;
+            anchored += 1;
+        }
+    }
+    debug!(anchored, correction, "anchored to the reference ephemeris");
+}
+
+fn max_position_difference(sim1: &SimState, sim2: &SimState) -> f64 {
+    sim1.bodies()
+        .zip(sim2.bodies())
+        .map(|(body1, body2)| body1.distance_from(body2))
+        .fold(0.0, f64::max)
+}
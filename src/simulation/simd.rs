@@ -0,0 +1,60 @@
+use std::array;
+use std::simd::{prelude::*, StdFloat};
+
+use nalgebra::MatrixSliceMut3xX;
+
+use super::Body;
+
+const LANES: usize = 4;
+
+/// Adds the Newtonian pull of the `sources` to the accelerations of the bodies from the one
+/// with index `start` on, the columns of `accel`, computing it for four of them at once.
+pub(super) fn add_newtonian_accels(
+    bodies: &[Body],
+    sources: &[usize],
+    start: usize,
+    accel: &mut MatrixSliceMut3xX<f64>,
+) {
+    let count = accel.ncols();
+    for first in (0..count).step_by(LANES) {
+        let lanes = LANES.min(count - first);
+        // the lanes past the last body are left at the origin and thrown away
+        let coordinate = |axis: usize| {
+            f64x4::from_array(array::from_fn(|lane| {
+                if lane < lanes {
+                    bodies[start + first + lane].pos[axis]
+                } else {
+                    0.0
+                }
+            }))
+        };
+        let (x, y, z) = (coordinate(0), coordinate(1), coordinate(2));
+        let indices = u64x4::from_array(array::from_fn(|lane| (start + first + lane) as u64));
+
+        let zero = f64x4::splat(0.0);
+        let (mut accel_x, mut accel_y, mut accel_z) = (zero, zero, zero);
+        for &source in sources {
+            let body = &bodies[source];
+            let diff_x = f64x4::splat(body.pos.x) - x;
+            let diff_y = f64x4::splat(body.pos.y) - y;
+            let diff_z = f64x4::splat(body.pos.z) - z;
+            let dist_sq = diff_x * diff_x + diff_y * diff_y + diff_z * diff_z;
+            let factor = f64x4::splat(body.gm) / (dist_sq * dist_sq.sqrt());
+            // a body doesn't pull itself
+            let factor = indices
+                .simd_eq(u64x4::splat(source as u64))
+                .select(zero, factor);
+            accel_x += factor * diff_x;
+            accel_y += factor * diff_y;
+            accel_z += factor * diff_z;
+        }
+
+        let (accel_x, accel_y, accel_z) =
+            (accel_x.to_array(), accel_y.to_array(), accel_z.to_array());
+        for lane in 0..lanes {
+            accel[(0, first + lane)] += accel_x[lane];
+            accel[(1, first + lane)] += accel_y[lane];
+            accel[(2, first + lane)] += accel_z[lane];
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    bodies,
+    error::{Error, Result},
+    simulation::{Propagator, SimState},
+    time::{legacy_ut_to_sim_time, sim_time_to_utc, utc_to_sim_time},
+};
+
+/// Directory used when no other is given.
+pub const DEFAULT_DIR: &str = "snapshots";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Version of the snapshot format written by `Snapshots::save`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The header preceding the bodies in a snapshot file. The checksum is the CRC-32 of the
+/// time (as little-endian bytes) and everything after the header.
+///
+/// Version 1 had no time, which was taken from the file name instead, and its checksum only
+/// covered the bodies.
+#[derive(Deserialize, Serialize)]
+struct Header {
+    version: u32,
+    /// Simulation time of the state.
+    time: Option<f64>,
+    checksum: String,
+}
+
+/// A directory of saved simulation states, one TOML file per state. They are saved with names
+/// after their UTC dates, e.g. `2021-03-01.toml`, but can be renamed freely, as the time is
+/// stored inside. They let long runs start from a state close to the period of interest
+/// instead of propagating from the initial one.
+///
+/// Only the times of the snapshots are read when opening the directory; the states are
+/// loaded when they are first needed.
+///
+/// The states are only valid for the setup they were computed with (the initial state, the
+/// integrator and the force model), so different setups should use separate directories.
+///
+/// Where there is no file system, like in a browser, the snapshots can be kept in memory
+/// instead, with the contents of the files inserted by `insert`.
+pub struct Snapshots {
+    /// `None` if the snapshots are only kept in memory.
+    dir: Option<PathBuf>,
+    /// Sorted by time.
+    snapshots: Vec<Snapshot>,
+    /// Files which couldn't be loaded, with the reasons.
+    skipped: Vec<(PathBuf, Error)>,
+    /// Snapshots closer than this to an existing one aren't saved.
+    min_interval: f64,
+}
+
+struct Snapshot {
+    time: f64,
+    /// `None` if the snapshot is only kept in memory.
+    path: Option<PathBuf>,
+    /// The state, if it has been loaded already. Always set without a path.
+    sim: Option<SimState>,
+}
+
+impl Snapshots {
+    /// Opens the snapshots in `DEFAULT_DIR`.
+    pub fn new() -> Result<Self> {
+        Self::open(DEFAULT_DIR)
+    }
+
+    /// Lists the snapshots in `dir`, creating the directory if it doesn't exist. Files in an
+    /// unknown format are skipped and returned by `take_skipped`, as are the corrupted ones
+    /// once they are found to be.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let mut snapshots = vec![];
+        let mut skipped = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("toml")) {
+                continue;
+            }
+            let time = read_time(&path).and_then(|time| {
+                time.or_else(|| time_from_file_name(&path))
+                    .ok_or_else(|| Error::Snapshot("no time in the file or its name".into()))
+            });
+            match time {
+                Ok(time) => snapshots.push(Snapshot {
+                    time,
+                    path: Some(path),
+                    sim: None,
+                }),
+                Err(err) => skipped.push((path, err)),
+            }
+        }
+        // a corrupted header can give any time, even NaN
+        snapshots.sort_by(|s1, s2| s1.time.total_cmp(&s2.time));
+        if snapshots.is_empty() {
+            info!(
+                dir = %dir.display(),
+                "no snapshots yet, starting from the initial state"
+            );
+        }
+
+        Ok(Self {
+            dir: Some(dir),
+            snapshots,
+            skipped,
+            min_interval: 0.0,
+        })
+    }
+
+    /// Snapshots kept only in memory, without any files. `save` keeps the states in memory
+    /// too.
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            snapshots: vec![],
+            skipped: vec![],
+            min_interval: 0.0,
+        }
+    }
+
+    /// Adds a snapshot given by the contents of a snapshot file, which has to contain its
+    /// time, replacing any other one at the same time. Returns the time.
+    pub fn insert(&mut self, contents: &[u8]) -> Result<f64> {
+        let contents = std::str::from_utf8(contents)
+            .map_err(|_| Error::Snapshot("the file isn't valid UTF-8".into()))?;
+        let (time, sim) = parse(contents)?;
+        let time = time.ok_or_else(|| Error::Snapshot("no time in the header".into()))?;
+        self.snapshots.retain(|snapshot| snapshot.time != time);
+        self.add(time, None, sim);
+        Ok(time)
+    }
+
+    /// Makes `save` skip the states closer than `interval` (in seconds) to an existing
+    /// snapshot.
+    pub fn with_min_interval(mut self, interval: f64) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// The files skipped since the last call, with the reasons.
+    pub fn take_skipped(&mut self) -> Vec<(PathBuf, Error)> {
+        std::mem::take(&mut self.skipped)
+    }
+
+    /// The latest snapshot not after `time`, loading it if needed. Snapshots which fail to
+    /// load are skipped.
+    pub fn get_closest(&mut self, time: f64) -> Option<(f64, &SimState)> {
+        let mut index = self
+            .snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.time <= time)
+            .count();
+        while index > 0 {
+            index -= 1;
+            if self.load(index) {
+                let snapshot = &self.snapshots[index];
+                return Some((snapshot.time, snapshot.sim.as_ref()?));
+            }
+        }
+        None
+    }
+
+    /// The earliest snapshot, loading it if needed. Snapshots which fail to load are skipped.
+    pub fn get_earliest(&mut self) -> Option<(f64, &SimState)> {
+        while !self.snapshots.is_empty() {
+            if self.load(0) {
+                let snapshot = &self.snapshots[0];
+                return Some((snapshot.time, snapshot.sim.as_ref()?));
+            }
+        }
+        None
+    }
+
+    /// Loads the snapshot at `index` if it isn't loaded yet. If it fails to load, it's removed
+    /// and `false` is returned.
+    fn load(&mut self, index: usize) -> bool {
+        let snapshot = &mut self.snapshots[index];
+        let path = match (&snapshot.sim, &snapshot.path) {
+            (Some(_), _) | (None, None) => return snapshot.sim.is_some(),
+            (None, Some(path)) => path,
+        };
+        let loaded = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|contents| parse(&contents));
+        match loaded {
+            Ok((_, sim)) => {
+                debug!(path = %path.display(), "loaded snapshot");
+                snapshot.sim = Some(sim);
+                true
+            }
+            Err(err) => {
+                let path = path.clone();
+                self.snapshots.remove(index);
+                self.skipped.push((path, err));
+                false
+            }
+        }
+    }
+
+    /// Saves the state at `time`, replacing any other snapshot saved on the same UTC date,
+    /// unless there is another one within the minimum interval.
+    pub fn save(&mut self, time: f64, sim: &SimState) -> Result<()> {
+        let date = sim_time_to_utc(time).format(DATE_FORMAT).to_string();
+        let path = self
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.toml", date)));
+        let same_date = |snapshot: &Snapshot| match (&path, &snapshot.path) {
+            (Some(path), Some(other)) => path == other,
+            (None, None) => {
+                sim_time_to_utc(snapshot.time)
+                    .format(DATE_FORMAT)
+                    .to_string()
+                    == date
+            }
+            _ => false,
+        };
+        let too_close = self.snapshots.iter().any(|snapshot| {
+            !same_date(snapshot) && (snapshot.time - time).abs() < self.min_interval
+        });
+        if too_close {
+            debug!(date, "skipped saving a snapshot close to another one");
+            return Ok(());
+        }
+        if let Some(ref path) = path {
+            fs::write(path, serialize(time, sim)).map_err(|err| Error::from(err).in_file(path))?;
+            info!(path = %path.display(), "saved snapshot");
+        }
+
+        self.snapshots.retain(|snapshot| !same_date(snapshot));
+        self.add(time, path, sim.clone());
+        Ok(())
+    }
+
+    /// Adds a loaded snapshot, keeping them sorted.
+    fn add(&mut self, time: f64, path: Option<PathBuf>, sim: SimState) {
+        let index = self
+            .snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.time < time)
+            .count();
+        self.snapshots.insert(
+            index,
+            Snapshot {
+                time,
+                path,
+                sim: Some(sim),
+            },
+        );
+    }
+
+    /// Deletes the snapshots before `before` (simulation time), except for the earliest one
+    /// in every period of `keep_every` seconds counted from the epoch. Returns the paths of
+    /// the deleted files; the snapshots kept only in memory are just dropped.
+    pub fn prune(&mut self, keep_every: f64, before: f64) -> Result<Vec<PathBuf>> {
+        let mut deleted = vec![];
+        let mut last_period = None;
+        let mut result = Ok(());
+        self.snapshots.retain(|snapshot| {
+            if snapshot.time >= before || result.is_err() {
+                return true;
+            }
+            let period = (snapshot.time / keep_every).floor();
+            if last_period == Some(period) {
+                let path = match snapshot.path {
+                    Some(ref path) => path,
+                    None => return false,
+                };
+                match fs::remove_file(path) {
+                    Ok(()) => {
+                        info!(path = %path.display(), "deleted snapshot");
+                        deleted.push(path.clone());
+                        return false;
+                    }
+                    Err(err) => result = Err(Error::from(err).in_file(path)),
+                }
+            }
+            last_period = Some(period);
+            true
+        });
+        result.map(|()| deleted)
+    }
+
+    /// Propagates `sim` from `time` to `target`, starting from the latest snapshot in between
+    /// if there is one, and saving a snapshot at the start of every month passed on the way.
+    /// If `target` is before `time`, the state is propagated backwards from the earliest
+    /// snapshot after `target` or `time`, whichever is earlier, without saving anything.
+    pub fn propagate(
+        &mut self,
+        sim: &mut SimState,
+        mut time: f64,
+        target: f64,
+        propagator: &mut Propagator,
+    ) -> Result<()> {
+        if target < time {
+            if let Some((snapshot_time, snapshot)) = self.get_earliest() {
+                if snapshot_time < time {
+                    *sim = snapshot.clone();
+                    time = snapshot_time;
+                }
+            }
+        }
+        if let Some((snapshot_time, snapshot)) = self.get_closest(target) {
+            if snapshot_time > time {
+                info!(date = %sim_time_to_utc(snapshot_time), "starting from a snapshot");
+                *sim = snapshot.clone();
+                time = snapshot_time;
+            }
+        }
+        propagator.set_time(time);
+        if target < time {
+            warn_backwards(sim, time, target, propagator);
+            propagator.advance(sim, target - time);
+            return Ok(());
+        }
+        loop {
+            let next = next_month(time);
+            if next > target {
+                break;
+            }
+            propagator.advance(sim, next - time);
+            time = next;
+            self.save(time, sim)?;
+        }
+        propagator.advance(sim, target - time);
+        Ok(())
+    }
+}
+
+/// Propagates `sim` from `time` back to `target`, saving a snapshot at the start of every
+/// month on the way, which `Snapshots::propagate` doesn't do going backwards.
+pub fn propagate_back(
+    snapshots: &mut Snapshots,
+    sim: &mut SimState,
+    mut time: f64,
+    target: f64,
+    propagator: &mut Propagator,
+) -> Result<()> {
+    propagator.set_time(time);
+    loop {
+        let previous = previous_month(time);
+        if previous < target {
+            break;
+        }
+        propagator.advance(sim, previous - time);
+        time = previous;
+        snapshots.save(time, sim)?;
+    }
+    propagator.advance(sim, target - time);
+    Ok(())
+}
+
+/// Warns about propagating from `time` back to `target`, with an estimate of the error.
+pub fn warn_backwards(sim: &SimState, time: f64, target: f64, propagator: &Propagator) {
+    warn!(
+        from = %sim_time_to_utc(time),
+        days = (time - target) / 86400.0,
+        error_km = propagator.estimated_error(sim, target - time),
+        "propagating backwards from the earliest available state"
+    );
+}
+
+fn checksum(time: Option<f64>, payload: &str) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    if let Some(time) = time {
+        hasher.update(&time.to_le_bytes());
+    }
+    hasher.update(payload.as_bytes());
+    format!("{:08x}", hasher.finalize())
+}
+
+fn serialize(time: f64, sim: &SimState) -> String {
+    let payload = bodies::to_toml(sim);
+    let header = Header {
+        version: SCHEMA_VERSION,
+        time: Some(time),
+        checksum: checksum(Some(time), &payload),
+    };
+    format!("{}\n{}", toml::to_string(&header).unwrap(), payload)
+}
+
+/// Reads a single state: a snapshot file, whose time is returned too if it's known, or any
+/// other file of bodies `bodies::load` reads.
+pub fn read(path: &Path) -> Result<(Option<f64>, SimState)> {
+    if path.extension() != Some(OsStr::new("toml")) {
+        return Ok((None, bodies::load(path)?));
+    }
+    let contents = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+    let (time, sim) = if contents.starts_with("version") {
+        parse(&contents).map_err(|err| err.in_file(path))?
+    } else {
+        (None, bodies::load(path)?)
+    };
+    Ok((time.or_else(|| time_from_file_name(path)), sim))
+}
+
+/// Parses a snapshot file, verifying its checksum. Returns the time, if the file has it, and
+/// the state.
+fn parse(contents: &str) -> Result<(Option<f64>, SimState)> {
+    // files from before the header was introduced start with the bodies right away
+    if contents.starts_with("[[body]]") {
+        return Ok((None, bodies::from_toml(contents)?));
+    }
+    let (header, payload) = match contents.find("\n\n") {
+        Some(idx) => (&contents[..idx], &contents[idx + 2..]),
+        None => return Err(Error::Snapshot("no snapshot header".into())),
+    };
+    let header: Header = toml::from_str(header)?;
+    let time = header_time(&header)?;
+    if checksum(time, payload) != header.checksum {
+        return Err(Error::Snapshot("checksum mismatch".into()));
+    }
+    Ok((time, bodies::from_toml(payload)?))
+}
+
+/// Reads the time stored in the header of a snapshot file, without reading the rest.
+fn read_time(path: &Path) -> Result<Option<f64>> {
+    let mut header = String::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.starts_with("[[body]]") {
+            break;
+        }
+        if line.is_empty() {
+            let header: Header = toml::from_str(&header)?;
+            return header_time(&header);
+        }
+        header.push_str(&line);
+        header.push('\n');
+    }
+    if header.is_empty() {
+        Ok(None)
+    } else {
+        Err(Error::Snapshot("no snapshot header".into()))
+    }
+}
+
+/// The time given by a header, checking its version.
+fn header_time(header: &Header) -> Result<Option<f64>> {
+    match (header.version, header.time) {
+        (1, _) => Ok(None),
+        (2, Some(time)) => Ok(Some(time)),
+        (2, None) => Err(Error::Snapshot("no time in the header".into())),
+        (version, _) => Err(Error::Snapshot(format!(
+            "unsupported snapshot version {}",
+            version
+        ))),
+    }
+}
+
+/// The time of a legacy snapshot, a midnight UT given by its file name.
+fn time_from_file_name(path: &Path) -> Option<f64> {
+    let date = DateTime::<Utc>::from_str(&format!("{}T00:00:00Z", path.file_stem()?.to_str()?));
+    date.ok().map(legacy_ut_to_sim_time)
+}
+
+/// Simulation time of the last midnight UTC starting a month before `time`.
+pub fn previous_month(time: f64) -> f64 {
+    let date = sim_time_to_utc(time);
+    let first = format!("{}-{:02}-01T00:00:00Z", date.year(), date.month());
+    let first = utc_to_sim_time(DateTime::<Utc>::from_str(&first).unwrap());
+    if first < time {
+        first
+    } else if date.month() == 1 {
+        let first = format!("{}-12-01T00:00:00Z", date.year() - 1);
+        utc_to_sim_time(DateTime::<Utc>::from_str(&first).unwrap())
+    } else {
+        let first = format!("{}-{:02}-01T00:00:00Z", date.year(), date.month() - 1);
+        utc_to_sim_time(DateTime::<Utc>::from_str(&first).unwrap())
+    }
+}
+
+/// Simulation time of the first midnight UTC of a month after `time`, where the snapshots are
+/// saved.
+pub fn next_month(time: f64) -> f64 {
+    let date = sim_time_to_utc(time);
+    let (year, month) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    let first = format!("{}-{:02}-01T00:00:00Z", year, month);
+    utc_to_sim_time(DateTime::<Utc>::from_str(&first).unwrap())
+}
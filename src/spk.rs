@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use nalgebra::Vector3;
+
+use crate::{
+    error::{Error, Result},
+    frames::equatorial_to_ecliptic,
+    time::tdb_minus_tt,
+};
+
+const RECORD_LEN: u64 = 1024;
+/// Simulation time of the J2000 epoch (2000-01-01T12:00:00 TDB), which SPK times count from.
+const J2000: f64 = 43200.0;
+
+/// NAIF ids of the bodies that can be compared against a kernel.
+pub fn naif_id(name: &str) -> Option<i32> {
+    let id = match name {
+        "Sun" => 10,
+        "Mercury" => 199,
+        "Venus" => 299,
+        "Earth" => 399,
+        "Moon" => 301,
+        "Mars" => 4,
+        "Jupiter" => 5,
+        "Saturn" => 6,
+        "Uranus" => 7,
+        "Neptune" => 8,
+        "Pluto" => 9,
+        _ => return None,
+    };
+    Some(id)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: f64,
+    end: f64,
+    target: i32,
+    center: i32,
+    data_type: i32,
+    /// Word addresses (1-based, in doubles) of the segment data.
+    begin: u64,
+    end_addr: u64,
+}
+
+/// A binary SPK kernel, such as the JPL DE ephemerides. Only the Chebyshev position segments
+/// (type 2) are supported, which is what the planetary ephemerides use.
+pub struct Spk {
+    file: Mutex<File>,
+    little_endian: bool,
+    segments: Vec<Segment>,
+}
+
+impl Spk {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_file(path).map_err(|err| err.in_file(path))
+    }
+
+    fn open_file(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut record = [0u8; RECORD_LEN as usize];
+        file.read_exact(&mut record)?;
+
+        if &record[0..8] != b"DAF/SPK " {
+            return Err(Error::Spk("not an SPK file".into()));
+        }
+        let little_endian = match &record[88..96] {
+            b"LTL-IEEE" => true,
+            b"BIG-IEEE" => false,
+            _ => return Err(Error::Spk("unknown binary format".into())),
+        };
+        let read_i32 = |bytes: &[u8]| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if little_endian {
+                i32::from_le_bytes(bytes)
+            } else {
+                i32::from_be_bytes(bytes)
+            }
+        };
+        let nd = read_i32(&record[8..12]) as usize;
+        let ni = read_i32(&record[12..16]) as usize;
+        if nd != 2 || ni != 6 {
+            return Err(Error::Spk("unexpected summary format".into()));
+        }
+        let summary_len = nd + ni.div_ceil(2);
+
+        let mut spk = Self {
+            file: Mutex::new(file),
+            little_endian,
+            segments: vec![],
+        };
+
+        let mut next = read_i32(&record[76..80]) as u64;
+        while next != 0 {
+            let words = spk.read_words((next - 1) * RECORD_LEN / 8 + 1, 128)?;
+            let count = words[2] as usize;
+            if 3 + count * summary_len > words.len() {
+                return Err(Error::Spk(format!(
+                    "{} segment summaries don't fit in a summary record",
+                    count
+                )));
+            }
+            for i in 0..count {
+                let summary = &words[3 + i * summary_len..3 + (i + 1) * summary_len];
+                let mut ints = vec![];
+                for word in &summary[nd..] {
+                    let bytes = if little_endian {
+                        word.to_le_bytes()
+                    } else {
+                        word.to_be_bytes()
+                    };
+                    ints.push(read_i32(&bytes[0..4]));
+                    ints.push(read_i32(&bytes[4..8]));
+                }
+                spk.segments.push(Segment {
+                    start: summary[0],
+                    end: summary[1],
+                    target: ints[0],
+                    center: ints[1],
+                    data_type: ints[3],
+                    begin: ints[4] as u64,
+                    end_addr: ints[5] as u64,
+                });
+            }
+            next = words[0] as u64;
+        }
+
+        Ok(spk)
+    }
+
+    /// Position and velocity (km, km/s, ecliptic J2000) of `target` relative to the solar
+    /// system barycenter at the given simulation time.
+    pub fn state(&self, target: i32, time: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        let et = time + tdb_minus_tt(time) - J2000;
+        let mut pos = Vector3::zeros();
+        let mut vel = Vector3::zeros();
+        let mut body = target;
+        while body != 0 {
+            let segment = self
+                .segments
+                .iter()
+                .rev()
+                .find(|seg| seg.target == body && seg.start <= et && et <= seg.end)
+                .copied()
+                .ok_or_else(|| Error::Spk(format!("no data for body {} at ET {}", body, et)))?;
+            let (p, v) = self.evaluate(&segment, et)?;
+            pos += p;
+            vel += v;
+            body = segment.center;
+        }
+        Ok((equatorial_to_ecliptic(pos), equatorial_to_ecliptic(vel)))
+    }
+
+    fn evaluate(&self, segment: &Segment, et: f64) -> Result<(Vector3<f64>, Vector3<f64>)> {
+        if segment.data_type != 2 {
+            return Err(Error::Spk(format!(
+                "unsupported SPK segment type {}",
+                segment.data_type
+            )));
+        }
+        let directory = self.read_words(segment.end_addr - 3, 4)?;
+        let (init, interval, record_size, count) = (
+            directory[0],
+            directory[1],
+            directory[2] as usize,
+            directory[3] as usize,
+        );
+        if count == 0 || record_size < 5 {
+            return Err(Error::Spk(format!(
+                "segment for body {} has no records",
+                segment.target
+            )));
+        }
+        let index = (((et - init) / interval) as usize).min(count - 1);
+        let record = self.read_words(segment.begin + (index * record_size) as u64, record_size)?;
+
+        let (mid, radius) = (record[0], record[1]);
+        let n = (record_size - 2) / 3;
+        let tau = (et - mid) / radius;
+
+        let mut pos = Vector3::zeros();
+        let mut vel = Vector3::zeros();
+        for axis in 0..3 {
+            let coeffs = &record[2 + axis * n..2 + (axis + 1) * n];
+            let (p, dp) = chebyshev(coeffs, tau);
+            pos[axis] = p;
+            vel[axis] = dp / radius;
+        }
+        Ok((pos, vel))
+    }
+
+    fn read_words(&self, address: u64, count: usize) -> io::Result<Vec<f64>> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start((address - 1) * 8))?;
+        let mut bytes = vec![0u8; count * 8];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0u8; 8];
+                word.copy_from_slice(chunk);
+                if self.little_endian {
+                    f64::from_le_bytes(word)
+                } else {
+                    f64::from_be_bytes(word)
+                }
+            })
+            .collect())
+    }
+}
+
+/// Value and derivative of a Chebyshev series at `x`.
+pub(crate) fn chebyshev(coeffs: &[f64], x: f64) -> (f64, f64) {
+    let (mut t0, mut t1) = (1.0, x);
+    let (mut d0, mut d1) = (0.0, 1.0);
+    let mut value = coeffs[0];
+    let mut derivative = 0.0;
+    if coeffs.len() > 1 {
+        value += coeffs[1] * x;
+        derivative += coeffs[1];
+    }
+    for coeff in coeffs.iter().skip(2) {
+        let t2 = 2.0 * x * t1 - t0;
+        let d2 = 2.0 * t1 + 2.0 * x * d1 - d0;
+        value += coeff * t2;
+        derivative += coeff * d2;
+        t0 = t1;
+        t1 = t2;
+        d0 = d1;
+        d1 = d2;
+    }
+    (value, derivative)
+}
@@ -0,0 +1,278 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, ParseError, SubsecRound, Utc};
+
+use crate::error::{Error, Result};
+
+/// Tidal acceleration of the Moon's mean longitude assumed by the ΔT before 1955, in arcseconds
+/// per century squared.
+const TIDAL_ACCELERATION: f64 = -25.858;
+
+/// ΔT = TT - UT in seconds, from the Espenak & Meeus polynomials, which cover -500 to 2150 and
+/// fall back to Morrison & Stephenson's parabola outside.
+///
+/// Before 1955, ΔT comes from old eclipses timed against a Moon slowed down by the tides,
+/// which the propagated Moon isn't. It's corrected as if there were no tidal acceleration, so
+/// that the Earth is turned the way it was when the propagated Moon's shadow gets there: by
+/// over four hours in the time of Thales.
+pub fn delta_t(date: DateTime<Utc>) -> f64 {
+    let y = date.year() as f64 + (date.month() as f64 - 0.5) / 12.0;
+    let u = (y - 1955.0) / 100.0;
+    let correction = if y < 1955.0 {
+        0.91072 * TIDAL_ACCELERATION * u * u
+    } else {
+        0.0
+    };
+    espenak_meeus(y) + correction
+}
+
+/// ΔT in seconds at the fractional year `y`.
+fn espenak_meeus(y: f64) -> f64 {
+    let poly = |t: f64, coeffs: &[f64]| coeffs.iter().rev().fold(0.0, |sum, c| sum * t + c);
+    let parabola = |y: f64| -20.0 + 32.0 * ((y - 1820.0) / 100.0).powi(2);
+    match y {
+        y if y < -500.0 => parabola(y),
+        y if y < 500.0 => poly(
+            y / 100.0,
+            &[
+                10583.6,
+                -1014.41,
+                33.78311,
+                -5.952053,
+                -0.1798452,
+                0.022174192,
+                0.0090316521,
+            ],
+        ),
+        y if y < 1600.0 => poly(
+            (y - 1000.0) / 100.0,
+            &[
+                1574.2,
+                -556.01,
+                71.23472,
+                0.319781,
+                -0.8503463,
+                -0.005050998,
+                0.0083572073,
+            ],
+        ),
+        y if y < 1700.0 => poly(y - 1600.0, &[120.0, -0.9808, -0.01532, 1.0 / 7129.0]),
+        y if y < 1800.0 => poly(
+            y - 1700.0,
+            &[8.83, 0.1603, -0.0059285, 0.00013336, -1.0 / 1174000.0],
+        ),
+        y if y < 1860.0 => poly(
+            y - 1800.0,
+            &[
+                13.72,
+                -0.332447,
+                0.0068612,
+                0.0041116,
+                -0.00037436,
+                0.0000121272,
+                -0.0000001699,
+                0.000000000875,
+            ],
+        ),
+        y if y < 1900.0 => poly(
+            y - 1860.0,
+            &[
+                7.62,
+                0.5737,
+                -0.251754,
+                0.01680668,
+                -0.0004473624,
+                1.0 / 233174.0,
+            ],
+        ),
+        y if y < 1920.0 => poly(
+            y - 1900.0,
+            &[-2.79, 1.494119, -0.0598939, 0.0061966, -0.000197],
+        ),
+        y if y < 1941.0 => poly(y - 1920.0, &[21.20, 0.84493, -0.076100, 0.0020936]),
+        y if y < 1961.0 => poly(y - 1950.0, &[29.07, 0.407, -1.0 / 233.0, 1.0 / 2547.0]),
+        y if y < 1986.0 => poly(y - 1975.0, &[45.45, 1.067, -1.0 / 260.0, -1.0 / 718.0]),
+        y if y < 2005.0 => poly(y - 2000.0, &[63.86, 0.3345, -0.060374, 0.0017275]),
+        y if y < 2050.0 => poly(y - 2000.0, &[62.92, 0.32217, 0.005589]),
+        y if y < 2150.0 => parabola(y) - 0.5628 * (2150.0 - y),
+        y => parabola(y),
+    }
+}
+
+/// TAI - UTC in seconds since the given UTC year and month.
+const LEAP_SECONDS: &[(i32, u32, f64)] = &[
+    (1972, 1, 10.0),
+    (1972, 7, 11.0),
+    (1973, 1, 12.0),
+    (1974, 1, 13.0),
+    (1975, 1, 14.0),
+    (1976, 1, 15.0),
+    (1977, 1, 16.0),
+    (1978, 1, 17.0),
+    (1979, 1, 18.0),
+    (1980, 1, 19.0),
+    (1981, 7, 20.0),
+    (1982, 7, 21.0),
+    (1983, 7, 22.0),
+    (1985, 7, 23.0),
+    (1988, 1, 24.0),
+    (1990, 1, 25.0),
+    (1991, 1, 26.0),
+    (1992, 7, 27.0),
+    (1993, 7, 28.0),
+    (1994, 7, 29.0),
+    (1996, 1, 30.0),
+    (1997, 7, 31.0),
+    (1999, 1, 32.0),
+    (2006, 1, 33.0),
+    (2009, 1, 34.0),
+    (2012, 7, 35.0),
+    (2015, 7, 36.0),
+    (2017, 1, 37.0),
+];
+
+/// TT - TAI in seconds.
+const TT_MINUS_TAI: f64 = 32.184;
+
+/// TT - UTC in seconds at the given UTC date. No leap seconds are assumed after the last
+/// known one, and before 1972, when UTC didn't count whole seconds of TAI, ΔT is used.
+pub fn tt_minus_utc(date: DateTime<Utc>) -> f64 {
+    let month = (date.year(), date.month());
+    match LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(year, first, _)| (*year, *first) <= month)
+    {
+        Some((_, _, tai_minus_utc)) => TT_MINUS_TAI + tai_minus_utc,
+        None => delta_t(date),
+    }
+}
+
+/// TDB - TT in seconds at the given simulation time, with the two largest periodic terms.
+pub fn tdb_minus_tt(time: f64) -> f64 {
+    let g = (357.53 + 0.98560028 * (time / 86400.0 + EPOCH_JD - 2451545.0)).to_radians();
+    0.001657 * g.sin() + 0.000014 * (2.0 * g).sin()
+}
+
+/// A duration of `seconds`, rounded to the nanosecond. The whole seconds are converted
+/// separately, so that it doesn't overflow for durations of more than 292 years and the
+/// fraction is rounded the same way for any of them.
+pub fn duration(seconds: f64) -> Duration {
+    let whole = seconds.floor();
+    Duration::seconds(whole as i64)
+        + Duration::nanoseconds(((seconds - whole) * 1e9).round() as i64)
+}
+
+/// The length of `duration` in seconds, the inverse of `duration`.
+pub fn seconds(duration: Duration) -> f64 {
+    let whole = duration.num_seconds();
+    let nanos = (duration - Duration::seconds(whole))
+        .num_nanoseconds()
+        .unwrap_or(0);
+    whole as f64 + nanos as f64 * 1e-9
+}
+
+/// Converts a UTC date to simulation time.
+pub fn utc_to_sim_time(date: DateTime<Utc>) -> f64 {
+    seconds(date - epoch()) + tt_minus_utc(date)
+}
+
+/// Converts simulation time (TT seconds since the epoch) to a UTC date, rounded to the
+/// millisecond once all the offsets are applied, as the dates are only shown.
+pub fn sim_time_to_utc(time: f64) -> DateTime<Utc> {
+    let tt = epoch() + duration(time);
+    // the offset at the TT date is wrong only within a minute after a leap second
+    let guess = tt - duration(tt_minus_utc(tt));
+    (tt - duration(tt_minus_utc(guess))).round_subsecs(3)
+}
+
+/// Converts a date expressed in TT to UT.
+pub fn tt_to_ut(date: DateTime<Utc>) -> DateTime<Utc> {
+    date - duration(delta_t(date))
+}
+
+/// Julian date of the epoch.
+pub const EPOCH_JD: f64 = 2451544.5;
+
+/// The moment simulation time is counted from, 2000-01-01T00:00:00 TT.
+pub fn epoch() -> DateTime<Utc> {
+    DateTime::<Utc>::from_str("2000-01-01T00:00:00Z").unwrap()
+}
+
+/// Converts a Julian date (TDB) to simulation time.
+pub fn jd_to_sim_time(jd: f64) -> f64 {
+    let time = (jd - EPOCH_JD) * 86400.0;
+    time - tdb_minus_tt(time)
+}
+
+/// Converts the UT date naming a legacy snapshot to simulation time, with ΔT in whole seconds
+/// as it was when they were written. Everything else is in UTC.
+pub fn legacy_ut_to_sim_time(date: DateTime<Utc>) -> f64 {
+    // the inverse of the old conversion to UT, which took ΔT at the TT date in whole seconds
+    let tt = date + Duration::seconds(delta_t(date) as i64);
+    seconds(date - epoch()) + (delta_t(tt) as i64) as f64
+}
+
+/// Converts simulation time (TT seconds since the epoch) to a UT date.
+pub fn sim_time_to_ut(time: f64) -> DateTime<Utc> {
+    tt_to_ut(epoch() + duration(time))
+}
+
+/// Converts simulation time to UT1, in seconds since 2000-01-01T00:00:00 UT1.
+pub fn sim_time_to_ut1(time: f64) -> f64 {
+    time - delta_t(epoch() + duration(time))
+}
+
+/// Earth rotation angle (IERS 2003) at the given simulation time, in radians.
+pub fn earth_rotation_angle(time: f64) -> f64 {
+    let days = sim_time_to_ut1(time) / 86400.0 + EPOCH_JD - 2451545.0;
+    // the whole days are whole turns, so only their fraction is added to keep the precision
+    let turns = 0.7790572732640 + 0.00273781191135448 * days + days.fract();
+    turns.rem_euclid(1.0) * 2.0 * PI
+}
+
+/// Greenwich mean sidereal time (IAU 2006) at the given simulation time, in radians.
+pub fn gmst(time: f64) -> f64 {
+    let t = (time / 86400.0 + EPOCH_JD - 2451545.0) / 36525.0;
+    let arcsec = 0.014506 + 4612.156534 * t + 1.3915817 * t * t
+        - 0.00000044 * t * t * t
+        - 0.000029956 * t * t * t * t;
+    (earth_rotation_angle(time) + (arcsec / 3600.0).to_radians()).rem_euclid(2.0 * PI)
+}
+
+/// The year, month and day of the date of `date` in the Julian calendar.
+pub fn julian_calendar(date: DateTime<Utc>) -> (i32, u32, u32) {
+    // from the Julian day number, valid for the dates after 4713 BC
+    let c = date.num_days_from_ce() + 1721425 + 32082;
+    let d = (4 * c + 3) / 1461;
+    let e = c - 1461 * d / 4;
+    let m = (5 * e + 2) / 153;
+    let day = e - (153 * m + 2) / 5 + 1;
+    (
+        d - 4800 + m / 10,
+        (m + 3 - 12 * (m / 10)) as u32,
+        day as u32,
+    )
+}
+
+/// Parses a UTC date given either as YYYY-MM-DD, in the RFC 3339 format, or as a year
+/// standing for its first day. Years before 1 or after 9999 are astronomical ones with a sign,
+/// e.g. -0584 for 585 BC, and all dates are in the proleptic Gregorian calendar, not in the
+/// Julian one historical dates are usually given in.
+pub fn parse_date(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(&format!("{}T00:00:00Z", value)))
+        .or_else(|_| DateTime::parse_from_rfc3339(&format!("{}-01-01T00:00:00Z", value)))
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| parse_signed_date(value))
+        .map_err(|_| Error::Date(value.to_owned()))
+}
+
+fn parse_signed_date(value: &str) -> std::result::Result<DateTime<Utc>, ParseError> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let parse = |value: &str| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f");
+    parse(value)
+        .or_else(|_| parse(&format!("{}T00:00:00", value)))
+        .or_else(|_| parse(&format!("{}-01-01T00:00:00", value)))
+        .map(|date| date.and_utc())
+}
@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use nalgebra::Vector3;
+use serde::Serialize;
+
+use crate::{
+    frames::Frame,
+    simulation::{DenseStep, Propagator, SimState},
+    time::sim_time_to_utc,
+};
+
+/// The position and velocity of a body at some time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateVector {
+    pub date: DateTime<Utc>,
+    pub body: String,
+    /// In km.
+    pub position: [f64; 3],
+    /// In km/s.
+    pub velocity: [f64; 3],
+}
+
+/// The states of some bodies at regular intervals, relative to `center` (the origin of the
+/// simulation if `None`) in the given frame.
+pub struct Trajectory {
+    pub bodies: Vec<String>,
+    pub center: Option<String>,
+    pub frame: Frame,
+    /// Interval between the states, in seconds.
+    pub every: f64,
+}
+
+impl Trajectory {
+    /// The states between `start` and `end` (simulation times), `sim` being the state of the
+    /// system at `start`, ordered by time and then like `bodies`. The bodies have to exist in
+    /// `sim`.
+    pub fn sample<P: Into<Propagator>>(
+        &self,
+        sim: &SimState,
+        start: f64,
+        end: f64,
+        propagator: P,
+    ) -> Vec<StateVector> {
+        let mut sim = sim.clone();
+        let mut propagator = propagator.into();
+        let mut states = vec![];
+        let mut step = DenseStep::new(start, sim.clone(), start, sim.clone());
+        let mut time = start;
+        while time <= end {
+            // the states are interpolated within the steps, however short the interval
+            while !step.contains(time) {
+                step = propagator.dense_step(&mut sim, step.end_time());
+            }
+            let sim = step.state_at(time);
+            let (center_pos, center_vel) = match &self.center {
+                Some(name) => {
+                    let center = sim.body_by_name(name).unwrap();
+                    (center.pos, center.vel)
+                }
+                None => (Vector3::zeros(), Vector3::zeros()),
+            };
+            for name in &self.bodies {
+                let body = sim.body_by_name(name).unwrap();
+                let pos = body.pos - center_pos;
+                let vel = self
+                    .frame
+                    .velocity_from_sim(pos, body.vel - center_vel, time);
+                let pos = self.frame.from_sim(pos, time);
+                states.push(StateVector {
+                    date: sim_time_to_utc(time),
+                    body: name.clone(),
+                    position: [pos.x, pos.y, pos.z],
+                    velocity: [vel.x, vel.y, vel.z],
+                });
+            }
+            time += self.every;
+        }
+        states
+    }
+}
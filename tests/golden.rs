@@ -0,0 +1,238 @@
+//! Comparisons of the predictions with published data and earlier results, so that numerical
+//! changes can't make them worse unnoticed.
+//!
+//! The tests going past 2001 or back before 2000 propagate for over a decade and are too slow
+//! without optimizations, so they are ignored by default. Run them with
+//! `cargo test --release -- --include-ignored`.
+
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use eclipses::{
+    apsides::{self, ApsisKind},
+    bodies, eclipse, events,
+    events::Event,
+    observer::{self, GroundStation},
+    separation::{self, Viewpoint},
+    time::utc_to_sim_time,
+    Propagator, SimState,
+};
+
+const STEP: f64 = 300.0;
+
+/// The published times are rounded to the second, and the model of the Moon's orbit is good to
+/// some tens of seconds over the first years.
+const ECLIPSE_TOLERANCE: f64 = 30.0;
+/// The published perigees are rounded to the minute, and the minimum of the distance is flat.
+const PERIGEE_TOLERANCE: f64 = 180.0;
+const PERIGEE_DISTANCE_TOLERANCE: f64 = 10.0;
+/// The error of the Moon's position grows to a couple of minutes of its motion after decades.
+const TOTALITY_TOLERANCE: f64 = 180.0;
+/// The reference events were computed with the same model, so only rounding is expected.
+const EVENT_TOLERANCE: f64 = 1.0;
+/// Going back a century, the Moon drifts by minutes of its motion even with the full model.
+const HISTORICAL_TOLERANCE: f64 = 600.0;
+
+#[derive(Deserialize)]
+struct LunarEclipses {
+    eclipse: Vec<LunarEclipse>,
+}
+
+#[derive(Deserialize)]
+struct LunarEclipse {
+    greatest: DateTime<Utc>,
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct SolarEclipses {
+    eclipse: Vec<SolarEclipse>,
+}
+
+#[derive(Deserialize)]
+struct SolarEclipse {
+    greatest: DateTime<Utc>,
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct Perigees {
+    perigee: Vec<Perigee>,
+}
+
+#[derive(Deserialize)]
+struct Perigee {
+    date: DateTime<Utc>,
+    distance: f64,
+}
+
+#[derive(Deserialize)]
+struct Totalities {
+    totality: Vec<Totality>,
+}
+
+#[derive(Deserialize)]
+struct Totality {
+    place: String,
+    latitude: f64,
+    longitude: f64,
+    middle: DateTime<Utc>,
+}
+
+fn golden(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+    fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+/// The full model: the Earth's oblateness and the relativistic correction included.
+fn solar_system() -> SimState {
+    bodies::solar_system()
+        .with_oblateness("Earth", bodies::earth_oblateness())
+        .with_relativity("Sun")
+}
+
+/// The state at `time`, propagated from the initial one.
+fn state_at(time: f64) -> SimState {
+    let mut sim = solar_system();
+    Propagator::new(STEP).advance(&mut sim, time);
+    sim
+}
+
+fn seconds_between(date1: DateTime<Utc>, date2: DateTime<Utc>) -> f64 {
+    (date1 - date2).num_milliseconds() as f64 / 1e3
+}
+
+#[test]
+fn lunar_eclipses() {
+    let expected: LunarEclipses = toml::from_str(&golden("lunar_eclipses.toml")).unwrap();
+    let end = utc_to_sim_time(expected.eclipse.last().unwrap().greatest) + 86400.0;
+    let found = eclipse::find_lunar_eclipses(&solar_system(), 0.0, end, STEP);
+
+    assert_eq!(found.len(), expected.eclipse.len());
+    for (found, expected) in found.iter().zip(&expected.eclipse) {
+        assert_eq!(format!("{:?}", found.kind), expected.kind);
+        let error = seconds_between(found.greatest, expected.greatest);
+        assert!(
+            error.abs() < ECLIPSE_TOLERANCE,
+            "eclipse of {} is {} s off",
+            expected.greatest,
+            error
+        );
+    }
+}
+
+#[test]
+fn himawari_events() {
+    let expected: Vec<Event> = serde_json::from_str(&golden("himawari-2000-01.json")).unwrap();
+    let observer = observer::by_name("himawari").unwrap();
+    let end = utc_to_sim_time("2000-02-01T00:00:00Z".parse().unwrap());
+    let found = events::visibility_events(&solar_system(), 0.0, end, STEP, observer.as_ref());
+
+    assert_eq!(found.len(), expected.len());
+    for (found, expected) in found.iter().zip(&expected) {
+        assert_eq!(found.kind, expected.kind);
+        let error = seconds_between(found.date, expected.date);
+        assert!(
+            error.abs() < EVENT_TOLERANCE,
+            "{:?} at {} is {} s off",
+            expected.kind,
+            expected.date,
+            error
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn perigees() {
+    let expected: Perigees = toml::from_str(&golden("perigees.toml")).unwrap();
+    for perigee in &expected.perigee {
+        let time = utc_to_sim_time(perigee.date);
+        let start = time - 86400.0;
+        let found = apsides::lunar_apsides(&state_at(start), start, time + 86400.0, STEP);
+        let found = found
+            .iter()
+            .find(|apsis| apsis.kind == ApsisKind::Perigee)
+            .unwrap_or_else(|| panic!("no perigee around {}", perigee.date));
+
+        let error = seconds_between(found.date, perigee.date);
+        assert!(
+            error.abs() < PERIGEE_TOLERANCE,
+            "perigee of {} is {} s off",
+            perigee.date,
+            error
+        );
+        assert!(
+            (found.distance - perigee.distance).abs() < PERIGEE_DISTANCE_TOLERANCE,
+            "perigee of {} is {} km away instead of {} km",
+            perigee.date,
+            found.distance,
+            perigee.distance
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn solar_eclipses() {
+    let expected: Totalities = toml::from_str(&golden("solar_eclipses.toml")).unwrap();
+    for totality in &expected.totality {
+        let station =
+            GroundStation::new(&totality.place, totality.latitude, totality.longitude, 0.0);
+        let viewpoint = Viewpoint::observer(&station).apparent(true);
+        let time = utc_to_sim_time(totality.middle);
+        let start = time - 3600.0;
+        let found = separation::conjunctions(
+            &state_at(start),
+            start,
+            time + 3600.0,
+            STEP,
+            viewpoint,
+            &["Moon", "Sun"],
+            1.0f64.to_radians(),
+        );
+        assert_eq!(found.len(), 1, "no eclipse seen from {}", totality.place);
+
+        let contacts = found[0]
+            .occultation
+            .unwrap_or_else(|| panic!("the Sun isn't eclipsed from {}", totality.place));
+        assert!(
+            contacts.second.is_some(),
+            "the eclipse isn't total from {}",
+            totality.place
+        );
+        let error = seconds_between(found[0].date, totality.middle);
+        assert!(
+            error.abs() < TOTALITY_TOLERANCE,
+            "totality in {} is {} s off",
+            totality.place,
+            error
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn historical_eclipses() {
+    let expected: SolarEclipses = toml::from_str(&golden("historical_eclipses.toml")).unwrap();
+    for eclipse in &expected.eclipse {
+        let time = utc_to_sim_time(eclipse.greatest);
+        let start = time - 86400.0;
+        let found = eclipse::find_solar_eclipses(&state_at(start), start, time + 86400.0, STEP);
+        assert_eq!(found.len(), 1, "no eclipse around {}", eclipse.greatest);
+
+        assert_eq!(format!("{:?}", found[0].kind), eclipse.kind);
+        let error = seconds_between(found[0].greatest, eclipse.greatest);
+        assert!(
+            error.abs() < HISTORICAL_TOLERANCE,
+            "eclipse of {} is {} s off",
+            eclipse.greatest,
+            error
+        );
+    }
+}